@@ -0,0 +1,231 @@
+use crate::command_base::*;
+use crate::storage::save_json;
+use crate::time_parse::format_duration;
+
+use serde::{Deserialize, Serialize};
+use serenity::all::{CreateAllowedMentions, CreateMessage, Timestamp};
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+use std::fmt::Write;
+
+pub const AFK_PATH: &str = "data/afk.json";
+/// Longest reason that is stored, longer ones are cut.
+const MAX_REASON_CHARS: usize = 200;
+/// Mentions remembered per AFK member, older ones are dropped.
+const MAX_MISSED_MENTIONS: usize = 50;
+/// Missed mentions listed when the member comes back.
+const MISSED_MENTIONS_SHOWN: usize = 10;
+
+#[group]
+#[commands(afk)]
+#[only_in(guilds)]
+pub struct Afk;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MissedMention {
+    pub author: UserId,
+    pub channel: ChannelId,
+    pub message: MessageId,
+    /// Unix timestamp in seconds.
+    pub time: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AfkStatus {
+    pub reason: Option<String>,
+    /// Unix timestamp in seconds of when the member went AFK.
+    pub since: i64,
+    #[serde(default)]
+    pub mentions: Vec<MissedMention>,
+}
+
+/// The members who are AFK, per guild.
+pub struct AfkUsers;
+
+impl TypeMapKey for AfkUsers {
+    type Value = HashMap<GuildId, HashMap<UserId, AfkStatus>>;
+}
+
+#[command]
+#[description = "Marks you as AFK. Members who mention you are told why you are away, until you send your next message."]
+#[usage = "[reason]"]
+#[example = "lunch, back in an hour"]
+async fn afk(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let reason = args.rest().trim();
+    let reason = if reason.is_empty() {
+        None
+    } else if reason.chars().count() > MAX_REASON_CHARS {
+        let mut cut = reason
+            .chars()
+            .take(MAX_REASON_CHARS - 1)
+            .collect::<String>();
+        cut.push('…');
+        Some(cut)
+    } else {
+        Some(reason.to_string())
+    };
+
+    let afk_users = {
+        let mut data = ctx.data.write().await;
+        let afk_users = data
+            .get_mut::<AfkUsers>()
+            .expect("Expected AfkUsers in TypeMap.");
+        afk_users.entry(guild_id).or_default().insert(
+            msg.author.id,
+            AfkStatus {
+                reason: reason.clone(),
+                since: Timestamp::now().unix_timestamp(),
+                mentions: Vec::new(),
+            },
+        );
+        afk_users.clone()
+    };
+    save_json(AFK_PATH, &afk_users).await?;
+
+    let content = match reason {
+        Some(reason) => format!("You are now AFK: {}", reason),
+        None => String::from("You are now AFK."),
+    };
+    msg.channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(content)
+                .reference_message(msg)
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+    Ok(())
+}
+
+fn jump_link(guild_id: GuildId, mention: &MissedMention) -> String {
+    format!(
+        "https://discord.com/channels/{}/{}/{}",
+        guild_id, mention.channel, mention.message
+    )
+}
+
+/// Clears the AFK status of the author, and tells the author of `msg` about AFK members they
+/// mentioned. The mentions are remembered so the AFK members can catch up when they are back.
+pub async fn handle_message(ctx: &Context, msg: &Message) -> CommandResult {
+    let Some(guild_id) = msg.guild_id else {
+        return Ok(());
+    };
+    if msg.author.bot {
+        return Ok(());
+    }
+    let now = Timestamp::now().unix_timestamp();
+
+    let (returned, away, afk_users) = {
+        let mut data = ctx.data.write().await;
+        let afk_users = data
+            .get_mut::<AfkUsers>()
+            .expect("Expected AfkUsers in TypeMap.");
+        let Some(guild) = afk_users.get_mut(&guild_id) else {
+            return Ok(());
+        };
+        let returned = guild.remove(&msg.author.id);
+
+        let mut away = Vec::new();
+        for user in &msg.mentions {
+            if user.id == msg.author.id || away.iter().any(|(id, _)| *id == user.id) {
+                continue;
+            }
+            let Some(status) = guild.get_mut(&user.id) else {
+                continue;
+            };
+            status.mentions.push(MissedMention {
+                author: msg.author.id,
+                channel: msg.channel_id,
+                message: msg.id,
+                time: now,
+            });
+            if status.mentions.len() > MAX_MISSED_MENTIONS {
+                let excess = status.mentions.len() - MAX_MISSED_MENTIONS;
+                status.mentions.drain(..excess);
+            }
+            away.push((user.id, status.clone()));
+        }
+
+        if returned.is_none() && away.is_empty() {
+            return Ok(());
+        }
+        if guild.is_empty() {
+            afk_users.remove(&guild_id);
+        }
+        (returned, away, afk_users.clone())
+    };
+    save_json(AFK_PATH, &afk_users).await?;
+
+    if let Some(status) = returned {
+        let mut content = format!(
+            "Welcome back <@{}>, you were AFK for {}.",
+            msg.author.id,
+            format_duration(now - status.since)
+        );
+        if status.mentions.is_empty() {
+            content.push_str(" Nobody mentioned you.");
+        } else {
+            writeln!(
+                content,
+                " You were mentioned {} time{}:",
+                status.mentions.len(),
+                if status.mentions.len() == 1 { "" } else { "s" }
+            )?;
+            for mention in status.mentions.iter().rev().take(MISSED_MENTIONS_SHOWN) {
+                writeln!(
+                    content,
+                    "<@{}> <t:{}:R>: {}",
+                    mention.author,
+                    mention.time,
+                    jump_link(guild_id, mention)
+                )?;
+            }
+            if status.mentions.len() > MISSED_MENTIONS_SHOWN {
+                write!(
+                    content,
+                    "and {} more",
+                    status.mentions.len() - MISSED_MENTIONS_SHOWN
+                )?;
+            }
+        }
+        msg.channel_id
+            .send_message(
+                &ctx.http,
+                CreateMessage::new()
+                    .content(content)
+                    .reference_message(msg)
+                    .allowed_mentions(CreateAllowedMentions::new()),
+            )
+            .await?;
+    }
+
+    if !away.is_empty() {
+        let mut content = String::new();
+        for (user_id, status) in &away {
+            match &status.reason {
+                Some(reason) => writeln!(
+                    content,
+                    "<@{}> is AFK since <t:{}:R>: {}",
+                    user_id, status.since, reason
+                )?,
+                None => writeln!(
+                    content,
+                    "<@{}> is AFK since <t:{}:R>.",
+                    user_id, status.since
+                )?,
+            }
+        }
+        msg.channel_id
+            .send_message(
+                &ctx.http,
+                CreateMessage::new()
+                    .content(content)
+                    .reference_message(msg)
+                    .allowed_mentions(CreateAllowedMentions::new()),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,504 @@
+use crate::command_base::*;
+use crate::paginator::Paginator;
+use crate::recurrence::Recurrence;
+use crate::reminder_commands::{skip_words, user_timezone};
+use crate::scheduler::SchedulerContext;
+use crate::storage::save_json;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serenity::all::{CreateAllowedMentions, CreateEmbed, CreateMessage};
+use serenity::model::id::{ChannelId, GuildId};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+pub const ANNOUNCEMENTS_PATH: &str = "data/announcements.json";
+const MAX_ANNOUNCEMENTS_PER_GUILD: usize = 25;
+const MAX_PREVIEW: usize = 20;
+/// Runs missed by more than this, for example while the bot was down, are skipped instead of
+/// posted late.
+const GRACE_SECS: i64 = 15 * 60;
+
+#[group]
+#[prefixes("announce")]
+#[commands(add, embed, list, remove, pause, resume, skip, preview)]
+#[required_permissions(MANAGE_GUILD)]
+#[only_in(guilds)]
+pub struct Announce;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum AnnouncementContent {
+    Text { text: String },
+    Embed { title: String, description: String },
+}
+
+impl AnnouncementContent {
+    /// Users and roles named in the text are pinged, `@everyone` and `@here` never are.
+    fn message(&self) -> CreateMessage {
+        let message = match self {
+            AnnouncementContent::Text { text } => CreateMessage::new().content(text),
+            AnnouncementContent::Embed { title, description } => {
+                CreateMessage::new().embed(CreateEmbed::new().title(title).description(description))
+            }
+        };
+        message.allowed_mentions(CreateAllowedMentions::new().all_users(true).all_roles(true))
+    }
+
+    fn summary(&self) -> String {
+        let text = match self {
+            AnnouncementContent::Text { text } => text,
+            AnnouncementContent::Embed { title, .. } => title,
+        };
+        text.chars().take(60).collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Announcement {
+    pub guild: GuildId,
+    pub channel: ChannelId,
+    pub schedule: Recurrence,
+    pub content: AnnouncementContent,
+    /// Unix timestamp in seconds of the next run.
+    pub next: i64,
+    pub paused: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct StoredAnnouncements {
+    pub next_id: u64,
+    pub announcements: BTreeMap<u64, Announcement>,
+}
+
+pub struct Announcements;
+
+impl TypeMapKey for Announcements {
+    type Value = StoredAnnouncements;
+}
+
+async fn save_announcements(data: &RwLock<TypeMap>) -> CommandResult {
+    let announcements = {
+        let data = data.read().await;
+        data.get::<Announcements>()
+            .expect("Expected Announcements in TypeMap.")
+            .clone()
+    };
+    save_json(ANNOUNCEMENTS_PATH, &announcements).await?;
+    Ok(())
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    format!("<t:{}:F>", time.timestamp())
+}
+
+/// Posts every announcement that is due at `now` and moves it to its next run.
+pub async fn run_due(scheduler: &SchedulerContext, now: DateTime<Utc>) -> CommandResult {
+    let due = {
+        let mut data = scheduler.data.write().await;
+        let stored = data
+            .get_mut::<Announcements>()
+            .expect("Expected Announcements in TypeMap.");
+        let mut due = Vec::new();
+        let mut changed = false;
+        for (id, announcement) in stored.announcements.iter_mut() {
+            if announcement.paused || announcement.next > now.timestamp() {
+                continue;
+            }
+            changed = true;
+            let Some(previous) = DateTime::from_timestamp(announcement.next, 0) else {
+                continue;
+            };
+            match announcement.schedule.next_run(previous, now) {
+                Some(next) => announcement.next = next.timestamp(),
+                None => announcement.paused = true,
+            }
+            if now.timestamp() - previous.timestamp() <= GRACE_SECS {
+                due.push(announcement.clone());
+            } else {
+                println!("Skipped announcement {id}, it was due at {previous}");
+            }
+        }
+        if !changed {
+            return Ok(());
+        }
+        due
+    };
+    save_announcements(&scheduler.data).await?;
+
+    for announcement in due {
+        if let Err(why) = announcement
+            .channel
+            .send_message(&scheduler.http, announcement.content.message())
+            .await
+        {
+            println!(
+                "Could not post an announcement in {}: {why:?}",
+                announcement.channel
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Changes the announcement `id` of `guild_id`. Returns `None` if the guild has no such
+/// announcement.
+async fn update_announcement<T>(
+    ctx: &Context,
+    guild_id: GuildId,
+    id: u64,
+    f: impl FnOnce(&mut Announcement) -> T,
+) -> CommandResult<Option<T>> {
+    let result = {
+        let mut data = ctx.data.write().await;
+        data.get_mut::<Announcements>()
+            .expect("Expected Announcements in TypeMap.")
+            .announcements
+            .get_mut(&id)
+            .filter(|announcement| announcement.guild == guild_id)
+            .map(f)
+    };
+    if result.is_some() {
+        save_announcements(&ctx.data).await?;
+    }
+    Ok(result)
+}
+
+async fn add_announcement(
+    ctx: &Context,
+    msg: &Message,
+    mut args: Args,
+    embed: bool,
+) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let Some(channel) = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_channel(&arg))
+        .filter(|&channel| is_text_channel(ctx, guild_id, channel))
+    else {
+        msg.reply(ctx, "You need to provide a text channel of this server.")
+            .await?;
+        return Ok(());
+    };
+
+    let input = args.rest();
+    let words = input.split_whitespace().collect::<Vec<_>>();
+    let tz = user_timezone(&ctx.data, msg.author.id).await;
+    let Some((schedule, used)) = Recurrence::parse(&words, tz) else {
+        msg.reply(
+            ctx,
+            "I don't understand that schedule. Try `every 6h`, `every day 09:00`, \
+            `every monday 09:00 Europe/Berlin`, `every mon,fri 18:00` or `every month 1 12:00`.",
+        )
+        .await?;
+        return Ok(());
+    };
+    let text = skip_words(input, used);
+    if text.is_empty() {
+        msg.reply(ctx, "What should be announced?").await?;
+        return Ok(());
+    }
+    let content = if embed {
+        let (title, description) = text.split_once('|').unwrap_or((text, ""));
+        AnnouncementContent::Embed {
+            title: title.trim().to_string(),
+            description: description.trim().to_string(),
+        }
+    } else {
+        AnnouncementContent::Text {
+            text: text.to_string(),
+        }
+    };
+    let now = Utc::now();
+    let Some(next) = schedule.next_after(now) else {
+        msg.reply(ctx, "That schedule never runs.").await?;
+        return Ok(());
+    };
+
+    let added = {
+        let mut data = ctx.data.write().await;
+        let stored = data
+            .get_mut::<Announcements>()
+            .expect("Expected Announcements in TypeMap.");
+        let count = stored
+            .announcements
+            .values()
+            .filter(|announcement| announcement.guild == guild_id)
+            .count();
+        if count >= MAX_ANNOUNCEMENTS_PER_GUILD {
+            None
+        } else {
+            stored.next_id += 1;
+            let id = stored.next_id;
+            stored.announcements.insert(
+                id,
+                Announcement {
+                    guild: guild_id,
+                    channel,
+                    schedule: schedule.clone(),
+                    content,
+                    next: next.timestamp(),
+                    paused: false,
+                },
+            );
+            Some(id)
+        }
+    };
+    let Some(id) = added else {
+        msg.reply(
+            ctx,
+            format!(
+                "This server already has {} announcements.",
+                MAX_ANNOUNCEMENTS_PER_GUILD
+            ),
+        )
+        .await?;
+        return Ok(());
+    };
+    save_announcements(&ctx.data).await?;
+
+    msg.reply(
+        ctx,
+        format!(
+            "Announcement #{} in <#{}> `{}`, first run {}.",
+            id,
+            channel,
+            schedule,
+            timestamp(next)
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Schedules a recurring message. Calendar schedules without a time zone use \
+yours, see `remind timezone`."]
+#[usage = "<#channel> <schedule> <text>"]
+#[example = "#general every monday 09:00 Europe/Berlin Good morning, new week!"]
+async fn add(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    add_announcement(ctx, msg, args, false).await
+}
+
+#[command]
+#[description = "Schedules a recurring embed."]
+#[usage = "<#channel> <schedule> <title> | <description>"]
+#[example = "#events every friday 18:00 Game night | Join us in the voice channel!"]
+async fn embed(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    add_announcement(ctx, msg, args, true).await
+}
+
+#[command]
+#[description = "Lists the scheduled announcements of this server."]
+async fn list(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let announcements = {
+        let data = ctx.data.read().await;
+        data.get::<Announcements>()
+            .expect("Expected Announcements in TypeMap.")
+            .announcements
+            .iter()
+            .filter(|(_, announcement)| announcement.guild == guild_id)
+            .map(|(id, announcement)| (*id, announcement.clone()))
+            .collect::<Vec<_>>()
+    };
+    if announcements.is_empty() {
+        msg.reply(ctx, "There are no scheduled announcements.")
+            .await?;
+        return Ok(());
+    }
+
+    let entries = announcements
+        .into_iter()
+        .map(|(id, announcement)| {
+            let next = if announcement.paused {
+                String::from("paused")
+            } else {
+                format!("next <t:{}:R>", announcement.next)
+            };
+            format!(
+                "#{} <#{}> `{}` ({}): {}",
+                id,
+                announcement.channel,
+                announcement.schedule,
+                next,
+                announcement.content.summary()
+            )
+        })
+        .collect();
+    Paginator::new("Scheduled announcements", entries)
+        .send(ctx, msg)
+        .await
+}
+
+#[command]
+#[aliases("delete")]
+#[description = "Deletes a scheduled announcement."]
+#[usage = "<id>"]
+async fn remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let Ok(id) = args.single::<u64>() else {
+        msg.reply(ctx, "You need to provide an announcement id.")
+            .await?;
+        return Ok(());
+    };
+    let removed = {
+        let mut data = ctx.data.write().await;
+        let stored = data
+            .get_mut::<Announcements>()
+            .expect("Expected Announcements in TypeMap.");
+        if stored
+            .announcements
+            .get(&id)
+            .is_some_and(|announcement| announcement.guild == guild_id)
+        {
+            stored.announcements.remove(&id)
+        } else {
+            None
+        }
+    };
+    if removed.is_none() {
+        msg.reply(ctx, "There is no announcement with that id.")
+            .await?;
+        return Ok(());
+    }
+    save_announcements(&ctx.data).await?;
+    msg.reply(ctx, format!("Announcement #{} deleted.", id))
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Stops an announcement from posting until it is resumed."]
+#[usage = "<id>"]
+async fn pause(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let id = args.single::<u64>().unwrap_or_default();
+    let response = match update_announcement(ctx, guild_id, id, |announcement| {
+        announcement.paused = true;
+    })
+    .await?
+    {
+        Some(()) => format!("Announcement #{} paused.", id),
+        None => String::from("There is no announcement with that id."),
+    };
+    msg.reply(ctx, response).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Resumes a paused announcement from its next run on."]
+#[usage = "<id>"]
+async fn resume(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let id = args.single::<u64>().unwrap_or_default();
+    let now = Utc::now();
+    let response = match update_announcement(ctx, guild_id, id, |announcement| {
+        let next = announcement.schedule.next_after(now)?;
+        announcement.paused = false;
+        announcement.next = next.timestamp();
+        Some(next)
+    })
+    .await?
+    {
+        Some(Some(next)) => format!(
+            "Announcement #{} resumed, next run {}.",
+            id,
+            timestamp(next)
+        ),
+        Some(None) => String::from("That schedule never runs."),
+        None => String::from("There is no announcement with that id."),
+    };
+    msg.reply(ctx, response).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Skips the next run of an announcement."]
+#[usage = "<id>"]
+async fn skip(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let id = args.single::<u64>().unwrap_or_default();
+    let response = match update_announcement(ctx, guild_id, id, |announcement| {
+        let current = DateTime::from_timestamp(announcement.next, 0)?;
+        let next = announcement.schedule.next_after(current)?;
+        announcement.next = next.timestamp();
+        Some((current, next))
+    })
+    .await?
+    {
+        Some(Some((skipped, next))) => format!(
+            "Skipping the run at {}, the next one is {}.",
+            timestamp(skipped),
+            timestamp(next)
+        ),
+        Some(None) => String::from("That schedule never runs."),
+        None => String::from("There is no announcement with that id."),
+    };
+    msg.reply(ctx, response).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Shows the next runs of an announcement."]
+#[usage = "<id> [count]"]
+async fn preview(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let id = args.single::<u64>().unwrap_or_default();
+    let count = args.single::<usize>().unwrap_or(5).clamp(1, MAX_PREVIEW);
+    let announcement = {
+        let data = ctx.data.read().await;
+        data.get::<Announcements>()
+            .expect("Expected Announcements in TypeMap.")
+            .announcements
+            .get(&id)
+            .filter(|announcement| announcement.guild == guild_id)
+            .cloned()
+    };
+    let Some(announcement) = announcement else {
+        msg.reply(ctx, "There is no announcement with that id.")
+            .await?;
+        return Ok(());
+    };
+
+    // A paused announcement would continue from the next run after now.
+    let first = if announcement.paused {
+        announcement.schedule.next_after(Utc::now())
+    } else {
+        DateTime::from_timestamp(announcement.next, 0)
+    };
+    let runs = first
+        .into_iter()
+        .chain(
+            first
+                .map(|first| announcement.schedule.preview(first, count - 1))
+                .unwrap_or_default(),
+        )
+        .collect::<Vec<_>>();
+
+    let mut contents = String::new();
+    if announcement.paused {
+        writeln!(contents, "*Paused, these are the runs if resumed now.*")?;
+    }
+    for run in runs {
+        writeln!(contents, "{} (<t:{}:R>)", timestamp(run), run.timestamp())?;
+    }
+    msg.channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new().embed(
+                CreateEmbed::new()
+                    .title(format!("Announcement #{} `{}`", id, announcement.schedule))
+                    .description(contents),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
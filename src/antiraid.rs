@@ -0,0 +1,410 @@
+use crate::automod::normalize;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// Longest detection window that can be set, in seconds.
+const MAX_WINDOW_SECS: u64 = 60 * 60;
+/// Oldest age up to which accounts can count as new, in days.
+const MAX_NEW_ACCOUNT_DAYS: u64 = 10 * 365;
+
+/// What happens to the members that set off the anti-spam or anti-raid detection.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Punishment {
+    Timeout,
+    Kick,
+}
+
+impl Punishment {
+    pub fn from_name(name: &str) -> Option<Punishment> {
+        match name {
+            "timeout" => Some(Punishment::Timeout),
+            "kick" => Some(Punishment::Kick),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Punishment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Punishment::Timeout => f.write_str("timeout"),
+            Punishment::Kick => f.write_str("kick"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AntiRaidConfig {
+    pub enabled: bool,
+    pub punishment: Punishment,
+    pub timeout_minutes: u32,
+    /// Sending this many messages within `flood_window_secs` is a flood.
+    pub flood_messages: usize,
+    pub flood_window_secs: u64,
+    /// Posting the same message in this many channels within `duplicate_window_secs` is spam.
+    pub duplicate_channels: usize,
+    pub duplicate_window_secs: u64,
+    /// This many new accounts joining within `raid_window_secs` is a raid.
+    pub raid_joins: usize,
+    pub raid_window_secs: u64,
+    /// Accounts younger than this count as new.
+    pub new_account_days: u64,
+    /// Lock the server down automatically when a raid is detected.
+    pub auto_lockdown: bool,
+}
+
+impl Default for AntiRaidConfig {
+    fn default() -> Self {
+        AntiRaidConfig {
+            enabled: false,
+            punishment: Punishment::Timeout,
+            timeout_minutes: 10,
+            flood_messages: 6,
+            flood_window_secs: 5,
+            duplicate_channels: 3,
+            duplicate_window_secs: 30,
+            raid_joins: 5,
+            raid_window_secs: 10,
+            new_account_days: 7,
+            auto_lockdown: false,
+        }
+    }
+}
+
+impl AntiRaidConfig {
+    pub const SETTINGS: [&'static str; 8] = [
+        "timeout_minutes",
+        "flood_messages",
+        "flood_window_secs",
+        "duplicate_channels",
+        "duplicate_window_secs",
+        "raid_joins",
+        "raid_window_secs",
+        "new_account_days",
+    ];
+
+    /// Sets one of the numeric `SETTINGS`. Returns false if there is no such setting.
+    pub fn set(&mut self, name: &str, value: u64) -> bool {
+        let value_usize = value as usize;
+        match name {
+            "timeout_minutes" => self.timeout_minutes = value.min(28 * 24 * 60) as u32,
+            "flood_messages" => self.flood_messages = value_usize,
+            "flood_window_secs" => self.flood_window_secs = value.min(MAX_WINDOW_SECS),
+            "duplicate_channels" => self.duplicate_channels = value_usize,
+            "duplicate_window_secs" => self.duplicate_window_secs = value.min(MAX_WINDOW_SECS),
+            "raid_joins" => self.raid_joins = value_usize,
+            "raid_window_secs" => self.raid_window_secs = value.min(MAX_WINDOW_SECS),
+            "new_account_days" => self.new_account_days = value.min(MAX_NEW_ACCOUNT_DAYS),
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn get(&self, name: &str) -> Option<u64> {
+        Some(match name {
+            "timeout_minutes" => self.timeout_minutes as u64,
+            "flood_messages" => self.flood_messages as u64,
+            "flood_window_secs" => self.flood_window_secs,
+            "duplicate_channels" => self.duplicate_channels as u64,
+            "duplicate_window_secs" => self.duplicate_window_secs,
+            "raid_joins" => self.raid_joins as u64,
+            "raid_window_secs" => self.raid_window_secs,
+            "new_account_days" => self.new_account_days,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Detection {
+    /// Too many messages in a short time.
+    Flood { messages: usize },
+    /// The same message in several channels.
+    CrossPost { channels: usize },
+}
+
+impl fmt::Display for Detection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Detection::Flood { messages } => write!(f, "Sent {} messages in a row", messages),
+            Detection::CrossPost { channels } => {
+                write!(f, "Posted the same message in {} channels", channels)
+            }
+        }
+    }
+}
+
+/// Tracks the recent messages of every user. `U` identifies a user and `C` a channel, so the
+/// detector can be fed with made up timelines as well as with real Discord events.
+pub struct MessageDetector<U, C> {
+    history: HashMap<U, VecDeque<(Instant, C, String)>>,
+}
+
+impl<U, C> Default for MessageDetector<U, C> {
+    fn default() -> Self {
+        MessageDetector {
+            history: HashMap::new(),
+        }
+    }
+}
+
+impl<U: Hash + Eq, C: Hash + Eq + Copy> MessageDetector<U, C> {
+    /// Records a message sent at `now`. Once something is detected the history of the user is
+    /// cleared, so one burst is only reported once.
+    pub fn record(
+        &mut self,
+        config: &AntiRaidConfig,
+        user: U,
+        channel: C,
+        text: &str,
+        now: Instant,
+    ) -> Option<Detection> {
+        let flood_window = Duration::from_secs(config.flood_window_secs);
+        let duplicate_window = Duration::from_secs(config.duplicate_window_secs);
+        let keep = flood_window.max(duplicate_window);
+
+        let history = self.history.entry(user).or_default();
+        while history
+            .front()
+            .is_some_and(|(sent, _, _)| now.duration_since(*sent) > keep)
+        {
+            history.pop_front();
+        }
+        let text = normalize(text);
+        history.push_back((now, channel, text.clone()));
+
+        let messages = history
+            .iter()
+            .filter(|(sent, _, _)| now.duration_since(*sent) <= flood_window)
+            .count();
+        // Attachments and stickers have no text, those only count towards floods.
+        let channels = if text.is_empty() {
+            0
+        } else {
+            history
+                .iter()
+                .filter(|(sent, _, sent_text)| {
+                    now.duration_since(*sent) <= duplicate_window && *sent_text == text
+                })
+                .map(|(_, channel, _)| *channel)
+                .collect::<HashSet<_>>()
+                .len()
+        };
+
+        let detection = if config.flood_messages > 0 && messages >= config.flood_messages {
+            Some(Detection::Flood { messages })
+        } else if config.duplicate_channels > 1 && channels >= config.duplicate_channels {
+            Some(Detection::CrossPost { channels })
+        } else {
+            None
+        };
+        if detection.is_some() {
+            history.clear();
+        }
+        detection
+    }
+
+    /// Forgets users that have not written anything in a while.
+    pub fn prune(&mut self, now: Instant, max_age: Duration) {
+        self.history.retain(|_, history| {
+            history
+                .back()
+                .is_some_and(|(sent, _, _)| now.duration_since(*sent) <= max_age)
+        });
+    }
+}
+
+/// Watches the joins of one server for raids.
+pub struct RaidDetector<U> {
+    /// Recent joins of new accounts.
+    joins: VecDeque<(Instant, U)>,
+    /// A raid is ongoing until no new account joined for a whole raid window.
+    raid_until: Option<Instant>,
+}
+
+impl<U> Default for RaidDetector<U> {
+    fn default() -> Self {
+        RaidDetector {
+            joins: VecDeque::new(),
+            raid_until: None,
+        }
+    }
+}
+
+impl<U: Copy> RaidDetector<U> {
+    /// Records a member joining at `now` with an account of age `account_age`. Returns the
+    /// members that should be punished: all new accounts of the burst when a raid starts, and
+    /// then every further new account until the raid is over.
+    pub fn record(
+        &mut self,
+        config: &AntiRaidConfig,
+        user: U,
+        account_age: Duration,
+        now: Instant,
+    ) -> Vec<U> {
+        let window = Duration::from_secs(config.raid_window_secs.min(MAX_WINDOW_SECS));
+        let new_account_age = config.new_account_days.saturating_mul(24 * 60 * 60);
+        if account_age >= Duration::from_secs(new_account_age) {
+            return Vec::new();
+        }
+
+        while self
+            .joins
+            .front()
+            .is_some_and(|(joined, _)| now.duration_since(*joined) > window)
+        {
+            self.joins.pop_front();
+        }
+        self.joins.push_back((now, user));
+
+        if self.raid_until.is_some_and(|until| now <= until) {
+            self.raid_until = Some(now + window);
+            self.joins.clear();
+            return vec![user];
+        }
+        if config.raid_joins > 0 && self.joins.len() >= config.raid_joins {
+            self.raid_until = Some(now + window);
+            return self.joins.drain(..).map(|(_, user)| user).collect();
+        }
+        Vec::new()
+    }
+
+    /// Whether a raid was detected and is still going on at `now`.
+    pub fn is_raided(&self, now: Instant) -> bool {
+        self.raid_until.is_some_and(|until| now <= until)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AntiRaidConfig {
+        AntiRaidConfig {
+            raid_joins: 3,
+            raid_window_secs: 10,
+            new_account_days: 7,
+            ..AntiRaidConfig::default()
+        }
+    }
+
+    const NEW: Duration = Duration::from_secs(60 * 60);
+    const OLD: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+    #[test]
+    fn a_burst_of_new_accounts_is_a_raid() {
+        let config = config();
+        let mut detector = RaidDetector::default();
+        let start = Instant::now();
+        assert!(detector.record(&config, 1, NEW, start).is_empty());
+        assert!(detector
+            .record(&config, 2, NEW, start + Duration::from_secs(2))
+            .is_empty());
+        assert!(!detector.is_raided(start + Duration::from_secs(3)));
+        assert_eq!(
+            detector.record(&config, 3, NEW, start + Duration::from_secs(4)),
+            vec![1, 2, 3]
+        );
+        assert!(detector.is_raided(start + Duration::from_secs(5)));
+        // Every further new account is punished while the raid goes on.
+        assert_eq!(
+            detector.record(&config, 4, NEW, start + Duration::from_secs(12)),
+            vec![4]
+        );
+        assert!(detector.is_raided(start + Duration::from_secs(22)));
+        assert!(!detector.is_raided(start + Duration::from_secs(23)));
+    }
+
+    #[test]
+    fn joins_outside_the_window_do_not_count() {
+        let config = config();
+        let mut detector = RaidDetector::default();
+        let start = Instant::now();
+        assert!(detector.record(&config, 1, NEW, start).is_empty());
+        assert!(detector
+            .record(&config, 2, NEW, start + Duration::from_secs(6))
+            .is_empty());
+        assert!(detector
+            .record(&config, 3, NEW, start + Duration::from_secs(11))
+            .is_empty());
+        assert_eq!(
+            detector.record(&config, 4, NEW, start + Duration::from_secs(12)),
+            vec![2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn old_accounts_are_ignored() {
+        let config = config();
+        let mut detector = RaidDetector::default();
+        let start = Instant::now();
+        for user in 0..10 {
+            assert!(detector.record(&config, user, OLD, start).is_empty());
+        }
+        assert!(!detector.is_raided(start));
+        let week = Duration::from_secs(7 * 24 * 60 * 60);
+        assert!(detector.record(&config, 10, week, start).is_empty());
+        assert!(detector
+            .record(&config, 11, week - Duration::from_secs(1), start)
+            .is_empty());
+        assert!(!detector.is_raided(start));
+    }
+
+    #[test]
+    fn a_zero_threshold_disables_raid_detection() {
+        let config = AntiRaidConfig {
+            raid_joins: 0,
+            ..config()
+        };
+        let mut detector = RaidDetector::default();
+        let start = Instant::now();
+        for user in 0..10 {
+            assert!(detector.record(&config, user, NEW, start).is_empty());
+        }
+    }
+
+    #[test]
+    fn huge_settings_do_not_overflow() {
+        let mut config = config();
+        assert!(config.set("new_account_days", u64::MAX));
+        assert!(config.set("raid_window_secs", u64::MAX));
+        assert_eq!(config.get("new_account_days"), Some(MAX_NEW_ACCOUNT_DAYS));
+        assert_eq!(config.get("raid_window_secs"), Some(MAX_WINDOW_SECS));
+        // Loaded settings are not clamped by `set`.
+        config.new_account_days = u64::MAX;
+        config.raid_window_secs = u64::MAX;
+        let mut detector = RaidDetector::default();
+        let start = Instant::now();
+        assert!(detector.record(&config, 1, OLD, start).is_empty());
+    }
+
+    #[test]
+    fn floods_and_cross_posts_are_detected() {
+        let config = AntiRaidConfig {
+            flood_messages: 3,
+            flood_window_secs: 5,
+            duplicate_channels: 2,
+            duplicate_window_secs: 30,
+            ..AntiRaidConfig::default()
+        };
+        let mut detector = MessageDetector::default();
+        let start = Instant::now();
+        assert_eq!(detector.record(&config, 1, 1, "a", start), None);
+        assert_eq!(detector.record(&config, 1, 1, "b", start), None);
+        assert_eq!(
+            detector.record(&config, 1, 1, "c", start),
+            Some(Detection::Flood { messages: 3 })
+        );
+        let later = start + Duration::from_secs(10);
+        assert_eq!(detector.record(&config, 1, 1, "Hello", later), None);
+        assert_eq!(
+            detector.record(&config, 1, 2, "hello", later),
+            Some(Detection::CrossPost { channels: 2 })
+        );
+    }
+}
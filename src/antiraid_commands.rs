@@ -0,0 +1,471 @@
+use crate::antiraid::*;
+use crate::command_base::*;
+use crate::moderator_commands::{is_moderator, mod_log, mod_log_message};
+use crate::storage::save_json;
+
+use serde::{Deserialize, Serialize};
+use serenity::all::{
+    CreateAllowedMentions, CreateEmbed, CreateMessage, EditRole, Member, Permissions, Timestamp,
+};
+use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+use serenity::utils::parse_role_mention;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+pub const ANTIRAID_PATH: &str = "data/antiraid.json";
+
+#[group]
+#[prefixes("antiraid")]
+#[commands(
+    status, enable, disable, set, punishment, autolock, alertrole, lock, unlock
+)]
+#[required_permissions(MANAGE_GUILD)]
+#[only_in(guilds)]
+pub struct AntiRaid;
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct GuildAntiRaid {
+    #[serde(flatten)]
+    pub config: AntiRaidConfig,
+    /// Role that is pinged when a raid is detected.
+    pub alert_role: Option<RoleId>,
+    /// Whether `@everyone` could send messages before the current lockdown, `None` while the
+    /// server is not locked down.
+    pub unlocked_send_messages: Option<bool>,
+}
+
+pub struct AntiRaidConfigs;
+
+impl TypeMapKey for AntiRaidConfigs {
+    type Value = HashMap<GuildId, GuildAntiRaid>;
+}
+
+#[derive(Default)]
+pub struct AntiRaidState {
+    messages: MessageDetector<(GuildId, UserId), ChannelId>,
+    raids: HashMap<GuildId, RaidDetector<UserId>>,
+    /// Messages seen since the message history was last pruned.
+    since_prune: u32,
+}
+
+pub struct AntiRaidStateContainer;
+
+impl TypeMapKey for AntiRaidStateContainer {
+    type Value = AntiRaidState;
+}
+
+/// How often the message history is pruned, in messages.
+const PRUNE_INTERVAL: u32 = 1000;
+
+fn guild_config(data: &TypeMap, guild_id: GuildId) -> Option<GuildAntiRaid> {
+    data.get::<AntiRaidConfigs>()
+        .expect("Expected AntiRaidConfigs in TypeMap.")
+        .get(&guild_id)
+        .filter(|guild| guild.config.enabled)
+        .cloned()
+}
+
+/// Feeds a message that is not a command to the spam detection. Returns whether its author was
+/// punished.
+pub async fn handle_message(ctx: &Context, msg: &Message) -> CommandResult<bool> {
+    let Some(guild_id) = msg.guild_id else {
+        return Ok(false);
+    };
+    if msg.author.bot || is_moderator(ctx, msg) {
+        return Ok(false);
+    }
+
+    let (detection, config) = {
+        let mut data = ctx.data.write().await;
+        let Some(guild) = guild_config(&data, guild_id) else {
+            return Ok(false);
+        };
+        let state = data
+            .get_mut::<AntiRaidStateContainer>()
+            .expect("Expected AntiRaidStateContainer in TypeMap.");
+        let now = Instant::now();
+        state.since_prune += 1;
+        if state.since_prune >= PRUNE_INTERVAL {
+            state.since_prune = 0;
+            state.messages.prune(now, Duration::from_secs(10 * 60));
+        }
+        let detection = state.messages.record(
+            &guild.config,
+            (guild_id, msg.author.id),
+            msg.channel_id,
+            &msg.content,
+            now,
+        );
+        (detection, guild.config)
+    };
+    let Some(detection) = detection else {
+        return Ok(false);
+    };
+
+    let reason = format!("Anti-spam: {}", detection);
+    punish(ctx, guild_id, msg.author.id, &config, &reason).await?;
+
+    let embed = CreateEmbed::new()
+        .title("Spam detected")
+        .field("User", format!("<@{}>", msg.author.id), true)
+        .field("Channel", format!("<#{}>", msg.channel_id), true)
+        .field("Action", config.punishment.to_string(), true)
+        .field("Reason", detection.to_string(), false)
+        .timestamp(msg.timestamp);
+    mod_log(ctx, guild_id, embed).await?;
+
+    Ok(true)
+}
+
+/// Feeds a member joining to the raid detection.
+pub async fn handle_join(ctx: &Context, member: &Member) -> CommandResult {
+    let guild_id = member.guild_id;
+    if member.user.bot {
+        return Ok(());
+    }
+    let account_age =
+        Timestamp::now().unix_timestamp() - member.user.id.created_at().unix_timestamp();
+    let account_age = Duration::from_secs(account_age.max(0) as u64);
+
+    let (offenders, guild, raid_started) = {
+        let mut data = ctx.data.write().await;
+        let Some(guild) = guild_config(&data, guild_id) else {
+            return Ok(());
+        };
+        let state = data
+            .get_mut::<AntiRaidStateContainer>()
+            .expect("Expected AntiRaidStateContainer in TypeMap.");
+        let now = Instant::now();
+        let detector = state.raids.entry(guild_id).or_default();
+        let was_raided = detector.is_raided(now);
+        let offenders = detector.record(&guild.config, member.user.id, account_age, now);
+        (offenders, guild, !was_raided && detector.is_raided(now))
+    };
+    if offenders.is_empty() {
+        return Ok(());
+    }
+
+    let reason = "Anti-raid: new account joined during a raid";
+    for user in &offenders {
+        if let Err(why) = punish(ctx, guild_id, *user, &guild.config, reason).await {
+            println!("Could not punish raider {user}: {why:?}");
+        }
+    }
+    if !raid_started {
+        return Ok(());
+    }
+
+    let locked = guild.config.auto_lockdown && set_lockdown(ctx, guild_id, true).await.is_ok();
+    let users = offenders
+        .iter()
+        .map(|user| format!("<@{}>", user))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let embed = CreateEmbed::new()
+        .title("Raid detected")
+        .description(format!(
+            "{} new accounts joined within {} seconds.",
+            offenders.len(),
+            guild.config.raid_window_secs
+        ))
+        .field("Action", guild.config.punishment.to_string(), true)
+        .field("Lockdown", if locked { "on" } else { "off" }, true)
+        .field("Accounts", users, false)
+        .timestamp(Timestamp::now());
+    let mut message = CreateMessage::new().embed(embed);
+    if let Some(role) = guild.alert_role {
+        message = message
+            .content(format!("<@&{}>", role))
+            .allowed_mentions(CreateAllowedMentions::new().roles(vec![role]));
+    }
+    mod_log_message(ctx, guild_id, message).await
+}
+
+async fn punish(
+    ctx: &Context,
+    guild_id: GuildId,
+    user: UserId,
+    config: &AntiRaidConfig,
+    reason: &str,
+) -> CommandResult {
+    match config.punishment {
+        Punishment::Timeout => {
+            let until = Timestamp::from_unix_timestamp(
+                Timestamp::now().unix_timestamp() + i64::from(config.timeout_minutes) * 60,
+            )?;
+            let mut member = guild_id.member(&ctx.http, user).await?;
+            member
+                .disable_communication_until_datetime(&ctx.http, until)
+                .await?;
+        }
+        Punishment::Kick => guild_id.kick_with_reason(&ctx.http, user, reason).await?,
+    }
+    Ok(())
+}
+
+/// Takes the permission to send messages away from `@everyone`, or gives back what it was
+/// before. Returns false if the server already was in that state.
+async fn set_lockdown(ctx: &Context, guild_id: GuildId, locked: bool) -> CommandResult<bool> {
+    let unlocked_send_messages = {
+        let data = ctx.data.read().await;
+        data.get::<AntiRaidConfigs>()
+            .expect("Expected AntiRaidConfigs in TypeMap.")
+            .get(&guild_id)
+            .and_then(|guild| guild.unlocked_send_messages)
+    };
+    if locked == unlocked_send_messages.is_some() {
+        return Ok(false);
+    }
+
+    let everyone = guild_id.everyone_role();
+    let roles = guild_id.roles(&ctx.http).await?;
+    let mut permissions = roles
+        .get(&everyone)
+        .map(|role| role.permissions)
+        .unwrap_or_default();
+    let could_send = permissions.contains(Permissions::SEND_MESSAGES);
+    permissions.set(
+        Permissions::SEND_MESSAGES,
+        !locked && unlocked_send_messages.unwrap_or(true),
+    );
+    guild_id
+        .edit_role(
+            &ctx.http,
+            everyone,
+            EditRole::new().permissions(permissions),
+        )
+        .await?;
+    update_config(ctx, guild_id, |guild| {
+        guild.unlocked_send_messages = locked.then_some(could_send);
+    })
+    .await?;
+    Ok(true)
+}
+
+/// Applies `change` to the anti-raid config of the guild and saves all configs.
+async fn update_config(
+    ctx: &Context,
+    guild_id: GuildId,
+    change: impl FnOnce(&mut GuildAntiRaid),
+) -> CommandResult {
+    let configs = {
+        let mut data = ctx.data.write().await;
+        let configs = data
+            .get_mut::<AntiRaidConfigs>()
+            .expect("Expected AntiRaidConfigs in TypeMap.");
+        change(configs.entry(guild_id).or_default());
+        configs.clone()
+    };
+    save_json(ANTIRAID_PATH, &configs).await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Shows the anti-spam and anti-raid settings of this server."]
+async fn status(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap();
+    let guild = {
+        let data = ctx.data.read().await;
+        data.get::<AntiRaidConfigs>()
+            .expect("Expected AntiRaidConfigs in TypeMap.")
+            .get(&guild_id)
+            .cloned()
+            .unwrap_or_default()
+    };
+    let config = &guild.config;
+
+    let mut contents = format!(
+        "Anti-raid is {}.\n",
+        if config.enabled { "on" } else { "off" }
+    );
+    writeln!(contents, "Punishment: {}", config.punishment)?;
+    writeln!(
+        contents,
+        "Automatic lockdown: {}",
+        if config.auto_lockdown { "on" } else { "off" }
+    )?;
+    match guild.alert_role {
+        Some(role) => writeln!(contents, "Alert role: <@&{}>", role)?,
+        None => writeln!(contents, "Alert role: none")?,
+    }
+    for setting in AntiRaidConfig::SETTINGS {
+        writeln!(
+            contents,
+            "{}: {}",
+            setting,
+            config.get(setting).unwrap_or_default()
+        )?;
+    }
+
+    msg.channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(contents)
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Turns anti-spam and anti-raid detection on."]
+async fn enable(ctx: &Context, msg: &Message) -> CommandResult {
+    update_config(ctx, msg.guild_id.unwrap(), |guild| {
+        guild.config.enabled = true
+    })
+    .await?;
+    msg.channel_id.say(&ctx.http, "Anti-raid is on.").await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Turns anti-spam and anti-raid detection off."]
+async fn disable(ctx: &Context, msg: &Message) -> CommandResult {
+    update_config(ctx, msg.guild_id.unwrap(), |guild| {
+        guild.config.enabled = false
+    })
+    .await?;
+    msg.channel_id.say(&ctx.http, "Anti-raid is off.").await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Changes a threshold, see `antiraid status` for all settings."]
+#[usage = "<setting> <value>"]
+async fn set(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let name = args.single::<String>().unwrap_or_default();
+    let Ok(value) = args.single::<u64>() else {
+        msg.reply(ctx, "Usage: `antiraid set <setting> <value>`")
+            .await?;
+        return Ok(());
+    };
+    if !AntiRaidConfig::SETTINGS.contains(&name.as_str()) {
+        msg.reply(
+            ctx,
+            format!(
+                "Unknown setting, try one of: {}",
+                AntiRaidConfig::SETTINGS.join(", ")
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    // Values out of range are clamped, the reply shows what was stored.
+    let mut stored = value;
+    update_config(ctx, msg.guild_id.unwrap(), |guild| {
+        guild.config.set(&name, value);
+        stored = guild.config.get(&name).unwrap_or(value);
+    })
+    .await?;
+    msg.channel_id
+        .say(&ctx.http, format!("Set `{}` to {}.", name, stored))
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Sets whether spammers and raiders are timed out or kicked."]
+#[usage = "<timeout|kick>"]
+async fn punishment(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Some(punishment) = args
+        .single::<String>()
+        .ok()
+        .and_then(|name| Punishment::from_name(&name))
+    else {
+        msg.reply(ctx, "Usage: `antiraid punishment <timeout|kick>`")
+            .await?;
+        return Ok(());
+    };
+
+    update_config(ctx, msg.guild_id.unwrap(), |guild| {
+        guild.config.punishment = punishment
+    })
+    .await?;
+    msg.channel_id
+        .say(
+            &ctx.http,
+            format!("Spammers and raiders now get a {}.", punishment),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Sets whether the server is locked down automatically during a raid."]
+#[usage = "<on|off>"]
+async fn autolock(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let auto_lockdown = match args.single::<String>().unwrap_or_default().as_str() {
+        "on" => true,
+        "off" => false,
+        _ => {
+            msg.reply(ctx, "Usage: `antiraid autolock <on|off>`")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    update_config(ctx, msg.guild_id.unwrap(), |guild| {
+        guild.config.auto_lockdown = auto_lockdown
+    })
+    .await?;
+    msg.channel_id
+        .say(
+            &ctx.http,
+            format!(
+                "Automatic lockdown is {}.",
+                if auto_lockdown { "on" } else { "off" }
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Sets the role that is pinged in the mod log when a raid is detected. Leave it \
+out to not ping anyone."]
+#[usage = "[@role]"]
+async fn alertrole(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let role = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_role_mention(&arg).or_else(|| arg.parse().ok()));
+
+    update_config(ctx, msg.guild_id.unwrap(), |guild| guild.alert_role = role).await?;
+    match role {
+        Some(_) => msg.channel_id.say(&ctx.http, "Alert role set.").await?,
+        None => msg.channel_id.say(&ctx.http, "Alert role removed.").await?,
+    };
+
+    Ok(())
+}
+
+#[command]
+#[description = "Locks the server down: nobody without extra roles can send messages."]
+async fn lock(ctx: &Context, msg: &Message) -> CommandResult {
+    let response = if set_lockdown(ctx, msg.guild_id.unwrap(), true).await? {
+        "The server is locked down."
+    } else {
+        "The server already is locked down."
+    };
+    msg.channel_id.say(&ctx.http, response).await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Ends a lockdown, giving `@everyone` back the permission to send messages if it had \
+it before."]
+async fn unlock(ctx: &Context, msg: &Message) -> CommandResult {
+    let response = if set_lockdown(ctx, msg.guild_id.unwrap(), false).await? {
+        "The lockdown is over."
+    } else {
+        "The server is not locked down."
+    };
+    msg.channel_id.say(&ctx.http, response).await?;
+    Ok(())
+}
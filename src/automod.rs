@@ -0,0 +1,540 @@
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::hash::Hash;
+use std::sync::{LazyLock, OnceLock};
+use std::time::{Duration, Instant};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+static INVITE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(discord\.gg|discord(app)?\.com/invite)/[a-z0-9-]+").unwrap()
+});
+static LINK_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)https?://([^/\s:?#<>]+)").unwrap());
+
+/// Longest window of the spam rule, in seconds. The spam history is kept this long.
+pub const MAX_SPAM_WINDOW_SECS: u64 = 60 * 60;
+/// The spam history of everyone is pruned every this many messages.
+const PRUNE_INTERVAL: u32 = 1000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleKind {
+    Words,
+    Regex,
+    Invites,
+    Links,
+    Caps,
+    Mentions,
+    Spam,
+}
+
+impl RuleKind {
+    pub const ALL: [RuleKind; 7] = [
+        RuleKind::Words,
+        RuleKind::Regex,
+        RuleKind::Invites,
+        RuleKind::Links,
+        RuleKind::Caps,
+        RuleKind::Mentions,
+        RuleKind::Spam,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RuleKind::Words => "words",
+            RuleKind::Regex => "regex",
+            RuleKind::Invites => "invites",
+            RuleKind::Links => "links",
+            RuleKind::Caps => "caps",
+            RuleKind::Mentions => "mentions",
+            RuleKind::Spam => "spam",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<RuleKind> {
+        RuleKind::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+}
+
+/// What happens to a message that breaks a rule. Everything but `Log` also deletes the message,
+/// and every action is reported in the mod log.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Delete,
+    Warn,
+    Timeout,
+    Log,
+}
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Action> {
+        match name {
+            "delete" => Some(Action::Delete),
+            "warn" => Some(Action::Warn),
+            "timeout" => Some(Action::Timeout),
+            "log" => Some(Action::Log),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Action::Delete => "delete",
+            Action::Warn => "warn",
+            Action::Timeout => "timeout",
+            Action::Log => "log",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AutomodConfig {
+    /// The enabled rules and what to do when they are broken.
+    pub rules: BTreeMap<RuleKind, Action>,
+    pub words: Vec<String>,
+    pub regexes: Vec<String>,
+    /// If not empty, links to any other domain break the `links` rule.
+    pub allowed_domains: Vec<String>,
+    pub denied_domains: Vec<String>,
+    /// Messages with fewer letters than this are never checked for caps.
+    pub caps_min_letters: usize,
+    pub caps_max_percent: u8,
+    pub max_mentions: usize,
+    /// Sending the same message this many times within `spam_window_secs` is spam.
+    pub spam_max_repeats: usize,
+    pub spam_window_secs: u64,
+    pub timeout_minutes: u32,
+    /// `regexes` compiled, filled on first use.
+    #[serde(skip)]
+    compiled_regexes: OnceLock<Vec<(String, Regex)>>,
+}
+
+impl Default for AutomodConfig {
+    fn default() -> Self {
+        AutomodConfig {
+            rules: BTreeMap::new(),
+            words: Vec::new(),
+            regexes: Vec::new(),
+            allowed_domains: Vec::new(),
+            denied_domains: Vec::new(),
+            caps_min_letters: 10,
+            caps_max_percent: 70,
+            max_mentions: 5,
+            spam_max_repeats: 3,
+            spam_window_secs: 30,
+            timeout_minutes: 10,
+            compiled_regexes: OnceLock::new(),
+        }
+    }
+}
+
+impl AutomodConfig {
+    /// The blocked patterns that compile, compiled once and reused for every message.
+    fn compiled_regexes(&self) -> &[(String, Regex)] {
+        self.compiled_regexes.get_or_init(|| {
+            self.regexes
+                .iter()
+                .filter_map(|pattern| Some((pattern.clone(), compile_regex(pattern).ok()?)))
+                .collect()
+        })
+    }
+
+    /// Must be called after changing `regexes`, so they are compiled again.
+    pub fn regexes_changed(&mut self) {
+        self.compiled_regexes = OnceLock::new();
+    }
+}
+
+/// What a message looks like to the rules.
+pub struct Content<'a> {
+    pub text: &'a str,
+    /// User and role mentions, plus one for `@everyone` / `@here`.
+    pub mentions: usize,
+}
+
+pub struct Violation {
+    pub rule: RuleKind,
+    pub action: Action,
+    pub reason: String,
+}
+
+/// Maps look-alike characters from other scripts to the latin letter they imitate.
+fn unconfuse(c: char) -> char {
+    match c {
+        'а' | 'α' => 'a',
+        'в' | 'β' => 'b',
+        'с' | 'ϲ' => 'c',
+        'ԁ' => 'd',
+        'е' | 'ε' => 'e',
+        'ɡ' => 'g',
+        'һ' | 'η' => 'h',
+        'і' | 'ι' | 'ı' => 'i',
+        'ј' => 'j',
+        'к' | 'κ' => 'k',
+        'м' | 'μ' => 'm',
+        'п' => 'n',
+        'о' | 'ο' | 'σ' => 'o',
+        'р' | 'ρ' => 'p',
+        'ԛ' => 'q',
+        'г' => 'r',
+        'ѕ' => 's',
+        'т' | 'τ' => 't',
+        'υ' => 'u',
+        'ν' => 'v',
+        'ѡ' | 'ω' => 'w',
+        'х' | 'χ' => 'x',
+        'у' | 'γ' => 'y',
+        'ᴢ' => 'z',
+        _ => c,
+    }
+}
+
+const LEET_SYMBOLS: [char; 4] = ['!', '|', '@', '$'];
+
+/// Digits always stand for letters, symbols only `inside` a word: `sh!t` is `shit`, but the
+/// `!` of `darn!` ends the word.
+fn unleet(c: char, inside: bool) -> char {
+    match c {
+        '0' => 'o',
+        '1' => 'i',
+        '3' => 'e',
+        '4' => 'a',
+        '5' => 's',
+        '7' => 't',
+        '8' => 'b',
+        '9' => 'g',
+        '!' | '|' if inside => 'i',
+        '@' if inside => 'a',
+        '$' if inside => 's',
+        _ => c,
+    }
+}
+
+/// Brings text into a form that is hard to dodge: compatibility decomposition (which also folds
+/// full-width and styled letters), no accents, look-alikes and leetspeak replaced by latin
+/// letters, lower case and punctuation turned into spaces. With `collapse`, repeated letters are
+/// collapsed too.
+fn fold(text: &str, collapse: bool) -> String {
+    let chars = text
+        .nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .map(|c| unconfuse(c.to_lowercase().next().unwrap_or(c)))
+        .collect::<Vec<_>>();
+    let mut out = String::with_capacity(text.len());
+    let mut last = None;
+    for (i, &c) in chars.iter().enumerate() {
+        // Runs of symbols like the `$$` in `p@$$word` count as inside too.
+        let inside = chars[..i]
+            .iter()
+            .rev()
+            .find(|c| !LEET_SYMBOLS.contains(c))
+            .is_some_and(|c| c.is_alphanumeric())
+            && chars[i + 1..]
+                .iter()
+                .find(|c| !LEET_SYMBOLS.contains(c))
+                .is_some_and(|c| c.is_alphanumeric());
+        let c = unleet(c, inside);
+        let c = if c.is_alphanumeric() { c } else { ' ' };
+        if !collapse || Some(c) != last {
+            out.push(c);
+        }
+        last = Some(c);
+    }
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Folds `text` like the word rule does and collapses repeated letters, so `SP4AAM` and `spam`
+/// look the same.
+pub fn normalize(text: &str) -> String {
+    fold(text, true)
+}
+
+/// Splits a word into its runs of equal letters, `boob` into `b`, `oo` and `b`.
+fn runs(word: &str) -> Vec<(char, usize)> {
+    let mut runs: Vec<(char, usize)> = Vec::new();
+    for c in word.chars() {
+        match runs.last_mut() {
+            Some((last, count)) if *last == c => *count += 1,
+            _ => runs.push((c, 1)),
+        }
+    }
+    runs
+}
+
+/// Whether `token` is `word` with some of its letters repeated: they are equal once repeats
+/// are collapsed, and no run of `token` is shorter than in `word`. So `fuuuck` is `fuck`, but
+/// `bob` is not `boob`.
+fn is_stretched(token: &str, word: &str) -> bool {
+    let (token, word) = (runs(token), runs(word));
+    token.len() == word.len()
+        && token
+            .iter()
+            .zip(&word)
+            .all(|((c, count), (w, min))| c == w && count >= min)
+}
+
+/// Whether the folded `text` contains the blocked `word`, which may have several words.
+fn contains_word(text: &[&str], word: &str) -> bool {
+    let word = fold(word, false);
+    let word = word.split(' ').collect::<Vec<_>>();
+    !word[0].is_empty()
+        && text.windows(word.len()).any(|window| {
+            window
+                .iter()
+                .zip(&word)
+                .all(|(token, word)| is_stretched(token, word))
+        })
+}
+
+pub fn compile_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern).size_limit(1 << 20).build()
+}
+
+/// Extracts the host names of all links in `text`.
+pub fn link_domains(text: &str) -> Vec<String> {
+    LINK_REGEX
+        .captures_iter(text)
+        .map(|captures| captures[1].to_lowercase())
+        .collect()
+}
+
+/// Whether `domain` is `listed` or one of its subdomains.
+fn domain_matches(domain: &str, listed: &str) -> bool {
+    let listed = listed.to_lowercase();
+    domain == listed || domain.ends_with(&format!(".{}", listed))
+}
+
+/// Checks `content` against every enabled rule except spam, which needs history and is handled
+/// by `SpamTracker`. Returns the first rule that was broken.
+pub fn check(config: &AutomodConfig, content: &Content) -> Option<Violation> {
+    let folded = fold(content.text, false);
+    for (&rule, &action) in &config.rules {
+        let reason = match rule {
+            RuleKind::Words => {
+                let text = folded.split(' ').collect::<Vec<_>>();
+                config
+                    .words
+                    .iter()
+                    .any(|word| contains_word(&text, word))
+                    .then(|| String::from("Blocked word"))
+            }
+            RuleKind::Regex => config
+                .compiled_regexes()
+                .iter()
+                .find(|(_, regex)| regex.is_match(content.text) || regex.is_match(&folded))
+                .map(|(pattern, _)| format!("Matched blocked pattern `{}`", pattern)),
+            RuleKind::Invites => INVITE_REGEX
+                .is_match(content.text)
+                .then(|| String::from("Server invite")),
+            RuleKind::Links => link_domains(content.text)
+                .into_iter()
+                .find(|domain| {
+                    let allowed = config.allowed_domains.is_empty()
+                        || config
+                            .allowed_domains
+                            .iter()
+                            .any(|listed| domain_matches(domain, listed));
+                    let denied = config
+                        .denied_domains
+                        .iter()
+                        .any(|listed| domain_matches(domain, listed));
+                    !allowed || denied
+                })
+                .map(|domain| format!("Link to `{}`", domain)),
+            RuleKind::Caps => {
+                let letters = content.text.chars().filter(|c| c.is_alphabetic());
+                let (total, upper) = letters.fold((0, 0), |(total, upper), c| {
+                    (total + 1, upper + usize::from(c.is_uppercase()))
+                });
+                (total >= config.caps_min_letters
+                    && upper * 100 > total * config.caps_max_percent as usize)
+                    .then(|| format!("Too many capital letters ({}%)", upper * 100 / total))
+            }
+            RuleKind::Mentions => (content.mentions > config.max_mentions)
+                .then(|| format!("Too many mentions ({})", content.mentions)),
+            RuleKind::Spam => None,
+        };
+        if let Some(reason) = reason {
+            return Some(Violation {
+                rule,
+                action,
+                reason,
+            });
+        }
+    }
+    None
+}
+
+/// Remembers recent messages per key (usually guild and user) to spot the same message being
+/// sent over and over.
+pub struct SpamTracker<K> {
+    history: HashMap<K, VecDeque<(Instant, String)>>,
+    /// Messages recorded since the history was last pruned.
+    since_prune: u32,
+}
+
+impl<K> Default for SpamTracker<K> {
+    fn default() -> Self {
+        SpamTracker {
+            history: HashMap::new(),
+            since_prune: 0,
+        }
+    }
+}
+
+impl<K: Hash + Eq> SpamTracker<K> {
+    /// Records a message and returns whether it was sent at least `max_repeats` times within
+    /// `window`, this one included. Windows longer than `MAX_SPAM_WINDOW_SECS` are shortened.
+    pub fn record(
+        &mut self,
+        key: K,
+        text: &str,
+        now: Instant,
+        window: Duration,
+        max_repeats: usize,
+    ) -> bool {
+        let max_window = Duration::from_secs(MAX_SPAM_WINDOW_SECS);
+        let window = window.min(max_window);
+        self.since_prune += 1;
+        if self.since_prune >= PRUNE_INTERVAL {
+            self.since_prune = 0;
+            self.prune(now, max_window);
+        }
+
+        let text = normalize(text);
+        let history = self.history.entry(key).or_default();
+        while history
+            .front()
+            .is_some_and(|(sent, _)| now.duration_since(*sent) > window)
+        {
+            history.pop_front();
+        }
+        history.push_back((now, text.clone()));
+        let repeats = history.iter().filter(|(_, sent)| *sent == text).count();
+        max_repeats > 0 && repeats >= max_repeats
+    }
+
+    /// Forgets keys without a message in the last `max_age`.
+    fn prune(&mut self, now: Instant, max_age: Duration) {
+        self.history.retain(|_, history| {
+            history
+                .back()
+                .is_some_and(|(sent, _)| now.duration_since(*sent) <= max_age)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(rule: RuleKind) -> AutomodConfig {
+        AutomodConfig {
+            rules: BTreeMap::from([(rule, Action::Delete)]),
+            ..AutomodConfig::default()
+        }
+    }
+
+    fn breaks(config: &AutomodConfig, text: &str) -> bool {
+        check(config, &Content { text, mentions: 0 }).is_some()
+    }
+
+    #[test]
+    fn normalize_folds_disguises() {
+        assert_eq!(normalize("Ｈéllo, Wörld."), "helo world");
+        assert_eq!(normalize("SP4AAM"), "spam");
+        assert_eq!(normalize("h3ll0 th3r3"), "helo there");
+        assert_eq!(fold("boob", false), "boob");
+        assert_eq!(fold("b.o.o.b", false), "b o o b");
+    }
+
+    #[test]
+    fn leet_symbols_only_count_inside_words() {
+        assert_eq!(fold("sh!t", false), "shit");
+        assert_eq!(fold("p@$$word", false), "password");
+        for text in ["darn!", "darn$", "@darn", "darn|", "(darn)", "!!darn!!"] {
+            assert_eq!(fold(text, false), "darn", "{text}");
+        }
+    }
+
+    #[test]
+    fn words_keep_their_double_letters() {
+        let config = AutomodConfig {
+            words: vec![String::from("boob")],
+            ..config(RuleKind::Words)
+        };
+        assert!(breaks(&config, "boob"));
+        assert!(breaks(&config, "B00B!"));
+        assert!(breaks(&config, "booooob"));
+        assert!(!breaks(&config, "bob"));
+        assert!(!breaks(&config, "hi bob"));
+        assert!(!breaks(&config, "boobs are birds"));
+    }
+
+    #[test]
+    fn words_catch_stretched_and_disguised_words() {
+        let config = AutomodConfig {
+            words: vec![String::from("darn"), String::from("bad word")],
+            ..config(RuleKind::Words)
+        };
+        assert!(breaks(&config, "well daaarn it"));
+        assert!(breaks(&config, "ԁ4гп"));
+        assert!(breaks(&config, "that is a BAD... w0rd"));
+        assert!(breaks(&config, "d@rn"));
+        assert!(!breaks(&config, "darning socks"));
+        assert!(!breaks(&config, "bad"));
+        assert!(!breaks(&config, ""));
+    }
+
+    #[test]
+    fn regexes_match_the_raw_and_the_normalized_text() {
+        let mut config = AutomodConfig {
+            regexes: vec![String::from(r"\bfree nitro\b"), String::from("(")],
+            ..config(RuleKind::Regex)
+        };
+        assert!(breaks(&config, "get FREE nitro now"));
+        assert!(breaks(&config, "fr33 n1tr0"));
+        assert!(!breaks(&config, "nitro is free"));
+        // Invalid patterns are skipped.
+        assert_eq!(config.compiled_regexes().len(), 1);
+
+        config.regexes = vec![String::from("nitro")];
+        config.regexes_changed();
+        assert!(breaks(&config, "nitro is free"));
+    }
+
+    #[test]
+    fn spam_needs_repeats_within_the_window() {
+        let mut tracker = SpamTracker::default();
+        let start = Instant::now();
+        let window = Duration::from_secs(10);
+        assert!(!tracker.record(1, "hi", start, window, 3));
+        assert!(!tracker.record(1, "HI!", start + Duration::from_secs(1), window, 3));
+        assert!(!tracker.record(2, "hi", start + Duration::from_secs(2), window, 3));
+        assert!(tracker.record(1, "hi", start + Duration::from_secs(3), window, 3));
+        assert!(!tracker.record(1, "hi", start + Duration::from_secs(20), window, 3));
+        assert!(!tracker.record(1, "hi", start, window, 0));
+    }
+
+    #[test]
+    fn spam_history_is_pruned() {
+        let mut tracker = SpamTracker::default();
+        let start = Instant::now();
+        let window = Duration::from_secs(10);
+        for key in 0..PRUNE_INTERVAL - 1 {
+            tracker.record(key, "hi", start, window, 3);
+        }
+        assert_eq!(tracker.history.len(), PRUNE_INTERVAL as usize - 1);
+        let later = start + Duration::from_secs(MAX_SPAM_WINDOW_SECS + 1);
+        tracker.record(PRUNE_INTERVAL, "hi", later, window, 3);
+        assert_eq!(tracker.history.len(), 1);
+    }
+}
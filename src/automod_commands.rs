@@ -0,0 +1,496 @@
+use crate::automod::*;
+use crate::command_base::*;
+use crate::moderator_commands::{add_warning, is_moderator, mod_log};
+use crate::storage::save_json;
+
+use serenity::all::{CreateEmbed, Timestamp};
+use serenity::model::id::{GuildId, UserId};
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+pub const AUTOMOD_PATH: &str = "data/automod.json";
+
+#[group]
+#[prefixes("automod")]
+#[commands(
+    status, enable, disable, words, regex, domains, caps, mentions, spam, timeout
+)]
+#[required_permissions(MANAGE_GUILD)]
+#[only_in(guilds)]
+pub struct Automod;
+
+/// The automod configuration of every guild.
+pub struct AutomodConfigs;
+
+impl TypeMapKey for AutomodConfigs {
+    type Value = HashMap<GuildId, AutomodConfig>;
+}
+
+/// Recent messages for the spam rule. Behind its own lock, so checking a message only needs to
+/// read the TypeMap.
+pub struct AutomodSpam;
+
+impl TypeMapKey for AutomodSpam {
+    type Value = Arc<Mutex<SpamTracker<(GuildId, UserId)>>>;
+}
+
+/// Runs the automod rules of the guild on a message, commands included.
+pub async fn handle_message(ctx: &Context, msg: &Message) -> CommandResult {
+    let Some(guild_id) = msg.guild_id else {
+        return Ok(());
+    };
+    if msg.author.bot {
+        return Ok(());
+    }
+    // Moderators are trusted to not need automod.
+    if is_moderator(ctx, msg) {
+        return Ok(());
+    }
+
+    let violation = {
+        let data = ctx.data.read().await;
+        // The config is not cloned, it keeps its compiled regexes for the next message.
+        let Some(config) = data
+            .get::<AutomodConfigs>()
+            .expect("Expected AutomodConfigs in TypeMap.")
+            .get(&guild_id)
+        else {
+            return Ok(());
+        };
+
+        let content = Content {
+            text: &msg.content,
+            mentions: msg.mentions.len()
+                + msg.mention_roles.len()
+                + usize::from(msg.mention_everyone),
+        };
+        let violation = check(config, &content);
+        let timeout_minutes = config.timeout_minutes;
+        let spam = config.rules.get(&RuleKind::Spam).map(|action| {
+            (
+                *action,
+                Duration::from_secs(config.spam_window_secs),
+                config.spam_max_repeats,
+            )
+        });
+        let violation = match (violation, spam) {
+            (None, Some((action, window, max_repeats))) => {
+                let mut tracker = data
+                    .get::<AutomodSpam>()
+                    .expect("Expected AutomodSpam in TypeMap.")
+                    .lock()
+                    .await;
+                tracker
+                    .record(
+                        (guild_id, msg.author.id),
+                        &msg.content,
+                        Instant::now(),
+                        window,
+                        max_repeats,
+                    )
+                    .then(|| Violation {
+                        rule: RuleKind::Spam,
+                        action,
+                        reason: String::from("Repeated message"),
+                    })
+            }
+            (violation, _) => violation,
+        };
+        violation.map(|violation| (violation, timeout_minutes))
+    };
+    let Some((violation, timeout_minutes)) = violation else {
+        return Ok(());
+    };
+
+    // The violation is logged even if the message could not be deleted.
+    let mut problem = None;
+    if violation.action != Action::Log {
+        if let Err(why) = msg.delete(&ctx.http).await {
+            problem = Some(format!("Could not delete the message: {}", why));
+        }
+    }
+    match violation.action {
+        Action::Warn => {
+            let time = msg.timestamp.format("%Y-%m-%d %H:%M:%S").to_string();
+            let reason = format!("Automod: {}", violation.reason);
+            add_warning(&msg.author.name, &time, &reason).await?;
+            msg.channel_id
+                .say(
+                    &ctx.http,
+                    format!("Warned <@{}> for {}", msg.author.id, reason),
+                )
+                .await?;
+        }
+        Action::Timeout => {
+            let until = Timestamp::from_unix_timestamp(
+                Timestamp::now().unix_timestamp() + i64::from(timeout_minutes) * 60,
+            )?;
+            let mut member = guild_id.member(&ctx.http, msg.author.id).await?;
+            member
+                .disable_communication_until_datetime(&ctx.http, until)
+                .await?;
+        }
+        Action::Delete | Action::Log => {}
+    }
+
+    let mut excerpt = msg.content.chars().take(1000).collect::<String>();
+    if excerpt.is_empty() {
+        excerpt.push_str("*no text*");
+    }
+    let embed = CreateEmbed::new()
+        .title(format!("Automod: {}", violation.rule.name()))
+        .field("User", format!("<@{}>", msg.author.id), true)
+        .field("Channel", format!("<#{}>", msg.channel_id), true)
+        .field("Action", violation.action.to_string(), true)
+        .field("Reason", violation.reason, false)
+        .field("Message", excerpt, false)
+        .timestamp(msg.timestamp);
+    let embed = match problem {
+        Some(problem) => embed.field("Problem", problem, false),
+        None => embed,
+    };
+    mod_log(ctx, guild_id, embed).await?;
+
+    Ok(())
+}
+
+/// Applies `change` to the automod config of the guild and saves all configs.
+async fn update_config(
+    ctx: &Context,
+    guild_id: GuildId,
+    change: impl FnOnce(&mut AutomodConfig),
+) -> CommandResult {
+    let configs = {
+        let mut data = ctx.data.write().await;
+        let configs = data
+            .get_mut::<AutomodConfigs>()
+            .expect("Expected AutomodConfigs in TypeMap.");
+        change(configs.entry(guild_id).or_default());
+        configs.clone()
+    };
+    save_json(AUTOMOD_PATH, &configs).await?;
+    Ok(())
+}
+
+/// Splits `add`/`remove` list arguments and applies them to `list`.
+fn edit_list(list: &mut Vec<String>, mode: &str, items: Vec<String>) {
+    match mode {
+        "add" => {
+            for item in items {
+                if !list.contains(&item) {
+                    list.push(item);
+                }
+            }
+        }
+        "remove" => list.retain(|entry| !items.contains(entry)),
+        _ => {}
+    }
+}
+
+#[command]
+#[description = "Shows the automod configuration of this server."]
+async fn status(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap();
+    let config = {
+        let data = ctx.data.read().await;
+        data.get::<AutomodConfigs>()
+            .expect("Expected AutomodConfigs in TypeMap.")
+            .get(&guild_id)
+            .cloned()
+            .unwrap_or_default()
+    };
+
+    let mut contents = String::from("Rules:\n");
+    for rule in RuleKind::ALL {
+        match config.rules.get(&rule) {
+            Some(action) => writeln!(contents, "- {}: {}", rule.name(), action)?,
+            None => writeln!(contents, "- {}: off", rule.name())?,
+        }
+    }
+    writeln!(contents, "Blocked words: {}", config.words.len())?;
+    writeln!(contents, "Blocked patterns: {}", config.regexes.len())?;
+    writeln!(
+        contents,
+        "Allowed domains: {}",
+        if config.allowed_domains.is_empty() {
+            String::from("all")
+        } else {
+            config.allowed_domains.join(", ")
+        }
+    )?;
+    writeln!(
+        contents,
+        "Denied domains: {}",
+        config.denied_domains.join(", ")
+    )?;
+    writeln!(
+        contents,
+        "Caps: more than {}% of at least {} letters",
+        config.caps_max_percent, config.caps_min_letters
+    )?;
+    writeln!(contents, "Mentions: more than {}", config.max_mentions)?;
+    writeln!(
+        contents,
+        "Spam: {} repeats within {} seconds",
+        config.spam_max_repeats, config.spam_window_secs
+    )?;
+    writeln!(contents, "Timeouts last {} minutes", config.timeout_minutes)?;
+
+    msg.channel_id.say(&ctx.http, &contents).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Turns on a rule (`words`, `regex`, `invites`, `links`, `caps`, `mentions`, \
+`spam`) with an action (`delete`, `warn`, `timeout`, `log`)."]
+#[usage = "<rule> <action>"]
+async fn enable(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let rule = args
+        .single::<String>()
+        .ok()
+        .and_then(|rule| RuleKind::from_name(&rule));
+    let action = args
+        .single::<String>()
+        .ok()
+        .and_then(|action| Action::from_name(&action));
+    let (Some(rule), Some(action)) = (rule, action) else {
+        msg.reply(
+            ctx,
+            "Usage: `automod enable <rule> <delete|warn|timeout|log>`, see `help automod enable`.",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    update_config(ctx, msg.guild_id.unwrap(), |config| {
+        config.rules.insert(rule, action);
+    })
+    .await?;
+    msg.channel_id
+        .say(
+            &ctx.http,
+            format!(
+                "Automod rule `{}` is on with action `{}`.",
+                rule.name(),
+                action
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Turns off a rule."]
+#[usage = "<rule>"]
+async fn disable(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Some(rule) = args
+        .single::<String>()
+        .ok()
+        .and_then(|rule| RuleKind::from_name(&rule))
+    else {
+        msg.reply(ctx, "You need to provide a rule!").await?;
+        return Ok(());
+    };
+
+    update_config(ctx, msg.guild_id.unwrap(), |config| {
+        config.rules.remove(&rule);
+    })
+    .await?;
+    msg.channel_id
+        .say(&ctx.http, format!("Automod rule `{}` is off.", rule.name()))
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Adds or removes blocked words. Leetspeak, accents and look-alike letters are \
+matched too."]
+#[usage = "<add|remove> <words...>"]
+async fn words(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mode = args.single::<String>().unwrap_or_default();
+    let items = args
+        .iter::<String>()
+        .filter_map(|word| word.ok())
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>();
+    if !["add", "remove"].contains(&mode.as_str()) || items.is_empty() {
+        msg.reply(ctx, "Usage: `automod words <add|remove> <words...>`")
+            .await?;
+        return Ok(());
+    }
+
+    update_config(ctx, msg.guild_id.unwrap(), |config| {
+        edit_list(&mut config.words, &mode, items)
+    })
+    .await?;
+    // Don't repeat the words, the point is that they should not show up in chat.
+    msg.delete(&ctx.http).await?;
+    msg.channel_id
+        .say(&ctx.http, "Updated the blocked words.")
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Adds or removes a blocked regular expression. Patterns are matched against the \
+message as sent and as the word rule sees it, without accents, look-alikes or leetspeak."]
+#[usage = "<add|remove> <pattern>"]
+async fn regex(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mode = args.single::<String>().unwrap_or_default();
+    let pattern = args.rest().trim().to_string();
+    if !["add", "remove"].contains(&mode.as_str()) || pattern.is_empty() {
+        msg.reply(ctx, "Usage: `automod regex <add|remove> <pattern>`")
+            .await?;
+        return Ok(());
+    }
+    if let Err(why) = compile_regex(&pattern) {
+        msg.reply(ctx, format!("That is not a valid regex: {}", why))
+            .await?;
+        return Ok(());
+    }
+
+    update_config(ctx, msg.guild_id.unwrap(), |config| {
+        edit_list(&mut config.regexes, &mode, vec![pattern]);
+        config.regexes_changed();
+    })
+    .await?;
+    msg.channel_id
+        .say(&ctx.http, "Updated the blocked patterns.")
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Edits the link domain lists. With allowed domains set, links to any other \
+domain break the `links` rule. Subdomains are included."]
+#[usage = "<allow|deny> <add|remove> <domains...>"]
+async fn domains(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let list = args.single::<String>().unwrap_or_default();
+    let mode = args.single::<String>().unwrap_or_default();
+    let items = args
+        .iter::<String>()
+        .filter_map(|domain| domain.ok())
+        .map(|domain| domain.to_lowercase())
+        .collect::<Vec<_>>();
+    if !["allow", "deny"].contains(&list.as_str())
+        || !["add", "remove"].contains(&mode.as_str())
+        || items.is_empty()
+    {
+        msg.reply(
+            ctx,
+            "Usage: `automod domains <allow|deny> <add|remove> <domains...>`",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    update_config(ctx, msg.guild_id.unwrap(), |config| {
+        let list = if list == "allow" {
+            &mut config.allowed_domains
+        } else {
+            &mut config.denied_domains
+        };
+        edit_list(list, &mode, items)
+    })
+    .await?;
+    msg.channel_id
+        .say(&ctx.http, "Updated the domain lists.")
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Sets when a message has too many capital letters."]
+#[usage = "<min letters> <max percent>"]
+async fn caps(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (Ok(min_letters), Ok(max_percent)) = (args.single::<usize>(), args.single::<u8>()) else {
+        msg.reply(ctx, "Usage: `automod caps <min letters> <max percent>`")
+            .await?;
+        return Ok(());
+    };
+
+    update_config(ctx, msg.guild_id.unwrap(), |config| {
+        config.caps_min_letters = min_letters;
+        config.caps_max_percent = max_percent.min(100);
+    })
+    .await?;
+    msg.channel_id
+        .say(&ctx.http, "Updated the caps rule.")
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Sets how many mentions a message may have."]
+#[usage = "<max mentions>"]
+async fn mentions(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Ok(max_mentions) = args.single::<usize>() else {
+        msg.reply(ctx, "Usage: `automod mentions <max mentions>`")
+            .await?;
+        return Ok(());
+    };
+
+    update_config(ctx, msg.guild_id.unwrap(), |config| {
+        config.max_mentions = max_mentions;
+    })
+    .await?;
+    msg.channel_id
+        .say(&ctx.http, "Updated the mentions rule.")
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Sets how often the same message may be sent within a number of seconds."]
+#[usage = "<repeats> <seconds>"]
+async fn spam(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (Ok(repeats), Ok(seconds)) = (args.single::<usize>(), args.single::<u64>()) else {
+        msg.reply(ctx, "Usage: `automod spam <repeats> <seconds>`")
+            .await?;
+        return Ok(());
+    };
+
+    update_config(ctx, msg.guild_id.unwrap(), |config| {
+        config.spam_max_repeats = repeats;
+        config.spam_window_secs = seconds.min(MAX_SPAM_WINDOW_SECS);
+    })
+    .await?;
+    msg.channel_id
+        .say(&ctx.http, "Updated the spam rule.")
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Sets how many minutes the `timeout` action lasts."]
+#[usage = "<minutes>"]
+async fn timeout(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Ok(minutes) = args.single::<u32>() else {
+        msg.reply(ctx, "Usage: `automod timeout <minutes>`").await?;
+        return Ok(());
+    };
+    // Discord does not allow timeouts longer than 28 days.
+    let minutes = minutes.clamp(1, 28 * 24 * 60);
+
+    update_config(ctx, msg.guild_id.unwrap(), |config| {
+        config.timeout_minutes = minutes;
+    })
+    .await?;
+    msg.channel_id
+        .say(
+            &ctx.http,
+            format!("Automod timeouts now last {} minutes.", minutes),
+        )
+        .await?;
+
+    Ok(())
+}
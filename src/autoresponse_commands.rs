@@ -0,0 +1,581 @@
+use crate::command_base::*;
+use crate::paginator::Paginator;
+use crate::storage::save_json;
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use serenity::all::{CreateAllowedMentions, CreateEmbed, CreateMessage, ReactionType};
+use serenity::model::id::{ChannelId, GuildId, UserId};
+use serenity::utils::parse_channel_mention;
+use std::fmt::Write;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+pub const AUTORESPONSES_PATH: &str = "data/autoresponses.json";
+/// User supplied patterns are compiled with this size limit so a rule cannot eat all memory.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+#[group]
+#[prefixes("autoresponse", "ar")]
+#[commands(add, list, remove, cooldown, channels)]
+#[required_permissions(MANAGE_GUILD)]
+#[only_in(guilds)]
+pub struct AutoResponse;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TriggerKind {
+    /// The trigger is a regular expression.
+    Regex,
+    /// The whole message has to be the trigger, ignoring case.
+    Exact,
+    /// The trigger has to appear as a whole word, ignoring case.
+    Word,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseKind {
+    Text,
+    React,
+    Embed,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Rule {
+    pub id: u64,
+    pub kind: TriggerKind,
+    pub trigger: String,
+    pub response_kind: ResponseKind,
+    pub response: String,
+    #[serde(default)]
+    pub channel_cooldown_secs: u64,
+    #[serde(default)]
+    pub user_cooldown_secs: u64,
+    /// If not empty, the rule only fires in these channels.
+    #[serde(default)]
+    pub allowed_channels: Vec<ChannelId>,
+    #[serde(default)]
+    pub denied_channels: Vec<ChannelId>,
+    /// `pattern` compiled, filled on first use. `None` if it does not compile.
+    #[serde(skip)]
+    compiled: OnceLock<Option<Regex>>,
+}
+
+impl Rule {
+    /// The regular expression the trigger is matched with.
+    pub fn pattern(&self) -> String {
+        match self.kind {
+            TriggerKind::Regex => self.trigger.clone(),
+            TriggerKind::Exact => format!(r"(?i)^\s*{}\s*$", regex::escape(&self.trigger)),
+            TriggerKind::Word => format!(r"(?i)\b{}\b", regex::escape(&self.trigger)),
+        }
+    }
+
+    pub fn applies_to_channel(&self, channel_id: ChannelId) -> bool {
+        !self.denied_channels.contains(&channel_id)
+            && (self.allowed_channels.is_empty() || self.allowed_channels.contains(&channel_id))
+    }
+
+    /// Whether `content` triggers the rule. Triggers that do not compile never match.
+    fn is_match(&self, content: &str) -> bool {
+        self.compiled
+            .get_or_init(|| compile(&self.pattern()).ok())
+            .as_ref()
+            .is_some_and(|regex| regex.is_match(content))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct GuildRules {
+    pub next_id: u64,
+    pub rules: Vec<Rule>,
+}
+
+/// The auto-responder rules of every guild.
+pub struct AutoResponses;
+
+impl TypeMapKey for AutoResponses {
+    type Value = HashMap<GuildId, GuildRules>;
+}
+
+#[derive(Hash, PartialEq, Eq)]
+enum CooldownKey {
+    Channel(GuildId, u64, ChannelId),
+    User(GuildId, u64, UserId),
+}
+
+/// The last time each rule fired. It does not need to survive a restart.
+#[derive(Default)]
+pub struct AutoResponseState {
+    last_fired: HashMap<CooldownKey, Instant>,
+}
+
+impl AutoResponseState {
+    fn on_cooldown(&self, key: &CooldownKey, secs: u64, now: Instant) -> bool {
+        self.last_fired
+            .get(key)
+            .is_some_and(|last| now.duration_since(*last) < Duration::from_secs(secs))
+    }
+}
+
+/// Behind its own lock, so checking a message only needs to read the TypeMap.
+pub struct AutoResponseStateContainer;
+
+impl TypeMapKey for AutoResponseStateContainer {
+    type Value = Arc<Mutex<AutoResponseState>>;
+}
+
+fn compile(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+}
+
+/// Checks a message that is not a command against the rules of its guild and sends the response
+/// of the first rule that matches and is not on cooldown.
+pub async fn handle_message(ctx: &Context, msg: &Message) -> CommandResult {
+    let Some(guild_id) = msg.guild_id else {
+        return Ok(());
+    };
+    if msg.author.bot {
+        return Ok(());
+    }
+
+    let fired = {
+        let data = ctx.data.read().await;
+        let Some(guild) = data
+            .get::<AutoResponses>()
+            .expect("Expected AutoResponses in TypeMap.")
+            .get(&guild_id)
+        else {
+            return Ok(());
+        };
+        let mut state = data
+            .get::<AutoResponseStateContainer>()
+            .expect("Expected AutoResponseStateContainer in TypeMap.")
+            .lock()
+            .await;
+
+        let now = Instant::now();
+        let fired = guild.rules.iter().find(|rule| {
+            rule.applies_to_channel(msg.channel_id)
+                && !state.on_cooldown(
+                    &CooldownKey::Channel(guild_id, rule.id, msg.channel_id),
+                    rule.channel_cooldown_secs,
+                    now,
+                )
+                && !state.on_cooldown(
+                    &CooldownKey::User(guild_id, rule.id, msg.author.id),
+                    rule.user_cooldown_secs,
+                    now,
+                )
+                && rule.is_match(&msg.content)
+        });
+        fired.map(|rule| {
+            state
+                .last_fired
+                .insert(CooldownKey::Channel(guild_id, rule.id, msg.channel_id), now);
+            state
+                .last_fired
+                .insert(CooldownKey::User(guild_id, rule.id, msg.author.id), now);
+            (rule.response_kind, rule.response.clone())
+        })
+    };
+    let Some((response_kind, response)) = fired else {
+        return Ok(());
+    };
+
+    // Responses never ping, so a rule cannot turn into a mass ping.
+    match response_kind {
+        ResponseKind::Text => {
+            msg.channel_id
+                .send_message(
+                    &ctx.http,
+                    CreateMessage::new()
+                        .content(response)
+                        .allowed_mentions(CreateAllowedMentions::new()),
+                )
+                .await?;
+        }
+        ResponseKind::React => {
+            let reaction = response.parse::<ReactionType>()?;
+            msg.react(ctx, reaction).await?;
+        }
+        ResponseKind::Embed => {
+            let embed = CreateEmbed::new().description(response);
+            msg.channel_id
+                .send_message(
+                    &ctx.http,
+                    CreateMessage::new()
+                        .embed(embed)
+                        .allowed_mentions(CreateAllowedMentions::new()),
+                )
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn save_rules(ctx: &Context) -> CommandResult {
+    let rules = {
+        let data = ctx.data.read().await;
+        data.get::<AutoResponses>()
+            .expect("Expected AutoResponses in TypeMap.")
+            .clone()
+    };
+    save_json(AUTORESPONSES_PATH, &rules).await?;
+    Ok(())
+}
+
+/// Applies `change` to the rule `id` of the guild and saves. Returns `false` if there is no
+/// such rule.
+async fn update_rule(
+    ctx: &Context,
+    guild_id: GuildId,
+    id: u64,
+    change: impl FnOnce(&mut Rule),
+) -> CommandResult<bool> {
+    let found = {
+        let mut data = ctx.data.write().await;
+        let rule = data
+            .get_mut::<AutoResponses>()
+            .expect("Expected AutoResponses in TypeMap.")
+            .get_mut(&guild_id)
+            .and_then(|guild| guild.rules.iter_mut().find(|rule| rule.id == id));
+        match rule {
+            Some(rule) => {
+                change(rule);
+                true
+            }
+            None => false,
+        }
+    };
+    if found {
+        save_rules(ctx).await?;
+    }
+    Ok(found)
+}
+
+#[command]
+#[description = "Adds an auto-responder rule. The trigger kind is `regex`, `exact` (the whole \
+message) or `word` (a whole word anywhere in the message). The response kind is `text`, `react` \
+or `embed`. Put triggers with spaces in quotes."]
+#[usage = "<regex|exact|word> <trigger> <text|react|embed> <response>"]
+#[example = "word \"good morning\" text Good morning to you too!"]
+async fn add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let kind = match args.single::<String>().as_deref() {
+        Ok("regex") => TriggerKind::Regex,
+        Ok("exact") => TriggerKind::Exact,
+        Ok("word") => TriggerKind::Word,
+        _ => {
+            msg.reply(
+                ctx,
+                "The trigger kind has to be `regex`, `exact` or `word`.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    let Ok(trigger) = args.single_quoted::<String>() else {
+        msg.reply(ctx, "You need to provide a trigger!").await?;
+        return Ok(());
+    };
+    let response_kind = match args.single::<String>().as_deref() {
+        Ok("text") => ResponseKind::Text,
+        Ok("react") => ResponseKind::React,
+        Ok("embed") => ResponseKind::Embed,
+        _ => {
+            msg.reply(
+                ctx,
+                "The response kind has to be `text`, `react` or `embed`.",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    let response = args.rest().trim().to_string();
+    if response.is_empty() {
+        msg.reply(ctx, "You need to provide a response!").await?;
+        return Ok(());
+    }
+
+    let mut rule = Rule {
+        id: 0,
+        kind,
+        trigger,
+        response_kind,
+        response,
+        channel_cooldown_secs: 0,
+        user_cooldown_secs: 0,
+        allowed_channels: Vec::new(),
+        denied_channels: Vec::new(),
+        compiled: OnceLock::new(),
+    };
+    if let Err(why) = compile(&rule.pattern()) {
+        msg.reply(ctx, format!("That is not a valid regex: {}", why))
+            .await?;
+        return Ok(());
+    }
+    if rule.response_kind == ResponseKind::React && rule.response.parse::<ReactionType>().is_err() {
+        msg.reply(ctx, "That is not an emoji I can react with.")
+            .await?;
+        return Ok(());
+    }
+
+    let guild_id = msg.guild_id.unwrap();
+    {
+        let mut data = ctx.data.write().await;
+        let guild = data
+            .get_mut::<AutoResponses>()
+            .expect("Expected AutoResponses in TypeMap.")
+            .entry(guild_id)
+            .or_default();
+        guild.next_id += 1;
+        rule.id = guild.next_id;
+        guild.rules.push(rule.clone());
+    }
+    save_rules(ctx).await?;
+
+    msg.channel_id
+        .say(&ctx.http, format!("Added auto-response #{}.", rule.id))
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Lists the auto-responder rules of this server."]
+async fn list(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap();
+    let mut entries = Vec::new();
+    {
+        let data = ctx.data.read().await;
+        let rules = data
+            .get::<AutoResponses>()
+            .expect("Expected AutoResponses in TypeMap.");
+        for rule in rules.get(&guild_id).into_iter().flat_map(|g| &g.rules) {
+            let mut contents = String::new();
+            let kind = match rule.kind {
+                TriggerKind::Regex => "regex",
+                TriggerKind::Exact => "exact",
+                TriggerKind::Word => "word",
+            };
+            let response_kind = match rule.response_kind {
+                ResponseKind::Text => "text",
+                ResponseKind::React => "react",
+                ResponseKind::Embed => "embed",
+            };
+            write!(
+                contents,
+                "#{} {} `{}` -> {} `{}`",
+                rule.id, kind, rule.trigger, response_kind, rule.response
+            )?;
+            if rule.channel_cooldown_secs > 0 || rule.user_cooldown_secs > 0 {
+                write!(
+                    contents,
+                    " (cooldown {}s channel, {}s user)",
+                    rule.channel_cooldown_secs, rule.user_cooldown_secs
+                )?;
+            }
+            entries.push(contents);
+        }
+    }
+
+    if entries.is_empty() {
+        msg.channel_id
+            .say(&ctx.http, "This server has no auto-responses.")
+            .await?;
+    } else {
+        Paginator::new("Auto-responses", entries)
+            .send(ctx, msg)
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[command]
+#[aliases("delete")]
+#[description = "Removes an auto-responder rule."]
+#[usage = "<id>"]
+async fn remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Ok(id) = args.single::<u64>() else {
+        msg.reply(ctx, "You need to provide the id of the rule!")
+            .await?;
+        return Ok(());
+    };
+    let guild_id = msg.guild_id.unwrap();
+
+    let removed = {
+        let mut data = ctx.data.write().await;
+        data.get_mut::<AutoResponses>()
+            .expect("Expected AutoResponses in TypeMap.")
+            .get_mut(&guild_id)
+            .is_some_and(|guild| {
+                let before = guild.rules.len();
+                guild.rules.retain(|rule| rule.id != id);
+                guild.rules.len() != before
+            })
+    };
+    if !removed {
+        msg.reply(ctx, format!("There is no auto-response #{}.", id))
+            .await?;
+        return Ok(());
+    }
+    save_rules(ctx).await?;
+
+    msg.channel_id
+        .say(&ctx.http, format!("Removed auto-response #{}.", id))
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Sets how many seconds a rule waits before it fires again in the same channel \
+and for the same user."]
+#[usage = "<id> <channel seconds> <user seconds>"]
+async fn cooldown(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (Ok(id), Ok(channel_secs), Ok(user_secs)) = (
+        args.single::<u64>(),
+        args.single::<u64>(),
+        args.single::<u64>(),
+    ) else {
+        msg.reply(
+            ctx,
+            "You need to provide the id, the channel cooldown and the user cooldown!",
+        )
+        .await?;
+        return Ok(());
+    };
+    let guild_id = msg.guild_id.unwrap();
+
+    let found = update_rule(ctx, guild_id, id, |rule| {
+        rule.channel_cooldown_secs = channel_secs;
+        rule.user_cooldown_secs = user_secs;
+    })
+    .await?;
+    if found {
+        msg.channel_id
+            .say(
+                &ctx.http,
+                format!("Updated the cooldowns of auto-response #{}.", id),
+            )
+            .await?;
+    } else {
+        msg.reply(ctx, format!("There is no auto-response #{}.", id))
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[command]
+#[description = "Restricts a rule to the mentioned channels (`allow`), keeps it out of them \
+(`deny`) or lifts all restrictions (`clear`)."]
+#[usage = "<id> <allow|deny|clear> [#channels...]"]
+async fn channels(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Ok(id) = args.single::<u64>() else {
+        msg.reply(ctx, "You need to provide the id of the rule!")
+            .await?;
+        return Ok(());
+    };
+    let mode = args.single::<String>().unwrap_or_default();
+    let mentioned = args
+        .iter::<String>()
+        .filter_map(|arg| arg.ok())
+        .filter_map(|arg| parse_channel_mention(&arg).or_else(|| arg.parse().ok()))
+        .collect::<Vec<ChannelId>>();
+    if mode != "clear" && mentioned.is_empty() {
+        msg.reply(ctx, "You need to mention at least one channel!")
+            .await?;
+        return Ok(());
+    }
+    let guild_id = msg.guild_id.unwrap();
+
+    let found = match mode.as_str() {
+        "allow" => update_rule(ctx, guild_id, id, |rule| rule.allowed_channels = mentioned).await?,
+        "deny" => update_rule(ctx, guild_id, id, |rule| rule.denied_channels = mentioned).await?,
+        "clear" => {
+            update_rule(ctx, guild_id, id, |rule| {
+                rule.allowed_channels.clear();
+                rule.denied_channels.clear();
+            })
+            .await?
+        }
+        _ => {
+            msg.reply(ctx, "The mode has to be `allow`, `deny` or `clear`.")
+                .await?;
+            return Ok(());
+        }
+    };
+    if found {
+        msg.channel_id
+            .say(
+                &ctx.http,
+                format!("Updated the channels of auto-response #{}.", id),
+            )
+            .await?;
+    } else {
+        msg.reply(ctx, format!("There is no auto-response #{}.", id))
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(kind: TriggerKind, trigger: &str) -> Rule {
+        Rule {
+            id: 1,
+            kind,
+            trigger: String::from(trigger),
+            response_kind: ResponseKind::Text,
+            response: String::from("hi"),
+            channel_cooldown_secs: 0,
+            user_cooldown_secs: 0,
+            allowed_channels: Vec::new(),
+            denied_channels: Vec::new(),
+            compiled: OnceLock::new(),
+        }
+    }
+
+    #[test]
+    fn patterns_follow_the_trigger_kind() {
+        let exact = rule(TriggerKind::Exact, "Hello?");
+        assert_eq!(exact.pattern(), r"(?i)^\s*Hello\?\s*$");
+        assert!(exact.is_match("  hello? "));
+        assert!(!exact.is_match("hello? there"));
+
+        let word = rule(TriggerKind::Word, "cat");
+        assert_eq!(word.pattern(), r"(?i)\bcat\b");
+        assert!(word.is_match("my CAT is cute"));
+        assert!(!word.is_match("concatenate"));
+
+        let regex = rule(TriggerKind::Regex, r"^\d+$");
+        assert_eq!(regex.pattern(), r"^\d+$");
+        assert!(regex.is_match("123"));
+        assert!(!rule(TriggerKind::Regex, "(").is_match("("));
+    }
+
+    #[test]
+    fn channel_lists_limit_where_rules_fire() {
+        let (a, b, c) = (ChannelId::new(1), ChannelId::new(2), ChannelId::new(3));
+        let mut rule = rule(TriggerKind::Word, "cat");
+        assert!(rule.applies_to_channel(a));
+
+        rule.denied_channels = vec![a];
+        assert!(!rule.applies_to_channel(a));
+        assert!(rule.applies_to_channel(b));
+
+        rule.allowed_channels = vec![a, b];
+        assert!(!rule.applies_to_channel(a));
+        assert!(rule.applies_to_channel(b));
+        assert!(!rule.applies_to_channel(c));
+    }
+}
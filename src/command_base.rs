@@ -1,13 +1,22 @@
 #![allow(deprecated)] // We recommend migrating to poise, instead of using the standard command framework.
+use crate::paginator::Paginator;
 pub use rand::Rng;
 use serenity::async_trait;
+use serenity::framework::standard::help_commands::{CustomisedHelpData, GroupCommandsPair};
 pub use serenity::framework::standard::{
     help_commands, Args, CommandGroup, CommandResult, DispatchError, HelpOptions,
 };
 use serenity::gateway::ShardManager;
+use serenity::model::application::Interaction;
 pub use serenity::model::channel::Message;
+use serenity::model::channel::{ChannelType, GuildChannel, Reaction};
 use serenity::model::gateway::Ready;
-use serenity::model::id::UserId;
+use serenity::model::event::{GuildMemberUpdateEvent, MessageUpdateEvent};
+use serenity::model::guild::{Member, Role};
+use serenity::model::id::{ChannelId, GuildId, MessageId, RoleId, UserId};
+use serenity::model::user::User;
+use serenity::model::voice::VoiceState;
+use serenity::utils::parse_channel_mention;
 
 pub use serenity::framework::standard::buckets::LimitedFor;
 pub use serenity::framework::standard::macros::{command, group, help, hook};
@@ -27,6 +36,22 @@ impl TypeMapKey for CommandCounter {
     type Value = HashMap<String, u64>;
 }
 
+/// Parses a channel mention or a raw channel id.
+pub fn parse_channel(arg: &str) -> Option<ChannelId> {
+    parse_channel_mention(arg).or_else(|| arg.parse().ok())
+}
+
+/// Whether `channel_id` is a text channel of the guild. Channel ids from arguments can point
+/// anywhere, including into other servers the bot is on.
+pub fn is_text_channel(ctx: &Context, guild_id: GuildId, channel_id: ChannelId) -> bool {
+    guild_id.to_guild_cached(&ctx.cache).is_some_and(|guild| {
+        guild
+            .channels
+            .get(&channel_id)
+            .is_some_and(|channel| matches!(channel.kind, ChannelType::Text | ChannelType::News))
+    })
+}
+
 pub struct Handler;
 
 #[async_trait]
@@ -34,6 +59,245 @@ impl EventHandler for Handler {
     async fn ready(&self, _: Context, ready: Ready) {
         println!("{} is connected!", ready.user.name);
     }
+
+    async fn message(&self, ctx: Context, new_message: Message) {
+        if let Err(why) = crate::message_log::handle_message(&ctx, &new_message).await {
+            println!("Message log returned error {why:?}");
+        }
+        // Runs here rather than in `normal_message`, so commands are checked too.
+        if let Err(why) = crate::automod_commands::handle_message(&ctx, &new_message).await {
+            println!("Automod returned error {why:?}");
+        }
+    }
+
+    async fn message_update(
+        &self,
+        ctx: Context,
+        _old_if_available: Option<Message>,
+        _new: Option<Message>,
+        event: MessageUpdateEvent,
+    ) {
+        if let Err(why) = crate::message_log::handle_update(&ctx, &event).await {
+            println!("Message log returned error {why:?}");
+        }
+    }
+
+    async fn message_delete(
+        &self,
+        ctx: Context,
+        channel_id: ChannelId,
+        deleted_message_id: MessageId,
+        guild_id: Option<GuildId>,
+    ) {
+        if let Err(why) =
+            crate::message_log::handle_delete(&ctx, guild_id, channel_id, deleted_message_id).await
+        {
+            println!("Message log returned error {why:?}");
+        }
+    }
+
+    async fn message_delete_bulk(
+        &self,
+        ctx: Context,
+        channel_id: ChannelId,
+        multiple_deleted_messages_ids: Vec<MessageId>,
+        guild_id: Option<GuildId>,
+    ) {
+        if let Err(why) = crate::message_log::handle_bulk_delete(
+            &ctx,
+            guild_id,
+            channel_id,
+            &multiple_deleted_messages_ids,
+        )
+        .await
+        {
+            println!("Message log returned error {why:?}");
+        }
+    }
+
+    async fn guild_member_addition(&self, ctx: Context, new_member: Member) {
+        if let Err(why) = crate::antiraid_commands::handle_join(&ctx, &new_member).await {
+            println!("Anti-raid returned error {why:?}");
+        }
+        if let Err(why) = crate::welcome_commands::handle_join(&ctx, &new_member).await {
+            println!("Welcome returned error {why:?}");
+        }
+    }
+
+    async fn guild_member_update(
+        &self,
+        ctx: Context,
+        old: Option<Member>,
+        new: Option<Member>,
+        event: GuildMemberUpdateEvent,
+    ) {
+        if let Err(why) =
+            crate::welcome_commands::handle_update(&ctx, old.as_ref(), &event).await
+        {
+            println!("Welcome returned error {why:?}");
+        }
+        if let Err(why) =
+            crate::event_log::handle_member_update(&ctx, old.as_ref(), new.as_ref(), &event).await
+        {
+            println!("Event log returned error {why:?}");
+        }
+    }
+
+    async fn channel_create(&self, ctx: Context, channel: GuildChannel) {
+        if let Err(why) = crate::event_log::handle_channel_create(&ctx, &channel).await {
+            println!("Event log returned error {why:?}");
+        }
+    }
+
+    async fn category_create(&self, ctx: Context, category: GuildChannel) {
+        if let Err(why) = crate::event_log::handle_channel_create(&ctx, &category).await {
+            println!("Event log returned error {why:?}");
+        }
+    }
+
+    async fn channel_delete(
+        &self,
+        ctx: Context,
+        channel: GuildChannel,
+        _messages: Option<Vec<Message>>,
+    ) {
+        if let Err(why) = crate::event_log::handle_channel_delete(&ctx, &channel).await {
+            println!("Event log returned error {why:?}");
+        }
+        if let Err(why) = crate::ticket_commands::handle_channel_delete(&ctx, &channel).await {
+            println!("Tickets returned error {why:?}");
+        }
+    }
+
+    async fn category_delete(&self, ctx: Context, category: GuildChannel) {
+        if let Err(why) = crate::event_log::handle_channel_delete(&ctx, &category).await {
+            println!("Event log returned error {why:?}");
+        }
+    }
+
+    async fn channel_update(&self, ctx: Context, old: Option<GuildChannel>, new: GuildChannel) {
+        if let Err(why) = crate::event_log::handle_channel_update(&ctx, old.as_ref(), &new).await {
+            println!("Event log returned error {why:?}");
+        }
+    }
+
+    async fn guild_role_create(&self, ctx: Context, new: Role) {
+        if let Err(why) = crate::event_log::handle_role_create(&ctx, &new).await {
+            println!("Event log returned error {why:?}");
+        }
+    }
+
+    async fn guild_role_delete(
+        &self,
+        ctx: Context,
+        guild_id: GuildId,
+        removed_role_id: RoleId,
+        removed_role_data_if_available: Option<Role>,
+    ) {
+        if let Err(why) = crate::event_log::handle_role_delete(
+            &ctx,
+            guild_id,
+            removed_role_id,
+            removed_role_data_if_available.as_ref(),
+        )
+        .await
+        {
+            println!("Event log returned error {why:?}");
+        }
+    }
+
+    async fn guild_role_update(
+        &self,
+        ctx: Context,
+        old_data_if_available: Option<Role>,
+        new: Role,
+    ) {
+        if let Err(why) =
+            crate::event_log::handle_role_update(&ctx, old_data_if_available.as_ref(), &new).await
+        {
+            println!("Event log returned error {why:?}");
+        }
+    }
+
+    async fn voice_state_update(&self, ctx: Context, old: Option<VoiceState>, new: VoiceState) {
+        if let Err(why) = crate::event_log::handle_voice_update(&ctx, old.as_ref(), &new).await {
+            println!("Event log returned error {why:?}");
+        }
+    }
+
+    async fn guild_ban_addition(&self, ctx: Context, guild_id: GuildId, banned_user: User) {
+        if let Err(why) = crate::event_log::handle_ban(&ctx, guild_id, &banned_user, true).await {
+            println!("Event log returned error {why:?}");
+        }
+    }
+
+    async fn guild_ban_removal(&self, ctx: Context, guild_id: GuildId, unbanned_user: User) {
+        if let Err(why) = crate::event_log::handle_ban(&ctx, guild_id, &unbanned_user, false).await
+        {
+            println!("Event log returned error {why:?}");
+        }
+    }
+
+    async fn guild_member_removal(
+        &self,
+        ctx: Context,
+        guild_id: GuildId,
+        user: User,
+        _member: Option<Member>,
+    ) {
+        if let Err(why) = crate::welcome_commands::handle_leave(&ctx, guild_id, &user).await {
+            println!("Goodbye returned error {why:?}");
+        }
+    }
+
+    async fn reaction_add(&self, ctx: Context, add_reaction: Reaction) {
+        if let Err(why) = crate::role_menu_commands::handle_reaction_add(&ctx, &add_reaction).await
+        {
+            println!("Reaction role returned error {why:?}");
+        }
+    }
+
+    async fn reaction_remove(&self, ctx: Context, removed_reaction: Reaction) {
+        if let Err(why) =
+            crate::role_menu_commands::handle_reaction_remove(&ctx, &removed_reaction).await
+        {
+            println!("Reaction role returned error {why:?}");
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        let Interaction::Component(component) = interaction else {
+            return;
+        };
+        // Custom ids are namespaced as `<prefix>:<...>`, the prefix decides who handles it.
+        let result = match component.data.custom_id.split(':').next() {
+            Some(crate::picture_commands::REVIEW_BUTTON_PREFIX) => {
+                crate::picture_commands::handle_review(&ctx, &component).await
+            }
+            Some(crate::role_menu_commands::ROLE_MENU_PREFIX) => {
+                crate::role_menu_commands::handle_interaction(&ctx, &component).await
+            }
+            Some(crate::poll_commands::POLL_PREFIX) => {
+                crate::poll_commands::handle_interaction(&ctx, &component).await
+            }
+            Some(crate::giveaway_commands::GIVEAWAY_PREFIX) => {
+                crate::giveaway_commands::handle_interaction(&ctx, &component).await
+            }
+            Some(crate::ticket_commands::TICKET_PREFIX) => {
+                crate::ticket_commands::handle_interaction(&ctx, &component).await
+            }
+            Some(crate::suggestion_commands::SUGGESTION_PREFIX) => {
+                crate::suggestion_commands::handle_interaction(&ctx, &component).await
+            }
+            _ => Ok(()),
+        };
+        if let Err(why) = result {
+            println!(
+                "Interaction '{}' returned error {why:?}",
+                component.data.custom_id
+            );
+        }
+    }
 }
 
 #[help]
@@ -59,8 +323,63 @@ pub async fn my_help(
     groups: &[&'static CommandGroup],
     owners: HashSet<UserId>,
 ) -> CommandResult {
-    let _ = help_commands::with_embeds(context, msg, args, help_options, groups, owners).await;
-    Ok(())
+    // The list of all commands gets long, so it is split into pages. Everything else is a single
+    // embed.
+    let grouped = match help_commands::create_customised_help_data(
+        context,
+        msg,
+        &args,
+        groups,
+        &owners,
+        help_options,
+    )
+    .await
+    {
+        CustomisedHelpData::GroupedCommands {
+            help_description,
+            groups,
+        } => Some((help_description, groups)),
+        _ => None,
+    };
+    let Some((help_description, groups)) = grouped else {
+        let _ = help_commands::with_embeds(context, msg, args, help_options, groups, owners).await;
+        return Ok(());
+    };
+
+    let mut entries = vec![help_description];
+    for group in &groups {
+        entries.push(help_entry(group, help_options, 0));
+    }
+    Paginator::with_page_size("Commands", entries, HELP_GROUPS_PER_PAGE)
+        .send(context, msg)
+        .await
+}
+
+/// Groups shown on one page of the command list.
+const HELP_GROUPS_PER_PAGE: usize = 5;
+
+/// The name, prefixes and commands of `group` for the command list.
+fn help_entry(group: &GroupCommandsPair, help_options: &HelpOptions, nest_level: usize) -> String {
+    let indent = help_options.indention_prefix.repeat(nest_level);
+    let mut entry = format!("{}**{}**", indent, group.name);
+    if !group.prefixes.is_empty() {
+        entry.push_str(&format!(
+            " ({}: `{}`)",
+            help_options.group_prefix,
+            group.prefixes.join("`, `")
+        ));
+    }
+    if let Some(summary) = group.summary {
+        entry.push_str(&format!("\n{}*{}*", indent, summary));
+    }
+    if !group.command_names.is_empty() {
+        entry.push_str(&format!("\n{}{}", indent, group.command_names.join(", ")));
+    }
+    for sub_group in &group.sub_groups {
+        entry.push('\n');
+        entry.push_str(&help_entry(sub_group, help_options, nest_level + 1));
+    }
+    entry
 }
 
 #[hook]
@@ -95,14 +414,54 @@ pub async fn after(
     }
 }
 
+/// The text after the prefix and the command `name` in `content`. The prefix is either `?` or a
+/// mention of the bot, which may contain the name too.
+fn command_args<'a>(content: &'a str, name: &str) -> &'a str {
+    let content = content.trim_start();
+    let rest = match content.strip_prefix('?') {
+        Some(rest) => rest,
+        None => content.split_once('>').map_or(content, |(_, rest)| rest),
+    };
+    rest.trim_start().strip_prefix(name).unwrap_or("").trim()
+}
+
 #[hook]
-pub async fn unknown_command(_ctx: &Context, _msg: &Message, unknown_command_name: &str) {
-    println!("Could not find command named '{unknown_command_name}'");
+pub async fn unknown_command(ctx: &Context, msg: &Message, unknown_command_name: &str) {
+    // Before giving up, check if the guild has a tag with that name.
+    let args = command_args(&msg.content, unknown_command_name);
+    match crate::tag_commands::run_tag(ctx, msg, unknown_command_name, args).await {
+        Ok(true) => println!("Processed tag '{unknown_command_name}'"),
+        Ok(false) => println!("Could not find command named '{unknown_command_name}'"),
+        Err(why) => println!("Tag '{unknown_command_name}' returned error {why:?}"),
+    }
 }
 
 #[hook]
-pub async fn normal_message(_ctx: &Context, msg: &Message) {
+pub async fn normal_message(ctx: &Context, msg: &Message) {
     println!("Message is not a command '{}'", msg.content);
+
+    match crate::antiraid_commands::handle_message(ctx, msg).await {
+        // The author was timed out or kicked, the other checks would only pile on.
+        Ok(true) => return,
+        Ok(false) => {}
+        Err(why) => println!("Anti-spam returned error {why:?}"),
+    }
+
+    if let Err(why) = crate::afk_commands::handle_message(ctx, msg).await {
+        println!("AFK returned error {why:?}");
+    }
+
+    if let Err(why) = crate::ticket_commands::handle_message(ctx, msg).await {
+        println!("Modmail returned error {why:?}");
+    }
+
+    if let Err(why) = crate::leveling_commands::handle_message(ctx, msg).await {
+        println!("Leveling returned error {why:?}");
+    }
+
+    if let Err(why) = crate::autoresponse_commands::handle_message(ctx, msg).await {
+        println!("Auto-responder returned error {why:?}");
+    }
 }
 
 #[hook]
@@ -131,3 +490,18 @@ pub async fn dispatch_error(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_args_skip_prefix_and_name() {
+        assert_eq!(command_args("?faq install", "faq"), "install");
+        assert_eq!(command_args("? faq  install it ", "faq"), "install it");
+        assert_eq!(command_args("?faq", "faq"), "");
+        // The name `12` also appears inside the mention.
+        assert_eq!(command_args("<@123> 12 args", "12"), "args");
+        assert_eq!(command_args("<@!123>12", "12"), "");
+    }
+}
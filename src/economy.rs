@@ -0,0 +1,293 @@
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{GuildId, UserId};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Time between two daily rewards.
+pub const DAILY_COOLDOWN_SECS: i64 = 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct GuildEconomy {
+    /// What the currency is called, for example `coins`.
+    pub currency: String,
+    pub daily_amount: u64,
+    pub balances: HashMap<UserId, u64>,
+    /// Unix timestamp in seconds of every member's last daily reward.
+    pub last_daily: HashMap<UserId, i64>,
+}
+
+impl Default for GuildEconomy {
+    fn default() -> Self {
+        GuildEconomy {
+            currency: String::from("coins"),
+            daily_amount: 100,
+            balances: HashMap::new(),
+            last_daily: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EconomyError {
+    ZeroAmount,
+    SameAccount,
+    InsufficientFunds {
+        balance: u64,
+    },
+    /// The balance would not fit.
+    Overflow,
+    /// The next daily reward can be claimed at this unix timestamp.
+    DailyNotReady {
+        next: i64,
+    },
+}
+
+impl fmt::Display for EconomyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EconomyError::ZeroAmount => f.write_str("The amount has to be at least 1."),
+            EconomyError::SameAccount => f.write_str("You can't pay yourself."),
+            EconomyError::InsufficientFunds { balance } => {
+                write!(f, "Not enough money, the balance is only {}.", balance)
+            }
+            EconomyError::Overflow => f.write_str("That balance would be too large."),
+            EconomyError::DailyNotReady { next } => {
+                write!(
+                    f,
+                    "You already claimed your daily reward, come back <t:{}:R>.",
+                    next
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for EconomyError {}
+
+impl GuildEconomy {
+    pub fn balance(&self, user: UserId) -> u64 {
+        self.balances.get(&user).copied().unwrap_or(0)
+    }
+
+    /// Adds `amount` to the balance of `user` and returns the new balance.
+    pub fn deposit(&mut self, user: UserId, amount: u64) -> Result<u64, EconomyError> {
+        if amount == 0 {
+            return Err(EconomyError::ZeroAmount);
+        }
+        let balance = self
+            .balance(user)
+            .checked_add(amount)
+            .ok_or(EconomyError::Overflow)?;
+        self.balances.insert(user, balance);
+        Ok(balance)
+    }
+
+    /// Takes `amount` from the balance of `user` and returns the new balance. Nothing changes if
+    /// the balance is too low.
+    pub fn withdraw(&mut self, user: UserId, amount: u64) -> Result<u64, EconomyError> {
+        if amount == 0 {
+            return Err(EconomyError::ZeroAmount);
+        }
+        let balance = self.balance(user);
+        let rest = balance
+            .checked_sub(amount)
+            .ok_or(EconomyError::InsufficientFunds { balance })?;
+        self.balances.insert(user, rest);
+        Ok(rest)
+    }
+
+    /// Moves `amount` from `from` to `to`. Either both balances change or neither does.
+    pub fn transfer(&mut self, from: UserId, to: UserId, amount: u64) -> Result<(), EconomyError> {
+        if from == to {
+            return Err(EconomyError::SameAccount);
+        }
+        if amount == 0 {
+            return Err(EconomyError::ZeroAmount);
+        }
+        let balance = self.balance(from);
+        if balance < amount {
+            return Err(EconomyError::InsufficientFunds { balance });
+        }
+        self.balance(to)
+            .checked_add(amount)
+            .ok_or(EconomyError::Overflow)?;
+        self.withdraw(from, amount)?;
+        self.deposit(to, amount)?;
+        Ok(())
+    }
+
+    /// Gives `user` the daily reward if a day has passed since the last one. Returns the amount.
+    pub fn claim_daily(&mut self, user: UserId, now: i64) -> Result<u64, EconomyError> {
+        if let Some(last) = self.last_daily.get(&user) {
+            let next = last + DAILY_COOLDOWN_SECS;
+            if now < next {
+                return Err(EconomyError::DailyNotReady { next });
+            }
+        }
+        let amount = self.daily_amount;
+        self.deposit(user, amount)?;
+        self.last_daily.insert(user, now);
+        Ok(amount)
+    }
+
+    /// Everyone with money, richest first.
+    pub fn richest(&self) -> Vec<(UserId, u64)> {
+        let mut richest = self
+            .balances
+            .iter()
+            .filter(|(_, balance)| **balance > 0)
+            .map(|(user, balance)| (*user, *balance))
+            .collect::<Vec<_>>();
+        richest.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        richest
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionKind {
+    Daily,
+    Pay,
+    /// Money created by an admin.
+    Give,
+    /// Money removed by an admin.
+    Take,
+    /// A lost bet.
+    Bet,
+    /// A won bet.
+    Win,
+}
+
+impl fmt::Display for TransactionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TransactionKind::Daily => "daily",
+            TransactionKind::Pay => "pay",
+            TransactionKind::Give => "give",
+            TransactionKind::Take => "take",
+            TransactionKind::Bet => "bet",
+            TransactionKind::Win => "win",
+        };
+        f.write_str(name)
+    }
+}
+
+/// One entry of the ledger. Money leaves `from` and goes to `to`, `None` on either side means
+/// it was created or destroyed.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Transaction {
+    /// Unix timestamp in seconds.
+    pub time: i64,
+    pub guild: GuildId,
+    pub kind: TransactionKind,
+    pub from: Option<UserId>,
+    pub to: Option<UserId>,
+    pub amount: u64,
+    /// The admin behind `give` and `take`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub by: Option<UserId>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: UserId = UserId::new(1);
+    const BOB: UserId = UserId::new(2);
+
+    #[test]
+    fn deposit_and_withdraw() {
+        let mut economy = GuildEconomy::default();
+        assert_eq!(economy.balance(ALICE), 0);
+        assert_eq!(economy.deposit(ALICE, 50), Ok(50));
+        assert_eq!(economy.withdraw(ALICE, 20), Ok(30));
+        assert_eq!(
+            economy.withdraw(ALICE, 31),
+            Err(EconomyError::InsufficientFunds { balance: 30 })
+        );
+        assert_eq!(economy.balance(ALICE), 30);
+        assert_eq!(economy.deposit(ALICE, 0), Err(EconomyError::ZeroAmount));
+        assert_eq!(economy.withdraw(ALICE, 0), Err(EconomyError::ZeroAmount));
+    }
+
+    #[test]
+    fn deposits_do_not_overflow() {
+        let mut economy = GuildEconomy::default();
+        economy.deposit(ALICE, u64::MAX).unwrap();
+        assert_eq!(economy.deposit(ALICE, 1), Err(EconomyError::Overflow));
+        assert_eq!(economy.balance(ALICE), u64::MAX);
+    }
+
+    #[test]
+    fn transfers_change_both_balances_or_neither() {
+        let mut economy = GuildEconomy::default();
+        economy.deposit(ALICE, 100).unwrap();
+        assert_eq!(economy.transfer(ALICE, BOB, 40), Ok(()));
+        assert_eq!((economy.balance(ALICE), economy.balance(BOB)), (60, 40));
+
+        assert_eq!(
+            economy.transfer(ALICE, BOB, 61),
+            Err(EconomyError::InsufficientFunds { balance: 60 })
+        );
+        assert_eq!(
+            economy.transfer(ALICE, ALICE, 1),
+            Err(EconomyError::SameAccount)
+        );
+        assert_eq!(
+            economy.transfer(ALICE, BOB, 0),
+            Err(EconomyError::ZeroAmount)
+        );
+
+        economy.deposit(BOB, u64::MAX - 40).unwrap();
+        assert_eq!(economy.transfer(ALICE, BOB, 1), Err(EconomyError::Overflow));
+        assert_eq!(
+            (economy.balance(ALICE), economy.balance(BOB)),
+            (60, u64::MAX)
+        );
+    }
+
+    #[test]
+    fn daily_rewards_wait_a_day() {
+        let mut economy = GuildEconomy::default();
+        let now = 1_000_000;
+        assert_eq!(economy.claim_daily(ALICE, now), Ok(100));
+        assert_eq!(
+            economy.claim_daily(ALICE, now + DAILY_COOLDOWN_SECS - 1),
+            Err(EconomyError::DailyNotReady {
+                next: now + DAILY_COOLDOWN_SECS
+            })
+        );
+        assert_eq!(economy.claim_daily(BOB, now), Ok(100));
+        assert_eq!(
+            economy.claim_daily(ALICE, now + DAILY_COOLDOWN_SECS),
+            Ok(100)
+        );
+        assert_eq!(economy.balance(ALICE), 200);
+    }
+
+    #[test]
+    fn a_failed_daily_does_not_start_the_cooldown() {
+        let mut economy = GuildEconomy {
+            daily_amount: 0,
+            ..GuildEconomy::default()
+        };
+        assert_eq!(economy.claim_daily(ALICE, 0), Err(EconomyError::ZeroAmount));
+        economy.daily_amount = 10;
+        assert_eq!(economy.claim_daily(ALICE, 1), Ok(10));
+    }
+
+    #[test]
+    fn richest_skips_empty_balances() {
+        let mut economy = GuildEconomy::default();
+        economy.deposit(ALICE, 10).unwrap();
+        economy.deposit(BOB, 30).unwrap();
+        economy.deposit(UserId::new(3), 10).unwrap();
+        economy.withdraw(UserId::new(3), 10).unwrap();
+        economy.deposit(UserId::new(4), 10).unwrap();
+        assert_eq!(
+            economy.richest(),
+            vec![(BOB, 30), (ALICE, 10), (UserId::new(4), 10)]
+        );
+    }
+}
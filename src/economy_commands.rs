@@ -0,0 +1,468 @@
+use crate::command_base::*;
+use crate::economy::{EconomyError, GuildEconomy, Transaction, TransactionKind};
+use crate::paginator::Paginator;
+use crate::storage::save_json;
+
+use serenity::all::{CreateAllowedMentions, CreateMessage, Timestamp};
+use serenity::model::id::{GuildId, UserId};
+use serenity::utils::parse_user_mention;
+use std::fmt::Write;
+use tokio::io::AsyncWriteExt;
+
+pub const ECONOMY_PATH: &str = "data/economy.json";
+pub const LEDGER_PATH: &str = "data/ledger.jsonl";
+
+#[group]
+#[commands(balance, daily, pay, richest)]
+#[only_in(guilds)]
+pub struct Economy;
+
+#[group]
+#[prefixes("eco")]
+#[commands(give, take, currency, dailyamount, ledger)]
+#[required_permissions(MANAGE_GUILD)]
+#[only_in(guilds)]
+pub struct Eco;
+
+/// The economies of all guilds. Behind its own lock, so a transaction can be checked, applied
+/// and saved without any other command seeing or touching the balances in between.
+pub struct Economies;
+
+impl TypeMapKey for Economies {
+    type Value = Arc<Mutex<HashMap<GuildId, GuildEconomy>>>;
+}
+
+pub async fn load_economies() -> std::io::Result<Arc<Mutex<HashMap<GuildId, GuildEconomy>>>> {
+    let economies = crate::storage::load_json(ECONOMY_PATH).await?;
+    Ok(Arc::new(Mutex::new(economies)))
+}
+
+async fn append_ledger(transactions: &[Transaction]) -> std::io::Result<()> {
+    if transactions.is_empty() {
+        return Ok(());
+    }
+    let mut lines = String::new();
+    for transaction in transactions {
+        let line = serde_json::to_string(transaction)
+            .map_err(|why| std::io::Error::new(std::io::ErrorKind::InvalidData, why))?;
+        lines.push_str(&line);
+        lines.push('\n');
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(LEDGER_PATH)
+        .await?;
+    file.write_all(lines.as_bytes()).await
+}
+
+/// Runs `change` on the economy of `guild_id`. If it succeeds, the balances are saved and the
+/// transactions it returns are written to the ledger before the lock is released.
+pub async fn transact<T>(
+    data: &RwLock<TypeMap>,
+    guild_id: GuildId,
+    change: impl FnOnce(&mut GuildEconomy) -> Result<(T, Vec<Transaction>), EconomyError>,
+) -> CommandResult<Result<T, EconomyError>> {
+    let economies = {
+        let data = data.read().await;
+        Arc::clone(
+            data.get::<Economies>()
+                .expect("Expected Economies in TypeMap."),
+        )
+    };
+    let mut economies = economies.lock().await;
+    let economy = economies.entry(guild_id).or_default();
+    // Work on a copy, so a failed change leaves nothing half done.
+    let mut changed = economy.clone();
+    let (result, transactions) = match change(&mut changed) {
+        Ok(result) => result,
+        Err(why) => return Ok(Err(why)),
+    };
+    *economy = changed;
+    save_json(ECONOMY_PATH, &*economies).await?;
+    append_ledger(&transactions).await?;
+    Ok(Ok(result))
+}
+
+async fn guild_economy(ctx: &Context, guild_id: GuildId) -> GuildEconomy {
+    let economies = {
+        let data = ctx.data.read().await;
+        Arc::clone(
+            data.get::<Economies>()
+                .expect("Expected Economies in TypeMap."),
+        )
+    };
+    let economies = economies.lock().await;
+    economies.get(&guild_id).cloned().unwrap_or_default()
+}
+
+fn parse_user(arg: &str) -> Option<UserId> {
+    parse_user_mention(arg).or_else(|| arg.parse().ok())
+}
+
+fn transaction(
+    guild: GuildId,
+    kind: TransactionKind,
+    from: Option<UserId>,
+    to: Option<UserId>,
+    amount: u64,
+) -> Transaction {
+    Transaction {
+        time: Timestamp::now().unix_timestamp(),
+        guild,
+        kind,
+        from,
+        to,
+        amount,
+        by: None,
+    }
+}
+
+async fn reply_quietly(ctx: &Context, msg: &Message, content: String) -> CommandResult {
+    msg.channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(content)
+                .reference_message(msg)
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Bets `amount` on a `sides` sided die showing `guess`. A hit pays `sides` times the bet, so
+/// the bet is fair.
+pub async fn bet_on_roll(
+    ctx: &Context,
+    msg: &Message,
+    sides: u32,
+    guess: u32,
+    amount: u64,
+) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let user = msg.author.id;
+    let result = transact(&ctx.data, guild_id, |economy| {
+        economy.withdraw(user, amount)?;
+        let rolled = rand::thread_rng().gen_range(1..=sides);
+        let mut transactions = vec![transaction(
+            guild_id,
+            TransactionKind::Bet,
+            Some(user),
+            None,
+            amount,
+        )];
+        if rolled == guess {
+            let winnings = amount
+                .checked_mul(sides as u64)
+                .ok_or(EconomyError::Overflow)?;
+            economy.deposit(user, winnings)?;
+            transactions.push(transaction(
+                guild_id,
+                TransactionKind::Win,
+                None,
+                Some(user),
+                winnings,
+            ));
+        }
+        Ok((
+            (rolled, economy.balance(user), economy.currency.clone()),
+            transactions,
+        ))
+    })
+    .await?;
+
+    let response = match result {
+        Ok((rolled, balance, currency)) if rolled == guess => format!(
+            "You rolled a {} and won {} {}! You now have {} {}.",
+            rolled,
+            amount * sides as u64,
+            currency,
+            balance,
+            currency
+        ),
+        Ok((rolled, balance, currency)) => format!(
+            "You rolled a {} and lost {} {}. You now have {} {}.",
+            rolled, amount, currency, balance, currency
+        ),
+        Err(why) => why.to_string(),
+    };
+    msg.reply(ctx, response).await?;
+    Ok(())
+}
+
+#[command]
+#[aliases("bal")]
+#[description = "Shows your balance, or the one of another member."]
+#[usage = "[@user]"]
+async fn balance(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let user = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_user(&arg))
+        .unwrap_or(msg.author.id);
+    let economy = guild_economy(ctx, guild_id).await;
+    reply_quietly(
+        ctx,
+        msg,
+        format!(
+            "<@{}> has {} {}.",
+            user,
+            economy.balance(user),
+            economy.currency
+        ),
+    )
+    .await
+}
+
+#[command]
+#[description = "Claims your daily reward."]
+async fn daily(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let user = msg.author.id;
+    let now = Timestamp::now().unix_timestamp();
+    let result = transact(&ctx.data, guild_id, |economy| {
+        let amount = economy.claim_daily(user, now)?;
+        Ok((
+            (amount, economy.balance(user), economy.currency.clone()),
+            vec![transaction(
+                guild_id,
+                TransactionKind::Daily,
+                None,
+                Some(user),
+                amount,
+            )],
+        ))
+    })
+    .await?;
+
+    let response = match result {
+        Ok((amount, balance, currency)) => format!(
+            "You got {} {}, you now have {} {}.",
+            amount, currency, balance, currency
+        ),
+        Err(why) => why.to_string(),
+    };
+    msg.reply(ctx, response).await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Gives some of your money to another member."]
+#[usage = "<@user> <amount>"]
+async fn pay(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let target = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_user(&arg));
+    let (Some(target), Ok(amount)) = (target, args.single::<u64>()) else {
+        msg.reply(ctx, "Usage: `pay <@user> <amount>`").await?;
+        return Ok(());
+    };
+    if target.to_user(ctx).await.is_ok_and(|user| user.bot) {
+        msg.reply(ctx, "Bots have no use for money.").await?;
+        return Ok(());
+    }
+    let from = msg.author.id;
+    let result = transact(&ctx.data, guild_id, |economy| {
+        economy.transfer(from, target, amount)?;
+        Ok((
+            (economy.balance(from), economy.currency.clone()),
+            vec![transaction(
+                guild_id,
+                TransactionKind::Pay,
+                Some(from),
+                Some(target),
+                amount,
+            )],
+        ))
+    })
+    .await?;
+
+    let response = match result {
+        Ok((balance, currency)) => format!(
+            "You paid <@{}> {} {}, you have {} {} left.",
+            target, amount, currency, balance, currency
+        ),
+        Err(why) => why.to_string(),
+    };
+    reply_quietly(ctx, msg, response).await
+}
+
+#[command]
+#[aliases("baltop")]
+#[description = "Shows the richest members."]
+#[usage = "[page]"]
+async fn richest(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let economy = guild_economy(ctx, guild_id).await;
+    let richest = economy.richest();
+    if richest.is_empty() {
+        msg.reply(ctx, "Nobody has any money yet.").await?;
+        return Ok(());
+    }
+    let page = args.single::<usize>().unwrap_or(1);
+
+    let entries = richest
+        .iter()
+        .enumerate()
+        .map(|(index, (user, balance))| {
+            format!(
+                "**#{}** <@{}> {} {}",
+                index + 1,
+                user,
+                balance,
+                economy.currency
+            )
+        })
+        .collect();
+    Paginator::new("Richest members", entries)
+        .start_at(page)
+        .send(ctx, msg)
+        .await
+}
+
+/// `eco give` and `eco take`.
+async fn adjust(ctx: &Context, msg: &Message, mut args: Args, give: bool) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let target = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_user(&arg));
+    let (Some(target), Ok(amount)) = (target, args.single::<u64>()) else {
+        let name = if give { "give" } else { "take" };
+        msg.reply(ctx, format!("Usage: `eco {} <@user> <amount>`", name))
+            .await?;
+        return Ok(());
+    };
+    let admin = msg.author.id;
+    let result = transact(&ctx.data, guild_id, |economy| {
+        let mut entry = if give {
+            economy.deposit(target, amount)?;
+            transaction(guild_id, TransactionKind::Give, None, Some(target), amount)
+        } else {
+            economy.withdraw(target, amount)?;
+            transaction(guild_id, TransactionKind::Take, Some(target), None, amount)
+        };
+        entry.by = Some(admin);
+        Ok((
+            (economy.balance(target), economy.currency.clone()),
+            vec![entry],
+        ))
+    })
+    .await?;
+
+    let response = match result {
+        Ok((balance, currency)) => {
+            format!("<@{}> now has {} {}.", target, balance, currency)
+        }
+        Err(why) => why.to_string(),
+    };
+    reply_quietly(ctx, msg, response).await
+}
+
+#[command]
+#[description = "Creates money in the account of a member."]
+#[usage = "<@user> <amount>"]
+async fn give(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    adjust(ctx, msg, args, true).await
+}
+
+#[command]
+#[description = "Removes money from the account of a member."]
+#[usage = "<@user> <amount>"]
+async fn take(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    adjust(ctx, msg, args, false).await
+}
+
+#[command]
+#[description = "Sets what the currency is called."]
+#[usage = "<name>"]
+async fn currency(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let name = args.rest().trim().to_string();
+    if name.is_empty() || name.chars().count() > 32 {
+        msg.reply(ctx, "The name needs 1 to 32 characters.").await?;
+        return Ok(());
+    }
+    transact(&ctx.data, guild_id, |economy| {
+        economy.currency = name.clone();
+        Ok(((), vec![]))
+    })
+    .await??;
+    reply_quietly(ctx, msg, format!("The currency is now called {}.", name)).await
+}
+
+#[command]
+#[description = "Sets how much the daily reward gives."]
+#[usage = "<amount>"]
+async fn dailyamount(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let Some(amount) = args.single::<u64>().ok().filter(|amount| *amount > 0) else {
+        msg.reply(ctx, "Usage: `eco dailyamount <amount>`").await?;
+        return Ok(());
+    };
+    transact(&ctx.data, guild_id, |economy| {
+        economy.daily_amount = amount;
+        Ok(((), vec![]))
+    })
+    .await??;
+    msg.reply(ctx, format!("The daily reward is now {}.", amount))
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Shows the transactions of this server, or of one member, newest first."]
+#[usage = "[@user]"]
+async fn ledger(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let user = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_user(&arg));
+    let content = match tokio::fs::read_to_string(LEDGER_PATH).await {
+        Ok(content) => content,
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(why) => return Err(why.into()),
+    };
+    let entries = content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Transaction>(line).ok())
+        .filter(|entry| entry.guild == guild_id)
+        .filter(|entry| user.is_none() || entry.from == user || entry.to == user)
+        .collect::<Vec<_>>();
+    if entries.is_empty() {
+        msg.reply(ctx, "There are no transactions.").await?;
+        return Ok(());
+    }
+
+    let account = |user: Option<UserId>| match user {
+        Some(user) => format!("<@{}>", user),
+        None => String::from("*bank*"),
+    };
+    let count = entries.len();
+    let mut lines = Vec::new();
+    for entry in entries.iter().rev() {
+        let mut contents = String::new();
+        write!(
+            contents,
+            "<t:{}:f> **{}** {} → {}: {}",
+            entry.time,
+            entry.kind,
+            account(entry.from),
+            account(entry.to),
+            entry.amount
+        )?;
+        if let Some(by) = entry.by {
+            write!(contents, " (by <@{}>)", by)?;
+        }
+        lines.push(contents);
+    }
+    Paginator::new("Ledger", lines)
+        .footer(format!("{} transactions, newest first", count))
+        .send(ctx, msg)
+        .await
+}
@@ -1,10 +1,54 @@
 use crate::command_base::*;
+use crate::paginator::Paginator;
+use crate::storage::save_json;
+
+use serenity::all::{CreateAllowedMentions, CreateAttachment, CreateEmbed, CreateMessage};
+use serenity::model::id::GuildId;
+use serenity::utils::parse_emoji;
+use std::collections::BTreeMap;
+
+pub const RESPONSES_PATH: &str = "data/emoji_responses.json";
 
 #[group]
 #[prefixes("emoji")]
-#[commands(cat, dog)]
+#[commands(cat, dog, big, steal, list_emojis, info, add_response, remove_response)]
+#[default_command(custom_response)]
 pub struct Emoji;
 
+/// Text responses admins added with `emoji add`, per guild and keyed by name.
+pub struct EmojiResponses;
+
+impl TypeMapKey for EmojiResponses {
+    type Value = HashMap<GuildId, BTreeMap<String, String>>;
+}
+
+/// Sends `content` without pinging anyone, it contains names or responses users typed.
+async fn say_quietly(ctx: &Context, msg: &Message, content: String) -> CommandResult {
+    msg.channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(content)
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Like `say_quietly`, as a reply to `msg`.
+async fn reply_quietly(ctx: &Context, msg: &Message, content: String) -> CommandResult {
+    msg.channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(content)
+                .reference_message(msg)
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+    Ok(())
+}
+
 #[command]
 // Adds multiple aliases
 #[aliases("kitty", "neko")]
@@ -24,3 +68,262 @@ async fn dog(ctx: &Context, msg: &Message) -> CommandResult {
 
     Ok(())
 }
+
+#[command]
+#[description = "Posts a large version of a custom emoji."]
+#[usage = "<emoji>"]
+#[bucket = "emoji"]
+async fn big(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Some(emoji) = args.single::<String>().ok().and_then(parse_emoji) else {
+        msg.reply(ctx, "You need to provide a custom emoji!")
+            .await?;
+        return Ok(());
+    };
+    let embed = CreateEmbed::new()
+        .title(&emoji.name)
+        .image(format!("{}?size=256", emoji.url()));
+    msg.channel_id
+        .send_message(&ctx.http, CreateMessage::new().embed(embed))
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Adds a custom emoji from another server to this one."]
+#[usage = "<emoji> [name]"]
+#[only_in(guilds)]
+#[bucket = "emoji"]
+#[required_permissions(MANAGE_GUILD_EXPRESSIONS)]
+async fn steal(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Some(emoji) = args.single::<String>().ok().and_then(parse_emoji) else {
+        msg.reply(ctx, "You need to provide a custom emoji!")
+            .await?;
+        return Ok(());
+    };
+    let name = args.single::<String>().unwrap_or(emoji.name.clone());
+    let image = CreateAttachment::url(&ctx.http, &emoji.url()).await?;
+
+    let guild_id = msg.guild_id.unwrap();
+    match guild_id
+        .create_emoji(&ctx.http, &name, &image.to_base64())
+        .await
+    {
+        Ok(created) => {
+            say_quietly(
+                ctx,
+                msg,
+                format!("Added {} as `{}`.", created, created.name),
+            )
+            .await?;
+        }
+        Err(why) => {
+            println!("Could not add emoji '{}': {:?}", name, why);
+            msg.reply(
+                ctx,
+                "I cannot add this emoji. Maybe the server is out of emoji slots?",
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[command("list")]
+#[description = "Lists the emojis of this server and the custom emoji responses."]
+#[only_in(guilds)]
+#[bucket = "emoji"]
+async fn list_emojis(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap();
+    let mut entries = Vec::new();
+    {
+        let guild = msg.guild(&ctx.cache).ok_or("The server is not cached")?;
+        let mut emojis = guild.emojis.values().collect::<Vec<_>>();
+        emojis.sort_by(|a, b| a.name.cmp(&b.name));
+        if emojis.is_empty() {
+            entries.push(String::from("This server has no emojis."));
+        }
+        for emoji in emojis {
+            entries.push(format!("{} `{}`", emoji, emoji.name));
+        }
+    }
+
+    {
+        let data = ctx.data.read().await;
+        let responses = data
+            .get::<EmojiResponses>()
+            .expect("Expected EmojiResponses in TypeMap.");
+        if let Some(responses) = responses.get(&guild_id).filter(|r| !r.is_empty()) {
+            for name in responses.keys() {
+                entries.push(format!("Response: `{name}`"));
+            }
+        }
+    }
+
+    Paginator::new("Emojis", entries).send(ctx, msg).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Shows information about a custom emoji."]
+#[usage = "<emoji>"]
+#[bucket = "emoji"]
+async fn info(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Some(emoji) = args.single::<String>().ok().and_then(parse_emoji) else {
+        msg.reply(ctx, "You need to provide a custom emoji!")
+            .await?;
+        return Ok(());
+    };
+
+    let mut embed = CreateEmbed::new()
+        .title(&emoji.name)
+        .thumbnail(emoji.url())
+        .field("ID", emoji.id.to_string(), true)
+        .field("Animated", if emoji.animated { "Yes" } else { "No" }, true)
+        .field(
+            "Created",
+            format!("<t:{}:f>", emoji.id.created_at().unix_timestamp()),
+            true,
+        );
+    let local = msg
+        .guild(&ctx.cache)
+        .and_then(|guild| guild.emojis.get(&emoji.id).cloned());
+    match local {
+        Some(local) => {
+            let roles = if local.roles.is_empty() {
+                String::from("Everyone")
+            } else {
+                local
+                    .roles
+                    .iter()
+                    .map(|role| format!("<@&{}>", role))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+            embed = embed.field("Usable by", roles, true).field(
+                "Managed",
+                if local.managed { "Yes" } else { "No" },
+                true,
+            );
+            if let Some(user) = &local.user {
+                embed = embed.field("Added by", format!("<@{}>", user.id), true);
+            }
+        }
+        None => embed = embed.field("Server", "Not from this server", true),
+    }
+
+    msg.channel_id
+        .send_message(&ctx.http, CreateMessage::new().embed(embed))
+        .await?;
+
+    Ok(())
+}
+
+#[command("add")]
+#[description = "Adds a text response that is sent for `emoji <name>`."]
+#[usage = "<name> <response>"]
+#[only_in(guilds)]
+#[bucket = "emoji"]
+#[required_permissions(MANAGE_GUILD)]
+async fn add_response(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Ok(name) = args.single::<String>().map(|name| name.to_lowercase()) else {
+        msg.reply(ctx, "You need to provide a name!").await?;
+        return Ok(());
+    };
+    let response = args.rest().trim().to_string();
+    if response.is_empty() {
+        msg.reply(ctx, "You need to provide a response!").await?;
+        return Ok(());
+    }
+    let is_builtin = EMOJI_GROUP_OPTIONS
+        .commands
+        .iter()
+        .any(|command| command.options.names.contains(&name.as_str()));
+    if is_builtin {
+        reply_quietly(ctx, msg, format!("`{}` is already a command.", name)).await?;
+        return Ok(());
+    }
+
+    let guild_id = msg.guild_id.unwrap();
+    let responses = {
+        let mut data = ctx.data.write().await;
+        let responses = data
+            .get_mut::<EmojiResponses>()
+            .expect("Expected EmojiResponses in TypeMap.");
+        responses
+            .entry(guild_id)
+            .or_default()
+            .insert(name.clone(), response);
+        responses.clone()
+    };
+    save_json(RESPONSES_PATH, &responses).await?;
+
+    say_quietly(ctx, msg, format!("Added `emoji {}`.", name)).await?;
+
+    Ok(())
+}
+
+#[command("remove")]
+#[description = "Removes a text response added with `emoji add`."]
+#[usage = "<name>"]
+#[only_in(guilds)]
+#[bucket = "emoji"]
+#[required_permissions(MANAGE_GUILD)]
+async fn remove_response(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Ok(name) = args.single::<String>().map(|name| name.to_lowercase()) else {
+        msg.reply(ctx, "You need to provide a name!").await?;
+        return Ok(());
+    };
+
+    let guild_id = msg.guild_id.unwrap();
+    let removed = {
+        let mut data = ctx.data.write().await;
+        let responses = data
+            .get_mut::<EmojiResponses>()
+            .expect("Expected EmojiResponses in TypeMap.");
+        let removed = responses
+            .get_mut(&guild_id)
+            .and_then(|guild| guild.remove(&name))
+            .is_some();
+        removed.then(|| responses.clone())
+    };
+    match removed {
+        Some(responses) => {
+            save_json(RESPONSES_PATH, &responses).await?;
+            say_quietly(ctx, msg, format!("Removed `emoji {}`.", name)).await?;
+        }
+        None => {
+            reply_quietly(ctx, msg, format!("There is no `emoji {}`.", name)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+// Runs for `emoji <name>` when `<name>` is not one of the commands above.
+#[command]
+#[bucket = "emoji"]
+async fn custom_response(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let name = args
+        .single::<String>()
+        .map(|name| name.to_lowercase())
+        .unwrap_or_default();
+
+    let response = {
+        let data = ctx.data.read().await;
+        let responses = data
+            .get::<EmojiResponses>()
+            .expect("Expected EmojiResponses in TypeMap.");
+        msg.guild_id
+            .and_then(|guild_id| responses.get(&guild_id))
+            .and_then(|guild| guild.get(&name))
+            .cloned()
+    };
+
+    match response {
+        Some(response) => say_quietly(ctx, msg, response).await,
+        None => reply_quietly(ctx, msg, format!("Could not find: `{}`.", name)).await,
+    }
+}
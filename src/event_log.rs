@@ -0,0 +1,369 @@
+use crate::command_base::*;
+use crate::log_commands::{send_log, truncate, LogCategory};
+
+use serenity::all::{
+    audit_log, CreateEmbed, CreateEmbedAuthor, CreateMessage, GuildChannel, GuildMemberUpdateEvent,
+    Member, Role, Timestamp, User, VoiceState,
+};
+use serenity::model::id::{ChannelId, GuildId, RoleId};
+
+/// Posts an embed in the log channel of `category`. `source` is the channel the event happened
+/// in, if any, so ignored channels stay quiet.
+async fn log(
+    ctx: &Context,
+    guild_id: GuildId,
+    category: LogCategory,
+    source: Option<ChannelId>,
+    embed: CreateEmbed,
+) -> CommandResult {
+    send_log(
+        ctx,
+        guild_id,
+        category,
+        source,
+        CreateMessage::new().embed(embed.timestamp(Timestamp::now())),
+    )
+    .await
+}
+
+fn user_embed(title: &str, user: &User) -> CreateEmbed {
+    CreateEmbed::new()
+        .title(title)
+        .author(CreateEmbedAuthor::new(&user.name).icon_url(user.face()))
+        .field("User", format!("<@{}>", user.id), true)
+}
+
+/// One line per changed value, for the update events.
+fn changes(fields: &[(&str, String, String)]) -> String {
+    fields
+        .iter()
+        .filter(|(_, before, after)| before != after)
+        .map(|(name, before, after)| format!("**{}**: {} → {}", name, before, after))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn role_list(roles: &[RoleId]) -> String {
+    roles
+        .iter()
+        .map(|role| format!("<@&{}>", role))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub async fn handle_member_update(
+    ctx: &Context,
+    old: Option<&Member>,
+    new: Option<&Member>,
+    event: &GuildMemberUpdateEvent,
+) -> CommandResult {
+    // Without the old state there is nothing to compare with.
+    let Some(old) = old else {
+        return Ok(());
+    };
+    let guild_id = event.guild_id;
+
+    if old.nick != event.nick {
+        let embed = user_embed("Nickname changed", &event.user)
+            .field("Before", old.nick.as_deref().unwrap_or("*none*"), true)
+            .field("After", event.nick.as_deref().unwrap_or("*none*"), true);
+        log(ctx, guild_id, LogCategory::Members, None, embed).await?;
+    }
+
+    let added = event
+        .roles
+        .iter()
+        .filter(|role| !old.roles.contains(role))
+        .copied()
+        .collect::<Vec<_>>();
+    let removed = old
+        .roles
+        .iter()
+        .filter(|role| !event.roles.contains(role))
+        .copied()
+        .collect::<Vec<_>>();
+    if !added.is_empty() || !removed.is_empty() {
+        let mut embed = user_embed("Roles changed", &event.user);
+        if !added.is_empty() {
+            embed = embed.field("Added", truncate(&role_list(&added), 1024), false);
+        }
+        if !removed.is_empty() {
+            embed = embed.field("Removed", truncate(&role_list(&removed), 1024), false);
+        }
+        log(ctx, guild_id, LogCategory::Members, None, embed).await?;
+    }
+
+    if old.user.avatar != event.user.avatar || old.avatar != event.avatar {
+        let face = new
+            .map(|member| member.face())
+            .unwrap_or_else(|| event.user.face());
+        let embed = user_embed("Avatar changed", &event.user)
+            .thumbnail(old.face())
+            .image(face);
+        log(ctx, guild_id, LogCategory::Members, None, embed).await?;
+    }
+
+    Ok(())
+}
+
+fn channel_embed(title: &str, channel: &GuildChannel) -> CreateEmbed {
+    let mut embed = CreateEmbed::new()
+        .title(title)
+        .field(
+            "Channel",
+            format!("{} (<#{}>)", channel.name, channel.id),
+            true,
+        )
+        .field("Type", format!("{:?}", channel.kind), true);
+    if let Some(parent) = channel.parent_id {
+        embed = embed.field("Category", format!("<#{}>", parent), true);
+    }
+    embed
+}
+
+pub async fn handle_channel_create(ctx: &Context, channel: &GuildChannel) -> CommandResult {
+    let embed = channel_embed("Channel created", channel);
+    log(
+        ctx,
+        channel.guild_id,
+        LogCategory::Channels,
+        Some(channel.id),
+        embed,
+    )
+    .await
+}
+
+pub async fn handle_channel_delete(ctx: &Context, channel: &GuildChannel) -> CommandResult {
+    let embed = channel_embed("Channel deleted", channel);
+    log(
+        ctx,
+        channel.guild_id,
+        LogCategory::Channels,
+        Some(channel.id),
+        embed,
+    )
+    .await
+}
+
+pub async fn handle_channel_update(
+    ctx: &Context,
+    old: Option<&GuildChannel>,
+    new: &GuildChannel,
+) -> CommandResult {
+    let Some(old) = old else {
+        return Ok(());
+    };
+    let optional = |value: Option<String>| value.unwrap_or_else(|| String::from("*none*"));
+    let mut changed = changes(&[
+        ("Name", old.name.clone(), new.name.clone()),
+        (
+            "Topic",
+            optional(old.topic.clone()),
+            optional(new.topic.clone()),
+        ),
+        ("NSFW", old.nsfw.to_string(), new.nsfw.to_string()),
+        (
+            "Slowmode",
+            format!("{}s", old.rate_limit_per_user.unwrap_or(0)),
+            format!("{}s", new.rate_limit_per_user.unwrap_or(0)),
+        ),
+        (
+            "Category",
+            optional(old.parent_id.map(|parent| format!("<#{}>", parent))),
+            optional(new.parent_id.map(|parent| format!("<#{}>", parent))),
+        ),
+    ]);
+    if old.permission_overwrites != new.permission_overwrites {
+        if !changed.is_empty() {
+            changed.push('\n');
+        }
+        changed.push_str("**Permissions** were changed");
+    }
+    // Position changes of other channels also cause updates, those are not interesting.
+    if changed.is_empty() {
+        return Ok(());
+    }
+
+    let embed = channel_embed("Channel updated", new).description(truncate(&changed, 4096));
+    log(
+        ctx,
+        new.guild_id,
+        LogCategory::Channels,
+        Some(new.id),
+        embed,
+    )
+    .await
+}
+
+fn role_embed(title: &str, role: &Role) -> CreateEmbed {
+    CreateEmbed::new().title(title).colour(role.colour).field(
+        "Role",
+        format!("{} (`{}`)", role.name, role.id),
+        true,
+    )
+}
+
+pub async fn handle_role_create(ctx: &Context, role: &Role) -> CommandResult {
+    let embed = role_embed("Role created", role);
+    log(ctx, role.guild_id, LogCategory::Roles, None, embed).await
+}
+
+pub async fn handle_role_delete(
+    ctx: &Context,
+    guild_id: GuildId,
+    role_id: RoleId,
+    role: Option<&Role>,
+) -> CommandResult {
+    let embed = match role {
+        Some(role) => role_embed("Role deleted", role),
+        None => {
+            CreateEmbed::new()
+                .title("Role deleted")
+                .field("Role", format!("`{}`", role_id), true)
+        }
+    };
+    log(ctx, guild_id, LogCategory::Roles, None, embed).await
+}
+
+pub async fn handle_role_update(ctx: &Context, old: Option<&Role>, new: &Role) -> CommandResult {
+    let Some(old) = old else {
+        return Ok(());
+    };
+    let mut changed = changes(&[
+        ("Name", old.name.clone(), new.name.clone()),
+        (
+            "Colour",
+            format!("#{}", old.colour.hex()),
+            format!("#{}", new.colour.hex()),
+        ),
+        ("Hoisted", old.hoist.to_string(), new.hoist.to_string()),
+        (
+            "Mentionable",
+            old.mentionable.to_string(),
+            new.mentionable.to_string(),
+        ),
+    ]);
+    if old.permissions != new.permissions {
+        let granted = new.permissions - old.permissions;
+        let revoked = old.permissions - new.permissions;
+        if !changed.is_empty() {
+            changed.push('\n');
+        }
+        changed.push_str(&format!(
+            "**Permissions**: granted {}; revoked {}",
+            if granted.is_empty() {
+                String::from("nothing")
+            } else {
+                granted.to_string()
+            },
+            if revoked.is_empty() {
+                String::from("nothing")
+            } else {
+                revoked.to_string()
+            }
+        ));
+    }
+    // Moving other roles changes the position of this one, which is not worth a log entry.
+    if changed.is_empty() {
+        return Ok(());
+    }
+
+    let embed = role_embed("Role updated", new).description(truncate(&changed, 4096));
+    log(ctx, new.guild_id, LogCategory::Roles, None, embed).await
+}
+
+pub async fn handle_voice_update(
+    ctx: &Context,
+    old: Option<&VoiceState>,
+    new: &VoiceState,
+) -> CommandResult {
+    let Some(guild_id) = new.guild_id else {
+        return Ok(());
+    };
+    let before = old.and_then(|old| old.channel_id);
+    let (title, description, source) = match (before, new.channel_id) {
+        (None, Some(after)) => ("Joined voice", format!("<#{}>", after), after),
+        (Some(before), None) => ("Left voice", format!("<#{}>", before), before),
+        (Some(before), Some(after)) if before != after => (
+            "Moved voice channel",
+            format!("<#{}> → <#{}>", before, after),
+            after,
+        ),
+        // Mutes, deafens and streams also come as voice state updates.
+        _ => return Ok(()),
+    };
+
+    let mut embed = CreateEmbed::new()
+        .title(title)
+        .description(description)
+        .field("User", format!("<@{}>", new.user_id), true);
+    if let Some(member) = &new.member {
+        embed = embed.author(CreateEmbedAuthor::new(&member.user.name).icon_url(member.face()));
+    }
+    log(ctx, guild_id, LogCategory::Voice, Some(source), embed).await
+}
+
+/// Logs a ban or unban, together with who did it according to the audit log. Bans the bot did
+/// itself are already announced by the ban command, those are skipped.
+pub async fn handle_ban(
+    ctx: &Context,
+    guild_id: GuildId,
+    user: &User,
+    banned: bool,
+) -> CommandResult {
+    let action = if banned {
+        audit_log::MemberAction::BanAdd
+    } else {
+        audit_log::MemberAction::BanRemove
+    };
+    // The audit log entry is not always written before the gateway event arrives.
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    let entry = match guild_id
+        .audit_logs(
+            &ctx.http,
+            Some(audit_log::Action::Member(action)),
+            None,
+            None,
+            Some(10),
+        )
+        .await
+    {
+        Ok(logs) => logs.entries.into_iter().find(|entry| {
+            entry
+                .target_id
+                .is_some_and(|target| target.get() == user.id.get())
+        }),
+        Err(why) => {
+            println!("Could not read the audit log of {guild_id}: {why:?}");
+            None
+        }
+    };
+    if entry
+        .as_ref()
+        .is_some_and(|entry| entry.user_id == ctx.cache.current_user().id)
+    {
+        return Ok(());
+    }
+
+    let mut embed = user_embed(
+        if banned {
+            "Member banned"
+        } else {
+            "Member unbanned"
+        },
+        user,
+    );
+    match &entry {
+        Some(entry) => {
+            embed = embed
+                .field("By", format!("<@{}>", entry.user_id), true)
+                .field(
+                    "Reason",
+                    entry.reason.as_deref().unwrap_or("No reason provided."),
+                    false,
+                )
+        }
+        None => embed = embed.field("By", "*unknown*", true),
+    }
+    log(ctx, guild_id, LogCategory::Bans, None, embed).await
+}
@@ -0,0 +1,680 @@
+use crate::command_base::*;
+use crate::games::{
+    parse_guess, Guess, Hand, Hangman, Outcome, RockPaperScissors, TicTacToe, TriviaQuestion,
+    TriviaRound,
+};
+use crate::storage::load_json;
+
+use chrono::Utc;
+use rand::seq::SliceRandom;
+use serenity::all::{
+    ButtonStyle, ComponentInteraction, CreateActionRow, CreateAllowedMentions, CreateButton,
+    CreateEmbed, CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseMessage,
+    CreateMessage, EditMessage, ReactionType,
+};
+use serenity::futures::StreamExt;
+use serenity::model::id::{ChannelId, UserId};
+use serenity::utils::parse_user_mention;
+use std::future::Future;
+use std::sync::PoisonError;
+use std::time::Duration;
+use tokio::time::{timeout, timeout_at, Instant};
+
+pub const TRIVIA_PATH: &str = "assets/trivia.json";
+const GAME_PREFIX: &str = "game";
+/// How long a player has for a move in tic-tac-toe and for picking a hand.
+const MOVE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Hangman ends when nobody guesses for this long.
+const GUESS_TIMEOUT: Duration = Duration::from_secs(120);
+const QUESTION_TIME: Duration = Duration::from_secs(20);
+const MAX_WRONG_GUESSES: u32 = 6;
+const DEFAULT_QUESTIONS: usize = 3;
+const MAX_QUESTIONS: usize = 10;
+
+const WORDS: &[&str] = &[
+    "anchor", "balloon", "biscuit", "blanket", "cactus", "candle", "castle", "compass", "crystal",
+    "dolphin", "dragon", "engine", "feather", "garden", "giraffe", "guitar", "hammock", "harbor",
+    "island", "jacket", "jungle", "kettle", "ladder", "lantern", "lemonade", "lobster", "magnet",
+    "meadow", "mirror", "monkey", "mustard", "noodle", "orchard", "oyster", "paddle", "parrot",
+    "pepper", "pillow", "planet", "pretzel", "puzzle", "rabbit", "rocket", "saddle", "sandwich",
+    "scarf", "shadow", "squirrel", "teapot", "thunder", "tomato", "trumpet", "tunnel", "umbrella",
+    "velvet", "violin", "volcano", "walnut", "whistle", "window", "wizard", "zipper",
+];
+
+#[group]
+#[prefixes("game")]
+#[commands(ttt, hangman, rps, trivia)]
+#[only_in(guilds)]
+pub struct Game;
+
+/// Channels with a game running. Only one game runs per channel at a time, so the guesses and
+/// buttons of different games never get mixed up.
+pub struct ActiveGames;
+
+impl TypeMapKey for ActiveGames {
+    type Value = Arc<std::sync::Mutex<HashSet<ChannelId>>>;
+}
+
+/// Frees the channel of a game when dropped, also when the game panics.
+struct GameSlot {
+    games: Arc<std::sync::Mutex<HashSet<ChannelId>>>,
+    channel: ChannelId,
+}
+
+impl Drop for GameSlot {
+    fn drop(&mut self) {
+        self.games
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.channel);
+    }
+}
+
+/// Plays `game` in the channel of `msg`, unless a game is already running there.
+async fn run_game(
+    ctx: &Context,
+    msg: &Message,
+    game: impl Future<Output = CommandResult>,
+) -> CommandResult {
+    let games = {
+        let data = ctx.data.read().await;
+        Arc::clone(
+            data.get::<ActiveGames>()
+                .expect("Expected ActiveGames in TypeMap."),
+        )
+    };
+    let claimed = games
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(msg.channel_id);
+    if !claimed {
+        msg.reply(ctx, "There is already a game running in this channel.")
+            .await?;
+        return Ok(());
+    }
+    let _slot = GameSlot {
+        games,
+        channel: msg.channel_id,
+    };
+    game.await
+}
+
+fn parse_user(arg: &str) -> Option<UserId> {
+    parse_user_mention(arg).or_else(|| arg.parse().ok())
+}
+
+/// The opponent given as the first argument. Tells the author and returns `None` if there is no
+/// one to play against.
+async fn opponent(ctx: &Context, msg: &Message, args: &mut Args) -> CommandResult<Option<UserId>> {
+    let Some(user) = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_user(&arg))
+    else {
+        msg.reply(ctx, "Mention who you want to play against.")
+            .await?;
+        return Ok(None);
+    };
+    let problem = if user == msg.author.id {
+        Some("You can't play against yourself.")
+    } else {
+        match user.to_user(ctx).await {
+            Ok(user) if user.bot => Some("Bots don't play games."),
+            Ok(_) => None,
+            Err(_) => Some("I could not find that user."),
+        }
+    };
+    if let Some(problem) = problem {
+        msg.reply(ctx, problem).await?;
+        return Ok(None);
+    }
+    Ok(Some(user))
+}
+
+async fn respond(
+    ctx: &Context,
+    component: &ComponentInteraction,
+    content: String,
+) -> CommandResult {
+    component
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Answers a button click by updating the message the button is on.
+async fn update(
+    ctx: &Context,
+    component: &ComponentInteraction,
+    content: String,
+    components: Vec<CreateActionRow>,
+) -> CommandResult {
+    component
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .components(components),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+/// The last part of the custom id of a clicked button.
+fn clicked(component: &ComponentInteraction) -> &str {
+    component
+        .data
+        .custom_id
+        .rsplit(':')
+        .next()
+        .unwrap_or_default()
+}
+
+fn ttt_status(game: &TicTacToe<UserId>) -> String {
+    let players = format!("❌ <@{}> vs ⭕ <@{}>", game.players[0], game.players[1]);
+    match game.outcome {
+        Outcome::Ongoing => format!("{}\nIt's <@{}>'s turn.", players, game.current_player()),
+        Outcome::Won(winner) => format!("{}\n🏆 <@{}> wins!", players, winner),
+        Outcome::Draw => format!("{}\nIt's a draw.", players),
+    }
+}
+
+fn ttt_components(game: &TicTacToe<UserId>) -> Vec<CreateActionRow> {
+    ttt_buttons(game, game.outcome != Outcome::Ongoing)
+}
+
+/// The board, with every button disabled if the game is `over`.
+fn ttt_buttons(game: &TicTacToe<UserId>, over: bool) -> Vec<CreateActionRow> {
+    (0..3)
+        .map(|row| {
+            CreateActionRow::Buttons(
+                (0..3)
+                    .map(|column| {
+                        let cell = row * 3 + column;
+                        let style = match game.board[cell] {
+                            Some(0) => ButtonStyle::Primary,
+                            Some(_) => ButtonStyle::Danger,
+                            None => ButtonStyle::Secondary,
+                        };
+                        CreateButton::new(format!("{}:ttt:{}", GAME_PREFIX, cell))
+                            .label(game.symbol(cell))
+                            .style(style)
+                            .disabled(over || game.board[cell].is_some())
+                    })
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+#[command]
+#[description = "Challenges someone to tic-tac-toe. You play X and start. Whoever takes longer \
+than a minute for a move loses."]
+#[usage = "<user>"]
+#[example = "@Ferris"]
+async fn ttt(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Some(opponent) = opponent(ctx, msg, &mut args).await? else {
+        return Ok(());
+    };
+    run_game(ctx, msg, play_ttt(ctx, msg, opponent)).await
+}
+
+async fn play_ttt(ctx: &Context, msg: &Message, opponent: UserId) -> CommandResult {
+    let mut game = TicTacToe::new(msg.author.id, opponent);
+    let mut message = msg
+        .channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(ttt_status(&game))
+                .components(ttt_components(&game)),
+        )
+        .await?;
+
+    let mut clicks = Box::pin(message.await_component_interaction(&ctx.shard).stream());
+    let mut deadline = Instant::now() + MOVE_TIMEOUT;
+    while game.outcome == Outcome::Ongoing {
+        let Ok(Some(click)) = timeout_at(deadline, clicks.next()).await else {
+            let slow = game.current_player();
+            // An opponent who never made a move did not accept, nobody wins then.
+            let edit = if game.turn == 1 && !game.board.contains(&Some(1)) {
+                EditMessage::new()
+                    .content(format!(
+                        "❌ <@{}> vs ⭕ <@{}>\n⌛ <@{}> did not accept the challenge.",
+                        game.players[0], game.players[1], slow
+                    ))
+                    .components(ttt_buttons(&game, true))
+            } else {
+                game.outcome = Outcome::Won(game.players[1 - game.turn]);
+                EditMessage::new()
+                    .content(format!(
+                        "{}\n⌛ <@{}> took too long.",
+                        ttt_status(&game),
+                        slow
+                    ))
+                    .components(ttt_components(&game))
+            };
+            message.edit(ctx, edit).await?;
+            return Ok(());
+        };
+        let cell = clicked(&click).parse().unwrap_or(usize::MAX);
+        match game.play(click.user.id, cell) {
+            Ok(_) => {
+                deadline = Instant::now() + MOVE_TIMEOUT;
+                update(ctx, &click, ttt_status(&game), ttt_components(&game)).await?;
+            }
+            Err(why) => respond(ctx, &click, why.to_string()).await?,
+        }
+    }
+
+    Ok(())
+}
+
+fn rps_components() -> Vec<CreateActionRow> {
+    vec![CreateActionRow::Buttons(
+        Hand::ALL
+            .into_iter()
+            .map(|hand| {
+                CreateButton::new(format!("{}:rps:{}", GAME_PREFIX, hand.name()))
+                    .label(hand.name())
+                    .emoji(ReactionType::Unicode(hand.emoji().to_string()))
+                    .style(ButtonStyle::Secondary)
+            })
+            .collect(),
+    )]
+}
+
+fn rps_result(game: &RockPaperScissors<UserId>) -> String {
+    let mut result = String::new();
+    for (player, hand) in game.players.iter().zip(game.hands) {
+        if let Some(hand) = hand {
+            result.push_str(&format!(
+                "<@{}> picked {} {}.\n",
+                player,
+                hand.emoji(),
+                hand.name()
+            ));
+        }
+    }
+    match game.outcome() {
+        Outcome::Won(winner) => result.push_str(&format!("🏆 <@{}> wins!", winner)),
+        Outcome::Draw => result.push_str("It's a draw."),
+        Outcome::Ongoing => {}
+    }
+    result
+}
+
+#[command]
+#[description = "Challenges someone to rock paper scissors. Both of you pick a hand with the \
+buttons within a minute, nobody sees the other's pick until both picked."]
+#[usage = "<user>"]
+#[example = "@Ferris"]
+async fn rps(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Some(opponent) = opponent(ctx, msg, &mut args).await? else {
+        return Ok(());
+    };
+    run_game(ctx, msg, play_rps(ctx, msg, opponent)).await
+}
+
+async fn play_rps(ctx: &Context, msg: &Message, opponent: UserId) -> CommandResult {
+    let mut game = RockPaperScissors::new(msg.author.id, opponent);
+    let challenge = format!(
+        "<@{}> challenges <@{}> to rock paper scissors! Pick your hand.",
+        msg.author.id, opponent
+    );
+    let mut message = msg
+        .channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(&challenge)
+                .components(rps_components()),
+        )
+        .await?;
+
+    let mut clicks = Box::pin(message.await_component_interaction(&ctx.shard).stream());
+    let deadline = Instant::now() + MOVE_TIMEOUT;
+    while game.outcome() == Outcome::Ongoing {
+        let Ok(Some(click)) = timeout_at(deadline, clicks.next()).await else {
+            let missing = game
+                .players
+                .iter()
+                .zip(game.hands)
+                .filter(|(_, hand)| hand.is_none())
+                .map(|(player, _)| format!("<@{}>", player))
+                .collect::<Vec<_>>();
+            message
+                .edit(
+                    ctx,
+                    EditMessage::new()
+                        .content(format!(
+                            "{}\n⌛ {} did not pick in time.",
+                            challenge,
+                            missing.join(" and ")
+                        ))
+                        .components(vec![]),
+                )
+                .await?;
+            return Ok(());
+        };
+        let Some(hand) = Hand::from_name(clicked(&click)) else {
+            continue;
+        };
+        match game.choose(click.user.id, hand) {
+            Ok(Outcome::Ongoing) => {
+                respond(
+                    ctx,
+                    &click,
+                    format!("You picked {} {}.", hand.emoji(), hand.name()),
+                )
+                .await?
+            }
+            Ok(_) => {
+                update(
+                    ctx,
+                    &click,
+                    format!("{}\n{}", challenge, rps_result(&game)),
+                    vec![],
+                )
+                .await?
+            }
+            Err(why) => respond(ctx, &click, why.to_string()).await?,
+        }
+    }
+
+    Ok(())
+}
+
+fn hangman_embed(game: &Hangman) -> CreateEmbed {
+    let word = game
+        .masked()
+        .chars()
+        .map(String::from)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let wrong = game
+        .guessed
+        .iter()
+        .filter(|letter| !game.word().contains(**letter))
+        .map(char::to_string)
+        .collect::<Vec<_>>();
+    let lives = game.max_wrong_guesses.saturating_sub(game.wrong_guesses);
+    let title = if game.is_won() {
+        "Hangman: solved!"
+    } else if game.is_lost() {
+        "Hangman: out of lives"
+    } else {
+        "Hangman"
+    };
+    CreateEmbed::new()
+        .title(title)
+        .description(format!(
+            "`{}`\n\nLives: {}\nWrong letters: {}",
+            word,
+            if lives == 0 {
+                String::from("none")
+            } else {
+                "❤️".repeat(lives as usize)
+            },
+            if wrong.is_empty() {
+                String::from("none")
+            } else {
+                wrong.join(", ")
+            }
+        ))
+        .footer(CreateEmbedFooter::new(
+            "Guess a letter with \"guess e\" or the whole word with \"guess <word>\".",
+        ))
+}
+
+#[command]
+#[description = "Starts a game of hangman for everyone in the channel. Guess by sending `guess` \
+and a letter or the whole word, like `guess e`. The game ends when nobody guesses for two minutes."]
+async fn hangman(ctx: &Context, msg: &Message) -> CommandResult {
+    run_game(ctx, msg, play_hangman(ctx, msg)).await
+}
+
+async fn play_hangman(ctx: &Context, msg: &Message) -> CommandResult {
+    let word = WORDS
+        .choose(&mut rand::thread_rng())
+        .copied()
+        .unwrap_or("ferris");
+    let mut game = Hangman::new(word, MAX_WRONG_GUESSES);
+    let mut message = msg
+        .channel_id
+        .send_message(&ctx.http, CreateMessage::new().embed(hangman_embed(&game)))
+        .await?;
+
+    // Only messages like `guess e` count, the channel can keep chatting meanwhile.
+    let length = game.word().chars().count();
+    let mut guesses = Box::pin(
+        msg.channel_id
+            .await_reply(&ctx.shard)
+            .filter(move |message| {
+                !message.author.bot
+                    && parse_guess(&message.content).is_some_and(|guess| {
+                        let count = guess.chars().count();
+                        count == 1 || count == length
+                    })
+            })
+            .stream(),
+    );
+    let mut last_guesser = msg.author.id;
+    while !game.is_over() {
+        let Ok(Some(guess)) = timeout(GUESS_TIMEOUT, guesses.next()).await else {
+            msg.channel_id
+                .say(
+                    ctx,
+                    format!(
+                        "⌛ Nobody guessed for two minutes, the game is over. The word was **{}**.",
+                        game.word()
+                    ),
+                )
+                .await?;
+            return Ok(());
+        };
+        last_guesser = guess.author.id;
+        let reaction = match game.guess(parse_guess(&guess.content).unwrap_or_default()) {
+            Ok(Guess::Hit) => '✅',
+            Ok(Guess::Miss) => '❌',
+            Ok(Guess::Repeated) | Err(_) => '🔁',
+        };
+        guess.react(ctx, reaction).await?;
+        message
+            .edit(ctx, EditMessage::new().embed(hangman_embed(&game)))
+            .await?;
+    }
+
+    let result = if game.is_won() {
+        format!(
+            "🎉 <@{}> solved it! The word was **{}**.",
+            last_guesser,
+            game.word()
+        )
+    } else {
+        format!("💀 Out of lives. The word was **{}**.", game.word())
+    };
+    msg.channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(result)
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+
+    Ok(())
+}
+
+fn option_letter(index: usize) -> char {
+    (b'A' + index as u8) as char
+}
+
+/// The embed of question `number` out of `total`. While it can be answered `closes_at` is when
+/// the time is up, once it is `None` the right answer and who got it are shown.
+fn trivia_embed(
+    round: &TriviaRound<UserId>,
+    number: usize,
+    total: usize,
+    closes_at: Option<i64>,
+) -> CreateEmbed {
+    let mut description = format!("{}\n\n", round.question.question);
+    for (index, option) in round.options.iter().enumerate() {
+        let mark = if closes_at.is_none() && index == round.correct {
+            " ✅"
+        } else {
+            ""
+        };
+        description.push_str(&format!(
+            "**{}.** {}{}\n",
+            option_letter(index),
+            option,
+            mark
+        ));
+    }
+    match closes_at {
+        Some(closes_at) => description.push_str(&format!("\nTime is up <t:{}:R>.", closes_at)),
+        None => {
+            let winners = round
+                .winners()
+                .iter()
+                .map(|winner| format!("<@{}>", winner))
+                .collect::<Vec<_>>();
+            description.push_str(&format!(
+                "\n{} answer(s), right: {}",
+                round.answer_count(),
+                if winners.is_empty() {
+                    String::from("nobody")
+                } else {
+                    winners.join(", ")
+                }
+            ));
+        }
+    }
+
+    let mut footer = format!("Question {} of {}", number, total);
+    if let Some(category) = &round.question.category {
+        footer.push_str(&format!(" · {}", category));
+    }
+    CreateEmbed::new()
+        .title("Trivia")
+        .description(description)
+        .footer(CreateEmbedFooter::new(footer))
+}
+
+fn trivia_components(round: &TriviaRound<UserId>) -> Vec<CreateActionRow> {
+    vec![CreateActionRow::Buttons(
+        (0..round.options.len())
+            .map(|index| {
+                CreateButton::new(format!("{}:trivia:{}", GAME_PREFIX, index))
+                    .label(option_letter(index).to_string())
+                    .style(ButtonStyle::Primary)
+            })
+            .collect(),
+    )]
+}
+
+#[command]
+#[description = "Asks trivia questions from the question bank to everyone in the channel. Every \
+question can be answered once within 20 seconds, each right answer scores a point."]
+#[usage = "[questions]"]
+#[example = "5"]
+async fn trivia(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let count = args.single::<usize>().unwrap_or(DEFAULT_QUESTIONS);
+    if count == 0 || count > MAX_QUESTIONS {
+        msg.reply(
+            ctx,
+            format!("A round has 1 to {} questions.", MAX_QUESTIONS),
+        )
+        .await?;
+        return Ok(());
+    }
+    run_game(ctx, msg, play_trivia(ctx, msg, count)).await
+}
+
+async fn play_trivia(ctx: &Context, msg: &Message, count: usize) -> CommandResult {
+    // Read on every game, so questions can be added without restarting the bot.
+    let bank = load_json::<Vec<TriviaQuestion>>(TRIVIA_PATH).await?;
+    let bank = bank
+        .into_iter()
+        .filter(|question| (1..=4).contains(&question.wrong.len()))
+        .collect::<Vec<_>>();
+    let questions = bank
+        .choose_multiple(&mut rand::thread_rng(), count)
+        .cloned()
+        .collect::<Vec<_>>();
+    if questions.is_empty() {
+        msg.reply(ctx, "The trivia question bank is empty.").await?;
+        return Ok(());
+    }
+
+    let total = questions.len();
+    let mut scores = HashMap::<UserId, usize>::new();
+    for (index, question) in questions.into_iter().enumerate() {
+        let mut round = TriviaRound::new(question, &mut rand::thread_rng());
+        let closes_at = Utc::now().timestamp() + QUESTION_TIME.as_secs() as i64;
+        let mut message = msg
+            .channel_id
+            .send_message(
+                &ctx.http,
+                CreateMessage::new()
+                    .embed(trivia_embed(&round, index + 1, total, Some(closes_at)))
+                    .components(trivia_components(&round)),
+            )
+            .await?;
+
+        let mut clicks = Box::pin(message.await_component_interaction(&ctx.shard).stream());
+        let deadline = Instant::now() + QUESTION_TIME;
+        while let Ok(Some(click)) = timeout_at(deadline, clicks.next()).await {
+            let option = clicked(&click).parse().unwrap_or(usize::MAX);
+            let content = match round.answer(click.user.id, option) {
+                Ok(()) => format!("You answered **{}**.", round.options[option]),
+                Err(why) => why.to_string(),
+            };
+            respond(ctx, &click, content).await?;
+        }
+
+        for winner in round.winners() {
+            *scores.entry(winner).or_default() += 1;
+        }
+        message
+            .edit(
+                ctx,
+                EditMessage::new()
+                    .embed(trivia_embed(&round, index + 1, total, None))
+                    .components(vec![]),
+            )
+            .await?;
+    }
+
+    if total > 1 {
+        let mut scores = scores.into_iter().collect::<Vec<_>>();
+        scores.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        let mut content = String::from("🏆 **Final scores**\n");
+        if scores.is_empty() {
+            content.push_str("Nobody answered right.");
+        }
+        for (place, (user, score)) in scores.iter().enumerate() {
+            content.push_str(&format!("{}. <@{}>: {}\n", place + 1, user, score));
+        }
+        msg.channel_id
+            .send_message(
+                &ctx.http,
+                CreateMessage::new()
+                    .content(content)
+                    .allowed_mentions(CreateAllowedMentions::new()),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
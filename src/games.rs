@@ -0,0 +1,484 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::hash::Hash;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MoveError {
+    /// The player is not part of the game.
+    NotPlaying,
+    NotYourTurn,
+    /// The cell is taken or the option does not exist.
+    InvalidMove,
+    AlreadyChosen,
+    GameOver,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveError::NotPlaying => f.write_str("You are not playing in this game."),
+            MoveError::NotYourTurn => f.write_str("It's not your turn."),
+            MoveError::InvalidMove => f.write_str("That move is not possible."),
+            MoveError::AlreadyChosen => f.write_str("You already made your choice."),
+            MoveError::GameOver => f.write_str("The game is over."),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Outcome<P> {
+    Ongoing,
+    Won(P),
+    Draw,
+}
+
+/// Tic-tac-toe. `P` identifies a player, the first one is X and starts.
+pub struct TicTacToe<P> {
+    pub players: [P; 2],
+    /// The cells row by row, with the index of the player who took them.
+    pub board: [Option<usize>; 9],
+    /// Index of the player whose turn it is.
+    pub turn: usize,
+    pub outcome: Outcome<P>,
+}
+
+const LINES: [[usize; 3]; 8] = [
+    [0, 1, 2],
+    [3, 4, 5],
+    [6, 7, 8],
+    [0, 3, 6],
+    [1, 4, 7],
+    [2, 5, 8],
+    [0, 4, 8],
+    [2, 4, 6],
+];
+
+impl<P: Copy + PartialEq> TicTacToe<P> {
+    pub fn new(first: P, second: P) -> Self {
+        TicTacToe {
+            players: [first, second],
+            board: [None; 9],
+            turn: 0,
+            outcome: Outcome::Ongoing,
+        }
+    }
+
+    pub fn current_player(&self) -> P {
+        self.players[self.turn]
+    }
+
+    /// `player` takes `cell`, numbered 0 to 8 row by row.
+    pub fn play(&mut self, player: P, cell: usize) -> Result<Outcome<P>, MoveError> {
+        if self.outcome != Outcome::Ongoing {
+            return Err(MoveError::GameOver);
+        }
+        if !self.players.contains(&player) {
+            return Err(MoveError::NotPlaying);
+        }
+        if player != self.current_player() {
+            return Err(MoveError::NotYourTurn);
+        }
+        match self.board.get(cell) {
+            Some(None) => self.board[cell] = Some(self.turn),
+            _ => return Err(MoveError::InvalidMove),
+        }
+
+        if LINES
+            .iter()
+            .any(|line| line.iter().all(|cell| self.board[*cell] == Some(self.turn)))
+        {
+            self.outcome = Outcome::Won(player);
+        } else if self.board.iter().all(Option::is_some) {
+            self.outcome = Outcome::Draw;
+        } else {
+            self.turn = 1 - self.turn;
+        }
+        Ok(self.outcome)
+    }
+
+    /// The symbol shown for `cell`.
+    pub fn symbol(&self, cell: usize) -> &'static str {
+        match self.board[cell] {
+            Some(0) => "X",
+            Some(_) => "O",
+            None => "\u{200b}",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Guess {
+    Hit,
+    Miss,
+    /// Letters and words that were already guessed change nothing.
+    Repeated,
+}
+
+/// Hangman, played by everyone in the channel together.
+pub struct Hangman {
+    word: String,
+    pub guessed: BTreeSet<char>,
+    pub wrong_guesses: u32,
+    pub max_wrong_guesses: u32,
+    solved: bool,
+}
+
+impl Hangman {
+    pub fn new(word: &str, max_wrong_guesses: u32) -> Self {
+        Hangman {
+            word: word.to_lowercase(),
+            guessed: BTreeSet::new(),
+            wrong_guesses: 0,
+            max_wrong_guesses,
+            solved: false,
+        }
+    }
+
+    pub fn word(&self) -> &str {
+        &self.word
+    }
+
+    /// Guesses a letter, or the whole word if `guess` is longer than one character.
+    pub fn guess(&mut self, guess: &str) -> Result<Guess, MoveError> {
+        if self.is_over() {
+            return Err(MoveError::GameOver);
+        }
+        let guess = guess.trim().to_lowercase();
+        let mut chars = guess.chars();
+        let result = match (chars.next(), chars.next()) {
+            (Some(letter), None) => {
+                if !self.guessed.insert(letter) {
+                    Guess::Repeated
+                } else if self.word.contains(letter) {
+                    Guess::Hit
+                } else {
+                    Guess::Miss
+                }
+            }
+            _ if guess == self.word => {
+                self.solved = true;
+                Guess::Hit
+            }
+            _ => Guess::Miss,
+        };
+        if result == Guess::Miss {
+            self.wrong_guesses += 1;
+        }
+        Ok(result)
+    }
+
+    /// The word with the letters that were not guessed yet hidden.
+    pub fn masked(&self) -> String {
+        self.word
+            .chars()
+            .map(|letter| {
+                if self.is_won() || !letter.is_alphabetic() || self.guessed.contains(&letter) {
+                    letter
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    }
+
+    pub fn is_won(&self) -> bool {
+        self.solved
+            || self
+                .word
+                .chars()
+                .all(|letter| !letter.is_alphabetic() || self.guessed.contains(&letter))
+    }
+
+    pub fn is_lost(&self) -> bool {
+        !self.is_won() && self.wrong_guesses >= self.max_wrong_guesses
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.is_won() || self.is_lost()
+    }
+}
+
+/// Extracts the guess from a message like `guess e` or `guess ferris`, so chatting in the
+/// channel does not count as guessing.
+pub fn parse_guess(content: &str) -> Option<&str> {
+    let (command, guess) = content.trim().split_once(char::is_whitespace)?;
+    let guess = guess.trim();
+    (command.eq_ignore_ascii_case("guess")
+        && !guess.is_empty()
+        && guess.chars().all(char::is_alphabetic))
+    .then_some(guess)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Hand {
+    Rock,
+    Paper,
+    Scissors,
+}
+
+impl Hand {
+    pub const ALL: [Hand; 3] = [Hand::Rock, Hand::Paper, Hand::Scissors];
+
+    pub fn beats(self, other: Hand) -> bool {
+        matches!(
+            (self, other),
+            (Hand::Rock, Hand::Scissors)
+                | (Hand::Paper, Hand::Rock)
+                | (Hand::Scissors, Hand::Paper)
+        )
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Hand::Rock => "rock",
+            Hand::Paper => "paper",
+            Hand::Scissors => "scissors",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Hand> {
+        Hand::ALL.into_iter().find(|hand| hand.name() == name)
+    }
+
+    pub fn emoji(self) -> &'static str {
+        match self {
+            Hand::Rock => "🪨",
+            Hand::Paper => "📄",
+            Hand::Scissors => "✂️",
+        }
+    }
+}
+
+/// Rock paper scissors. Both players choose in secret, the result is known once both did.
+pub struct RockPaperScissors<P> {
+    pub players: [P; 2],
+    pub hands: [Option<Hand>; 2],
+}
+
+impl<P: Copy + PartialEq> RockPaperScissors<P> {
+    pub fn new(first: P, second: P) -> Self {
+        RockPaperScissors {
+            players: [first, second],
+            hands: [None; 2],
+        }
+    }
+
+    /// Records the choice of `player`. Returns the outcome once both players chose.
+    pub fn choose(&mut self, player: P, hand: Hand) -> Result<Outcome<P>, MoveError> {
+        let index = self
+            .players
+            .iter()
+            .position(|playing| *playing == player)
+            .ok_or(MoveError::NotPlaying)?;
+        if self.hands[index].is_some() {
+            return Err(MoveError::AlreadyChosen);
+        }
+        self.hands[index] = Some(hand);
+        Ok(self.outcome())
+    }
+
+    pub fn outcome(&self) -> Outcome<P> {
+        match self.hands {
+            [Some(first), Some(second)] if first.beats(second) => Outcome::Won(self.players[0]),
+            [Some(first), Some(second)] if second.beats(first) => Outcome::Won(self.players[1]),
+            [Some(_), Some(_)] => Outcome::Draw,
+            _ => Outcome::Ongoing,
+        }
+    }
+}
+
+/// A question of the trivia bank.
+#[derive(Deserialize, Clone, Debug)]
+pub struct TriviaQuestion {
+    pub question: String,
+    pub answer: String,
+    /// Wrong answers shown next to the right one.
+    pub wrong: Vec<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+}
+
+/// One trivia question. Everyone gets one answer, the round ends when the time is up.
+pub struct TriviaRound<P> {
+    pub question: TriviaQuestion,
+    /// The right answer and the wrong ones, shuffled.
+    pub options: Vec<String>,
+    pub correct: usize,
+    answers: HashMap<P, usize>,
+    /// Players in the order they answered.
+    order: Vec<P>,
+}
+
+impl<P: Copy + Eq + Hash> TriviaRound<P> {
+    pub fn new<R: Rng + ?Sized>(question: TriviaQuestion, rng: &mut R) -> Self {
+        let mut options = question.wrong.clone();
+        options.push(question.answer.clone());
+        options.shuffle(rng);
+        let correct = options
+            .iter()
+            .position(|option| *option == question.answer)
+            .unwrap_or_default();
+        TriviaRound {
+            question,
+            options,
+            correct,
+            answers: HashMap::new(),
+            order: Vec::new(),
+        }
+    }
+
+    pub fn answer(&mut self, player: P, option: usize) -> Result<(), MoveError> {
+        if option >= self.options.len() {
+            return Err(MoveError::InvalidMove);
+        }
+        if self.answers.contains_key(&player) {
+            return Err(MoveError::AlreadyChosen);
+        }
+        self.answers.insert(player, option);
+        self.order.push(player);
+        Ok(())
+    }
+
+    /// Everyone who answered right, fastest first.
+    pub fn winners(&self) -> Vec<P> {
+        self.order
+            .iter()
+            .filter(|player| self.answers.get(player) == Some(&self.correct))
+            .copied()
+            .collect()
+    }
+
+    pub fn answer_count(&self) -> usize {
+        self.order.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tic_tac_toe_row_wins() {
+        let mut game = TicTacToe::new('a', 'b');
+        assert_eq!(game.play('a', 0), Ok(Outcome::Ongoing));
+        assert_eq!(game.play('b', 3), Ok(Outcome::Ongoing));
+        assert_eq!(game.play('a', 1), Ok(Outcome::Ongoing));
+        assert_eq!(game.play('b', 4), Ok(Outcome::Ongoing));
+        assert_eq!(game.play('a', 2), Ok(Outcome::Won('a')));
+        assert_eq!(game.symbol(0), "X");
+        assert_eq!(game.symbol(3), "O");
+        assert_eq!(game.play('b', 5), Err(MoveError::GameOver));
+    }
+
+    #[test]
+    fn tic_tac_toe_full_board_is_a_draw() {
+        let mut game = TicTacToe::new('a', 'b');
+        for cell in [0, 1, 2, 4, 3, 5, 7, 6] {
+            let player = game.current_player();
+            assert_eq!(game.play(player, cell), Ok(Outcome::Ongoing));
+        }
+        assert_eq!(game.play('a', 8), Ok(Outcome::Draw));
+    }
+
+    #[test]
+    fn tic_tac_toe_rejects_invalid_moves() {
+        let mut game = TicTacToe::new('a', 'b');
+        assert_eq!(game.play('c', 0), Err(MoveError::NotPlaying));
+        assert_eq!(game.play('b', 0), Err(MoveError::NotYourTurn));
+        assert_eq!(game.play('a', 9), Err(MoveError::InvalidMove));
+        assert_eq!(game.play('a', 4), Ok(Outcome::Ongoing));
+        assert_eq!(game.play('b', 4), Err(MoveError::InvalidMove));
+        assert_eq!(game.current_player(), 'b');
+    }
+
+    #[test]
+    fn hangman_letters_and_words() {
+        let mut game = Hangman::new("Ferris", 3);
+        assert_eq!(game.masked(), "______");
+        assert_eq!(game.guess("r"), Ok(Guess::Hit));
+        assert_eq!(game.guess("R"), Ok(Guess::Repeated));
+        assert_eq!(game.guess("x"), Ok(Guess::Miss));
+        assert_eq!(game.masked(), "__rr__");
+        assert_eq!(game.wrong_guesses, 1);
+        assert_eq!(game.guess("crab"), Ok(Guess::Miss));
+        assert_eq!(game.guess("ferris"), Ok(Guess::Hit));
+        assert!(game.is_won());
+        assert_eq!(game.masked(), "ferris");
+        assert_eq!(game.guess("e"), Err(MoveError::GameOver));
+    }
+
+    #[test]
+    fn hangman_is_won_by_letters_and_lost_by_misses() {
+        let mut game = Hangman::new("ab-ba", 2);
+        assert_eq!(game.masked(), "__-__");
+        game.guess("a").unwrap();
+        game.guess("b").unwrap();
+        assert!(game.is_won());
+
+        let mut game = Hangman::new("ab", 2);
+        game.guess("x").unwrap();
+        assert!(!game.is_over());
+        game.guess("y").unwrap();
+        assert!(game.is_lost());
+        assert!(!game.is_won());
+    }
+
+    #[test]
+    fn only_prefixed_messages_are_guesses() {
+        assert_eq!(parse_guess("guess e"), Some("e"));
+        assert_eq!(parse_guess("  Guess   ferris "), Some("ferris"));
+        assert_eq!(parse_guess("e"), None);
+        assert_eq!(parse_guess("guess"), None);
+        assert_eq!(parse_guess("guess 1"), None);
+        assert_eq!(parse_guess("guess two words"), None);
+        assert_eq!(parse_guess("i guess so"), None);
+    }
+
+    #[test]
+    fn rock_paper_scissors() {
+        let mut game = RockPaperScissors::new('a', 'b');
+        assert_eq!(game.choose('a', Hand::Rock), Ok(Outcome::Ongoing));
+        assert_eq!(game.choose('a', Hand::Paper), Err(MoveError::AlreadyChosen));
+        assert_eq!(game.choose('c', Hand::Paper), Err(MoveError::NotPlaying));
+        assert_eq!(game.choose('b', Hand::Scissors), Ok(Outcome::Won('a')));
+
+        let mut game = RockPaperScissors::new('a', 'b');
+        game.choose('b', Hand::Paper).unwrap();
+        assert_eq!(game.choose('a', Hand::Paper), Ok(Outcome::Draw));
+
+        for hand in Hand::ALL {
+            assert!(!hand.beats(hand));
+            assert_eq!(Hand::from_name(hand.name()), Some(hand));
+        }
+        assert!(Hand::Paper.beats(Hand::Rock));
+        assert!(Hand::Scissors.beats(Hand::Paper));
+    }
+
+    #[test]
+    fn trivia_winners_are_the_right_answers_in_order() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let question = TriviaQuestion {
+            question: String::from("Which crab is the Rust mascot?"),
+            answer: String::from("Ferris"),
+            wrong: vec![String::from("Corro"), String::from("Bob")],
+            category: None,
+        };
+        let mut round = TriviaRound::new(question, &mut StdRng::seed_from_u64(1));
+        assert_eq!(round.options.len(), 3);
+        assert_eq!(round.options[round.correct], "Ferris");
+        let wrong = (round.correct + 1) % 3;
+
+        assert_eq!(round.answer('a', wrong), Ok(()));
+        assert_eq!(round.answer('b', round.correct), Ok(()));
+        assert_eq!(round.answer('c', round.correct), Ok(()));
+        assert_eq!(round.answer('b', wrong), Err(MoveError::AlreadyChosen));
+        assert_eq!(round.answer('d', 3), Err(MoveError::InvalidMove));
+        assert_eq!(round.winners(), vec!['b', 'c']);
+        assert_eq!(round.answer_count(), 3);
+    }
+}
@@ -1,10 +1,10 @@
 use crate::command_base::*;
+use crate::paginator::Paginator;
 
 use rand::seq::IteratorRandom;
 use serenity::futures::StreamExt;
 use serenity::model::id::ChannelId;
 use serenity::utils::{content_safe, ContentSafeOptions};
-use std::fmt::Write;
 
 #[group]
 #[commands(say, commands, roll, meme)]
@@ -16,18 +16,23 @@ pub struct General;
 // Make this command use the "complicated" bucket.
 #[bucket = "complicated"]
 async fn commands(ctx: &Context, msg: &Message) -> CommandResult {
-    let mut contents = "Commands used:\n".to_string();
-
-    let data = ctx.data.read().await;
-    let counter = data
-        .get::<CommandCounter>()
-        .expect("Expected CommandCounter in TypeMap.");
-
-    for (name, amount) in counter {
-        writeln!(contents, "- {name}: {amount}")?;
-    }
+    let mut counts = {
+        let data = ctx.data.read().await;
+        data.get::<CommandCounter>()
+            .expect("Expected CommandCounter in TypeMap.")
+            .iter()
+            .map(|(name, amount)| (name.clone(), *amount))
+            .collect::<Vec<_>>()
+    };
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let entries = counts
+        .iter()
+        .map(|(name, amount)| format!("- {name}: {amount}"))
+        .collect();
 
-    msg.channel_id.say(&ctx.http, &contents).await?;
+    Paginator::new("Commands used", entries)
+        .send(ctx, msg)
+        .await?;
 
     Ok(())
 }
@@ -104,15 +109,41 @@ async fn zitat(ctx: &Context, msg: &Message) -> CommandResult {
 }
 
 #[command]
+#[description = "Rolls a die with the given number of sides. In a server you can bet on the \
+result: guessing right pays the bet times the number of sides."]
+#[usage = "<sides> [bet <amount> <guess>]"]
+#[example = "6 bet 50 4"]
 async fn roll(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
     let roll = match args.single::<u32>() {
-        Ok(roll) => roll,
-        Err(_) => {
+        Ok(roll) if roll >= 1 => roll,
+        _ => {
             msg.reply(ctx, "You need to provide a number!").await?;
             return Err("No number supplied".into());
         }
     };
 
+    if args.current() == Some("bet") {
+        args.advance();
+        let (Ok(amount), Ok(guess)) = (args.single::<u64>(), args.single::<u32>()) else {
+            msg.reply(ctx, "Usage: `roll <sides> bet <amount> <guess>`")
+                .await?;
+            return Ok(());
+        };
+        if roll < 2 || !(1..=roll).contains(&guess) {
+            msg.reply(
+                ctx,
+                "Betting needs at least 2 sides and a guess between 1 and the number of sides.",
+            )
+            .await?;
+            return Ok(());
+        }
+        if msg.guild_id.is_none() {
+            msg.reply(ctx, "Betting only works in servers.").await?;
+            return Ok(());
+        }
+        return crate::economy_commands::bet_on_roll(ctx, msg, roll, guess, amount).await;
+    }
+
     let result = rand::thread_rng().gen_range(1..=roll);
 
     msg.reply(ctx, format!("You rolled a {}!", result)).await?;
@@ -0,0 +1,202 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::RoleId;
+use std::fmt;
+
+/// Who may enter a giveaway.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(default)]
+pub struct Eligibility {
+    /// Entrants need this role.
+    pub required_role: Option<RoleId>,
+    /// Entrants must not have any of these roles.
+    pub excluded_roles: Vec<RoleId>,
+    /// Minimum age of the Discord account, in seconds.
+    pub min_account_age: i64,
+    /// Minimum time since joining the server, in seconds.
+    pub min_member_age: i64,
+}
+
+/// Why someone may not enter a giveaway.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Ineligible {
+    MissingRole(RoleId),
+    ExcludedRole(RoleId),
+    AccountTooNew,
+    MemberTooNew,
+}
+
+impl fmt::Display for Ineligible {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ineligible::MissingRole(role) => write!(f, "You need the <@&{}> role to enter.", role),
+            Ineligible::ExcludedRole(role) => {
+                write!(f, "Members with the <@&{}> role can't enter.", role)
+            }
+            Ineligible::AccountTooNew => f.write_str("Your account is too new to enter."),
+            Ineligible::MemberTooNew => {
+                f.write_str("You haven't been on this server long enough to enter.")
+            }
+        }
+    }
+}
+
+impl Eligibility {
+    /// Checks a member with `roles`, whose account is `account_age` and who joined
+    /// `member_age` ago, both in seconds.
+    pub fn check(
+        &self,
+        roles: &[RoleId],
+        account_age: i64,
+        member_age: i64,
+    ) -> Result<(), Ineligible> {
+        if let Some(role) = self.required_role {
+            if !roles.contains(&role) {
+                return Err(Ineligible::MissingRole(role));
+            }
+        }
+        if let Some(role) = self.excluded_roles.iter().find(|role| roles.contains(role)) {
+            return Err(Ineligible::ExcludedRole(*role));
+        }
+        if account_age < self.min_account_age {
+            return Err(Ineligible::AccountTooNew);
+        }
+        if member_age < self.min_member_age {
+            return Err(Ineligible::MemberTooNew);
+        }
+        Ok(())
+    }
+
+    /// A short description of the rules, for the giveaway embed.
+    pub fn describe(&self) -> Vec<String> {
+        let mut rules = Vec::new();
+        if let Some(role) = self.required_role {
+            rules.push(format!("Requires <@&{}>", role));
+        }
+        if !self.excluded_roles.is_empty() {
+            let roles = self
+                .excluded_roles
+                .iter()
+                .map(|role| format!("<@&{}>", role))
+                .collect::<Vec<_>>();
+            rules.push(format!("Excludes {}", roles.join(", ")));
+        }
+        if self.min_account_age > 0 {
+            rules.push(format!(
+                "Account at least {} old",
+                format_age(self.min_account_age)
+            ));
+        }
+        if self.min_member_age > 0 {
+            rules.push(format!(
+                "Member for at least {}",
+                format_age(self.min_member_age)
+            ));
+        }
+        rules
+    }
+}
+
+fn format_age(seconds: i64) -> String {
+    match seconds {
+        s if s % (24 * 60 * 60) == 0 => format!("{} day(s)", s / (24 * 60 * 60)),
+        s if s % (60 * 60) == 0 => format!("{} hour(s)", s / (60 * 60)),
+        s => format!("{} minute(s)", s / 60),
+    }
+}
+
+/// Draws up to `count` distinct winners from `entries`, leaving out everyone in `excluded`, for
+/// example the winners of an earlier draw. Every entry has the same chance. Pass a
+/// cryptographically secure `rng` for real giveaways and a seeded one to reproduce a draw.
+pub fn draw_winners<U: Clone + PartialEq, R: Rng + ?Sized>(
+    entries: &[U],
+    excluded: &[U],
+    count: usize,
+    rng: &mut R,
+) -> Vec<U> {
+    let candidates = entries
+        .iter()
+        .filter(|entry| !excluded.contains(entry))
+        .collect::<Vec<_>>();
+    candidates
+        .choose_multiple(rng, count)
+        .map(|entry| (*entry).clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::HashSet;
+
+    #[test]
+    fn winners_are_distinct_entries() {
+        let entries = (0..50).collect::<Vec<u32>>();
+        for seed in 0..20 {
+            let winners = draw_winners(&entries, &[], 10, &mut StdRng::seed_from_u64(seed));
+            assert_eq!(winners.len(), 10);
+            assert_eq!(winners.iter().collect::<HashSet<_>>().len(), 10);
+            assert!(winners.iter().all(|winner| entries.contains(winner)));
+        }
+    }
+
+    #[test]
+    fn previous_winners_are_excluded() {
+        let entries = (0..10).collect::<Vec<u32>>();
+        let excluded = [1, 3, 5, 7];
+        for seed in 0..20 {
+            let winners = draw_winners(&entries, &excluded, 3, &mut StdRng::seed_from_u64(seed));
+            assert_eq!(winners.len(), 3);
+            assert!(winners.iter().all(|winner| !excluded.contains(winner)));
+        }
+    }
+
+    #[test]
+    fn fewer_entrants_than_winners() {
+        let entries = [1u32, 2, 3];
+        let mut winners = draw_winners(&entries, &[2], 5, &mut StdRng::seed_from_u64(7));
+        winners.sort();
+        assert_eq!(winners, vec![1, 3]);
+        assert!(draw_winners(&entries, &entries, 5, &mut StdRng::seed_from_u64(7)).is_empty());
+        assert!(draw_winners::<u32, _>(&[], &[], 5, &mut StdRng::seed_from_u64(7)).is_empty());
+    }
+
+    #[test]
+    fn the_same_seed_draws_the_same_winners() {
+        let entries = (0..100).collect::<Vec<u32>>();
+        let first = draw_winners(&entries, &[], 5, &mut StdRng::seed_from_u64(42));
+        let second = draw_winners(&entries, &[], 5, &mut StdRng::seed_from_u64(42));
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn eligibility_rules() {
+        let eligibility = Eligibility {
+            required_role: Some(RoleId::new(1)),
+            excluded_roles: vec![RoleId::new(2)],
+            min_account_age: 100,
+            min_member_age: 50,
+        };
+        let roles = [RoleId::new(1)];
+        assert_eq!(eligibility.check(&roles, 100, 50), Ok(()));
+        assert_eq!(
+            eligibility.check(&[], 100, 50),
+            Err(Ineligible::MissingRole(RoleId::new(1)))
+        );
+        assert_eq!(
+            eligibility.check(&[RoleId::new(1), RoleId::new(2)], 100, 50),
+            Err(Ineligible::ExcludedRole(RoleId::new(2)))
+        );
+        assert_eq!(
+            eligibility.check(&roles, 99, 50),
+            Err(Ineligible::AccountTooNew)
+        );
+        assert_eq!(
+            eligibility.check(&roles, 100, 49),
+            Err(Ineligible::MemberTooNew)
+        );
+    }
+}
@@ -0,0 +1,594 @@
+use crate::command_base::*;
+use crate::giveaway::{draw_winners, Eligibility};
+use crate::paginator::Paginator;
+use crate::scheduler::SchedulerContext;
+use crate::storage::save_json;
+use crate::time_parse::parse_duration;
+
+use chrono::{DateTime, Utc};
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use serenity::all::{
+    ButtonStyle, ComponentInteraction, CreateActionRow, CreateAllowedMentions, CreateButton,
+    CreateEmbed, CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseMessage,
+    CreateMessage, EditMessage, MessageReference, Timestamp,
+};
+use serenity::cache::Cache;
+use serenity::futures::StreamExt;
+use serenity::http::Http;
+use serenity::model::id::{ChannelId, GuildId, MessageId, RoleId, UserId};
+use serenity::utils::parse_role_mention;
+use std::collections::BTreeSet;
+
+pub const GIVEAWAYS_PATH: &str = "data/giveaways.json";
+pub const GIVEAWAY_PREFIX: &str = "giveaway";
+const MAX_WINNERS: usize = 20;
+const MAX_DURATION_SECS: i64 = 60 * 24 * 60 * 60;
+/// Ended giveaways can be rerolled for this long before they are forgotten.
+const KEEP_ENDED_SECS: i64 = 7 * 24 * 60 * 60;
+/// How many uncached members of a giveaway are fetched at once.
+const MEMBER_FETCHES: usize = 10;
+
+#[group]
+#[prefixes("giveaway")]
+#[commands(start, end, reroll, list)]
+#[required_permissions(MANAGE_GUILD)]
+#[only_in(guilds)]
+pub struct Giveaway;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StoredGiveaway {
+    pub guild: GuildId,
+    pub channel: ChannelId,
+    pub host: UserId,
+    pub prize: String,
+    pub winner_count: usize,
+    /// Unix timestamp in seconds. For ended giveaways, when they ended.
+    pub ends_at: i64,
+    pub eligibility: Eligibility,
+    pub entries: BTreeSet<UserId>,
+    /// Everyone drawn so far, including rerolls.
+    pub winners: Vec<UserId>,
+    pub ended: bool,
+}
+
+impl StoredGiveaway {
+    fn embed(&self) -> CreateEmbed {
+        let mut description = format!("Hosted by <@{}>\n", self.host);
+        if self.ended {
+            let winners = if self.winners.is_empty() {
+                String::from("nobody")
+            } else {
+                mentions(&self.winners)
+            };
+            description.push_str(&format!(
+                "Ended <t:{}:R>\nWinners: {}\n",
+                self.ends_at, winners
+            ));
+        } else {
+            description.push_str(&format!(
+                "Ends <t:{}:R> (<t:{}:f>)\nWinners: {}\n",
+                self.ends_at, self.ends_at, self.winner_count
+            ));
+        }
+        for rule in self.eligibility.describe() {
+            description.push_str(&format!("• {}\n", rule));
+        }
+        CreateEmbed::new()
+            .title(format!("🎉 {}", self.prize))
+            .description(description)
+            .footer(CreateEmbedFooter::new(format!(
+                "{} entries",
+                self.entries.len()
+            )))
+    }
+
+    fn components(&self) -> Vec<CreateActionRow> {
+        if self.ended {
+            return vec![];
+        }
+        vec![CreateActionRow::Buttons(vec![CreateButton::new(format!(
+            "{}:enter",
+            GIVEAWAY_PREFIX
+        ))
+        .label("Enter")
+        .emoji('🎉')
+        .style(ButtonStyle::Primary)])]
+    }
+}
+
+/// Giveaways by the message they were posted as.
+pub struct Giveaways;
+
+impl TypeMapKey for Giveaways {
+    type Value = HashMap<MessageId, StoredGiveaway>;
+}
+
+async fn save_giveaways(data: &RwLock<TypeMap>) -> CommandResult {
+    let giveaways = {
+        let data = data.read().await;
+        data.get::<Giveaways>()
+            .expect("Expected Giveaways in TypeMap.")
+            .clone()
+    };
+    save_json(GIVEAWAYS_PATH, &giveaways).await?;
+    Ok(())
+}
+
+fn mentions(users: &[UserId]) -> String {
+    users
+        .iter()
+        .map(|user| format!("<@{}>", user))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn parse_role(arg: &str) -> Option<RoleId> {
+    parse_role_mention(arg).or_else(|| arg.parse().ok())
+}
+
+/// The entries that are still eligible: members can leave the server or lose roles after
+/// entering. Members missing from the cache are fetched, those who left are left out.
+async fn eligible_entries(http: &Http, cache: &Cache, giveaway: &StoredGiveaway) -> Vec<UserId> {
+    let mut roles = Vec::new();
+    let mut uncached = Vec::new();
+    for user in &giveaway.entries {
+        let cached = cache
+            .guild(giveaway.guild)
+            .and_then(|guild| guild.members.get(user).map(|member| member.roles.clone()));
+        match cached {
+            Some(cached) => roles.push((*user, cached)),
+            None => uncached.push(*user),
+        }
+    }
+    let fetched = serenity::futures::stream::iter(uncached)
+        .map(|user| async move {
+            http.get_member(giveaway.guild, user)
+                .await
+                .ok()
+                .map(|member| (user, member.roles))
+        })
+        .buffer_unordered(MEMBER_FETCHES)
+        .filter_map(|member| async move { member })
+        .collect::<Vec<_>>()
+        .await;
+    roles.extend(fetched);
+    roles
+        .into_iter()
+        .filter(|(_, roles)| {
+            giveaway
+                .eligibility
+                .check(roles, i64::MAX, i64::MAX)
+                .is_ok()
+        })
+        .map(|(user, _)| user)
+        .collect()
+}
+
+/// Ends the giveaway posted as `message` if it is still running and returns it. Rerolls, with a
+/// `count`, also get giveaways that already ended.
+async fn end_giveaway(
+    data: &RwLock<TypeMap>,
+    message: MessageId,
+    count: Option<usize>,
+) -> Option<StoredGiveaway> {
+    let mut data = data.write().await;
+    data.get_mut::<Giveaways>()
+        .expect("Expected Giveaways in TypeMap.")
+        .get_mut(&message)
+        // Ending twice must not draw twice, only rerolls draw from an ended giveaway.
+        .filter(|giveaway| count.is_some() || !giveaway.ended)
+        .map(|giveaway| {
+            if !giveaway.ended {
+                giveaway.ended = true;
+                giveaway.ends_at = giveaway.ends_at.min(Utc::now().timestamp());
+            }
+            giveaway.clone()
+        })
+}
+
+/// Draws `count` new winners of the ended `giveaway` posted as `message` and announces them.
+async fn draw_ended(
+    http: &Http,
+    cache: &Cache,
+    data: &RwLock<TypeMap>,
+    message: MessageId,
+    giveaway: StoredGiveaway,
+    count: Option<usize>,
+) -> CommandResult {
+    let entries = eligible_entries(http, cache, &giveaway).await;
+    let count = count.unwrap_or(giveaway.winner_count);
+    // Drawn under the lock, so concurrent rerolls see each other's winners.
+    let (giveaway, winners) = {
+        let mut data = data.write().await;
+        let stored = data
+            .get_mut::<Giveaways>()
+            .expect("Expected Giveaways in TypeMap.")
+            .get_mut(&message);
+        match stored {
+            Some(stored) => {
+                let winners = draw_winners(&entries, &stored.winners, count, &mut OsRng);
+                stored.winners.extend(&winners);
+                (stored.clone(), winners)
+            }
+            None => {
+                let winners = draw_winners(&entries, &giveaway.winners, count, &mut OsRng);
+                (giveaway, winners)
+            }
+        }
+    };
+    save_giveaways(data).await?;
+
+    giveaway
+        .channel
+        .edit_message(
+            http,
+            message,
+            EditMessage::new()
+                .embed(giveaway.embed())
+                .components(giveaway.components()),
+        )
+        .await?;
+    let content = if giveaway.winners.is_empty() {
+        format!("Nobody eligible entered **{}**.", giveaway.prize)
+    } else if winners.is_empty() {
+        format!("Nobody else is eligible to win **{}**.", giveaway.prize)
+    } else {
+        format!(
+            "🎉 Congratulations {}, you won **{}**!",
+            mentions(&winners),
+            giveaway.prize
+        )
+    };
+    giveaway
+        .channel
+        .send_message(
+            http,
+            CreateMessage::new()
+                .content(content)
+                .reference_message(MessageReference::from((giveaway.channel, message)))
+                .allowed_mentions(CreateAllowedMentions::new().users(winners)),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Draws `count` new winners of the giveaway posted as `message` and announces them. Ends the
+/// giveaway first if it is still running.
+async fn draw(
+    http: &Http,
+    cache: &Cache,
+    data: &RwLock<TypeMap>,
+    message: MessageId,
+    count: Option<usize>,
+) -> CommandResult {
+    match end_giveaway(data, message, count).await {
+        Some(giveaway) => draw_ended(http, cache, data, message, giveaway, count).await,
+        None => Ok(()),
+    }
+}
+
+/// Ends every giveaway whose time is up at `now` and forgets old ones. The draws run in their own
+/// tasks, fetching the members of a big giveaway would hold up the other jobs.
+pub async fn run_due(scheduler: &SchedulerContext, now: DateTime<Utc>) -> CommandResult {
+    let (due, forgotten) = {
+        let mut data = scheduler.data.write().await;
+        let giveaways = data
+            .get_mut::<Giveaways>()
+            .expect("Expected Giveaways in TypeMap.");
+        let count = giveaways.len();
+        giveaways.retain(|_, giveaway| {
+            !giveaway.ended || giveaway.ends_at + KEEP_ENDED_SECS > now.timestamp()
+        });
+        let due = giveaways
+            .iter()
+            .filter(|(_, giveaway)| !giveaway.ended && giveaway.ends_at <= now.timestamp())
+            .map(|(message, _)| *message)
+            .collect::<Vec<_>>();
+        (due, count != giveaways.len())
+    };
+    if forgotten {
+        save_giveaways(&scheduler.data).await?;
+    }
+    for message in due {
+        // Ended before the task starts, so the next tick does not pick it up again.
+        let Some(giveaway) = end_giveaway(&scheduler.data, message, None).await else {
+            continue;
+        };
+        let http = Arc::clone(&scheduler.http);
+        let cache = Arc::clone(&scheduler.cache);
+        let data = Arc::clone(&scheduler.data);
+        tokio::spawn(async move {
+            if let Err(why) = draw_ended(&http, &cache, &data, message, giveaway, None).await {
+                println!("Could not end giveaway {message}: {why:?}");
+            }
+        });
+    }
+    Ok(())
+}
+
+async fn respond(
+    ctx: &Context,
+    component: &ComponentInteraction,
+    content: String,
+) -> CommandResult {
+    component
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Handles a click on the enter button. Clicking again leaves the giveaway.
+pub async fn handle_interaction(ctx: &Context, component: &ComponentInteraction) -> CommandResult {
+    let Some(member) = &component.member else {
+        return Ok(());
+    };
+    let eligibility = {
+        let data = ctx.data.read().await;
+        data.get::<Giveaways>()
+            .expect("Expected Giveaways in TypeMap.")
+            .get(&component.message.id)
+            .filter(|giveaway| !giveaway.ended)
+            .map(|giveaway| {
+                (
+                    giveaway.eligibility.clone(),
+                    giveaway.entries.contains(&member.user.id),
+                )
+            })
+    };
+    let Some((eligibility, entered)) = eligibility else {
+        return respond(ctx, component, String::from("This giveaway has ended.")).await;
+    };
+
+    if !entered {
+        let now = Timestamp::now().unix_timestamp();
+        let account_age = now - member.user.id.created_at().unix_timestamp();
+        let member_age = member
+            .joined_at
+            .map_or(0, |joined| now - joined.unix_timestamp());
+        if let Err(reason) = eligibility.check(&member.roles, account_age, member_age) {
+            return respond(ctx, component, reason.to_string()).await;
+        }
+    }
+
+    let giveaway = {
+        let mut data = ctx.data.write().await;
+        data.get_mut::<Giveaways>()
+            .expect("Expected Giveaways in TypeMap.")
+            .get_mut(&component.message.id)
+            .map(|giveaway| {
+                if entered {
+                    giveaway.entries.remove(&member.user.id);
+                } else {
+                    giveaway.entries.insert(member.user.id);
+                }
+                giveaway.clone()
+            })
+    };
+    let Some(giveaway) = giveaway else {
+        return Ok(());
+    };
+    save_giveaways(&ctx.data).await?;
+
+    let content = if entered {
+        "You left the giveaway."
+    } else {
+        "You entered the giveaway, good luck!"
+    };
+    respond(ctx, component, String::from(content)).await?;
+    component
+        .channel_id
+        .edit_message(
+            &ctx.http,
+            component.message.id,
+            EditMessage::new().embed(giveaway.embed()),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Starts a giveaway that members enter with a button. Entry can be limited with \
+`--role`, `--exclude` (repeatable), `--account-age` and `--member-age`."]
+#[usage = "<duration> <winners> [winners] <prize> [--role <@role>] [--exclude <@role>] \
+[--account-age <time>] [--member-age <time>]"]
+#[example = "3d 2 winners Discord Nitro --account-age 30d"]
+async fn start(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let usage = "Usage: `giveaway start <duration> <winners> [winners] <prize> [--role <@role>] \
+        [--exclude <@role>] [--account-age <time>] [--member-age <time>]`";
+    let duration = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_duration(&[&arg]))
+        .map(|(seconds, _)| seconds)
+        .filter(|seconds| *seconds <= MAX_DURATION_SECS);
+    let winner_count = args
+        .single::<usize>()
+        .ok()
+        .filter(|count| (1..=MAX_WINNERS).contains(count));
+    let (Some(duration), Some(winner_count)) = (duration, winner_count) else {
+        msg.reply(ctx, usage).await?;
+        return Ok(());
+    };
+
+    let mut prize = Vec::new();
+    let mut eligibility = Eligibility::default();
+    while !args.is_empty() {
+        let arg = args.single_quoted::<String>()?;
+        match arg.as_str() {
+            "--role" | "--exclude" => {
+                let Some(role) = args
+                    .single_quoted::<String>()
+                    .ok()
+                    .and_then(|arg| parse_role(&arg))
+                else {
+                    msg.reply(ctx, format!("`{}` needs a role.", arg)).await?;
+                    return Ok(());
+                };
+                if arg == "--role" {
+                    eligibility.required_role = Some(role);
+                } else {
+                    eligibility.excluded_roles.push(role);
+                }
+            }
+            "--account-age" | "--member-age" => {
+                let Some(seconds) = args
+                    .single_quoted::<String>()
+                    .ok()
+                    .and_then(|arg| parse_duration(&[&arg]))
+                    .map(|(seconds, _)| seconds)
+                else {
+                    msg.reply(ctx, format!("`{}` needs a time like `7d`.", arg))
+                        .await?;
+                    return Ok(());
+                };
+                if arg == "--account-age" {
+                    eligibility.min_account_age = seconds;
+                } else {
+                    eligibility.min_member_age = seconds;
+                }
+            }
+            "winner" | "winners" if prize.is_empty() => {}
+            _ => prize.push(arg),
+        }
+    }
+    if prize.is_empty() {
+        msg.reply(ctx, usage).await?;
+        return Ok(());
+    }
+
+    let giveaway = StoredGiveaway {
+        guild: guild_id,
+        channel: msg.channel_id,
+        host: msg.author.id,
+        prize: prize.join(" ").chars().take(200).collect(),
+        winner_count,
+        ends_at: Utc::now().timestamp() + duration,
+        eligibility,
+        entries: BTreeSet::new(),
+        winners: Vec::new(),
+        ended: false,
+    };
+    let message = msg
+        .channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .embed(giveaway.embed())
+                .components(giveaway.components()),
+        )
+        .await?;
+    {
+        let mut data = ctx.data.write().await;
+        data.get_mut::<Giveaways>()
+            .expect("Expected Giveaways in TypeMap.")
+            .insert(message.id, giveaway);
+    }
+    save_giveaways(&ctx.data).await?;
+
+    Ok(())
+}
+
+/// The giveaway message given as an id or replied to, if it belongs to this guild.
+async fn find_giveaway(
+    ctx: &Context,
+    msg: &Message,
+    args: &mut Args,
+) -> Option<(MessageId, StoredGiveaway)> {
+    let message = args
+        .single::<MessageId>()
+        .ok()
+        .or_else(|| msg.referenced_message.as_ref().map(|message| message.id))?;
+    let data = ctx.data.read().await;
+    data.get::<Giveaways>()
+        .expect("Expected Giveaways in TypeMap.")
+        .get(&message)
+        .filter(|giveaway| Some(giveaway.guild) == msg.guild_id)
+        .map(|giveaway| (message, giveaway.clone()))
+}
+
+#[command]
+#[description = "Ends a giveaway early and draws its winners."]
+#[usage = "<message id>"]
+async fn end(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Some((message, giveaway)) = find_giveaway(ctx, msg, &mut args).await else {
+        msg.reply(ctx, "Reply to a giveaway or give the id of its message.")
+            .await?;
+        return Ok(());
+    };
+    if giveaway.ended {
+        msg.reply(
+            ctx,
+            "That giveaway already ended, use `giveaway reroll` to draw again.",
+        )
+        .await?;
+        return Ok(());
+    }
+    draw(&ctx.http, &ctx.cache, &ctx.data, message, None).await
+}
+
+#[command]
+#[description = "Draws new winners for an ended giveaway, leaving out everyone who already won."]
+#[usage = "<message id> [count]"]
+async fn reroll(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Some((message, giveaway)) = find_giveaway(ctx, msg, &mut args).await else {
+        msg.reply(ctx, "Reply to a giveaway or give the id of its message.")
+            .await?;
+        return Ok(());
+    };
+    if !giveaway.ended {
+        msg.reply(
+            ctx,
+            "That giveaway is still running, use `giveaway end` to end it now.",
+        )
+        .await?;
+        return Ok(());
+    }
+    let count = args.single::<usize>().unwrap_or(1).clamp(1, MAX_WINNERS);
+    draw(&ctx.http, &ctx.cache, &ctx.data, message, Some(count)).await
+}
+
+#[command]
+#[description = "Lists the running giveaways of this server."]
+async fn list(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let giveaways = {
+        let data = ctx.data.read().await;
+        data.get::<Giveaways>()
+            .expect("Expected Giveaways in TypeMap.")
+            .iter()
+            .filter(|(_, giveaway)| giveaway.guild == guild_id && !giveaway.ended)
+            .map(|(message, giveaway)| (*message, giveaway.clone()))
+            .collect::<Vec<_>>()
+    };
+    if giveaways.is_empty() {
+        msg.reply(ctx, "There are no running giveaways.").await?;
+        return Ok(());
+    }
+
+    let entries = giveaways
+        .into_iter()
+        .map(|(message, giveaway)| {
+            format!(
+                "**{}** in <#{}>, ends <t:{}:R>, {} entries (`{}`)",
+                giveaway.prize,
+                giveaway.channel,
+                giveaway.ends_at,
+                giveaway.entries.len(),
+                message
+            )
+        })
+        .collect();
+    Paginator::new("Running giveaways", entries)
+        .send(ctx, msg)
+        .await
+}
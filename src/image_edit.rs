@@ -0,0 +1,404 @@
+use ab_glyph::{FontRef, PxScale};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageReader, Limits, Rgba, RgbaImage};
+use imageproc::drawing::{
+    draw_filled_ellipse_mut, draw_filled_rect_mut, draw_polygon_mut, draw_text_mut, text_size,
+};
+use imageproc::point::Point;
+use imageproc::rect::Rect;
+use std::io::Cursor;
+
+/// Largest input file that will be downloaded for editing.
+pub const MAX_INPUT_BYTES: u64 = 8 * 1024 * 1024;
+/// Inputs are scaled down so that their longest side is at most this many pixels, which keeps
+/// every transform fast enough to run on the bot's machine.
+pub const MAX_OUTPUT_SIDE: u32 = 1024;
+/// Refuse to even decode images bigger than this, to protect against decompression bombs.
+const MAX_DECODE_SIDE: u32 = 8192;
+/// Most memory the decoder may use. This caps the number of pixels, and with it how long the
+/// edits can run, which is about 16 million for RGBA images.
+const MAX_DECODE_BYTES: u64 = 64 * 1024 * 1024;
+/// Strongest blur, the time it takes grows with the strength.
+pub const MAX_BLUR: f32 = 20.0;
+/// Longer captions are cut off, every line is drawn many times for the outline.
+const MAX_CAPTION_CHARS: usize = 200;
+
+static FONT_DATA: &[u8] = include_bytes!("../assets/DejaVuSans-Bold.ttf");
+
+pub enum FlipAxis {
+    Horizontal,
+    Vertical,
+}
+
+pub enum Transform {
+    Caption { top: String, bottom: String },
+    Invert,
+    Greyscale,
+    Blur(f32),
+    DeepFry,
+    Rotate(u16),
+    Flip(FlipAxis),
+    SpeechBubble,
+}
+
+/// Decodes `bytes`, applies `transform` and returns the result encoded as PNG.
+pub fn apply(bytes: &[u8], transform: &Transform) -> image::ImageResult<Vec<u8>> {
+    let img = shrink(decode(bytes)?);
+    let edited = match transform {
+        Transform::Caption { top, bottom } => caption(img, top, bottom),
+        Transform::Invert => {
+            let mut img = img;
+            img.invert();
+            img
+        }
+        Transform::Greyscale => img.grayscale(),
+        Transform::Blur(sigma) if sigma.is_nan() => img,
+        Transform::Blur(sigma) => img.blur(sigma.clamp(1.0, MAX_BLUR)),
+        Transform::DeepFry => deep_fry(img)?,
+        Transform::Rotate(90) => img.rotate90(),
+        Transform::Rotate(180) => img.rotate180(),
+        Transform::Rotate(270) => img.rotate270(),
+        Transform::Rotate(_) => img,
+        Transform::Flip(FlipAxis::Horizontal) => img.fliph(),
+        Transform::Flip(FlipAxis::Vertical) => img.flipv(),
+        Transform::SpeechBubble => speech_bubble(img),
+    };
+
+    let mut out = Vec::new();
+    edited.write_to(&mut Cursor::new(&mut out), ImageFormat::Png)?;
+    Ok(out)
+}
+
+fn decode(bytes: &[u8]) -> image::ImageResult<DynamicImage> {
+    let mut reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DECODE_SIDE);
+    limits.max_image_height = Some(MAX_DECODE_SIDE);
+    limits.max_alloc = Some(MAX_DECODE_BYTES);
+    reader.limits(limits);
+    reader.decode()
+}
+
+fn shrink(img: DynamicImage) -> DynamicImage {
+    if img.width() > MAX_OUTPUT_SIDE || img.height() > MAX_OUTPUT_SIDE {
+        img.resize(MAX_OUTPUT_SIDE, MAX_OUTPUT_SIDE, FilterType::Triangle)
+    } else {
+        img
+    }
+}
+
+fn font() -> FontRef<'static> {
+    FontRef::try_from_slice(FONT_DATA).expect("The bundled font is valid")
+}
+
+/// Classic impact-style meme captions: white upper case text with a black outline.
+fn caption(img: DynamicImage, top: &str, bottom: &str) -> DynamicImage {
+    let mut canvas = img.to_rgba8();
+    let font = font();
+    let scale = PxScale::from((canvas.height() as f32 / 9.0).max(16.0));
+    let margin = canvas.height() / 40;
+
+    let top = top.chars().take(MAX_CAPTION_CHARS).collect::<String>();
+    let bottom = bottom.chars().take(MAX_CAPTION_CHARS).collect::<String>();
+
+    let top_lines = wrap_text(&top.to_uppercase(), &font, scale, canvas.width());
+    let mut y = margin as i32;
+    for line in &top_lines {
+        y += draw_outlined_line(&mut canvas, &font, scale, line, y);
+    }
+
+    let bottom_lines = wrap_text(&bottom.to_uppercase(), &font, scale, canvas.width());
+    let line_height = text_size(scale, &font, "Ag").1 as i32;
+    let mut y = canvas.height() as i32 - margin as i32 - line_height * bottom_lines.len() as i32;
+    for line in &bottom_lines {
+        y += draw_outlined_line(&mut canvas, &font, scale, line, y);
+    }
+
+    DynamicImage::ImageRgba8(canvas)
+}
+
+/// Draws one horizontally centered line and returns the height it took up.
+fn draw_outlined_line(
+    canvas: &mut RgbaImage,
+    font: &FontRef,
+    scale: PxScale,
+    line: &str,
+    y: i32,
+) -> i32 {
+    let (width, height) = text_size(scale, font, line);
+    let x = (canvas.width() as i32 - width as i32) / 2;
+    let outline = (scale.y / 18.0).max(1.0) as i32;
+    for dx in -outline..=outline {
+        for dy in -outline..=outline {
+            draw_text_mut(
+                canvas,
+                Rgba([0, 0, 0, 255]),
+                x + dx,
+                y + dy,
+                scale,
+                font,
+                line,
+            );
+        }
+    }
+    draw_text_mut(canvas, Rgba([255, 255, 255, 255]), x, y, scale, font, line);
+    height.max(text_size(scale, font, "Ag").1) as i32
+}
+
+/// Greedily breaks `text` into lines that fit into `max_width` pixels.
+fn wrap_text(text: &str, font: &FontRef, scale: PxScale, max_width: u32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for word in text.split_whitespace() {
+        let candidate = if current.is_empty() {
+            word.to_string()
+        } else {
+            format!("{} {}", current, word)
+        };
+        if text_size(scale, font, &candidate).0 > max_width * 95 / 100 && !current.is_empty() {
+            lines.push(std::mem::replace(&mut current, word.to_string()));
+        } else {
+            current = candidate;
+        }
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+/// Oversaturates and sharpens the image and then runs it through a very lossy JPEG encoding.
+fn deep_fry(img: DynamicImage) -> image::ImageResult<DynamicImage> {
+    let mut rgb = img.adjust_contrast(60.0).brighten(20).to_rgb8();
+    for pixel in rgb.pixels_mut() {
+        let grey = (pixel[0] as f32 + pixel[1] as f32 + pixel[2] as f32) / 3.0;
+        for channel in pixel.0.iter_mut() {
+            *channel = (grey + (*channel as f32 - grey) * 2.5).clamp(0.0, 255.0) as u8;
+        }
+    }
+    let fried = DynamicImage::ImageRgb8(rgb).unsharpen(3.0, 5);
+
+    let mut jpeg = Vec::new();
+    fried.write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, 8))?;
+    image::load_from_memory_with_format(&jpeg, ImageFormat::Jpeg)
+}
+
+/// Cuts a transparent speech bubble into the top of the image, like the reaction images that
+/// make it look as if the picture is talking.
+fn speech_bubble(img: DynamicImage) -> DynamicImage {
+    let mut canvas = img.to_rgba8();
+    let (width, height) = (canvas.width() as i32, canvas.height() as i32);
+    let clear = Rgba([0, 0, 0, 0]);
+
+    let bubble_height = (height / 5).max(4);
+    draw_filled_ellipse_mut(
+        &mut canvas,
+        (width / 2, 0),
+        width * 3 / 4,
+        bubble_height,
+        clear,
+    );
+    let tail = [
+        Point::new(width * 3 / 10, bubble_height - 2),
+        Point::new(width * 45 / 100, bubble_height - 2),
+        Point::new(width * 35 / 100, bubble_height * 2),
+    ];
+    draw_polygon_mut(&mut canvas, &tail, clear);
+
+    DynamicImage::ImageRgba8(canvas)
+}
+
+const CARD_WIDTH: u32 = 800;
+const CARD_HEIGHT: u32 = 250;
+const CARD_AVATAR_SIDE: u32 = 180;
+
+/// The background shared by all cards, with the round avatar on the left. Returns the canvas and
+/// where the text next to the avatar starts.
+fn card_canvas(avatar: &[u8]) -> image::ImageResult<(RgbaImage, i32)> {
+    let mut canvas = RgbaImage::from_fn(CARD_WIDTH, CARD_HEIGHT, |_, y| {
+        let shade = (40 - y * 20 / CARD_HEIGHT) as u8;
+        Rgba([shade, shade, shade + 12, 255])
+    });
+
+    let avatar = decode(avatar)?
+        .resize_to_fill(CARD_AVATAR_SIDE, CARD_AVATAR_SIDE, FilterType::Triangle)
+        .to_rgba8();
+    let offset = (CARD_HEIGHT - CARD_AVATAR_SIDE) / 2;
+    let radius = CARD_AVATAR_SIDE as f32 / 2.0;
+    for (x, y, pixel) in avatar.enumerate_pixels() {
+        let dx = x as f32 + 0.5 - radius;
+        let dy = y as f32 + 0.5 - radius;
+        if dx * dx + dy * dy <= radius * radius {
+            canvas.put_pixel(x + offset, y + offset, *pixel);
+        }
+    }
+    Ok((canvas, (offset * 2 + CARD_AVATAR_SIDE) as i32))
+}
+
+fn encode_card(canvas: RgbaImage) -> image::ImageResult<Vec<u8>> {
+    let mut out = Vec::new();
+    DynamicImage::ImageRgba8(canvas).write_to(&mut Cursor::new(&mut out), ImageFormat::Png)?;
+    Ok(out)
+}
+
+/// Draws a welcome card: the round avatar on the left, `title` and `subtitle` next to it.
+pub fn welcome_card(avatar: &[u8], title: &str, subtitle: &str) -> image::ImageResult<Vec<u8>> {
+    let (mut canvas, text_x) = card_canvas(avatar)?;
+    let font = font();
+    let max_width = CARD_WIDTH - text_x as u32 - (CARD_HEIGHT - CARD_AVATAR_SIDE) / 2;
+    let title_scale = fitting_scale(title, &font, 44.0, max_width);
+    draw_text_mut(
+        &mut canvas,
+        Rgba([255, 255, 255, 255]),
+        text_x,
+        70,
+        title_scale,
+        &font,
+        title,
+    );
+    let subtitle_scale = fitting_scale(subtitle, &font, 30.0, max_width);
+    draw_text_mut(
+        &mut canvas,
+        Rgba([190, 190, 200, 255]),
+        text_x,
+        140,
+        subtitle_scale,
+        &font,
+        subtitle,
+    );
+    encode_card(canvas)
+}
+
+/// Draws a rank card: the round avatar, the name, rank and level, and a bar showing how far the
+/// member is into the current level.
+pub fn rank_card(
+    avatar: &[u8],
+    name: &str,
+    rank: usize,
+    level: u32,
+    xp: u64,
+    needed: u64,
+) -> image::ImageResult<Vec<u8>> {
+    let (mut canvas, text_x) = card_canvas(avatar)?;
+    let font = font();
+    let max_width = CARD_WIDTH - text_x as u32 - (CARD_HEIGHT - CARD_AVATAR_SIDE) / 2;
+    let name_scale = fitting_scale(name, &font, 40.0, max_width);
+    draw_text_mut(
+        &mut canvas,
+        Rgba([255, 255, 255, 255]),
+        text_x,
+        45,
+        name_scale,
+        &font,
+        name,
+    );
+    let stats = format!("Rank #{}   Level {}", rank, level);
+    draw_text_mut(
+        &mut canvas,
+        Rgba([190, 190, 200, 255]),
+        text_x,
+        100,
+        fitting_scale(&stats, &font, 28.0, max_width),
+        &font,
+        &stats,
+    );
+
+    let bar_height = 26;
+    let filled = (max_width as u64 * xp.min(needed) / needed.max(1)) as u32;
+    draw_filled_rect_mut(
+        &mut canvas,
+        Rect::at(text_x, 145).of_size(max_width, bar_height),
+        Rgba([70, 70, 85, 255]),
+    );
+    if filled > 0 {
+        draw_filled_rect_mut(
+            &mut canvas,
+            Rect::at(text_x, 145).of_size(filled, bar_height),
+            Rgba([88, 101, 242, 255]),
+        );
+    }
+    let progress = format!("{} / {} XP", xp, needed);
+    draw_text_mut(
+        &mut canvas,
+        Rgba([190, 190, 200, 255]),
+        text_x,
+        182,
+        PxScale::from(22.0),
+        &font,
+        &progress,
+    );
+    encode_card(canvas)
+}
+
+/// The largest scale up to `max` at which `text` is at most `max_width` pixels wide.
+fn fitting_scale(text: &str, font: &FontRef, max: f32, max_width: u32) -> PxScale {
+    let width = text_size(PxScale::from(max), font, text).0;
+    if width <= max_width {
+        PxScale::from(max)
+    } else {
+        PxScale::from((max * max_width as f32 / width as f32).max(8.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let img = RgbaImage::from_fn(width, height, |x, y| Rgba([x as u8, y as u8, 128, 255]));
+        let mut out = Vec::new();
+        DynamicImage::ImageRgba8(img)
+            .write_to(&mut Cursor::new(&mut out), ImageFormat::Png)
+            .unwrap();
+        out
+    }
+
+    fn edited_size(bytes: &[u8], transform: Transform) -> (u32, u32) {
+        let edited = image::load_from_memory(&apply(bytes, &transform).unwrap()).unwrap();
+        (edited.width(), edited.height())
+    }
+
+    #[test]
+    fn transforms_keep_or_turn_the_size() {
+        let bytes = png(40, 20);
+        let caption = Transform::Caption {
+            top: String::from("top"),
+            bottom: "very long bottom text ".repeat(50),
+        };
+        assert_eq!(edited_size(&bytes, caption), (40, 20));
+        assert_eq!(edited_size(&bytes, Transform::Invert), (40, 20));
+        assert_eq!(edited_size(&bytes, Transform::Greyscale), (40, 20));
+        assert_eq!(edited_size(&bytes, Transform::Blur(f32::NAN)), (40, 20));
+        assert_eq!(edited_size(&bytes, Transform::DeepFry), (40, 20));
+        assert_eq!(edited_size(&bytes, Transform::Rotate(90)), (20, 40));
+        assert_eq!(edited_size(&bytes, Transform::Rotate(45)), (40, 20));
+        assert_eq!(
+            edited_size(&bytes, Transform::Flip(FlipAxis::Vertical)),
+            (40, 20)
+        );
+        assert_eq!(edited_size(&bytes, Transform::SpeechBubble), (40, 20));
+    }
+
+    #[test]
+    fn large_images_are_shrunk() {
+        let (width, height) = edited_size(&png(2048, 512), Transform::Invert);
+        assert_eq!((width, height), (MAX_OUTPUT_SIDE, MAX_OUTPUT_SIDE / 4));
+    }
+
+    #[test]
+    fn oversized_and_invalid_images_are_refused() {
+        assert!(apply(&png(MAX_DECODE_SIDE + 1, 1), &Transform::Invert).is_err());
+        assert!(apply(b"not an image", &Transform::Invert).is_err());
+    }
+
+    #[test]
+    fn cards_have_the_card_size() {
+        let card = welcome_card(&png(64, 64), "Welcome!", "You are member #42").unwrap();
+        let card = image::load_from_memory(&card).unwrap();
+        assert_eq!((card.width(), card.height()), (CARD_WIDTH, CARD_HEIGHT));
+        let card = rank_card(&png(64, 64), "name", 1, 3, 50, 0).unwrap();
+        let card = image::load_from_memory(&card).unwrap();
+        assert_eq!((card.width(), card.height()), (CARD_WIDTH, CARD_HEIGHT));
+    }
+}
@@ -0,0 +1,480 @@
+use crate::command_base::*;
+use crate::moderator_commands::{is_moderator, read_warnings};
+
+use serenity::all::{
+    ChannelType, CreateEmbed, CreateEmbedFooter, CreateMessage, OnlineStatus, Permissions,
+    PremiumTier, Timestamp,
+};
+use serenity::model::id::{ChannelId, RoleId, UserId};
+use serenity::utils::{parse_channel_mention, parse_role_mention, parse_user_mention};
+
+/// Discord's limit for the value of an embed field.
+const FIELD_LIMIT: usize = 1024;
+const AVATAR_SIZES: [u16; 9] = [16, 32, 64, 128, 256, 512, 1024, 2048, 4096];
+const AVATAR_FORMATS: [&str; 4] = ["png", "jpg", "webp", "gif"];
+
+/// Permissions worth pointing out, the rest are granted to most members anyway.
+const KEY_PERMISSIONS: Permissions = Permissions::ADMINISTRATOR
+    .union(Permissions::MANAGE_GUILD)
+    .union(Permissions::MANAGE_ROLES)
+    .union(Permissions::MANAGE_CHANNELS)
+    .union(Permissions::MANAGE_MESSAGES)
+    .union(Permissions::MANAGE_NICKNAMES)
+    .union(Permissions::MANAGE_WEBHOOKS)
+    .union(Permissions::VIEW_AUDIT_LOG)
+    .union(Permissions::KICK_MEMBERS)
+    .union(Permissions::BAN_MEMBERS)
+    .union(Permissions::MODERATE_MEMBERS)
+    .union(Permissions::MENTION_EVERYONE);
+
+#[group]
+#[commands(userinfo, serverinfo, roleinfo, channelinfo, avatar)]
+#[only_in(guilds)]
+pub struct Info;
+
+fn parse_user(arg: &str) -> Option<UserId> {
+    parse_user_mention(arg).or_else(|| arg.parse().ok())
+}
+
+/// The full date of `timestamp` and how long ago it was.
+fn date(timestamp: Timestamp) -> String {
+    let seconds = timestamp.unix_timestamp();
+    format!("<t:{}:F> (<t:{}:R>)", seconds, seconds)
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+/// Joins `items` with `, ` for an embed field. Items that don't fit are counted instead.
+fn field_list(items: &[String]) -> String {
+    if items.is_empty() {
+        return String::from("none");
+    }
+    let mut list = String::new();
+    for (index, item) in items.iter().enumerate() {
+        let rest = format!(" and {} more", items.len() - index);
+        if list.len() + item.len() + 2 + rest.len() > FIELD_LIMIT {
+            list.push_str(&rest);
+            break;
+        }
+        if !list.is_empty() {
+            list.push_str(", ");
+        }
+        list.push_str(item);
+    }
+    list
+}
+
+fn key_permissions(permissions: Permissions) -> String {
+    if permissions.administrator() {
+        return String::from("Administrator (all permissions)");
+    }
+    let names = (permissions & KEY_PERMISSIONS)
+        .get_permission_names()
+        .into_iter()
+        .map(String::from)
+        .collect::<Vec<_>>();
+    field_list(&names)
+}
+
+#[command]
+#[aliases("whois")]
+#[description = "Shows who a member is: account age, roles and key permissions, and for \
+moderators their warnings. Without a user it shows you."]
+#[usage = "[@user]"]
+async fn userinfo(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let user_id = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_user(&arg))
+        .unwrap_or(msg.author.id);
+    // Cached users come without a banner, only the API has it.
+    let Ok(user) = ctx.http.get_user(user_id).await else {
+        msg.reply(ctx, "I could not find that user.").await?;
+        return Ok(());
+    };
+
+    let mut embed = CreateEmbed::new()
+        .title(user.global_name.as_deref().unwrap_or(&user.name))
+        .thumbnail(user.face())
+        .field("Username", format!("@{}", user.name), true)
+        .field("ID", format!("`{}`", user.id), true)
+        .field("Bot", yes_no(user.bot), true)
+        .field("Account created", date(user.id.created_at()), false);
+    if let Some(banner) = user.banner_url() {
+        embed = embed.image(banner);
+    }
+
+    let member = {
+        let guild = msg.guild(&ctx.cache).ok_or("Only in guilds")?;
+        guild.members.get(&user_id).map(|member| {
+            let mut roles = member
+                .roles
+                .iter()
+                .filter_map(|role| guild.roles.get(role))
+                .collect::<Vec<_>>();
+            roles.sort_by_key(|role| std::cmp::Reverse(role.position));
+            let roles = roles
+                .iter()
+                .map(|role| format!("<@&{}>", role.id))
+                .collect::<Vec<_>>();
+            (
+                member.joined_at,
+                member.premium_since,
+                member.nick.clone(),
+                roles,
+                guild.member_permissions(member),
+            )
+        })
+    };
+    match member {
+        Some((joined_at, premium_since, nick, roles, permissions)) => {
+            if let Some(nick) = nick {
+                embed = embed.field("Nickname", nick, true);
+            }
+            if let Some(joined_at) = joined_at {
+                embed = embed.field("Joined the server", date(joined_at), false);
+            }
+            if let Some(premium_since) = premium_since {
+                embed = embed.field("Boosting since", date(premium_since), false);
+            }
+            embed = embed
+                .field(
+                    format!("Roles ({})", roles.len()),
+                    field_list(&roles),
+                    false,
+                )
+                .field("Key permissions", key_permissions(permissions), false);
+        }
+        None => embed = embed.footer(CreateEmbedFooter::new("Not a member of this server")),
+    }
+    // Warnings are private to moderators.
+    if is_moderator(ctx, msg) {
+        let warnings = read_warnings(&user.name).await.len();
+        embed = embed.field("Warnings", warnings.to_string(), true);
+    }
+
+    msg.channel_id
+        .send_message(&ctx.http, CreateMessage::new().embed(embed))
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[aliases("guildinfo")]
+#[description = "Shows the owner, members, channels, roles and boosts of this server."]
+async fn serverinfo(ctx: &Context, msg: &Message) -> CommandResult {
+    let embed = {
+        let guild = msg.guild(&ctx.cache).ok_or("Only in guilds")?;
+
+        let bots = guild
+            .members
+            .values()
+            .filter(|member| member.user.bot)
+            .count();
+        let mut online = 0;
+        let mut idle = 0;
+        let mut dnd = 0;
+        for presence in guild.presences.values() {
+            match presence.status {
+                OnlineStatus::Online => online += 1,
+                OnlineStatus::Idle => idle += 1,
+                OnlineStatus::DoNotDisturb => dnd += 1,
+                _ => {}
+            }
+        }
+        let offline = (guild.member_count as usize).saturating_sub(online + idle + dnd);
+
+        let count_channels = |kinds: &[ChannelType]| {
+            guild
+                .channels
+                .values()
+                .filter(|channel| kinds.contains(&channel.kind))
+                .count()
+        };
+        let channels = format!(
+            "{} text, {} voice, {} categories, {} forums, {} threads",
+            count_channels(&[ChannelType::Text, ChannelType::News]),
+            count_channels(&[ChannelType::Voice, ChannelType::Stage]),
+            count_channels(&[ChannelType::Category]),
+            count_channels(&[ChannelType::Forum]),
+            guild.threads.len()
+        );
+
+        let level = match guild.premium_tier {
+            PremiumTier::Tier0 => 0,
+            PremiumTier::Tier1 => 1,
+            PremiumTier::Tier2 => 2,
+            PremiumTier::Tier3 => 3,
+            PremiumTier::Unknown(level) => level,
+            _ => 0,
+        };
+        let mut features = guild
+            .features
+            .iter()
+            .map(|feature| feature.to_lowercase().replace('_', " "))
+            .collect::<Vec<_>>();
+        features.sort();
+
+        let mut embed = CreateEmbed::new()
+            .title(&guild.name)
+            .field("Owner", format!("<@{}>", guild.owner_id), true)
+            .field("ID", format!("`{}`", guild.id), true)
+            .field("Created", date(guild.id.created_at()), false)
+            .field(
+                format!("Members ({})", guild.member_count),
+                format!(
+                    "{} humans, {} bots\n🟢 {} online · 🌙 {} idle · ⛔ {} do not disturb · \
+                    ⚫ {} offline",
+                    (guild.member_count as usize).saturating_sub(bots),
+                    bots,
+                    online,
+                    idle,
+                    dnd,
+                    offline
+                ),
+                false,
+            )
+            .field("Channels", channels, false)
+            .field("Roles", guild.roles.len().to_string(), true)
+            .field("Emojis", guild.emojis.len().to_string(), true)
+            .field(
+                "Boosts",
+                format!(
+                    "Level {} ({} boosts)",
+                    level,
+                    guild.premium_subscription_count.unwrap_or(0)
+                ),
+                true,
+            )
+            .field("Features", field_list(&features), false);
+        if let Some(icon) = guild.icon_url() {
+            embed = embed.thumbnail(icon);
+        }
+        if let Some(banner) = guild.banner_url() {
+            embed = embed.image(banner);
+        }
+        embed
+    };
+
+    msg.channel_id
+        .send_message(&ctx.http, CreateMessage::new().embed(embed))
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Shows a role's colour, position, members and permissions. The role can be a \
+mention, an ID or its name."]
+#[usage = "<role>"]
+#[example = "Moderators"]
+async fn roleinfo(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let query = args.rest().trim();
+    let embed = {
+        let guild = msg.guild(&ctx.cache).ok_or("Only in guilds")?;
+        let role = parse_role_mention(query)
+            .or_else(|| query.parse::<RoleId>().ok())
+            .and_then(|role| guild.roles.get(&role))
+            .or_else(|| {
+                guild
+                    .roles
+                    .values()
+                    .find(|role| role.name.eq_ignore_ascii_case(query))
+            });
+        role.map(|role| {
+            let members = guild
+                .members
+                .values()
+                .filter(|member| member.roles.contains(&role.id))
+                .count();
+            let permissions = role
+                .permissions
+                .get_permission_names()
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<_>>();
+            CreateEmbed::new()
+                .title(&role.name)
+                .colour(role.colour)
+                .field("ID", format!("`{}`", role.id), true)
+                .field("Colour", format!("#{}", role.colour.hex()), true)
+                .field("Position", role.position.to_string(), true)
+                .field("Members", members.to_string(), true)
+                .field("Shown separately", yes_no(role.hoist), true)
+                .field("Mentionable", yes_no(role.mentionable), true)
+                .field("Managed by an integration", yes_no(role.managed), true)
+                .field("Created", date(role.id.created_at()), false)
+                .field("Permissions", field_list(&permissions), false)
+        })
+    };
+    let Some(embed) = embed else {
+        msg.reply(ctx, "I could not find that role.").await?;
+        return Ok(());
+    };
+
+    msg.channel_id
+        .send_message(&ctx.http, CreateMessage::new().embed(embed))
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Shows the type, category, topic and settings of a channel. Without a channel it \
+shows this one."]
+#[usage = "[#channel]"]
+async fn channelinfo(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let channel_id = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_channel_mention(&arg).or_else(|| arg.parse::<ChannelId>().ok()))
+        .unwrap_or(msg.channel_id);
+    let embed = {
+        let guild = msg.guild(&ctx.cache).ok_or("Only in guilds")?;
+        let channel = guild
+            .channels
+            .get(&channel_id)
+            .or_else(|| guild.threads.iter().find(|thread| thread.id == channel_id));
+        channel.map(|channel| {
+            let mut embed = CreateEmbed::new()
+                .title(format!("#{}", channel.name))
+                .field("ID", format!("`{}`", channel.id), true)
+                .field("Type", channel.kind.name().replace('_', " "), true)
+                .field("Position", channel.position.to_string(), true);
+            if let Some(parent) = channel.parent_id {
+                embed = embed.field("Category", format!("<#{}>", parent), true);
+            }
+            embed = embed.field("NSFW", yes_no(channel.nsfw), true);
+            if let Some(slowmode) = channel.rate_limit_per_user.filter(|seconds| *seconds > 0) {
+                embed = embed.field("Slowmode", format!("{} seconds", slowmode), true);
+            }
+            if let Some(bitrate) = channel.bitrate {
+                embed = embed.field("Bitrate", format!("{} kbps", bitrate / 1000), true);
+            }
+            if let Some(limit) = channel.user_limit.filter(|limit| *limit > 0) {
+                embed = embed.field("User limit", limit.to_string(), true);
+            }
+            embed = embed.field(
+                "Permission overwrites",
+                channel.permission_overwrites.len().to_string(),
+                true,
+            );
+            if let Some(topic) = channel.topic.as_ref().filter(|topic| !topic.is_empty()) {
+                embed = embed.field(
+                    "Topic",
+                    topic.chars().take(FIELD_LIMIT).collect::<String>(),
+                    false,
+                );
+            }
+            embed.field("Created", date(channel.id.created_at()), false)
+        })
+    };
+    let Some(embed) = embed else {
+        msg.reply(ctx, "I could not find that channel in this server.")
+            .await?;
+        return Ok(());
+    };
+
+    msg.channel_id
+        .send_message(&ctx.http, CreateMessage::new().embed(embed))
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[aliases("av")]
+#[description = "Shows someone's avatar in full size. The size can be a power of two from 16 to \
+4096, the format png, jpg, webp or gif (animated avatars only)."]
+#[usage = "[@user] [size] [format]"]
+#[example = "@Ferris 256 png"]
+async fn avatar(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mut user_id = msg.author.id;
+    let mut size = 1024;
+    let mut format = None;
+    while let Ok(arg) = args.single::<String>() {
+        let arg = arg.to_lowercase();
+        if AVATAR_FORMATS.contains(&arg.as_str()) || arg == "jpeg" {
+            format = Some(if arg == "jpeg" {
+                String::from("jpg")
+            } else {
+                arg
+            });
+        } else if let Ok(number) = arg.parse::<u16>() {
+            if !AVATAR_SIZES.contains(&number) {
+                msg.reply(
+                    ctx,
+                    "The size has to be a power of two from 16 to 4096, like 256 or 1024.",
+                )
+                .await?;
+                return Ok(());
+            }
+            size = number;
+        } else if let Some(user) = parse_user(&arg) {
+            user_id = user;
+        } else {
+            msg.reply(ctx, format!("I don't know what `{}` means here.", arg))
+                .await?;
+            return Ok(());
+        }
+    }
+    let Ok(user) = user_id.to_user(ctx).await else {
+        msg.reply(ctx, "I could not find that user.").await?;
+        return Ok(());
+    };
+
+    let Some(hash) = user.avatar else {
+        // Default avatars only exist as small PNGs.
+        msg.channel_id
+            .send_message(
+                &ctx.http,
+                CreateMessage::new().embed(
+                    CreateEmbed::new()
+                        .title(format!("Avatar of {}", user.name))
+                        .image(user.default_avatar_url()),
+                ),
+            )
+            .await?;
+        return Ok(());
+    };
+    let animated = hash.is_animated();
+    let format = format.unwrap_or_else(|| String::from(if animated { "gif" } else { "png" }));
+    if format == "gif" && !animated {
+        msg.reply(ctx, "That avatar is not animated, so there is no gif.")
+            .await?;
+        return Ok(());
+    }
+
+    let url = |format: &str| {
+        format!(
+            "https://cdn.discordapp.com/avatars/{}/{}.{}?size={}",
+            user.id, hash, format, size
+        )
+    };
+    let links = AVATAR_FORMATS
+        .iter()
+        .filter(|format| animated || **format != "gif")
+        .map(|format| format!("[{}]({})", format, url(format)))
+        .collect::<Vec<_>>();
+    msg.channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new().embed(
+                CreateEmbed::new()
+                    .title(format!("Avatar of {}", user.name))
+                    .description(links.join(" · "))
+                    .image(url(&format))
+                    .footer(CreateEmbedFooter::new(format!("{}×{}", size, size))),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
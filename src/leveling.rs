@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+
+/// Levels stop here, so a misconfigured curve can't make the level lookup run forever.
+pub const MAX_LEVEL: u32 = 1000;
+
+/// How much XP each level takes: going from level `L` to `L + 1` needs
+/// `quadratic * L² + linear * L + constant` XP.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct LevelCurve {
+    pub quadratic: u64,
+    pub linear: u64,
+    pub constant: u64,
+}
+
+impl Default for LevelCurve {
+    fn default() -> Self {
+        LevelCurve {
+            quadratic: 5,
+            linear: 50,
+            constant: 100,
+        }
+    }
+}
+
+impl LevelCurve {
+    /// XP needed to go from `level` to the next one. Never zero.
+    pub fn xp_to_next(&self, level: u32) -> u64 {
+        let level = level as u64;
+        self.quadratic
+            .saturating_mul(level * level)
+            .saturating_add(self.linear.saturating_mul(level))
+            .saturating_add(self.constant)
+            .max(1)
+    }
+
+    /// Total XP needed to reach `level`.
+    pub fn total_for_level(&self, level: u32) -> u64 {
+        (0..level).fold(0u64, |total, level| {
+            total.saturating_add(self.xp_to_next(level))
+        })
+    }
+
+    /// The level reached with `xp`, how far into that level it is and how much the level takes
+    /// in total.
+    pub fn progress(&self, xp: u64) -> (u32, u64, u64) {
+        let mut level = 0;
+        let mut rest = xp;
+        while level < MAX_LEVEL && rest >= self.xp_to_next(level) {
+            rest -= self.xp_to_next(level);
+            level += 1;
+        }
+        (level, rest, self.xp_to_next(level))
+    }
+
+    pub fn level_for(&self, xp: u64) -> u32 {
+        self.progress(xp).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_curve_costs() {
+        let curve = LevelCurve::default();
+        assert_eq!(curve.xp_to_next(0), 100);
+        assert_eq!(curve.xp_to_next(1), 155);
+        assert_eq!(curve.xp_to_next(2), 220);
+        assert_eq!(curve.total_for_level(0), 0);
+        assert_eq!(curve.total_for_level(3), 475);
+    }
+
+    #[test]
+    fn progress_within_a_level() {
+        let curve = LevelCurve::default();
+        assert_eq!(curve.progress(0), (0, 0, 100));
+        assert_eq!(curve.progress(99), (0, 99, 100));
+        assert_eq!(curve.progress(100), (1, 0, 155));
+        assert_eq!(curve.progress(300), (2, 45, 220));
+    }
+
+    #[test]
+    fn level_for_inverts_total_for_level() {
+        let curve = LevelCurve::default();
+        for level in 0..50 {
+            let total = curve.total_for_level(level);
+            assert_eq!(curve.level_for(total), level);
+            if total > 0 {
+                assert_eq!(curve.level_for(total - 1), level - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn a_free_curve_still_costs_xp() {
+        let curve = LevelCurve {
+            quadratic: 0,
+            linear: 0,
+            constant: 0,
+        };
+        assert_eq!(curve.xp_to_next(0), 1);
+        assert_eq!(curve.level_for(5), 5);
+        assert_eq!(curve.level_for(u64::MAX), MAX_LEVEL);
+    }
+
+    #[test]
+    fn huge_curves_saturate() {
+        let curve = LevelCurve {
+            quadratic: u64::MAX,
+            linear: u64::MAX,
+            constant: u64::MAX,
+        };
+        assert_eq!(curve.xp_to_next(MAX_LEVEL), u64::MAX);
+        assert_eq!(curve.total_for_level(MAX_LEVEL), u64::MAX);
+        assert_eq!(curve.level_for(u64::MAX), 1);
+        assert_eq!(curve.level_for(u64::MAX - 1), 0);
+    }
+}
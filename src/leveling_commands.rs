@@ -0,0 +1,880 @@
+use crate::command_base::*;
+use crate::image_edit;
+use crate::leveling::{LevelCurve, MAX_LEVEL};
+use crate::paginator::Paginator;
+use crate::role_menu_commands::{role_problem, setup_problem};
+use crate::scheduler::SchedulerContext;
+use crate::storage::save_json;
+
+use serde::{Deserialize, Serialize};
+use serenity::all::{CreateAllowedMentions, CreateAttachment, CreateEmbed, CreateMessage, User};
+use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+use serenity::utils::{parse_channel_mention, parse_role_mention, parse_user_mention};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+pub const LEVEL_CONFIGS_PATH: &str = "data/level_configs.json";
+pub const EXPERIENCE_PATH: &str = "data/experience.json";
+/// Longest XP cooldown, cooldowns older than this are forgotten.
+const MAX_COOLDOWN_SECS: u64 = 60 * 60;
+
+#[group]
+#[prefixes("levels")]
+#[commands(
+    status, enable, disable, gain, cooldown, curve, reward, stack, exclude, include, announce,
+    setxp, setlevel, reset
+)]
+#[required_permissions(MANAGE_GUILD)]
+#[only_in(guilds)]
+pub struct Levels;
+
+#[group]
+#[commands(rank, leaderboard)]
+#[only_in(guilds)]
+pub struct Leveling;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct LevelConfig {
+    pub enabled: bool,
+    pub curve: LevelCurve,
+    /// Every message that counts gives a random amount of XP in this range.
+    pub min_xp: u64,
+    pub max_xp: u64,
+    /// Messages within this many seconds of the last one that counted give nothing.
+    pub cooldown_secs: u64,
+    /// Roles given when reaching a level.
+    pub rewards: BTreeMap<u32, RoleId>,
+    /// Keep the rewards of lower levels, instead of only the highest one.
+    pub stack_rewards: bool,
+    pub excluded_channels: Vec<ChannelId>,
+    pub excluded_roles: Vec<RoleId>,
+    pub announce: bool,
+    /// Where level ups are announced, the channel of the message if `None`.
+    pub announce_channel: Option<ChannelId>,
+}
+
+impl Default for LevelConfig {
+    fn default() -> Self {
+        LevelConfig {
+            enabled: false,
+            curve: LevelCurve::default(),
+            min_xp: 15,
+            max_xp: 25,
+            cooldown_secs: 60,
+            rewards: BTreeMap::new(),
+            stack_rewards: true,
+            excluded_channels: Vec::new(),
+            excluded_roles: Vec::new(),
+            announce: true,
+            announce_channel: None,
+        }
+    }
+}
+
+pub struct LevelConfigs;
+
+impl TypeMapKey for LevelConfigs {
+    type Value = HashMap<GuildId, LevelConfig>;
+}
+
+/// The XP of every member, per guild.
+pub struct Experience;
+
+impl TypeMapKey for Experience {
+    type Value = HashMap<GuildId, HashMap<UserId, u64>>;
+}
+
+/// Whether the XP changed since it was last saved.
+pub struct ExperienceChanged;
+
+impl TypeMapKey for ExperienceChanged {
+    type Value = bool;
+}
+
+/// When each member last got XP.
+pub struct XpCooldowns;
+
+impl TypeMapKey for XpCooldowns {
+    type Value = HashMap<(GuildId, UserId), Instant>;
+}
+
+async fn guild_config(ctx: &Context, guild_id: GuildId) -> LevelConfig {
+    let data = ctx.data.read().await;
+    data.get::<LevelConfigs>()
+        .expect("Expected LevelConfigs in TypeMap.")
+        .get(&guild_id)
+        .cloned()
+        .unwrap_or_default()
+}
+
+async fn update_config(
+    ctx: &Context,
+    guild_id: GuildId,
+    change: impl FnOnce(&mut LevelConfig),
+) -> CommandResult {
+    let configs = {
+        let mut data = ctx.data.write().await;
+        let configs = data
+            .get_mut::<LevelConfigs>()
+            .expect("Expected LevelConfigs in TypeMap.");
+        change(configs.entry(guild_id).or_default());
+        configs.clone()
+    };
+    save_json(LEVEL_CONFIGS_PATH, &configs).await?;
+    Ok(())
+}
+
+/// Changes the XP of `user` and returns it before and after. The XP is not saved right away,
+/// the scheduler saves it with `save_experience`.
+async fn update_xp(
+    ctx: &Context,
+    guild_id: GuildId,
+    user: UserId,
+    change: impl FnOnce(u64) -> u64,
+) -> (u64, u64) {
+    let mut data = ctx.data.write().await;
+    let xp = data
+        .get_mut::<Experience>()
+        .expect("Expected Experience in TypeMap.")
+        .entry(guild_id)
+        .or_default()
+        .entry(user)
+        .or_default();
+    let before = *xp;
+    *xp = change(before);
+    let after = *xp;
+    data.insert::<ExperienceChanged>(true);
+    (before, after)
+}
+
+/// Saves the XP of everyone if it changed since it was last saved.
+async fn save_experience(data: &RwLock<TypeMap>) -> CommandResult {
+    let experience = {
+        let mut data = data.write().await;
+        let changed = data
+            .get_mut::<ExperienceChanged>()
+            .expect("Expected ExperienceChanged in TypeMap.");
+        if !std::mem::take(changed) {
+            return Ok(());
+        }
+        data.get::<Experience>()
+            .expect("Expected Experience in TypeMap.")
+            .clone()
+    };
+    if let Err(why) = save_json(EXPERIENCE_PATH, &experience).await {
+        data.write().await.insert::<ExperienceChanged>(true);
+        return Err(why.into());
+    }
+    Ok(())
+}
+
+/// Saves the XP gained since the last tick. Saving on every message would write the XP of
+/// everyone to disk for each message.
+pub async fn run_due(scheduler: &SchedulerContext) -> CommandResult {
+    save_experience(&scheduler.data).await
+}
+
+/// Gives `user` the reward roles for `level` and takes the ones they should no longer have.
+async fn sync_rewards(
+    ctx: &Context,
+    guild_id: GuildId,
+    user: UserId,
+    roles: &[RoleId],
+    level: u32,
+    config: &LevelConfig,
+) -> CommandResult {
+    let earned = config
+        .rewards
+        .range(..=level)
+        .map(|(_, role)| *role)
+        .collect::<Vec<_>>();
+    let wanted = if config.stack_rewards {
+        earned
+    } else {
+        earned.last().copied().into_iter().collect()
+    };
+    for role in config.rewards.values() {
+        let has = roles.contains(role);
+        let wants = wanted.contains(role);
+        if has == wants || role_problem(ctx, guild_id, *role).is_some() {
+            continue;
+        }
+        if wants {
+            ctx.http
+                .add_member_role(guild_id, user, *role, Some("Level reward"))
+                .await?;
+        } else {
+            ctx.http
+                .remove_member_role(guild_id, user, *role, Some("Level reward"))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Gives XP for a message, unless it is in an excluded channel, from a member with an excluded
+/// role or the author got XP too recently.
+pub async fn handle_message(ctx: &Context, msg: &Message) -> CommandResult {
+    let (Some(guild_id), Some(member)) = (msg.guild_id, &msg.member) else {
+        return Ok(());
+    };
+    if msg.author.bot {
+        return Ok(());
+    }
+    let config = guild_config(ctx, guild_id).await;
+    if !config.enabled
+        || config.excluded_channels.contains(&msg.channel_id)
+        || member
+            .roles
+            .iter()
+            .any(|role| config.excluded_roles.contains(role))
+    {
+        return Ok(());
+    }
+
+    {
+        let mut data = ctx.data.write().await;
+        let cooldowns = data
+            .get_mut::<XpCooldowns>()
+            .expect("Expected XpCooldowns in TypeMap.");
+        let now = Instant::now();
+        let cooldown = Duration::from_secs(config.cooldown_secs);
+        let key = (guild_id, msg.author.id);
+        if cooldowns
+            .get(&key)
+            .is_some_and(|last| now.duration_since(*last) < cooldown)
+        {
+            return Ok(());
+        }
+        cooldowns.insert(key, now);
+        cooldowns
+            .retain(|_, last| now.duration_since(*last) < Duration::from_secs(MAX_COOLDOWN_SECS));
+    }
+
+    let gained = rand::thread_rng().gen_range(config.min_xp..=config.max_xp.max(config.min_xp));
+    let (before, after) =
+        update_xp(ctx, guild_id, msg.author.id, |xp| xp.saturating_add(gained)).await;
+    let old_level = config.curve.level_for(before);
+    let new_level = config.curve.level_for(after);
+    if new_level <= old_level {
+        return Ok(());
+    }
+
+    sync_rewards(
+        ctx,
+        guild_id,
+        msg.author.id,
+        &member.roles,
+        new_level,
+        &config,
+    )
+    .await?;
+    if config.announce {
+        let mut content = format!(
+            "GG <@{}>, you reached level **{}**!",
+            msg.author.id, new_level
+        );
+        // Several levels can be gained at once, every reward on the way is earned.
+        let mut rewards = config
+            .rewards
+            .range(old_level + 1..=new_level)
+            .map(|(_, role)| format!("<@&{}>", role))
+            .collect::<Vec<_>>();
+        if !config.stack_rewards {
+            rewards.drain(..rewards.len().saturating_sub(1));
+        }
+        if !rewards.is_empty() {
+            write!(content, " You got {}.", rewards.join(", "))?;
+        }
+        config
+            .announce_channel
+            .unwrap_or(msg.channel_id)
+            .send_message(
+                &ctx.http,
+                CreateMessage::new()
+                    .content(content)
+                    .allowed_mentions(CreateAllowedMentions::new().users([msg.author.id])),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Everyone with XP in `guild_id`, best first.
+async fn ranking(ctx: &Context, guild_id: GuildId) -> Vec<(UserId, u64)> {
+    let data = ctx.data.read().await;
+    let mut ranking = data
+        .get::<Experience>()
+        .expect("Expected Experience in TypeMap.")
+        .get(&guild_id)
+        .map(|users| {
+            users
+                .iter()
+                .filter(|(_, xp)| **xp > 0)
+                .map(|(user, xp)| (*user, *xp))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    ranking.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    ranking
+}
+
+fn parse_user(arg: &str) -> Option<UserId> {
+    parse_user_mention(arg).or_else(|| arg.parse().ok())
+}
+
+async fn rank_card(
+    user: &User,
+    name: String,
+    rank: usize,
+    level: u32,
+    xp: u64,
+    needed: u64,
+) -> CommandResult<Vec<u8>> {
+    let response = reqwest::get(user.static_face()).await?.error_for_status()?;
+    let avatar = response.bytes().await?;
+    if avatar.len() as u64 > image_edit::MAX_INPUT_BYTES {
+        return Err("Avatar is too big".into());
+    }
+    let card = tokio::task::spawn_blocking(move || {
+        image_edit::rank_card(&avatar, &name, rank, level, xp, needed)
+    })
+    .await??;
+    Ok(card)
+}
+
+#[command]
+#[description = "Shows your rank card, or the one of another member."]
+#[usage = "[@user]"]
+async fn rank(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let user_id = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_user(&arg))
+        .unwrap_or(msg.author.id);
+    let user = user_id.to_user(ctx).await?;
+    let config = guild_config(ctx, guild_id).await;
+    let ranking = ranking(ctx, guild_id).await;
+    let Some(position) = ranking.iter().position(|(ranked, _)| *ranked == user_id) else {
+        let content = if user_id == msg.author.id {
+            String::from("No XP yet, go chat a bit!")
+        } else {
+            format!("<@{}> has no XP yet.", user_id)
+        };
+        msg.channel_id
+            .send_message(
+                &ctx.http,
+                CreateMessage::new()
+                    .content(content)
+                    .reference_message(msg)
+                    .allowed_mentions(CreateAllowedMentions::new()),
+            )
+            .await?;
+        return Ok(());
+    };
+    let (level, xp, needed) = config.curve.progress(ranking[position].1);
+    let name = user
+        .global_name
+        .clone()
+        .unwrap_or_else(|| user.name.clone());
+
+    let _typing = msg.channel_id.start_typing(&ctx.http);
+    match rank_card(&user, name.clone(), position + 1, level, xp, needed).await {
+        Ok(card) => {
+            msg.channel_id
+                .send_message(
+                    &ctx.http,
+                    CreateMessage::new().add_file(CreateAttachment::bytes(card, "rank.png")),
+                )
+                .await?;
+        }
+        Err(why) => {
+            println!("Could not draw the rank card of {}: {why:?}", user_id);
+            msg.reply(
+                ctx,
+                format!(
+                    "**{}**: rank #{}, level {}, {} / {} XP",
+                    name,
+                    position + 1,
+                    level,
+                    xp,
+                    needed
+                ),
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[command]
+#[aliases("lb", "top")]
+#[description = "Shows the members with the most XP."]
+#[usage = "[page]"]
+async fn leaderboard(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let config = guild_config(ctx, guild_id).await;
+    let ranking = ranking(ctx, guild_id).await;
+    if ranking.is_empty() {
+        msg.reply(ctx, "Nobody has any XP yet.").await?;
+        return Ok(());
+    }
+    let page = args.single::<usize>().unwrap_or(1);
+
+    let entries = ranking
+        .iter()
+        .enumerate()
+        .map(|(index, (user, xp))| {
+            format!(
+                "**#{}** <@{}> level {} ({} XP)",
+                index + 1,
+                user,
+                config.curve.level_for(*xp),
+                xp
+            )
+        })
+        .collect();
+    Paginator::new("Leaderboard", entries)
+        .start_at(page)
+        .send(ctx, msg)
+        .await
+}
+
+#[command]
+#[description = "Shows the leveling settings of this server."]
+async fn status(ctx: &Context, msg: &Message) -> CommandResult {
+    let config = guild_config(ctx, msg.guild_id.unwrap()).await;
+
+    let mut contents = String::new();
+    writeln!(
+        contents,
+        "Enabled: {}",
+        if config.enabled { "yes" } else { "no" }
+    )?;
+    writeln!(
+        contents,
+        "XP per message: {} to {}",
+        config.min_xp, config.max_xp
+    )?;
+    writeln!(contents, "Cooldown: {} seconds", config.cooldown_secs)?;
+    writeln!(
+        contents,
+        "Curve: {}·L² + {}·L + {} XP per level (level 10 at {} XP)",
+        config.curve.quadratic,
+        config.curve.linear,
+        config.curve.constant,
+        config.curve.total_for_level(10)
+    )?;
+    let announce = match (config.announce, config.announce_channel) {
+        (false, _) => String::from("off"),
+        (true, Some(channel)) => format!("<#{}>", channel),
+        (true, None) => String::from("in the channel of the message"),
+    };
+    writeln!(contents, "Level up messages: {}", announce)?;
+    let rewards = config
+        .rewards
+        .iter()
+        .map(|(level, role)| format!("{}: <@&{}>", level, role))
+        .collect::<Vec<_>>();
+    writeln!(
+        contents,
+        "Rewards ({}): {}",
+        if config.stack_rewards {
+            "stacked"
+        } else {
+            "highest only"
+        },
+        if rewards.is_empty() {
+            String::from("none")
+        } else {
+            rewards.join(", ")
+        }
+    )?;
+    let excluded = config
+        .excluded_channels
+        .iter()
+        .map(|channel| format!("<#{}>", channel))
+        .chain(
+            config
+                .excluded_roles
+                .iter()
+                .map(|role| format!("<@&{}>", role)),
+        )
+        .collect::<Vec<_>>();
+    writeln!(
+        contents,
+        "Excluded: {}",
+        if excluded.is_empty() {
+            String::from("nothing")
+        } else {
+            excluded.join(", ")
+        }
+    )?;
+
+    msg.channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new().embed(CreateEmbed::new().title("Leveling").description(contents)),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Turns XP gain on."]
+async fn enable(ctx: &Context, msg: &Message) -> CommandResult {
+    update_config(ctx, msg.guild_id.unwrap(), |config| config.enabled = true).await?;
+    msg.reply(ctx, "Members now get XP for chatting.").await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Turns XP gain off. Existing XP is kept."]
+async fn disable(ctx: &Context, msg: &Message) -> CommandResult {
+    update_config(ctx, msg.guild_id.unwrap(), |config| config.enabled = false).await?;
+    msg.reply(ctx, "Members no longer get XP.").await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Sets how much XP a message gives, a random amount between min and max."]
+#[usage = "<min> <max>"]
+async fn gain(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (Ok(min), Ok(max)) = (args.single::<u64>(), args.single::<u64>()) else {
+        msg.reply(ctx, "Usage: `levels gain <min> <max>`").await?;
+        return Ok(());
+    };
+    if min > max || max > 1000 {
+        msg.reply(
+            ctx,
+            "The minimum can't be above the maximum, and the maximum is 1000.",
+        )
+        .await?;
+        return Ok(());
+    }
+    update_config(ctx, msg.guild_id.unwrap(), |config| {
+        config.min_xp = min;
+        config.max_xp = max;
+    })
+    .await?;
+    msg.reply(ctx, format!("Messages now give {} to {} XP.", min, max))
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Sets how many seconds have to pass before a member gets XP again."]
+#[usage = "<seconds>"]
+async fn cooldown(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Some(seconds) = args
+        .single::<u64>()
+        .ok()
+        .filter(|seconds| *seconds <= MAX_COOLDOWN_SECS)
+    else {
+        msg.reply(
+            ctx,
+            format!(
+                "Usage: `levels cooldown <seconds>`, at most {} seconds.",
+                MAX_COOLDOWN_SECS
+            ),
+        )
+        .await?;
+        return Ok(());
+    };
+    update_config(ctx, msg.guild_id.unwrap(), |config| {
+        config.cooldown_secs = seconds
+    })
+    .await?;
+    msg.reply(ctx, format!("The XP cooldown is now {} seconds.", seconds))
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Sets the level curve: going from level L to L+1 takes `a·L² + b·L + c` XP. \
+Levels are recalculated from the stored XP, reward roles follow the next time someone levels up."]
+#[usage = "<a> <b> <c>"]
+#[example = "5 50 100"]
+async fn curve(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (Ok(quadratic), Ok(linear), Ok(constant)) = (
+        args.single::<u64>(),
+        args.single::<u64>(),
+        args.single::<u64>(),
+    ) else {
+        msg.reply(
+            ctx,
+            "Usage: `levels curve <a> <b> <c>`, the default is `5 50 100`.",
+        )
+        .await?;
+        return Ok(());
+    };
+    if constant == 0 {
+        msg.reply(ctx, "`c` has to be at least 1.").await?;
+        return Ok(());
+    }
+    let curve = LevelCurve {
+        quadratic,
+        linear,
+        constant,
+    };
+    let examples = [1, 5, 10, 50]
+        .iter()
+        .map(|level| format!("level {} at {} XP", level, curve.total_for_level(*level)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    update_config(ctx, msg.guild_id.unwrap(), |config| config.curve = curve).await?;
+    msg.reply(ctx, format!("Level curve updated: {}.", examples))
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Sets the role given when reaching a level, or removes it with `off`."]
+#[usage = "<level> <@role|off>"]
+async fn reward(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap();
+    let level = args
+        .single::<u32>()
+        .ok()
+        .filter(|level| (1..=MAX_LEVEL).contains(level));
+    let target = args.single::<String>().unwrap_or_default();
+    let Some(level) = level else {
+        msg.reply(ctx, "Usage: `levels reward <level> <@role|off>`")
+            .await?;
+        return Ok(());
+    };
+    if target == "off" {
+        update_config(ctx, guild_id, |config| {
+            config.rewards.remove(&level);
+        })
+        .await?;
+        msg.reply(ctx, format!("Level {} no longer gives a role.", level))
+            .await?;
+        return Ok(());
+    }
+    let Some(role) = parse_role_mention(&target).or_else(|| target.parse().ok()) else {
+        msg.reply(ctx, "Usage: `levels reward <level> <@role|off>`")
+            .await?;
+        return Ok(());
+    };
+    if let Some(problem) = setup_problem(ctx, guild_id, msg.author.id, role) {
+        msg.channel_id
+            .send_message(
+                &ctx.http,
+                CreateMessage::new()
+                    .content(problem)
+                    .allowed_mentions(CreateAllowedMentions::new()),
+            )
+            .await?;
+        return Ok(());
+    }
+    update_config(ctx, guild_id, |config| {
+        config.rewards.insert(level, role);
+    })
+    .await?;
+    msg.channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(format!("Reaching level {} now gives <@&{}>.", level, role))
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Sets whether members keep the reward roles of lower levels."]
+#[usage = "<on|off>"]
+async fn stack(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let stack = match args.single::<String>().unwrap_or_default().as_str() {
+        "on" => true,
+        "off" => false,
+        _ => {
+            msg.reply(ctx, "Usage: `levels stack <on|off>`").await?;
+            return Ok(());
+        }
+    };
+    update_config(ctx, msg.guild_id.unwrap(), |config| {
+        config.stack_rewards = stack
+    })
+    .await?;
+    let response = if stack {
+        "Members keep the rewards of lower levels."
+    } else {
+        "Members only keep the reward of their highest level."
+    };
+    msg.reply(ctx, response).await?;
+    Ok(())
+}
+
+/// Adds the channels and roles in `args` to the exclusions, or removes them from it.
+async fn change_exclusions(
+    ctx: &Context,
+    msg: &Message,
+    args: Args,
+    exclude: bool,
+) -> CommandResult {
+    let mut channels = Vec::new();
+    let mut roles = Vec::new();
+    for arg in args.raw() {
+        if let Some(channel) = parse_channel_mention(arg) {
+            channels.push(channel);
+        } else if let Some(role) = parse_role_mention(arg) {
+            roles.push(role);
+        }
+    }
+    if channels.is_empty() && roles.is_empty() {
+        msg.reply(ctx, "You need to mention channels or roles.")
+            .await?;
+        return Ok(());
+    }
+    update_config(ctx, msg.guild_id.unwrap(), |config| {
+        config
+            .excluded_channels
+            .retain(|channel| !channels.contains(channel));
+        config.excluded_roles.retain(|role| !roles.contains(role));
+        if exclude {
+            config.excluded_channels.extend(&channels);
+            config.excluded_roles.extend(&roles);
+        }
+    })
+    .await?;
+    msg.react(ctx, '✅').await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Stops messages in these channels, or from members with these roles, from giving XP."]
+#[usage = "<#channel|@role>..."]
+async fn exclude(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    change_exclusions(ctx, msg, args, true).await
+}
+
+#[command]
+#[description = "Lets excluded channels and roles give XP again."]
+#[usage = "<#channel|@role>..."]
+async fn include(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    change_exclusions(ctx, msg, args, false).await
+}
+
+#[command]
+#[description = "Sets where level ups are announced: a channel, `here` for the channel of the \
+message, or `off`."]
+#[usage = "<#channel|here|off>"]
+async fn announce(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let arg = args.single::<String>().unwrap_or_default();
+    let (announce, channel) = match arg.as_str() {
+        "off" => (false, None),
+        "here" => (true, None),
+        _ => match parse_channel(&arg) {
+            Some(channel) if is_text_channel(ctx, guild_id, channel) => (true, Some(channel)),
+            _ => {
+                msg.reply(ctx, "Usage: `levels announce <#channel|here|off>`")
+                    .await?;
+                return Ok(());
+            }
+        },
+    };
+    update_config(ctx, guild_id, |config| {
+        config.announce = announce;
+        config.announce_channel = channel;
+    })
+    .await?;
+    msg.react(ctx, '✅').await?;
+    Ok(())
+}
+
+/// Sets the XP of a member to `xp` and fixes their reward roles.
+async fn set_member_xp(ctx: &Context, msg: &Message, user: UserId, xp: u64) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let config = guild_config(ctx, guild_id).await;
+    update_xp(ctx, guild_id, user, |_| xp).await;
+    save_experience(&ctx.data).await?;
+    let level = config.curve.level_for(xp);
+    if let Ok(member) = guild_id.member(ctx, user).await {
+        sync_rewards(ctx, guild_id, user, &member.roles, level, &config).await?;
+    }
+    msg.channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(format!("<@{}> now has {} XP, level {}.", user, xp, level))
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Sets the XP of a member."]
+#[usage = "<@user> <xp>"]
+async fn setxp(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let user = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_user(&arg));
+    let (Some(user), Ok(xp)) = (user, args.single::<u64>()) else {
+        msg.reply(ctx, "Usage: `levels setxp <@user> <xp>`").await?;
+        return Ok(());
+    };
+    set_member_xp(ctx, msg, user, xp).await
+}
+
+#[command]
+#[description = "Sets a member to the start of a level."]
+#[usage = "<@user> <level>"]
+async fn setlevel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let user = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_user(&arg));
+    let level = args
+        .single::<u32>()
+        .ok()
+        .filter(|level| *level <= MAX_LEVEL);
+    let (Some(user), Some(level)) = (user, level) else {
+        msg.reply(ctx, "Usage: `levels setlevel <@user> <level>`")
+            .await?;
+        return Ok(());
+    };
+    let xp = guild_config(ctx, guild_id)
+        .await
+        .curve
+        .total_for_level(level);
+    set_member_xp(ctx, msg, user, xp).await
+}
+
+#[command]
+#[description = "Resets the XP of a member, or of everyone with `all`. Reward roles are only \
+taken from a single member, not with `all`."]
+#[usage = "<@user|all>"]
+async fn reset(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let arg = args.single::<String>().unwrap_or_default();
+    if arg == "all" {
+        {
+            let mut data = ctx.data.write().await;
+            data.get_mut::<Experience>()
+                .expect("Expected Experience in TypeMap.")
+                .remove(&guild_id);
+            data.insert::<ExperienceChanged>(true);
+        }
+        save_experience(&ctx.data).await?;
+        msg.reply(ctx, "Everyone's XP was reset.").await?;
+        return Ok(());
+    }
+    let Some(user) = parse_user(&arg) else {
+        msg.reply(ctx, "Usage: `levels reset <@user|all>`").await?;
+        return Ok(());
+    };
+    set_member_xp(ctx, msg, user, 0).await
+}
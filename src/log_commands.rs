@@ -0,0 +1,279 @@
+use crate::command_base::*;
+use crate::storage::save_json;
+
+use serde::{Deserialize, Serialize};
+use serenity::all::CreateMessage;
+use serenity::model::id::{ChannelId, GuildId};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+pub const LOG_CONFIGS_PATH: &str = "data/log_configs.json";
+
+#[group]
+#[prefixes("log")]
+#[commands(channel, ignore, unignore, status)]
+#[required_permissions(MANAGE_GUILD)]
+#[only_in(guilds)]
+pub struct Log;
+
+/// The kinds of server events that can be logged, each to its own channel.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LogCategory {
+    /// Message edits and deletions.
+    Messages,
+    /// Nickname, role and avatar changes of members.
+    Members,
+    /// Channels being created, deleted and edited.
+    Channels,
+    /// Roles being created, deleted and edited.
+    Roles,
+    /// Members joining, leaving and moving between voice channels.
+    Voice,
+    /// Bans and unbans.
+    Bans,
+}
+
+impl LogCategory {
+    pub const ALL: [LogCategory; 6] = [
+        LogCategory::Messages,
+        LogCategory::Members,
+        LogCategory::Channels,
+        LogCategory::Roles,
+        LogCategory::Voice,
+        LogCategory::Bans,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LogCategory::Messages => "messages",
+            LogCategory::Members => "members",
+            LogCategory::Channels => "channels",
+            LogCategory::Roles => "roles",
+            LogCategory::Voice => "voice",
+            LogCategory::Bans => "bans",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<LogCategory> {
+        LogCategory::ALL
+            .into_iter()
+            .find(|category| category.name() == name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct GuildLogConfig {
+    pub channels: BTreeMap<LogCategory, ChannelId>,
+    /// Events in these channels are never logged.
+    pub ignored: Vec<ChannelId>,
+}
+
+pub struct LogConfigs;
+
+impl TypeMapKey for LogConfigs {
+    type Value = HashMap<GuildId, GuildLogConfig>;
+}
+
+/// The channel events of `category` are logged in, unless the event happened in `source`, which
+/// is ignored or the log channel itself.
+pub async fn log_channel(
+    ctx: &Context,
+    guild_id: GuildId,
+    category: LogCategory,
+    source: Option<ChannelId>,
+) -> Option<ChannelId> {
+    let data = ctx.data.read().await;
+    let config = data
+        .get::<LogConfigs>()
+        .expect("Expected LogConfigs in TypeMap.")
+        .get(&guild_id)?;
+    let channel = *config.channels.get(&category)?;
+    match source {
+        Some(source) if source == channel || config.ignored.contains(&source) => None,
+        _ => Some(channel),
+    }
+}
+
+/// Posts `message` in the log channel of `category`, if the guild has one.
+pub async fn send_log(
+    ctx: &Context,
+    guild_id: GuildId,
+    category: LogCategory,
+    source: Option<ChannelId>,
+    message: CreateMessage,
+) -> CommandResult {
+    if let Some(channel) = log_channel(ctx, guild_id, category, source).await {
+        channel.send_message(&ctx.http, message).await?;
+    }
+    Ok(())
+}
+
+/// Shortens `text` to at most `max` characters, for embed fields.
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut short = text.chars().take(max - 1).collect::<String>();
+    short.push('…');
+    short
+}
+
+/// Applies `change` to the log config of the guild and saves all configs.
+async fn update_config(
+    ctx: &Context,
+    guild_id: GuildId,
+    change: impl FnOnce(&mut GuildLogConfig),
+) -> CommandResult {
+    let configs = {
+        let mut data = ctx.data.write().await;
+        let configs = data
+            .get_mut::<LogConfigs>()
+            .expect("Expected LogConfigs in TypeMap.");
+        change(configs.entry(guild_id).or_default());
+        configs.clone()
+    };
+    save_json(LOG_CONFIGS_PATH, &configs).await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Sets the channel a category of events is logged in, or turns it off with `off`. \
+`all` sets every category at once."]
+#[usage = "<category|all> <#channel|off>"]
+async fn channel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let name = args.single::<String>().unwrap_or_default();
+    let categories = if name == "all" {
+        LogCategory::ALL.to_vec()
+    } else {
+        LogCategory::from_name(&name).into_iter().collect()
+    };
+    let guild_id = msg.guild_id.unwrap();
+    let target = args.single::<String>().unwrap_or_default();
+    let channel = parse_channel(&target).filter(|&channel| is_text_channel(ctx, guild_id, channel));
+    if categories.is_empty() || (channel.is_none() && target != "off") {
+        let names = LogCategory::ALL.map(LogCategory::name).join(", ");
+        msg.reply(
+            ctx,
+            format!(
+                "Usage: `log channel <category|all> <#channel|off>`, categories are: {}",
+                names
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    update_config(ctx, guild_id, |config| {
+        for category in &categories {
+            match channel {
+                Some(channel) => config.channels.insert(*category, channel),
+                None => config.channels.remove(category),
+            };
+        }
+    })
+    .await?;
+    let reply = match channel {
+        Some(channel) => format!("Logging `{}` in <#{}>.", name, channel),
+        None => format!("No longer logging `{}`.", name),
+    };
+    msg.channel_id.say(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Stops logging events in a channel."]
+#[usage = "<#channel>"]
+async fn ignore(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap();
+    let Some(channel) = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_channel(&arg))
+        .filter(|channel| {
+            guild_id
+                .to_guild_cached(&ctx.cache)
+                .is_some_and(|guild| guild.channels.contains_key(channel))
+        })
+    else {
+        msg.reply(ctx, "You need to provide a channel of this server!")
+            .await?;
+        return Ok(());
+    };
+
+    update_config(ctx, guild_id, |config| {
+        if !config.ignored.contains(&channel) {
+            config.ignored.push(channel);
+        }
+    })
+    .await?;
+    msg.channel_id
+        .say(
+            &ctx.http,
+            format!("Events in <#{}> are not logged.", channel),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Logs events in a channel again."]
+#[usage = "<#channel>"]
+async fn unignore(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Some(channel) = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_channel(&arg))
+    else {
+        msg.reply(ctx, "You need to provide a channel!").await?;
+        return Ok(());
+    };
+
+    update_config(ctx, msg.guild_id.unwrap(), |config| {
+        config.ignored.retain(|ignored| *ignored != channel)
+    })
+    .await?;
+    msg.channel_id
+        .say(
+            &ctx.http,
+            format!("Events in <#{}> are logged again.", channel),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Shows where each category of events is logged."]
+async fn status(ctx: &Context, msg: &Message) -> CommandResult {
+    let config = {
+        let data = ctx.data.read().await;
+        data.get::<LogConfigs>()
+            .expect("Expected LogConfigs in TypeMap.")
+            .get(&msg.guild_id.unwrap())
+            .cloned()
+            .unwrap_or_default()
+    };
+
+    let mut contents = String::new();
+    for category in LogCategory::ALL {
+        match config.channels.get(&category) {
+            Some(channel) => writeln!(contents, "{}: <#{}>", category.name(), channel)?,
+            None => writeln!(contents, "{}: off", category.name())?,
+        }
+    }
+    if !config.ignored.is_empty() {
+        let ignored = config
+            .ignored
+            .iter()
+            .map(|channel| format!("<#{}>", channel))
+            .collect::<Vec<_>>();
+        writeln!(contents, "Ignored channels: {}", ignored.join(", "))?;
+    }
+    msg.channel_id.say(&ctx.http, contents).await?;
+
+    Ok(())
+}
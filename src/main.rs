@@ -5,7 +5,7 @@
 //! ```toml
 //! [dependencies.serenity]
 //! git = "https://github.com/serenity-rs/serenity.git"
-//! features = ["framework", "standard_framework"]
+//! features = ["collector", "framework", "standard_framework"]
 //! ```
 #![allow(deprecated)]
 use dotenv::dotenv;
@@ -13,17 +13,103 @@ use serenity::framework::standard::{BucketBuilder, Configuration, StandardFramew
 use serenity::http::Http;
 use std::env;
 
+mod afk_commands;
+mod announcement_commands;
+mod antiraid;
+mod antiraid_commands;
+mod automod;
+mod automod_commands;
+mod autoresponse_commands;
 mod command_base;
+mod economy;
+mod economy_commands;
 mod emoji_commands;
+mod event_log;
+mod game_commands;
+mod games;
 mod general_commands;
+mod giveaway;
+mod giveaway_commands;
+mod image_edit;
+mod info_commands;
+mod leveling;
+mod leveling_commands;
+mod log_commands;
+mod message_log;
+mod picture_catalog;
 mod picture_commands;
+mod poll_commands;
+mod recurrence;
+mod reminder_commands;
+mod role_menu_commands;
+mod scheduler;
+mod self_role_commands;
 mod moderator_commands;
+mod paginator;
+mod storage;
+mod suggestion_commands;
+mod tag_commands;
+mod ticket_commands;
+mod time_parse;
+mod welcome_commands;
 
+use afk_commands::*;
+use announcement_commands::*;
+use antiraid_commands::*;
+use automod_commands::*;
+use autoresponse_commands::*;
 use command_base::*;
+use economy_commands::*;
 use emoji_commands::*;
+use game_commands::*;
 use general_commands::*;
+use giveaway_commands::*;
+use info_commands::*;
+use leveling_commands::*;
+use log_commands::*;
+use message_log::{clear_attachment_cache, MessageCacheContainer};
+use picture_catalog::{load_catalog, load_pending, PendingPictures, PictureBags, PictureCatalog};
 use picture_commands::*;
+use poll_commands::*;
+use reminder_commands::*;
+use role_menu_commands::*;
+use self_role_commands::*;
+use suggestion_commands::*;
 use moderator_commands::*;
+use tag_commands::*;
+use ticket_commands::*;
+use welcome_commands::*;
+
+/// Every command group of the bot, in the order they are registered with the framework.
+pub static GROUPS: &[&CommandGroup] = &[
+    &GENERAL_GROUP,
+    &EMOJI_GROUP,
+    &PIC_GROUP,
+    &MOD_GROUP,
+    &TAG_GROUP,
+    &AUTORESPONSE_GROUP,
+    &AUTOMOD_GROUP,
+    &ANTIRAID_GROUP,
+    &WELCOME_GROUP,
+    &ROLES_GROUP,
+    &ROLE_GROUP,
+    &LOG_GROUP,
+    &REMIND_GROUP,
+    &ANNOUNCE_GROUP,
+    &POLL_GROUP,
+    &GIVEAWAY_GROUP,
+    &LEVELS_GROUP,
+    &LEVELING_GROUP,
+    &ECONOMY_GROUP,
+    &ECO_GROUP,
+    &GAME_GROUP,
+    &INFO_GROUP,
+    &AFK_GROUP,
+    &TICKETS_GROUP,
+    &TICKET_GROUP,
+    &SUGGEST_GROUP,
+    &SUGGESTION_GROUP,
+];
 
 #[tokio::main]
 async fn main() {
@@ -51,7 +137,7 @@ async fn main() {
         Err(why) => panic!("Could not access application info: {:?}", why),
     };
 
-    let framework = StandardFramework::new()
+    let mut framework = StandardFramework::new()
         // Set a function to be called prior to each command execution. This provides the context
         // of the command, the message that was received, and the full name of the command that
         // will be called.
@@ -102,11 +188,10 @@ async fn main() {
         // The `#[group]` macro generates `static` instances of the options set for the group.
         // They're made in the pattern: `#name_GROUP` for the group instance and `#name_GROUP_OPTIONS`.
         // #name is turned all uppercase
-        .help(&MY_HELP)
-        .group(&GENERAL_GROUP)
-        .group(&EMOJI_GROUP)
-        .group(&PIC_GROUP)
-        .group(&MOD_GROUP);
+        .help(&MY_HELP);
+    for group in GROUPS {
+        framework = framework.group(group);
+    }
 
     framework.configure(
         Configuration::new()
@@ -128,10 +213,133 @@ async fn main() {
     // You will need to enable these 2 options on the bot application, and possibly wait up to 5
     // minutes.
     let intents = GatewayIntents::all();
+    clear_attachment_cache()
+        .await
+        .expect("Could not clear the attachment cache");
     let mut client = Client::builder(&token, intents)
         .event_handler(Handler)
         .framework(framework)
         .type_map_insert::<CommandCounter>(HashMap::default())
+        .type_map_insert::<PictureCatalog>(load_catalog().await)
+        .type_map_insert::<PendingPictures>(load_pending().await)
+        .type_map_insert::<PictureBags>(HashMap::default())
+        .type_map_insert::<EmojiResponses>(
+            storage::load_json(RESPONSES_PATH)
+                .await
+                .expect("Could not read the emoji responses"),
+        )
+        .type_map_insert::<Tags>(
+            storage::load_json(TAGS_PATH)
+                .await
+                .expect("Could not read the tags"),
+        )
+        .type_map_insert::<AutoResponses>(
+            storage::load_json(AUTORESPONSES_PATH)
+                .await
+                .expect("Could not read the auto-responses"),
+        )
+        .type_map_insert::<AutoResponseStateContainer>(Arc::new(Mutex::new(
+            AutoResponseState::default(),
+        )))
+        .type_map_insert::<ModSettings>(
+            storage::load_json(MOD_SETTINGS_PATH)
+                .await
+                .expect("Could not read the moderation settings"),
+        )
+        .type_map_insert::<AutomodConfigs>(
+            storage::load_json(AUTOMOD_PATH)
+                .await
+                .expect("Could not read the automod configs"),
+        )
+        .type_map_insert::<AutomodSpam>(Arc::new(Mutex::new(
+            automod::SpamTracker::default(),
+        )))
+        .type_map_insert::<AntiRaidConfigs>(
+            storage::load_json(ANTIRAID_PATH)
+                .await
+                .expect("Could not read the anti-raid configs"),
+        )
+        .type_map_insert::<AntiRaidStateContainer>(AntiRaidState::default())
+        .type_map_insert::<WelcomeConfigs>(
+            storage::load_json(WELCOME_PATH)
+                .await
+                .expect("Could not read the welcome configs"),
+        )
+        .type_map_insert::<RoleMenus>(
+            storage::load_json(ROLE_MENUS_PATH)
+                .await
+                .expect("Could not read the role menus"),
+        )
+        .type_map_insert::<SelfRoles>(
+            storage::load_json(SELF_ROLES_PATH)
+                .await
+                .expect("Could not read the self-assignable roles"),
+        )
+        .type_map_insert::<LogConfigs>(
+            storage::load_json(LOG_CONFIGS_PATH)
+                .await
+                .expect("Could not read the log configs"),
+        )
+        .type_map_insert::<MessageCacheContainer>(Default::default())
+        .type_map_insert::<Reminders>(
+            storage::load_json(REMINDERS_PATH)
+                .await
+                .expect("Could not read the reminders"),
+        )
+        .type_map_insert::<UserTimezones>(
+            storage::load_json(TIMEZONES_PATH)
+                .await
+                .expect("Could not read the user time zones"),
+        )
+        .type_map_insert::<Announcements>(
+            storage::load_json(ANNOUNCEMENTS_PATH)
+                .await
+                .expect("Could not read the announcements"),
+        )
+        .type_map_insert::<Polls>(
+            storage::load_json(POLLS_PATH)
+                .await
+                .expect("Could not read the polls"),
+        )
+        .type_map_insert::<Giveaways>(
+            storage::load_json(GIVEAWAYS_PATH)
+                .await
+                .expect("Could not read the giveaways"),
+        )
+        .type_map_insert::<LevelConfigs>(
+            storage::load_json(LEVEL_CONFIGS_PATH)
+                .await
+                .expect("Could not read the level configs"),
+        )
+        .type_map_insert::<Experience>(
+            storage::load_json(EXPERIENCE_PATH)
+                .await
+                .expect("Could not read the experience"),
+        )
+        .type_map_insert::<ExperienceChanged>(false)
+        .type_map_insert::<TagUsesChanged>(false)
+        .type_map_insert::<XpCooldowns>(HashMap::default())
+        .type_map_insert::<Economies>(
+            load_economies()
+                .await
+                .expect("Could not read the economy"),
+        )
+        .type_map_insert::<ActiveGames>(Arc::default())
+        .type_map_insert::<AfkUsers>(
+            storage::load_json(AFK_PATH)
+                .await
+                .expect("Could not read the AFK statuses"),
+        )
+        .type_map_insert::<SupportTickets>(
+            storage::load_json(TICKETS_PATH)
+                .await
+                .expect("Could not read the tickets"),
+        )
+        .type_map_insert::<Suggestions>(
+            storage::load_json(SUGGESTIONS_PATH)
+                .await
+                .expect("Could not read the suggestions"),
+        )
         .await
         .expect("Err creating client");
 
@@ -140,6 +348,12 @@ async fn main() {
         data.insert::<ShardManagerContainer>(Arc::clone(&client.shard_manager));
     }
 
+    tokio::spawn(scheduler::run(scheduler::SchedulerContext {
+        http: Arc::clone(&client.http),
+        cache: Arc::clone(&client.cache),
+        data: Arc::clone(&client.data),
+    }));
+
     if let Err(why) = client.start().await {
         println!("Client error: {why:?}");
     }
@@ -0,0 +1,524 @@
+use crate::command_base::*;
+use crate::log_commands::{log_channel, send_log, truncate, LogCategory};
+
+use serenity::all::{
+    CreateAttachment, CreateEmbed, CreateEmbedAuthor, CreateMessage, MessageUpdateEvent, Timestamp,
+};
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Where attachments of recent messages are kept, so they can be re-uploaded once deleted.
+pub const ATTACHMENT_CACHE_DIR: &str = "data/attachment_cache";
+const MAX_CACHED_MESSAGES: usize = 10_000;
+const MAX_CACHED_ATTACHMENT_BYTES: u32 = 8 * 1024 * 1024;
+/// What the bot may upload in one message, a cached attachment always fits.
+const MAX_UPLOAD_BYTES: u64 = 8 * 1024 * 1024;
+const MAX_FILES_PER_MESSAGE: usize = 10;
+/// Cached attachments are deleted after this long, the text of the message stays cached.
+const ATTACHMENT_TTL: Duration = Duration::from_secs(60 * 60);
+
+pub struct CachedAttachment {
+    pub filename: String,
+    pub size: u32,
+    /// The local copy, until it expires.
+    pub path: Option<PathBuf>,
+}
+
+pub struct CachedMessage {
+    pub author: UserId,
+    pub author_name: String,
+    pub author_avatar: String,
+    pub content: String,
+    pub attachments: Vec<CachedAttachment>,
+    pub sent: Timestamp,
+}
+
+/// Recent messages of logged channels. Message ids grow with time, so the map is ordered oldest
+/// first.
+#[derive(Default)]
+pub struct MessageCache {
+    messages: BTreeMap<MessageId, CachedMessage>,
+    /// Messages with local attachment copies, by when they were cached.
+    with_files: VecDeque<(Instant, MessageId)>,
+}
+
+impl MessageCache {
+    /// Adds a message and returns the files that are no longer needed.
+    fn insert(&mut self, id: MessageId, message: CachedMessage) -> Vec<PathBuf> {
+        let now = Instant::now();
+        if message.attachments.iter().any(|a| a.path.is_some()) {
+            self.with_files.push_back((now, id));
+        }
+        let mut expired = Vec::new();
+        if let Some(replaced) = self.messages.insert(id, message) {
+            expired.extend(replaced.attachments.into_iter().filter_map(|a| a.path));
+        }
+        while self.messages.len() > MAX_CACHED_MESSAGES {
+            if let Some((_, message)) = self.messages.pop_first() {
+                expired.extend(message.attachments.into_iter().filter_map(|a| a.path));
+            }
+        }
+        while let Some((cached_at, id)) = self.with_files.front().copied() {
+            if now.duration_since(cached_at) < ATTACHMENT_TTL {
+                break;
+            }
+            self.with_files.pop_front();
+            if let Some(message) = self.messages.get_mut(&id) {
+                expired.extend(message.attachments.iter_mut().filter_map(|a| a.path.take()));
+            }
+        }
+        expired
+    }
+
+    fn remove(&mut self, id: MessageId) -> Option<CachedMessage> {
+        self.messages.remove(&id)
+    }
+}
+
+pub struct MessageCacheContainer;
+
+impl TypeMapKey for MessageCacheContainer {
+    type Value = MessageCache;
+}
+
+/// Throws away attachments left over from the last run, their messages are not cached anymore.
+pub async fn clear_attachment_cache() -> std::io::Result<()> {
+    match tokio::fs::remove_dir_all(ATTACHMENT_CACHE_DIR).await {
+        Err(why) if why.kind() != std::io::ErrorKind::NotFound => Err(why),
+        _ => Ok(()),
+    }
+}
+
+async fn remove_files(paths: Vec<PathBuf>) {
+    for path in paths {
+        if let Err(why) = tokio::fs::remove_file(&path).await {
+            println!(
+                "Could not remove cached attachment {}: {why:?}",
+                path.display()
+            );
+        }
+    }
+}
+
+/// Remembers a message of a logged channel, downloading its attachments.
+pub async fn handle_message(ctx: &Context, msg: &Message) -> CommandResult {
+    let Some(guild_id) = msg.guild_id else {
+        return Ok(());
+    };
+    if msg.author.bot
+        || log_channel(ctx, guild_id, LogCategory::Messages, Some(msg.channel_id))
+            .await
+            .is_none()
+    {
+        return Ok(());
+    }
+
+    let mut attachments = Vec::new();
+    for (index, attachment) in msg.attachments.iter().enumerate() {
+        let mut path = None;
+        if attachment.size <= MAX_CACHED_ATTACHMENT_BYTES {
+            // Only keep safe characters, the file name comes from the user.
+            let filename = attachment
+                .filename
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric() || *c == '.' || *c == '-' || *c == '_')
+                .collect::<String>();
+            let target = PathBuf::from(ATTACHMENT_CACHE_DIR)
+                .join(format!("{}-{}-{}", msg.id, index, filename));
+            match attachment.download().await {
+                Ok(bytes) => {
+                    tokio::fs::create_dir_all(ATTACHMENT_CACHE_DIR).await?;
+                    tokio::fs::write(&target, bytes).await?;
+                    path = Some(target);
+                }
+                Err(why) => println!("Could not cache attachment {}: {why:?}", attachment.url),
+            }
+        }
+        attachments.push(CachedAttachment {
+            filename: attachment.filename.clone(),
+            size: attachment.size,
+            path,
+        });
+    }
+
+    let message = CachedMessage {
+        author: msg.author.id,
+        author_name: msg.author.name.clone(),
+        author_avatar: msg.author.face(),
+        content: msg.content.clone(),
+        attachments,
+        sent: msg.timestamp,
+    };
+    let expired = {
+        let mut data = ctx.data.write().await;
+        data.get_mut::<MessageCacheContainer>()
+            .expect("Expected MessageCacheContainer in TypeMap.")
+            .insert(msg.id, message)
+    };
+    remove_files(expired).await;
+    Ok(())
+}
+
+/// Splits the local copies of `attachments` into groups that fit in one message each.
+fn upload_batches<'a>(
+    attachments: impl IntoIterator<Item = &'a CachedAttachment>,
+) -> Vec<Vec<PathBuf>> {
+    let mut batches = Vec::new();
+    let mut batch = Vec::new();
+    let mut batch_bytes = 0;
+    for attachment in attachments {
+        let Some(path) = &attachment.path else {
+            continue;
+        };
+        let size = u64::from(attachment.size);
+        if !batch.is_empty()
+            && (batch.len() == MAX_FILES_PER_MESSAGE || batch_bytes + size > MAX_UPLOAD_BYTES)
+        {
+            batches.push(std::mem::take(&mut batch));
+            batch_bytes = 0;
+        }
+        batch.push(path.clone());
+        batch_bytes += size;
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    batches
+}
+
+/// Re-uploads cached attachments after their log entry, one message per batch. The entry is
+/// already sent, so failures are only logged.
+async fn upload_files(
+    ctx: &Context,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    content: &str,
+    batches: &[Vec<PathBuf>],
+) {
+    for batch in batches {
+        let mut message = CreateMessage::new().content(content);
+        for path in batch {
+            match CreateAttachment::path(path).await {
+                Ok(file) => message = message.add_file(file),
+                Err(why) => println!("Could not re-upload {}: {why:?}", path.display()),
+            }
+        }
+        if let Err(why) = send_log(
+            ctx,
+            guild_id,
+            LogCategory::Messages,
+            Some(channel_id),
+            message,
+        )
+        .await
+        {
+            println!("Could not re-upload deleted attachments: {why:?}");
+        }
+    }
+}
+
+/// The CDN links of deleted messages stop working, so only the file name is listed.
+fn attachment_line(attachment: &CachedAttachment) -> String {
+    match attachment.path {
+        Some(_) => format!("{} (attached)", attachment.filename),
+        None => format!("{} (expired)", attachment.filename),
+    }
+}
+
+fn content_or_placeholder(content: &str) -> String {
+    if content.is_empty() {
+        String::from("*no text*")
+    } else {
+        truncate(content, 1024)
+    }
+}
+
+pub async fn handle_update(ctx: &Context, event: &MessageUpdateEvent) -> CommandResult {
+    let (Some(guild_id), Some(content)) = (event.guild_id, &event.content) else {
+        return Ok(());
+    };
+    if event.author.as_ref().is_some_and(|author| author.bot) {
+        return Ok(());
+    }
+
+    let before = {
+        let mut data = ctx.data.write().await;
+        data.get_mut::<MessageCacheContainer>()
+            .expect("Expected MessageCacheContainer in TypeMap.")
+            .messages
+            .get_mut(&event.id)
+            .map(|cached| std::mem::replace(&mut cached.content, content.clone()))
+    };
+    // Discord also sends updates when link previews load, those did not change the text.
+    if before.as_ref() == Some(content) {
+        return Ok(());
+    }
+
+    let mut embed = CreateEmbed::new()
+        .title("Message edited")
+        .description(format!(
+            "[Jump to message](https://discord.com/channels/{}/{}/{})",
+            guild_id, event.channel_id, event.id
+        ))
+        .field("Channel", format!("<#{}>", event.channel_id), true)
+        .field(
+            "Before",
+            before
+                .as_deref()
+                .map(content_or_placeholder)
+                .unwrap_or_else(|| String::from("*not cached*")),
+            false,
+        )
+        .field("After", content_or_placeholder(content), false)
+        .timestamp(event.edited_timestamp.unwrap_or_else(Timestamp::now));
+    if let Some(author) = &event.author {
+        embed = embed
+            .author(CreateEmbedAuthor::new(&author.name).icon_url(author.face()))
+            .field("Author", format!("<@{}>", author.id), true);
+    }
+    send_log(
+        ctx,
+        guild_id,
+        LogCategory::Messages,
+        Some(event.channel_id),
+        CreateMessage::new().embed(embed),
+    )
+    .await
+}
+
+pub async fn handle_delete(
+    ctx: &Context,
+    guild_id: Option<GuildId>,
+    channel_id: ChannelId,
+    message_id: MessageId,
+) -> CommandResult {
+    let Some(guild_id) = guild_id else {
+        return Ok(());
+    };
+    let cached = {
+        let mut data = ctx.data.write().await;
+        data.get_mut::<MessageCacheContainer>()
+            .expect("Expected MessageCacheContainer in TypeMap.")
+            .remove(message_id)
+    };
+    // Only messages of logged channels are cached, and there is nothing to say about the others.
+    let Some(cached) = cached else {
+        return Ok(());
+    };
+
+    let mut embed = CreateEmbed::new()
+        .title("Message deleted")
+        .author(CreateEmbedAuthor::new(&cached.author_name).icon_url(&cached.author_avatar))
+        .field("Channel", format!("<#{}>", channel_id), true)
+        .field("Author", format!("<@{}>", cached.author), true)
+        .field(
+            "Sent",
+            format!("<t:{}:f>", cached.sent.unix_timestamp()),
+            true,
+        )
+        .field("Content", content_or_placeholder(&cached.content), false)
+        .timestamp(Timestamp::now());
+    if !cached.attachments.is_empty() {
+        let mut list = String::new();
+        for attachment in &cached.attachments {
+            writeln!(list, "{}", attachment_line(attachment))?;
+        }
+        embed = embed.field("Attachments", truncate(&list, 1024), false);
+    }
+
+    let batches = upload_batches(&cached.attachments);
+    let result = send_log(
+        ctx,
+        guild_id,
+        LogCategory::Messages,
+        Some(channel_id),
+        CreateMessage::new().embed(embed),
+    )
+    .await;
+    if result.is_ok() {
+        upload_files(
+            ctx,
+            guild_id,
+            channel_id,
+            "Attachments of the deleted message above:",
+            &batches,
+        )
+        .await;
+    }
+    remove_files(batches.into_iter().flatten().collect()).await;
+    result
+}
+
+pub async fn handle_bulk_delete(
+    ctx: &Context,
+    guild_id: Option<GuildId>,
+    channel_id: ChannelId,
+    message_ids: &[MessageId],
+) -> CommandResult {
+    let Some(guild_id) = guild_id else {
+        return Ok(());
+    };
+    let mut cached = {
+        let mut data = ctx.data.write().await;
+        let cache = data
+            .get_mut::<MessageCacheContainer>()
+            .expect("Expected MessageCacheContainer in TypeMap.");
+        message_ids
+            .iter()
+            .filter_map(|id| cache.remove(*id).map(|message| (*id, message)))
+            .collect::<Vec<_>>()
+    };
+    cached.sort_by_key(|(id, _)| *id);
+
+    let mut transcript = String::new();
+    for (_, message) in &cached {
+        writeln!(
+            transcript,
+            "[{}] {} ({}): {}",
+            message.sent, message.author_name, message.author, message.content
+        )?;
+        for attachment in &message.attachments {
+            writeln!(
+                transcript,
+                "    attachment: {}",
+                attachment_line(attachment)
+            )?;
+        }
+    }
+    writeln!(
+        transcript,
+        "\n{} messages deleted, {} of them were not cached.",
+        message_ids.len(),
+        message_ids.len() - cached.len()
+    )?;
+
+    let embed = CreateEmbed::new()
+        .title("Messages bulk deleted")
+        .description(format!(
+            "{} messages were deleted in <#{}>.",
+            message_ids.len(),
+            channel_id
+        ))
+        .timestamp(Timestamp::now());
+    let message = CreateMessage::new()
+        .embed(embed)
+        .add_file(CreateAttachment::bytes(
+            transcript.into_bytes(),
+            format!("transcript-{}.txt", channel_id),
+        ));
+    let batches = upload_batches(cached.iter().flat_map(|(_, message)| &message.attachments));
+    let result = send_log(
+        ctx,
+        guild_id,
+        LogCategory::Messages,
+        Some(channel_id),
+        message,
+    )
+    .await;
+    if result.is_ok() {
+        upload_files(
+            ctx,
+            guild_id,
+            channel_id,
+            "Attachments of the bulk deleted messages above:",
+            &batches,
+        )
+        .await;
+    }
+    remove_files(batches.into_iter().flatten().collect()).await;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(content: &str, path: Option<&str>) -> CachedMessage {
+        CachedMessage {
+            author: UserId::new(1),
+            author_name: String::from("ferris"),
+            author_avatar: String::new(),
+            content: String::from(content),
+            attachments: path
+                .map(|path| CachedAttachment {
+                    filename: String::from("a.png"),
+                    size: 0,
+                    path: Some(PathBuf::from(path)),
+                })
+                .into_iter()
+                .collect(),
+            sent: Timestamp::now(),
+        }
+    }
+
+    #[test]
+    fn the_oldest_messages_are_dropped_first() {
+        let mut cache = MessageCache::default();
+        let mut expired = cache.insert(MessageId::new(1), message("first", Some("first.png")));
+        for id in 2..=MAX_CACHED_MESSAGES as u64 + 1 {
+            expired.extend(cache.insert(MessageId::new(id), message("", None)));
+        }
+        assert_eq!(expired, [PathBuf::from("first.png")]);
+        assert!(cache.remove(MessageId::new(1)).is_none());
+        assert!(cache.remove(MessageId::new(2)).is_some());
+        assert_eq!(cache.messages.len(), MAX_CACHED_MESSAGES - 1);
+    }
+
+    #[test]
+    fn removed_messages_are_returned_once() {
+        let mut cache = MessageCache::default();
+        cache.insert(MessageId::new(5), message("hello", None));
+        assert_eq!(
+            cache
+                .remove(MessageId::new(5))
+                .map(|message| message.content),
+            Some(String::from("hello"))
+        );
+        assert!(cache.remove(MessageId::new(5)).is_none());
+    }
+
+    fn attachment(path: Option<&str>, size: u32) -> CachedAttachment {
+        CachedAttachment {
+            filename: String::from("a.png"),
+            size,
+            path: path.map(PathBuf::from),
+        }
+    }
+
+    #[test]
+    fn uploads_are_split_to_fit_in_a_message() {
+        let big = MAX_CACHED_ATTACHMENT_BYTES;
+        let attachments = [
+            attachment(Some("1"), big),
+            attachment(None, big),
+            attachment(Some("2"), big / 2),
+            attachment(Some("3"), big / 2),
+        ];
+        assert_eq!(
+            upload_batches(&attachments),
+            [
+                vec![PathBuf::from("1")],
+                vec![PathBuf::from("2"), PathBuf::from("3")]
+            ]
+        );
+
+        let small = (0..MAX_FILES_PER_MESSAGE + 1)
+            .map(|_| attachment(Some("s"), 1))
+            .collect::<Vec<_>>();
+        let batches = upload_batches(&small);
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].len(), MAX_FILES_PER_MESSAGE);
+        assert!(upload_batches(&[attachment(None, 1)]).is_empty());
+    }
+
+    #[test]
+    fn replacing_a_message_frees_its_files() {
+        let mut cache = MessageCache::default();
+        cache.insert(MessageId::new(5), message("a", Some("old.png")));
+        let expired = cache.insert(MessageId::new(5), message("b", None));
+        assert_eq!(expired, [PathBuf::from("old.png")]);
+    }
+}
@@ -1,12 +1,91 @@
-use serenity::all::UserId;
+use serenity::all::{CreateEmbed, CreateMessage, UserId};
+use serenity::model::id::{ChannelId, GuildId};
 
 use crate::command_base::*;
+use crate::paginator::Paginator;
+use crate::storage::save_json;
+
+use serde::{Deserialize, Serialize};
+
+pub const MOD_SETTINGS_PATH: &str = "data/mod_settings.json";
 
 #[group]
 #[prefixes("mod")]
-#[commands(delete_msg, ban, kick, warn, checkwarns)]
+#[commands(delete_msg, ban, kick, warn, checkwarns, modlog)]
 pub struct Mod;
 
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct GuildModSettings {
+    /// Channel every automatic moderation action is reported in.
+    pub log_channel: Option<ChannelId>,
+}
+
+pub struct ModSettings;
+
+impl TypeMapKey for ModSettings {
+    type Value = HashMap<GuildId, GuildModSettings>;
+}
+
+/// Posts `embed` in the mod log channel of the guild, if it has one.
+pub async fn mod_log(ctx: &Context, guild_id: GuildId, embed: CreateEmbed) -> CommandResult {
+    mod_log_message(ctx, guild_id, CreateMessage::new().embed(embed)).await
+}
+
+/// Like `mod_log`, for messages that need more than an embed, e.g. to ping the moderators.
+pub async fn mod_log_message(
+    ctx: &Context,
+    guild_id: GuildId,
+    message: CreateMessage,
+) -> CommandResult {
+    let log_channel = {
+        let data = ctx.data.read().await;
+        data.get::<ModSettings>()
+            .expect("Expected ModSettings in TypeMap.")
+            .get(&guild_id)
+            .and_then(|settings| settings.log_channel)
+    };
+    if let Some(log_channel) = log_channel {
+        log_channel.send_message(&ctx.http, message).await?;
+    }
+    Ok(())
+}
+
+/// Whether the author of `msg` may manage messages in its guild. Automatic moderation leaves
+/// those members alone.
+pub fn is_moderator(ctx: &Context, msg: &Message) -> bool {
+    msg.guild(&ctx.cache).is_some_and(|guild| {
+        guild
+            .members
+            .get(&msg.author.id)
+            .is_some_and(|member| guild.member_permissions(member).manage_messages())
+    })
+}
+
+/// Appends a warning to the warnings file of `username`. `time` is when the warning was given.
+pub async fn add_warning(username: &str, time: &str, reason: &str) -> std::io::Result<()> {
+    let timed_reason = format!("[{}] {}", time, reason);
+
+    tokio::fs::create_dir_all("warnings").await?;
+    let path = format!("warnings/{}txt", username);
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await?;
+    use tokio::io::AsyncWriteExt;
+    file.write_all(format!("{}\n", timed_reason).as_bytes()).await?;
+    file.flush().await
+}
+
+/// The warnings of `username`, oldest first. Nobody warned yet has none.
+pub async fn read_warnings(username: &str) -> Vec<String> {
+    let path = format!("warnings/{}txt", username);
+    match tokio::fs::read_to_string(&path).await {
+        Ok(content) => content.lines().map(|line| line.to_string()).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
 #[command("del")]
 #[bucket = "mod"]
 #[required_permissions(MANAGE_MESSAGES)]
@@ -147,17 +226,7 @@ async fn warn(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
     };
 
     let time = msg.timestamp.format("%Y-%m-%d %H:%M:%S").to_string();
-    let timed_reason = format!("[{}] {}", time, reason);
-
-    let path = format!("warnings/{}txt", username);
-    let mut file = tokio::fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&path)
-        .await?;
-    use tokio::io::AsyncWriteExt;
-    file.write_all(format!("{}\n", timed_reason).as_bytes()).await?;
-    file.flush().await?;
+    add_warning(&username, &time, reason).await?;
 
     msg.channel_id
         .say(&ctx.http, format!("Warned <@{}> for {}", user, reason))
@@ -185,29 +254,59 @@ async fn checkwarns(ctx: &Context, msg: &Message, mut args: Args) -> CommandResu
         Some(member) => member.user.name.clone(),
         None => String::from("Unknown_user"),
     };
-    let path = format!("warnings/{}txt", username);
-    let content = match tokio::fs::read_to_string(&path).await {
-        Ok(content) => content,
-        Err(_) => {
-            msg.channel_id
-                .say(&ctx.http, format!("<@{}> has no warnings.", user))
-                .await?;
-            return Ok(());
-        }
-    };
-    let warnings = content.lines().map(|line| line.to_string()).collect::<Vec<String>>();
+    let warnings = read_warnings(&username).await;
     if warnings.is_empty() {
         msg.channel_id
             .say(&ctx.http, format!("<@{}> has no warnings.", user))
             .await?;
     } else {
-        let warning_list = warnings.join("\n");
-        msg.channel_id
-            .say(
-                &ctx.http,
-                format!("Warnings for <@{}>:\n{}", user, warning_list),
-            )
+        let count = warnings.len();
+        Paginator::new(format!("Warnings for {}", username), warnings)
+            .footer(format!("{} warning(s)", count))
+            .send(ctx, msg)
             .await?;
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+#[command("modlog")]
+#[description = "Sets the channel automatic moderation actions are logged in. Leave it out to \
+turn the mod log off."]
+#[usage = "[#channel]"]
+#[bucket = "mod"]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn modlog(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap();
+    let channel = match args.single::<String>() {
+        Err(_) => None,
+        Ok(arg) => match parse_channel(&arg) {
+            Some(channel) if is_text_channel(ctx, guild_id, channel) => Some(channel),
+            _ => {
+                msg.reply(ctx, "Usage: `mod modlog [#channel]`").await?;
+                return Ok(());
+            }
+        },
+    };
+
+    let settings = {
+        let mut data = ctx.data.write().await;
+        let settings = data
+            .get_mut::<ModSettings>()
+            .expect("Expected ModSettings in TypeMap.");
+        settings.entry(guild_id).or_default().log_channel = channel;
+        settings.clone()
+    };
+    save_json(MOD_SETTINGS_PATH, &settings).await?;
+
+    match channel {
+        Some(channel) => {
+            msg.channel_id
+                .say(&ctx.http, format!("Mod log set to <#{}>.", channel))
+                .await?
+        }
+        None => msg.channel_id.say(&ctx.http, "Mod log turned off.").await?,
+    };
+
+    Ok(())
+}
@@ -0,0 +1,280 @@
+use crate::command_base::*;
+
+use serenity::all::{
+    ActionRowComponent, ButtonStyle, ComponentInteraction, CreateActionRow, CreateButton,
+    CreateEmbed, CreateEmbedFooter, CreateInputText, CreateInteractionResponse,
+    CreateInteractionResponseMessage, CreateMessage, CreateModal, EditMessage, InputTextStyle,
+    ModalInteraction,
+};
+use serenity::futures::StreamExt;
+use std::time::Duration;
+use tokio::time::{sleep_until, Instant};
+
+const PAGE_PREFIX: &str = "page";
+/// Entries shown on one page.
+pub const PAGE_ENTRIES: usize = 10;
+/// Characters shown on one page, well below the 4096 an embed description can hold.
+const PAGE_CHARS: usize = 2000;
+/// The buttons stop working when nobody used them for this long.
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Splits `entries` into pages of at most `per_page` entries and `PAGE_CHARS` characters. Entries
+/// that don't fit on a page by themselves are cut.
+pub fn split_pages(entries: Vec<String>, per_page: usize) -> Vec<String> {
+    let mut pages = Vec::new();
+    let mut page = String::new();
+    let mut count = 0;
+    for entry in entries {
+        let entry = if entry.chars().count() > PAGE_CHARS {
+            let mut cut = entry.chars().take(PAGE_CHARS - 1).collect::<String>();
+            cut.push('…');
+            cut
+        } else {
+            entry
+        };
+        if count > 0
+            && (count == per_page.max(1)
+                || page.chars().count() + entry.chars().count() + 1 > PAGE_CHARS)
+        {
+            pages.push(std::mem::take(&mut page));
+            count = 0;
+        }
+        if count > 0 {
+            page.push('\n');
+        }
+        page.push_str(&entry);
+        count += 1;
+    }
+    if count > 0 {
+        pages.push(page);
+    }
+    pages
+}
+
+/// An embed that is split into pages. Only the member who ran the command can turn the pages.
+pub struct Paginator {
+    title: String,
+    pages: Vec<String>,
+    footer: Option<String>,
+    page: usize,
+}
+
+enum Turn {
+    Click(ComponentInteraction),
+    Jump(ModalInteraction),
+}
+
+impl Paginator {
+    /// One entry per line, `PAGE_ENTRIES` to a page.
+    pub fn new(title: impl Into<String>, entries: Vec<String>) -> Self {
+        Paginator::with_page_size(title, entries, PAGE_ENTRIES)
+    }
+
+    pub fn with_page_size(title: impl Into<String>, entries: Vec<String>, per_page: usize) -> Self {
+        Paginator {
+            title: title.into(),
+            pages: split_pages(entries, per_page),
+            footer: None,
+            page: 0,
+        }
+    }
+
+    /// Text shown after the page number.
+    pub fn footer(mut self, footer: impl Into<String>) -> Self {
+        self.footer = Some(footer.into());
+        self
+    }
+
+    /// The page shown first, counted from 1. Pages past the end show the last one.
+    pub fn start_at(mut self, page: usize) -> Self {
+        self.page = page.saturating_sub(1);
+        self
+    }
+
+    fn embed(&self) -> CreateEmbed {
+        let mut footer = format!("Page {} of {}", self.page + 1, self.pages.len().max(1));
+        if let Some(extra) = &self.footer {
+            footer.push_str(&format!(" · {}", extra));
+        }
+        CreateEmbed::new()
+            .title(&self.title)
+            .description(
+                self.pages
+                    .get(self.page)
+                    .map(String::as_str)
+                    .unwrap_or("Nothing to show."),
+            )
+            .footer(CreateEmbedFooter::new(footer))
+    }
+
+    fn components(&self) -> Vec<CreateActionRow> {
+        let last = self.pages.len() - 1;
+        let button = |id: &str, label: String, disabled: bool| {
+            CreateButton::new(format!("{}:{}", PAGE_PREFIX, id))
+                .label(label)
+                .style(ButtonStyle::Secondary)
+                .disabled(disabled)
+        };
+        vec![CreateActionRow::Buttons(vec![
+            button("first", String::from("⏮"), self.page == 0),
+            button("previous", String::from("◀"), self.page == 0),
+            button("jump", format!("{} / {}", self.page + 1, last + 1), false)
+                .style(ButtonStyle::Primary),
+            button("next", String::from("▶"), self.page == last),
+            button("last", String::from("⏭"), self.page == last),
+        ])]
+    }
+
+    fn jump_modal(&self) -> CreateModal {
+        CreateModal::new(format!("{}:jump", PAGE_PREFIX), "Jump to page").components(vec![
+            CreateActionRow::InputText(
+                CreateInputText::new(
+                    InputTextStyle::Short,
+                    format!("Page (1 to {})", self.pages.len()),
+                    "page",
+                )
+                .min_length(1)
+                .max_length(6),
+            ),
+        ])
+    }
+
+    /// Sends the embed as a reply to `msg` and turns the pages until nobody used the buttons for
+    /// a while. Then the buttons are removed.
+    pub async fn send(mut self, ctx: &Context, msg: &Message) -> CommandResult {
+        self.page = self.page.min(self.pages.len().saturating_sub(1));
+        let mut builder = CreateMessage::new()
+            .embed(self.embed())
+            .reference_message(msg);
+        if self.pages.len() > 1 {
+            builder = builder.components(self.components());
+        }
+        let mut message = msg.channel_id.send_message(&ctx.http, builder).await?;
+        if self.pages.len() <= 1 {
+            return Ok(());
+        }
+
+        let mut clicks = Box::pin(message.await_component_interaction(&ctx.shard).stream());
+        let mut jumps = Box::pin(
+            message
+                .await_modal_interaction(&ctx.shard)
+                .author_id(msg.author.id)
+                .stream(),
+        );
+        let mut deadline = Instant::now() + TIMEOUT;
+        loop {
+            let turn = tokio::select! {
+                Some(click) = clicks.next() => Turn::Click(click),
+                Some(jump) = jumps.next() => Turn::Jump(jump),
+                _ = sleep_until(deadline) => break,
+            };
+            // A failed answer only loses this turn, the buttons still have to be removed.
+            let answered = match turn {
+                Turn::Click(click) => {
+                    let response = if click.user.id != msg.author.id {
+                        CreateInteractionResponse::Message(
+                            CreateInteractionResponseMessage::new()
+                                .content(format!("Only <@{}> can turn these pages.", msg.author.id))
+                                .ephemeral(true),
+                        )
+                    } else {
+                        deadline = Instant::now() + TIMEOUT;
+                        let last = self.pages.len() - 1;
+                        self.page = match click.data.custom_id.split(':').nth(1) {
+                            Some("first") => 0,
+                            Some("previous") => self.page.saturating_sub(1),
+                            Some("next") => (self.page + 1).min(last),
+                            Some("last") => last,
+                            Some("jump") => {
+                                let modal = CreateInteractionResponse::Modal(self.jump_modal());
+                                if let Err(why) = click.create_response(&ctx.http, modal).await {
+                                    println!("Paginator could not answer a click: {why:?}");
+                                }
+                                continue;
+                            }
+                            _ => continue,
+                        };
+                        CreateInteractionResponse::UpdateMessage(
+                            CreateInteractionResponseMessage::new()
+                                .embed(self.embed())
+                                .components(self.components()),
+                        )
+                    };
+                    click.create_response(&ctx.http, response).await
+                }
+                Turn::Jump(jump) => {
+                    deadline = Instant::now() + TIMEOUT;
+                    let page = jump
+                        .data
+                        .components
+                        .iter()
+                        .flat_map(|row| &row.components)
+                        .find_map(|component| match component {
+                            ActionRowComponent::InputText(input) => input.value.clone(),
+                            _ => None,
+                        })
+                        .and_then(|value| value.trim().parse::<usize>().ok())
+                        .filter(|page| (1..=self.pages.len()).contains(page));
+                    let response = match page {
+                        Some(page) => {
+                            self.page = page - 1;
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .embed(self.embed())
+                                    .components(self.components()),
+                            )
+                        }
+                        None => CreateInteractionResponse::Message(
+                            CreateInteractionResponseMessage::new()
+                                .content(format!("The pages go from 1 to {}.", self.pages.len()))
+                                .ephemeral(true),
+                        ),
+                    };
+                    jump.create_response(&ctx.http, response).await
+                }
+            };
+            if let Err(why) = answered {
+                println!("Paginator could not answer a click: {why:?}");
+            }
+        }
+
+        message
+            .edit(ctx, EditMessage::new().components(vec![]))
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(count: usize, length: usize) -> Vec<String> {
+        (0..count).map(|_| "x".repeat(length)).collect()
+    }
+
+    #[test]
+    fn pages_hold_per_page_entries() {
+        let entries = vec![String::from("a"), String::from("b"), String::from("c")];
+        assert_eq!(split_pages(entries.clone(), 2), ["a\nb", "c"]);
+        // A page size of 0 still puts one entry on each page.
+        assert_eq!(split_pages(entries, 0), ["a", "b", "c"]);
+        assert!(split_pages(Vec::new(), 10).is_empty());
+    }
+
+    #[test]
+    fn pages_stay_within_the_char_limit() {
+        let pages = split_pages(entries(5, 900), 10);
+        assert_eq!(pages.len(), 3);
+        assert!(pages.iter().all(|page| page.chars().count() <= PAGE_CHARS));
+        assert_eq!(pages[0].lines().count(), 2);
+    }
+
+    #[test]
+    fn oversized_entries_are_cut() {
+        let pages = split_pages(entries(2, PAGE_CHARS + 50), 10);
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].chars().count(), PAGE_CHARS);
+        assert!(pages[0].ends_with('…'));
+    }
+}
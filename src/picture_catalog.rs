@@ -0,0 +1,310 @@
+use crate::command_base::*;
+use crate::storage::{load_json, save_json};
+
+use rand::distributions::WeightedIndex;
+use rand::prelude::Distribution;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{ChannelId, GuildId, UserId};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+pub const IMAGE_DIR: &str = "images";
+pub const CATALOG_PATH: &str = "images/catalog.json";
+pub const PENDING_DIR: &str = "images/pending";
+pub const PENDING_PATH: &str = "images/pending/submissions.json";
+
+/// Discord's upload limit for bots without boosts, nothing bigger can be re-posted anyway.
+pub const MAX_SUBMISSION_BYTES: u32 = 8 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Catalog {
+    pub categories: BTreeMap<String, Category>,
+    /// Names that pick one of several categories, mapped to the relative weight of each
+    /// category. An alias takes precedence over a category with the same name.
+    #[serde(default)]
+    pub aliases: BTreeMap<String, BTreeMap<String, u32>>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Category {
+    /// How likely `pic random` is to pick this category.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    pub images: Vec<CatalogImage>,
+}
+
+impl Default for Category {
+    fn default() -> Self {
+        Category {
+            weight: default_weight(),
+            images: Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CatalogImage {
+    /// File name relative to `IMAGE_DIR`.
+    pub file: String,
+    pub sha256: String,
+    /// How likely the image comes up early in a shuffle bag, relative to the others. Images with
+    /// weight 0 are never shown.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+impl Catalog {
+    /// Turns a command name into the category to serve from, rolling the probability table if
+    /// the name is an alias.
+    pub fn resolve_category(&self, name: &str, rng: &mut impl Rng) -> Option<String> {
+        let Some(alias) = self.aliases.get(name) else {
+            return self.categories.contains_key(name).then(|| name.to_string());
+        };
+        let entries = alias
+            .iter()
+            .filter(|(category, _)| self.categories.contains_key(*category))
+            .collect::<Vec<_>>();
+        let index = WeightedIndex::new(entries.iter().map(|(_, weight)| **weight)).ok()?;
+        Some(entries[index.sample(rng)].0.clone())
+    }
+
+    /// Picks a category by the categories' own weights.
+    pub fn random_category(&self, rng: &mut impl Rng) -> Option<String> {
+        let entries = self
+            .categories
+            .iter()
+            .filter(|(_, category)| !category.images.is_empty())
+            .collect::<Vec<_>>();
+        let index = WeightedIndex::new(entries.iter().map(|(_, category)| category.weight)).ok()?;
+        Some(entries[index.sample(rng)].0.clone())
+    }
+
+    pub fn contains_hash(&self, sha256: &str) -> bool {
+        self.categories
+            .values()
+            .flat_map(|category| &category.images)
+            .any(|image| image.sha256 == sha256)
+    }
+
+    /// Finds a file name for a new image of `category` that is not used by any catalog entry,
+    /// nor by the `reserved` files that are about to be added.
+    pub fn next_file_name(&self, category: &str, extension: &str, reserved: &[&str]) -> String {
+        let is_taken = |n: usize| {
+            let stem = format!("{}{}.", category, n);
+            self.categories
+                .values()
+                .flat_map(|category| &category.images)
+                .map(|image| image.file.as_str())
+                .chain(reserved.iter().copied())
+                .any(|file| file.starts_with(&stem))
+        };
+        let mut n = self
+            .categories
+            .get(category)
+            .map_or(0, |category| category.images.len())
+            + 1;
+        while is_taken(n) {
+            n += 1;
+        }
+        format!("{}{}.{}", category, n, extension)
+    }
+}
+
+/// Hands out the images of one category in a random order without repeating any of them until
+/// every image was shown. Weights only change the order: images with a higher weight tend to
+/// come up earlier in each round.
+#[derive(Default)]
+pub struct ShuffleBag {
+    remaining: Vec<String>,
+    last: Option<String>,
+}
+
+impl ShuffleBag {
+    pub fn draw(&mut self, images: &[CatalogImage], rng: &mut impl Rng) -> Option<String> {
+        // Images can disappear from the catalog while a bag is still being emptied.
+        self.remaining
+            .retain(|file| images.iter().any(|image| &image.file == file));
+        if self.remaining.is_empty() {
+            self.refill(images, rng);
+        }
+        let file = self.remaining.pop()?;
+        self.last = Some(file.clone());
+        Some(file)
+    }
+
+    fn refill(&mut self, images: &[CatalogImage], rng: &mut impl Rng) {
+        // Weighted random order: every image gets a key of u^(1 / weight), and the ones with the
+        // highest keys are drawn first. `draw` pops from the end, so those are sorted last.
+        let mut keyed = images
+            .iter()
+            .filter(|image| image.weight > 0)
+            .map(|image| {
+                let key = rng.gen::<f64>().powf(1.0 / f64::from(image.weight));
+                (key, image.file.clone())
+            })
+            .collect::<Vec<_>>();
+        keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self.remaining = keyed.into_iter().map(|(_, file)| file).collect();
+        // Don't show the last image of the previous round again right away.
+        if self.remaining.len() > 1 && self.remaining.last() == self.last.as_ref() {
+            let end = self.remaining.len() - 1;
+            let swap_with = rng.gen_range(0..end);
+            self.remaining.swap(swap_with, end);
+        }
+    }
+}
+
+/// One shuffle bag per channel and category.
+pub struct PictureBags;
+
+impl TypeMapKey for PictureBags {
+    type Value = HashMap<(ChannelId, String), ShuffleBag>;
+}
+
+pub struct PictureCatalog;
+
+impl TypeMapKey for PictureCatalog {
+    type Value = Catalog;
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct PendingSubmissions {
+    pub next_id: u64,
+    pub submissions: BTreeMap<u64, Submission>,
+    /// Submissions a moderator is reviewing right now, with the catalog file name they get if
+    /// they are approved.
+    #[serde(skip)]
+    pub reviewing: BTreeMap<u64, Option<String>>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Submission {
+    pub id: u64,
+    pub category: String,
+    /// File name relative to `PENDING_DIR`.
+    pub file: String,
+    pub sha256: String,
+    pub author: UserId,
+    pub guild_id: Option<GuildId>,
+    pub channel_id: ChannelId,
+}
+
+impl PendingSubmissions {
+    pub fn contains_hash(&self, sha256: &str) -> bool {
+        self.submissions.values().any(|s| s.sha256 == sha256)
+    }
+}
+
+pub struct PendingPictures;
+
+impl TypeMapKey for PendingPictures {
+    type Value = PendingSubmissions;
+}
+
+pub async fn load_catalog() -> Catalog {
+    load_json(CATALOG_PATH)
+        .await
+        .expect("Could not read the picture catalog")
+}
+
+pub async fn load_pending() -> PendingSubmissions {
+    load_json(PENDING_PATH)
+        .await
+        .expect("Could not read the pending picture submissions")
+}
+
+pub async fn save_catalog(catalog: &Catalog) -> std::io::Result<()> {
+    save_json(CATALOG_PATH, catalog).await
+}
+
+pub async fn save_pending(pending: &PendingSubmissions) -> std::io::Result<()> {
+    save_json(PENDING_PATH, pending).await
+}
+
+/// Detects the image format from the file's magic bytes. The content type Discord reports is
+/// derived from the file name, so it cannot be trusted on its own.
+pub fn sniff_image_extension(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("jpg")
+    } else if bytes.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+        Some("png")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("gif")
+    } else if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("webp")
+    } else {
+        None
+    }
+}
+
+pub fn is_allowed_content_type(content_type: Option<&str>) -> bool {
+    matches!(
+        content_type,
+        Some("image/jpeg" | "image/png" | "image/gif" | "image/webp")
+    )
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn image(file: &str, weight: u32) -> CatalogImage {
+        CatalogImage {
+            file: String::from(file),
+            sha256: String::new(),
+            weight,
+        }
+    }
+
+    #[test]
+    fn every_image_comes_up_once_per_round() {
+        let images = [image("a", 1), image("b", 5), image("c", 20), image("d", 1)];
+        let mut bag = ShuffleBag::default();
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..50 {
+            let mut round = (0..images.len())
+                .map(|_| bag.draw(&images, &mut rng).unwrap())
+                .collect::<Vec<_>>();
+            round.sort();
+            assert_eq!(round, ["a", "b", "c", "d"]);
+        }
+    }
+
+    #[test]
+    fn heavy_images_tend_to_come_first() {
+        let images = [image("light", 1), image("heavy", 50)];
+        let mut rng = StdRng::seed_from_u64(5);
+        let heavy_first = (0..200)
+            .filter(|_| ShuffleBag::default().draw(&images, &mut rng).unwrap() == "heavy")
+            .count();
+        assert!(heavy_first > 150);
+    }
+
+    #[test]
+    fn no_repeat_across_rounds_and_hidden_images() {
+        let images = [image("a", 1), image("b", 1), image("hidden", 0)];
+        let mut bag = ShuffleBag::default();
+        let mut rng = StdRng::seed_from_u64(9);
+        let mut last = bag.draw(&images, &mut rng).unwrap();
+        for _ in 0..100 {
+            let next = bag.draw(&images, &mut rng).unwrap();
+            assert_ne!(next, last);
+            assert_ne!(next, "hidden");
+            last = next;
+        }
+        assert_eq!(bag.draw(&[image("a", 0)], &mut rng), None);
+    }
+}
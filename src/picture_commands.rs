@@ -1,26 +1,64 @@
 use crate::command_base::*;
+use crate::image_edit::{self, FlipAxis, Transform};
+use crate::picture_catalog::*;
+
+use serenity::all::{
+    ButtonStyle, ComponentInteraction, CreateActionRow, CreateAttachment, CreateButton,
+    CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage,
+};
+use serenity::model::id::ChannelId;
+use std::env;
+
+/// Prefix of the custom ids on the approve / reject buttons in the review channel.
+pub const REVIEW_BUTTON_PREFIX: &str = "pic_review";
 
 #[group]
 #[prefixes("pic")]
-#[commands(bird, pov, demi, bimbo, ösi)]
+#[commands(bird, pov, demi, bimbo, ösi, random, submit, edit)]
 pub struct Pic;
 
-async fn base_picture_command(
+/// Posts an image of `name`, which is either a category or an alias from the catalog. Every
+/// channel gets its own shuffle bag per category, so images only repeat once all were shown.
+async fn base_picture_command(ctx: &Context, msg: &Message, name: &str) -> CommandResult {
+    let file_name = {
+        let mut data = ctx.data.write().await;
+        let mut rng = rand::thread_rng();
+        let catalog = data
+            .get::<PictureCatalog>()
+            .expect("Expected PictureCatalog in TypeMap.");
+        let picked = catalog.resolve_category(name, &mut rng).map(|category| {
+            let images = catalog.categories[&category].images.clone();
+            (category, images)
+        });
+        picked.and_then(|(category, images)| {
+            data.get_mut::<PictureBags>()
+                .expect("Expected PictureBags in TypeMap.")
+                .entry((msg.channel_id, category))
+                .or_default()
+                .draw(&images, &mut rng)
+        })
+    };
+    let Some(file_name) = file_name else {
+        msg.channel_id
+            .say(&ctx.http, format!("There are no `{}` pictures yet!", name))
+            .await?;
+        return Ok(());
+    };
+    let path = format!("{}/{}", IMAGE_DIR, file_name);
+    let f = &tokio::fs::File::open(path).await?;
+    let attachment = CreateAttachment::file(f, file_name).await?;
+    send_attachment(ctx, msg, attachment).await
+}
+
+/// Posts `attachment` in the channel `msg` was sent in.
+async fn send_attachment(
     ctx: &Context,
     msg: &Message,
-    file_name: &str,
-    file_count: u8,
+    attachment: CreateAttachment,
 ) -> CommandResult {
-    let rng = rand::thread_rng().gen_range(1..=file_count);
-    let path = format!("images/{}{}.jpg", file_name, rng);
-    let f = &tokio::fs::File::open(path).await?;
-    let attachment = serenity::all::CreateAttachment::file(f, format!("{}{}.jpg", file_name, rng)).await?;
     let _ = match msg
         .channel_id
-        .send_message(
-            &ctx.http,
-            serenity::all::CreateMessage::new().add_file(attachment),
-        )
+        .send_message(&ctx.http, CreateMessage::new().add_file(attachment))
         .await
     {
         Ok(_) => Ok(()),
@@ -32,34 +70,531 @@ async fn base_picture_command(
 #[command]
 #[bucket = "pic"]
 async fn bird(ctx: &Context, msg: &Message) -> CommandResult {
-    base_picture_command(ctx, msg, "bird", 2).await
+    base_picture_command(ctx, msg, "bird").await
 }
 
 #[command]
 #[bucket = "pic"]
 async fn pov(ctx: &Context, msg: &Message) -> CommandResult {
-    base_picture_command(ctx, msg, "pov", 3).await
+    base_picture_command(ctx, msg, "pov").await
 }
 
 #[command]
 #[bucket = "pic"]
 async fn demi(ctx: &Context, msg: &Message) -> CommandResult {
-    base_picture_command(ctx, msg, "demi", 4).await
+    base_picture_command(ctx, msg, "demi").await
 }
 
 #[command]
 #[bucket = "pic"]
 async fn bimbo(ctx: &Context, msg: &Message) -> CommandResult {
-    let demi_rng = rand::thread_rng().gen_range(0..=100);
-    if demi_rng < 90 {
-        base_picture_command(ctx, msg, "bimbo", 1).await
-    } else {
-        demi(ctx, msg, _args).await
-    }
+    base_picture_command(ctx, msg, "bimbo").await
 }
 
 #[command]
 #[bucket = "pic"]
 async fn ösi(ctx: &Context, msg: &Message) -> CommandResult {
-    base_picture_command(ctx, msg, "ösi", 4).await
+    base_picture_command(ctx, msg, "ösi").await
+}
+
+#[command]
+#[description = "Sends a picture from a random category."]
+#[bucket = "pic"]
+async fn random(ctx: &Context, msg: &Message) -> CommandResult {
+    let category = {
+        let data = ctx.data.read().await;
+        let catalog = data
+            .get::<PictureCatalog>()
+            .expect("Expected PictureCatalog in TypeMap.");
+        catalog.random_category(&mut rand::thread_rng())
+    };
+    match category {
+        Some(category) => base_picture_command(ctx, msg, &category).await,
+        None => {
+            msg.channel_id
+                .say(&ctx.http, "There are no pictures yet!")
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+#[command]
+#[description = "Submits the attached image to a picture category. A moderator has to approve it \
+before it shows up."]
+#[usage = "<category>"]
+#[only_in(guilds)]
+#[bucket = "pic"]
+async fn submit(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Ok(category) = args.single::<String>() else {
+        msg.reply(ctx, "You need to provide a category!").await?;
+        return Ok(());
+    };
+    let Some(review_channel_id) = env::var("PIC_REVIEW_CHANNEL_ID")
+        .ok()
+        .and_then(|id| id.parse::<u64>().ok())
+        .map(ChannelId::new)
+    else {
+        msg.reply(ctx, "Picture submissions are not set up on this bot.")
+            .await?;
+        return Ok(());
+    };
+
+    let known_category = {
+        let data = ctx.data.read().await;
+        let catalog = data
+            .get::<PictureCatalog>()
+            .expect("Expected PictureCatalog in TypeMap.");
+        catalog.categories.contains_key(&category)
+    };
+    if !known_category {
+        msg.reply(ctx, format!("There is no `{}` category.", category))
+            .await?;
+        return Ok(());
+    }
+
+    let Some(attachment) = msg.attachments.first() else {
+        msg.reply(ctx, "You need to attach an image!").await?;
+        return Ok(());
+    };
+    if attachment.size > MAX_SUBMISSION_BYTES {
+        msg.reply(
+            ctx,
+            format!(
+                "The image is too big, the limit is {} MB.",
+                MAX_SUBMISSION_BYTES / 1024 / 1024
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+    if !is_allowed_content_type(attachment.content_type.as_deref()) {
+        msg.reply(ctx, "Only JPEG, PNG, GIF and WebP images can be submitted.")
+            .await?;
+        return Ok(());
+    }
+
+    let bytes = attachment.download().await?;
+    let Some(extension) = sniff_image_extension(&bytes) else {
+        msg.reply(ctx, "That file is not a valid image.").await?;
+        return Ok(());
+    };
+    let sha256 = sha256_hex(&bytes);
+
+    let submission = {
+        let mut data = ctx.data.write().await;
+        let duplicate = data
+            .get::<PictureCatalog>()
+            .expect("Expected PictureCatalog in TypeMap.")
+            .contains_hash(&sha256);
+        let pending = data
+            .get_mut::<PendingPictures>()
+            .expect("Expected PendingPictures in TypeMap.");
+        if duplicate || pending.contains_hash(&sha256) {
+            None
+        } else {
+            pending.next_id += 1;
+            let submission = Submission {
+                id: pending.next_id,
+                category: category.clone(),
+                file: format!("{}.{}", pending.next_id, extension),
+                sha256,
+                author: msg.author.id,
+                guild_id: msg.guild_id,
+                channel_id: msg.channel_id,
+            };
+            pending
+                .submissions
+                .insert(submission.id, submission.clone());
+            Some((submission, pending.clone()))
+        }
+    };
+    let Some((submission, pending)) = submission else {
+        msg.reply(ctx, "This image has already been submitted.")
+            .await?;
+        return Ok(());
+    };
+
+    let path = format!("{}/{}", PENDING_DIR, submission.file);
+    let stored = async {
+        tokio::fs::create_dir_all(PENDING_DIR).await?;
+        tokio::fs::write(&path, &bytes).await?;
+        save_pending(&pending).await
+    }
+    .await;
+    if let Err(why) = stored {
+        // A submission without its file could never be approved.
+        {
+            let mut data = ctx.data.write().await;
+            data.get_mut::<PendingPictures>()
+                .expect("Expected PendingPictures in TypeMap.")
+                .submissions
+                .remove(&submission.id);
+        }
+        let _ = tokio::fs::remove_file(&path).await;
+        return Err(why.into());
+    }
+
+    let buttons = CreateActionRow::Buttons(vec![
+        CreateButton::new(format!(
+            "{}:approve:{}",
+            REVIEW_BUTTON_PREFIX, submission.id
+        ))
+        .label("Approve")
+        .style(ButtonStyle::Success),
+        CreateButton::new(format!("{}:reject:{}", REVIEW_BUTTON_PREFIX, submission.id))
+            .label("Reject")
+            .style(ButtonStyle::Danger),
+    ]);
+    review_channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(format!(
+                    "Submission #{} for `{}` by <@{}>",
+                    submission.id, submission.category, submission.author
+                ))
+                .add_file(CreateAttachment::bytes(bytes, submission.file.clone()))
+                .components(vec![buttons]),
+        )
+        .await?;
+
+    msg.reply(
+        ctx,
+        format!(
+            "Thanks! Your picture was submitted as #{} and is waiting for a moderator.",
+            submission.id
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Handles the approve / reject buttons under a submission in the review channel.
+pub async fn handle_review(ctx: &Context, component: &ComponentInteraction) -> CommandResult {
+    let mut parts = component.data.custom_id.split(':').skip(1);
+    let (Some(action), Some(id)) = (
+        parts.next(),
+        parts.next().and_then(|id| id.parse::<u64>().ok()),
+    ) else {
+        return Ok(());
+    };
+
+    let is_moderator = component
+        .member
+        .as_ref()
+        .and_then(|member| member.permissions)
+        .is_some_and(|permissions| permissions.manage_messages());
+    if !is_moderator {
+        component
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content("Only moderators can review submissions.")
+                        .ephemeral(true),
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    // The submission is claimed first, so a second click can't review it again while its file
+    // is moved. The stores are only changed once the file is where they say it is.
+    // The claim fails with `true` while someone else reviews it, `false` once it is gone.
+    let approved = action == "approve";
+    let claim = {
+        let mut data = ctx.data.write().await;
+        let pending = data
+            .get::<PendingPictures>()
+            .expect("Expected PendingPictures in TypeMap.");
+        let catalog = data
+            .get::<PictureCatalog>()
+            .expect("Expected PictureCatalog in TypeMap.");
+        let claim = match pending.submissions.get(&id) {
+            Some(_) if pending.reviewing.contains_key(&id) => Err(true),
+            Some(submission) => {
+                let reserved = pending
+                    .reviewing
+                    .values()
+                    .flatten()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>();
+                let file = approved.then(|| {
+                    let extension = submission.file.rsplit('.').next().unwrap_or("jpg");
+                    catalog.next_file_name(&submission.category, extension, &reserved)
+                });
+                Ok((submission.clone(), file))
+            }
+            None => Err(false),
+        };
+        if let Ok((_, file)) = &claim {
+            data.get_mut::<PendingPictures>()
+                .expect("Expected PendingPictures in TypeMap.")
+                .reviewing
+                .insert(id, file.clone());
+        }
+        claim
+    };
+    let (submission, file) = match claim {
+        Ok(claim) => claim,
+        Err(true) => {
+            component
+                .create_response(
+                    &ctx.http,
+                    CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content(format!("Submission #{} is being reviewed already.", id))
+                            .ephemeral(true),
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+        Err(false) => {
+            component
+                .create_response(
+                    &ctx.http,
+                    CreateInteractionResponse::UpdateMessage(
+                        CreateInteractionResponseMessage::new()
+                            .content(format!("Submission #{} was already reviewed.", id))
+                            .components(vec![]),
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let pending_path = format!("{}/{}", PENDING_DIR, submission.file);
+    let moved = match &file {
+        Some(file) => tokio::fs::rename(&pending_path, format!("{}/{}", IMAGE_DIR, file)).await,
+        None => tokio::fs::remove_file(&pending_path).await,
+    };
+    let (pending, catalog) = {
+        let mut data = ctx.data.write().await;
+        let pending = data
+            .get_mut::<PendingPictures>()
+            .expect("Expected PendingPictures in TypeMap.");
+        pending.reviewing.remove(&id);
+        // If the file could not be moved the submission stays pending and can be reviewed again.
+        moved?;
+        pending.submissions.remove(&id);
+        let pending = pending.clone();
+        let catalog = file.map(|file| {
+            let catalog = data
+                .get_mut::<PictureCatalog>()
+                .expect("Expected PictureCatalog in TypeMap.");
+            catalog
+                .categories
+                .entry(submission.category.clone())
+                .or_default()
+                .images
+                .push(CatalogImage {
+                    file,
+                    sha256: submission.sha256.clone(),
+                    weight: 1,
+                });
+            catalog.clone()
+        });
+        (pending, catalog)
+    };
+    save_pending(&pending).await?;
+    if let Some(catalog) = catalog {
+        save_catalog(&catalog).await?;
+    }
+
+    let verdict = if approved { "Approved" } else { "Rejected" };
+    component
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .content(format!(
+                        "Submission #{} for `{}` by <@{}>: {} by <@{}>",
+                        submission.id,
+                        submission.category,
+                        submission.author,
+                        verdict,
+                        component.user.id
+                    ))
+                    .components(vec![]),
+            ),
+        )
+        .await?;
+
+    let notice = format!(
+        "Your picture submission #{} for `{}` was {}.",
+        submission.id,
+        submission.category,
+        verdict.to_lowercase()
+    );
+    // The author may have DMs disabled, in which case they just don't get notified.
+    let _ = submission
+        .author
+        .direct_message(&ctx, CreateMessage::new().content(notice))
+        .await;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Edits an image. Uses the attached image, the image of the message you reply to, \
+the avatar of the mentioned user or your own avatar, in that order."]
+#[sub_commands(caption, invert, greyscale, blur, deepfry, rotate, flip, bubble)]
+#[bucket = "pic"]
+async fn edit(ctx: &Context, msg: &Message) -> CommandResult {
+    msg.reply(
+        ctx,
+        "Pick an edit: `caption`, `invert`, `greyscale`, `blur`, `deepfry`, `rotate`, `flip` or \
+`bubble`.",
+    )
+    .await?;
+    Ok(())
+}
+
+/// Finds the image a `pic edit` command should work on.
+fn edit_source_url(msg: &Message) -> String {
+    if let Some(attachment) = msg.attachments.first() {
+        return attachment.url.clone();
+    }
+    if let Some(replied) = &msg.referenced_message {
+        if let Some(attachment) = replied.attachments.first() {
+            return attachment.url.clone();
+        }
+        // Embed URLs are whatever the author put there, Discord's proxy only fetches images.
+        let embed_image = replied.embeds.iter().find_map(|embed| {
+            embed
+                .image
+                .as_ref()
+                .and_then(|image| image.proxy_url.clone())
+                .or_else(|| {
+                    embed
+                        .thumbnail
+                        .as_ref()
+                        .and_then(|thumb| thumb.proxy_url.clone())
+                })
+        });
+        if let Some(url) = embed_image {
+            return url;
+        }
+    }
+    match msg.mentions.first() {
+        Some(user) => user.face(),
+        None => msg.author.face(),
+    }
+}
+
+async fn base_edit_command(ctx: &Context, msg: &Message, transform: Transform) -> CommandResult {
+    let url = edit_source_url(msg);
+    let response = reqwest::get(&url).await?.error_for_status()?;
+    if response
+        .content_length()
+        .is_some_and(|length| length > image_edit::MAX_INPUT_BYTES)
+    {
+        msg.reply(ctx, "That image is too big to edit.").await?;
+        return Ok(());
+    }
+    let bytes = response.bytes().await?;
+    if bytes.len() as u64 > image_edit::MAX_INPUT_BYTES {
+        msg.reply(ctx, "That image is too big to edit.").await?;
+        return Ok(());
+    }
+
+    // The limits of `image_edit` keep the job short, a timeout could not stop it anyway.
+    let typing = msg.channel_id.start_typing(&ctx.http);
+    let result = tokio::task::spawn_blocking(move || image_edit::apply(&bytes, &transform)).await;
+    typing.stop();
+
+    match result? {
+        Ok(png) => send_attachment(ctx, msg, CreateAttachment::bytes(png, "edit.png")).await,
+        Err(why) => {
+            println!("Could not edit image '{}': {:?}", url, why);
+            msg.reply(ctx, "I could not read that image.").await?;
+            Ok(())
+        }
+    }
+}
+
+#[command]
+#[description = "Adds meme style captions. Separate the top and bottom text with `|`."]
+#[usage = "<top text> | <bottom text>"]
+#[bucket = "pic"]
+async fn caption(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let text = args.rest();
+    if text.trim().is_empty() {
+        msg.reply(ctx, "You need to provide a caption!").await?;
+        return Ok(());
+    }
+    let (top, bottom) = text.split_once('|').unwrap_or((text, ""));
+    let transform = Transform::Caption {
+        top: top.trim().to_string(),
+        bottom: bottom.trim().to_string(),
+    };
+    base_edit_command(ctx, msg, transform).await
+}
+
+#[command]
+#[description = "Inverts the colors of an image."]
+#[bucket = "pic"]
+async fn invert(ctx: &Context, msg: &Message) -> CommandResult {
+    base_edit_command(ctx, msg, Transform::Invert).await
+}
+
+#[command]
+#[aliases("grayscale")]
+#[description = "Turns an image black and white."]
+#[bucket = "pic"]
+async fn greyscale(ctx: &Context, msg: &Message) -> CommandResult {
+    base_edit_command(ctx, msg, Transform::Greyscale).await
+}
+
+#[command]
+#[description = "Blurs an image. The strength goes from 1 to 20 and defaults to 5."]
+#[usage = "[strength]"]
+#[bucket = "pic"]
+async fn blur(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let sigma = args.single::<f32>().unwrap_or(5.0);
+    base_edit_command(ctx, msg, Transform::Blur(sigma)).await
+}
+
+#[command]
+#[description = "Deep-fries an image."]
+#[bucket = "pic"]
+async fn deepfry(ctx: &Context, msg: &Message) -> CommandResult {
+    base_edit_command(ctx, msg, Transform::DeepFry).await
+}
+
+#[command]
+#[description = "Rotates an image clockwise by 90, 180 or 270 degrees."]
+#[usage = "<90|180|270>"]
+#[bucket = "pic"]
+async fn rotate(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let degrees = args.single::<u16>().unwrap_or(90);
+    if ![90, 180, 270].contains(&degrees) {
+        msg.reply(ctx, "You can only rotate by 90, 180 or 270 degrees!")
+            .await?;
+        return Ok(());
+    }
+    base_edit_command(ctx, msg, Transform::Rotate(degrees)).await
+}
+
+#[command]
+#[description = "Mirrors an image horizontally or vertically."]
+#[usage = "<horizontal|vertical>"]
+#[bucket = "pic"]
+async fn flip(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let axis = match args.single::<String>().as_deref() {
+        Ok("v" | "vertical") => FlipAxis::Vertical,
+        _ => FlipAxis::Horizontal,
+    };
+    base_edit_command(ctx, msg, Transform::Flip(axis)).await
+}
+
+#[command]
+#[description = "Cuts a speech bubble into the top of an image."]
+#[bucket = "pic"]
+async fn bubble(ctx: &Context, msg: &Message) -> CommandResult {
+    base_edit_command(ctx, msg, Transform::SpeechBubble).await
 }
@@ -0,0 +1,447 @@
+use crate::command_base::*;
+use crate::moderator_commands::is_moderator;
+use crate::scheduler::SchedulerContext;
+use crate::storage::save_json;
+use crate::time_parse::parse_duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serenity::all::{
+    ButtonStyle, ComponentInteraction, CreateActionRow, CreateAllowedMentions, CreateButton,
+    CreateEmbed, CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseMessage,
+    CreateMessage, EditMessage, MessageReference,
+};
+use serenity::http::Http;
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+use std::collections::BTreeMap;
+
+pub const POLLS_PATH: &str = "data/polls.json";
+pub const POLL_PREFIX: &str = "poll";
+const MAX_OPTIONS: usize = 10;
+const MAX_DURATION_SECS: i64 = 30 * 24 * 60 * 60;
+const BAR_WIDTH: usize = 12;
+
+#[group]
+#[prefixes("poll")]
+#[default_command(create)]
+#[commands(create, end)]
+#[only_in(guilds)]
+pub struct Poll;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OpenPoll {
+    pub guild: GuildId,
+    pub channel: ChannelId,
+    pub author: UserId,
+    pub question: String,
+    pub options: Vec<String>,
+    /// The options every user voted for, as indices into `options`.
+    pub votes: BTreeMap<UserId, Vec<usize>>,
+    pub multi: bool,
+    pub anonymous: bool,
+    /// Unix timestamp in seconds, `None` if the poll stays open until it is ended.
+    pub closes_at: Option<i64>,
+}
+
+impl OpenPoll {
+    /// Toggles the vote of `user` for `option`. Without `multi` a new vote replaces the old one.
+    /// Returns the options `user` now votes for.
+    fn toggle_vote(&mut self, user: UserId, option: usize) -> Vec<usize> {
+        let votes = self.votes.entry(user).or_default();
+        if let Some(position) = votes.iter().position(|vote| *vote == option) {
+            votes.remove(position);
+        } else if self.multi {
+            votes.push(option);
+            votes.sort_unstable();
+        } else {
+            *votes = vec![option];
+        }
+        let votes = votes.clone();
+        if votes.is_empty() {
+            self.votes.remove(&user);
+        }
+        votes
+    }
+
+    fn tally(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.options.len()];
+        for option in self.votes.values().flatten() {
+            if let Some(count) = counts.get_mut(*option) {
+                *count += 1;
+            }
+        }
+        counts
+    }
+
+    fn embed(&self, closed: bool) -> CreateEmbed {
+        let counts = self.tally();
+        let total = counts.iter().sum::<usize>();
+        let mut description = String::new();
+        for (index, (option, count)) in self.options.iter().zip(&counts).enumerate() {
+            let share = if total == 0 {
+                0.0
+            } else {
+                *count as f64 / total as f64
+            };
+            let filled = (share * BAR_WIDTH as f64).round() as usize;
+            description.push_str(&format!(
+                "**{}.** {}\n`{}{}` {:.0}% ({})\n",
+                index + 1,
+                option,
+                "█".repeat(filled),
+                "░".repeat(BAR_WIDTH - filled),
+                share * 100.0,
+                count
+            ));
+        }
+
+        let mut footer = format!("{} voter(s)", self.votes.len());
+        if self.multi {
+            footer.push_str(" · multiple choice");
+        }
+        if self.anonymous {
+            footer.push_str(" · anonymous");
+        }
+        if closed {
+            footer.push_str(" · closed");
+        } else if let Some(closes_at) = self.closes_at {
+            description.push_str(&format!("\nCloses <t:{}:R>.", closes_at));
+        }
+        CreateEmbed::new()
+            .title(&self.question)
+            .description(description)
+            .footer(CreateEmbedFooter::new(footer))
+    }
+
+    fn components(&self) -> Vec<CreateActionRow> {
+        self.options
+            .chunks(5)
+            .enumerate()
+            .map(|(row, options)| {
+                CreateActionRow::Buttons(
+                    options
+                        .iter()
+                        .enumerate()
+                        .map(|(column, option)| {
+                            let index = row * 5 + column;
+                            let label = format!("{}. {}", index + 1, option);
+                            CreateButton::new(format!("{}:{}", POLL_PREFIX, index))
+                                .label(label.chars().take(80).collect::<String>())
+                                .style(ButtonStyle::Secondary)
+                        })
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    /// The results posted when the poll closes. Unless the poll is anonymous they include who
+    /// voted for what.
+    fn results(&self) -> String {
+        let counts = self.tally();
+        let best = counts.iter().copied().max().unwrap_or(0);
+        let mut contents = format!("**Poll closed:** {}\n", self.question);
+        if best == 0 {
+            contents.push_str("Nobody voted.");
+            return contents;
+        }
+        let winners = self
+            .options
+            .iter()
+            .zip(&counts)
+            .filter(|(_, count)| **count == best)
+            .map(|(option, _)| option.as_str())
+            .collect::<Vec<_>>();
+        contents.push_str(&format!(
+            "{} **{}** with {} vote(s).\n",
+            if winners.len() == 1 {
+                "Winner:"
+            } else {
+                "Tied:"
+            },
+            winners.join("**, **"),
+            best
+        ));
+        if !self.anonymous {
+            for (index, option) in self.options.iter().enumerate() {
+                let voters = self
+                    .votes
+                    .iter()
+                    .filter(|(_, votes)| votes.contains(&index))
+                    .map(|(user, _)| format!("<@{}>", user))
+                    .collect::<Vec<_>>();
+                if !voters.is_empty() {
+                    contents.push_str(&format!("{}: {}\n", option, voters.join(", ")));
+                }
+            }
+        }
+        contents.chars().take(2000).collect()
+    }
+}
+
+/// Open polls by the message they were posted as.
+pub struct Polls;
+
+impl TypeMapKey for Polls {
+    type Value = HashMap<MessageId, OpenPoll>;
+}
+
+async fn save_polls(data: &RwLock<TypeMap>) -> CommandResult {
+    let polls = {
+        let data = data.read().await;
+        data.get::<Polls>()
+            .expect("Expected Polls in TypeMap.")
+            .clone()
+    };
+    save_json(POLLS_PATH, &polls).await?;
+    Ok(())
+}
+
+/// Closes the poll posted as `message`: removes its buttons, shows the final tally and posts the
+/// results. Polls that are already closed are left alone.
+async fn close_poll(
+    http: &Http,
+    data: &RwLock<TypeMap>,
+    message: MessageId,
+) -> CommandResult {
+    let poll = {
+        let mut data = data.write().await;
+        data.get_mut::<Polls>()
+            .expect("Expected Polls in TypeMap.")
+            .remove(&message)
+    };
+    let Some(poll) = poll else {
+        return Ok(());
+    };
+    save_polls(data).await?;
+
+    poll.channel
+        .edit_message(
+            http,
+            message,
+            EditMessage::new()
+                .embed(poll.embed(true))
+                .components(vec![]),
+        )
+        .await?;
+    poll.channel
+        .send_message(
+            http,
+            CreateMessage::new()
+                .content(poll.results())
+                .reference_message(MessageReference::from((poll.channel, message)))
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Closes every poll whose time is up at `now`.
+pub async fn run_due(scheduler: &SchedulerContext, now: DateTime<Utc>) -> CommandResult {
+    let due = {
+        let data = scheduler.data.read().await;
+        data.get::<Polls>()
+            .expect("Expected Polls in TypeMap.")
+            .iter()
+            .filter(|(_, poll)| {
+                poll.closes_at
+                    .is_some_and(|closes_at| closes_at <= now.timestamp())
+            })
+            .map(|(message, _)| *message)
+            .collect::<Vec<_>>()
+    };
+    for message in due {
+        if let Err(why) = close_poll(&scheduler.http, &scheduler.data, message).await {
+            println!("Could not close poll {message}: {why:?}");
+        }
+    }
+    Ok(())
+}
+
+async fn respond(
+    ctx: &Context,
+    component: &ComponentInteraction,
+    content: String,
+) -> CommandResult {
+    component
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Handles a click on a vote button.
+pub async fn handle_interaction(ctx: &Context, component: &ComponentInteraction) -> CommandResult {
+    let Some(option) = component
+        .data
+        .custom_id
+        .split(':')
+        .nth(1)
+        .and_then(|option| option.parse::<usize>().ok())
+    else {
+        return Ok(());
+    };
+    let voted = {
+        let mut data = ctx.data.write().await;
+        data.get_mut::<Polls>()
+            .expect("Expected Polls in TypeMap.")
+            .get_mut(&component.message.id)
+            .filter(|poll| option < poll.options.len())
+            .map(|poll| {
+                poll.toggle_vote(component.user.id, option)
+                    .iter()
+                    .map(|vote| poll.options[*vote].clone())
+                    .collect::<Vec<_>>()
+            })
+    };
+    let Some(chosen) = voted else {
+        return respond(ctx, component, String::from("This poll is closed.")).await;
+    };
+    save_polls(&ctx.data).await?;
+
+    let content = if chosen.is_empty() {
+        String::from("You removed your vote.")
+    } else {
+        format!("You voted for **{}**.", chosen.join("**, **"))
+    };
+    respond(ctx, component, content).await?;
+
+    // Other votes may have come in meanwhile, the embed shows the latest tally.
+    let embed = {
+        let data = ctx.data.read().await;
+        data.get::<Polls>()
+            .expect("Expected Polls in TypeMap.")
+            .get(&component.message.id)
+            .map(|poll| poll.embed(false))
+    };
+    if let Some(embed) = embed {
+        component
+            .channel_id
+            .edit_message(
+                &ctx.http,
+                component.message.id,
+                EditMessage::new().embed(embed),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+#[command]
+#[description = "Starts a poll with vote buttons. `--duration` closes it automatically, `--multi` \
+allows voting for several options and `--anonymous` keeps the voters secret."]
+#[usage = "\"<question>\" \"<option>\" \"<option>\"... [--duration <time>] [--multi] [--anonymous]"]
+#[example = "\"Pizza or pasta?\" \"Pizza\" \"Pasta\" --duration 1d"]
+async fn create(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mut texts = Vec::new();
+    let mut duration = None;
+    let mut multi = false;
+    let mut anonymous = false;
+    while !args.is_empty() {
+        let arg = args.single_quoted::<String>()?;
+        match arg.as_str() {
+            "--multi" => multi = true,
+            "--anonymous" => anonymous = true,
+            "--duration" => {
+                let time = args.single_quoted::<String>().unwrap_or_default();
+                match parse_duration(&[&time]) {
+                    Some((seconds, _)) if seconds <= MAX_DURATION_SECS => duration = Some(seconds),
+                    _ => {
+                        msg.reply(
+                            ctx,
+                            "The duration should be like `30m`, `2h` or `1d`, and at most 30 days.",
+                        )
+                        .await?;
+                        return Ok(());
+                    }
+                }
+            }
+            _ => texts.push(arg),
+        }
+    }
+    if texts.len() < 3 || texts.len() > MAX_OPTIONS + 1 {
+        msg.reply(
+            ctx,
+            format!(
+                "A poll needs a question and 2 to {} options, each in quotes.",
+                MAX_OPTIONS
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+    let question = texts.remove(0);
+
+    let poll = OpenPoll {
+        guild: msg.guild_id.ok_or("Only in guilds")?,
+        channel: msg.channel_id,
+        author: msg.author.id,
+        question: question.chars().take(256).collect(),
+        options: texts,
+        votes: BTreeMap::new(),
+        multi,
+        anonymous,
+        closes_at: duration.map(|seconds| Utc::now().timestamp() + seconds),
+    };
+    let message = msg
+        .channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .embed(poll.embed(false))
+                .components(poll.components()),
+        )
+        .await?;
+    {
+        let mut data = ctx.data.write().await;
+        data.get_mut::<Polls>()
+            .expect("Expected Polls in TypeMap.")
+            .insert(message.id, poll);
+    }
+    save_polls(&ctx.data).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Closes a poll early and posts the results. Only its creator and moderators can \
+do this."]
+#[usage = "<message id>"]
+async fn end(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let message = args
+        .single::<MessageId>()
+        .ok()
+        .or_else(|| msg.referenced_message.as_ref().map(|message| message.id));
+    let author = {
+        let data = ctx.data.read().await;
+        message.and_then(|message| {
+            data.get::<Polls>()
+                .expect("Expected Polls in TypeMap.")
+                .get(&message)
+                .filter(|poll| Some(poll.guild) == msg.guild_id)
+                .map(|poll| poll.author)
+        })
+    };
+    let (Some(message), Some(author)) = (message, author) else {
+        msg.reply(ctx, "Reply to an open poll or give the id of its message.")
+            .await?;
+        return Ok(());
+    };
+    if author != msg.author.id && !is_moderator(ctx, msg) {
+        msg.reply(
+            ctx,
+            "Only the creator of the poll and moderators can end it.",
+        )
+        .await?;
+        return Ok(());
+    }
+    close_poll(&ctx.http, &ctx.data, message).await?;
+
+    Ok(())
+}
@@ -0,0 +1,369 @@
+use crate::time_parse::{parse_duration, parse_time_of_day, resolve_local};
+
+use chrono::{DateTime, Datelike, Days, Months, NaiveDate, NaiveTime, TimeDelta, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Recurring schedules shorter than this would mostly be spam.
+pub const MIN_INTERVAL_SECS: i64 = 10 * 60;
+/// Longer intervals are better written as a calendar schedule.
+pub const MAX_INTERVAL_SECS: i64 = 366 * 24 * 60 * 60;
+
+/// When something repeats. Stored as the text it was parsed from, see [`Recurrence::parse`].
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(try_from = "String", into = "String")]
+pub enum Recurrence {
+    /// Every `seconds`, counted from the previous run.
+    Interval { seconds: i64 },
+    /// At a wall clock time on some days of the week.
+    Weekly {
+        days: Vec<Weekday>,
+        time: NaiveTime,
+        tz: Tz,
+    },
+    /// At a wall clock time on a day of the month, or the last day for shorter months.
+    Monthly { day: u32, time: NaiveTime, tz: Tz },
+}
+
+fn parse_weekday(word: &str) -> Option<Weekday> {
+    match word.trim_end_matches('s') {
+        "mon" | "monday" => Some(Weekday::Mon),
+        "tue" | "tuesday" => Some(Weekday::Tue),
+        "wed" | "wednesday" => Some(Weekday::Wed),
+        "thu" | "thur" | "thursday" => Some(Weekday::Thu),
+        "fri" | "friday" => Some(Weekday::Fri),
+        "sat" | "saturday" => Some(Weekday::Sat),
+        "sun" | "sunday" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+/// Parses the days part of a weekly schedule: `day`, `weekday`, `weekend` or a comma separated
+/// list like `mon,wed,fri`.
+fn parse_days(word: &str) -> Option<Vec<Weekday>> {
+    const WORK_DAYS: [Weekday; 5] = [
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+    ];
+    match word {
+        "day" | "daily" => Some(
+            WORK_DAYS
+                .into_iter()
+                .chain([Weekday::Sat, Weekday::Sun])
+                .collect(),
+        ),
+        "weekday" | "weekdays" => Some(WORK_DAYS.to_vec()),
+        "weekend" | "weekends" => Some(vec![Weekday::Sat, Weekday::Sun]),
+        _ => {
+            let mut days = word
+                .split(',')
+                .filter(|day| !day.is_empty())
+                .map(parse_weekday)
+                .collect::<Option<Vec<_>>>()?;
+            days.sort_by_key(|day| day.num_days_from_monday());
+            days.dedup();
+            (!days.is_empty()).then_some(days)
+        }
+    }
+}
+
+/// The `day` of the month of `year`/`month`, or its last day if the month is too short.
+fn day_of_month(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+    (1..=day)
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+}
+
+impl Recurrence {
+    /// Parses a schedule from the start of `words`, which should begin with `every`:
+    /// - `every 6h`, `every 2 days`
+    /// - `every day 09:00`, `every weekday 08:30`, `every weekend 10:00`
+    /// - `every monday 09:00 Europe/Berlin`, `every mon,wed,fri 18:00`
+    /// - `every month 1 12:00`
+    ///
+    /// Calendar schedules without a time zone use `default_tz`. Returns the schedule and how
+    /// many words it took up.
+    pub fn parse(words: &[&str], default_tz: Tz) -> Option<(Recurrence, usize)> {
+        if !words.first()?.eq_ignore_ascii_case("every") {
+            return None;
+        }
+        let rest = &words[1..];
+        if let Some((seconds, used)) = parse_duration(rest) {
+            return (MIN_INTERVAL_SECS..=MAX_INTERVAL_SECS)
+                .contains(&seconds)
+                .then_some((Recurrence::Interval { seconds }, used + 1));
+        }
+
+        let first = rest.first()?.to_lowercase();
+        let (month_day, used) = if first == "month" {
+            let day = rest
+                .get(1)?
+                .trim_end_matches(|c: char| c.is_ascii_alphabetic());
+            let day = day
+                .parse::<u32>()
+                .ok()
+                .filter(|day| (1..=31).contains(day))?;
+            (Some(day), 2)
+        } else {
+            (None, 1)
+        };
+        let time = parse_time_of_day(rest.get(used)?)?;
+        let (tz, used) = match rest.get(used + 1).and_then(|word| word.parse::<Tz>().ok()) {
+            Some(tz) => (tz, used + 2),
+            None => (default_tz, used + 1),
+        };
+        let recurrence = match month_day {
+            Some(day) => Recurrence::Monthly { day, time, tz },
+            None => Recurrence::Weekly {
+                days: parse_days(&first)?,
+                time,
+                tz,
+            },
+        };
+        Some((recurrence, used + 1))
+    }
+
+    /// The first run strictly after `after`.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Recurrence::Interval { seconds } => {
+                after.checked_add_signed(TimeDelta::try_seconds(*seconds)?)
+            }
+            Recurrence::Weekly { days, time, tz } => {
+                let today = after.with_timezone(tz).date_naive();
+                // A week and a day, in case today's run already passed.
+                (0..=7)
+                    .filter_map(|offset| today.checked_add_days(Days::new(offset)))
+                    .filter(|date| days.contains(&date.weekday()))
+                    .filter_map(|date| resolve_local(*tz, date.and_time(*time)))
+                    .find(|run| *run > after)
+            }
+            Recurrence::Monthly { day, time, tz } => {
+                let today = after.with_timezone(tz).date_naive();
+                let first = today.with_day(1)?;
+                (0..=1)
+                    .filter_map(|offset| first.checked_add_months(Months::new(offset)))
+                    .filter_map(|month| day_of_month(month.year(), month.month(), *day))
+                    .filter_map(|date| resolve_local(*tz, date.and_time(*time)))
+                    .find(|run| *run > after)
+            }
+        }
+    }
+
+    /// The run that follows `previous` and is after `now`. Runs missed in between, for example
+    /// while the bot was down, are skipped.
+    pub fn next_run(&self, previous: DateTime<Utc>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Recurrence::Interval { seconds } => {
+                let missed = (now - previous).num_seconds().max(0) / seconds;
+                let offset = missed.checked_add(1)?.checked_mul(*seconds)?;
+                previous.checked_add_signed(TimeDelta::try_seconds(offset)?)
+            }
+            _ => self.next_after(previous.max(now)),
+        }
+    }
+
+    /// The next `count` runs after `after`.
+    pub fn preview(&self, after: DateTime<Utc>, count: usize) -> Vec<DateTime<Utc>> {
+        std::iter::successors(self.next_after(after), |run| self.next_after(*run))
+            .take(count)
+            .collect()
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Recurrence::Interval { seconds } => {
+                let (amount, unit) = [
+                    (7 * 24 * 60 * 60, "w"),
+                    (24 * 60 * 60, "d"),
+                    (60 * 60, "h"),
+                    (60, "m"),
+                ]
+                .into_iter()
+                .find(|(unit, _)| seconds % unit == 0)
+                .map_or((*seconds, "s"), |(unit, name)| (seconds / unit, name));
+                write!(f, "every {}{}", amount, unit)
+            }
+            Recurrence::Weekly { days, time, tz } => {
+                let days = match days.len() {
+                    7 => String::from("day"),
+                    _ => days
+                        .iter()
+                        .map(|day| day.to_string().to_lowercase())
+                        .collect::<Vec<_>>()
+                        .join(","),
+                };
+                write!(f, "every {} {} {}", days, time.format("%H:%M"), tz.name())
+            }
+            Recurrence::Monthly { day, time, tz } => {
+                write!(
+                    f,
+                    "every month {} {} {}",
+                    day,
+                    time.format("%H:%M"),
+                    tz.name()
+                )
+            }
+        }
+    }
+}
+
+impl From<Recurrence> for String {
+    fn from(recurrence: Recurrence) -> String {
+        recurrence.to_string()
+    }
+}
+
+impl TryFrom<String> for Recurrence {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        let words = text.split_whitespace().collect::<Vec<_>>();
+        match Recurrence::parse(&words, Tz::UTC) {
+            Some((recurrence, used)) if used == words.len() => Ok(recurrence),
+            _ => Err(format!("invalid schedule `{}`", text)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn parse(text: &str) -> Option<Recurrence> {
+        let words = text.split_whitespace().collect::<Vec<_>>();
+        Recurrence::parse(&words, Tz::Europe__Berlin).map(|(recurrence, _)| recurrence)
+    }
+
+    #[test]
+    fn parses_intervals_within_bounds() {
+        assert_eq!(
+            parse("every 6h"),
+            Some(Recurrence::Interval {
+                seconds: 6 * 60 * 60
+            })
+        );
+        assert_eq!(parse("every 5m"), None);
+        assert_eq!(parse("every 400d"), None);
+        assert_eq!(parse("every 9999999999999999s"), None);
+    }
+
+    #[test]
+    fn parses_calendar_schedules() {
+        let nine = NaiveTime::from_hms_opt(9, 0, 0).unwrap();
+        assert_eq!(
+            parse("every mon,fri,mon 09:00 UTC"),
+            Some(Recurrence::Weekly {
+                days: vec![Weekday::Mon, Weekday::Fri],
+                time: nine,
+                tz: Tz::UTC,
+            })
+        );
+        assert_eq!(
+            parse("every month 31st 09:00"),
+            Some(Recurrence::Monthly {
+                day: 31,
+                time: nine,
+                tz: Tz::Europe__Berlin,
+            })
+        );
+        assert_eq!(parse("every month 32 09:00"), None);
+        assert_eq!(parse("every someday 09:00"), None);
+        assert_eq!(parse("each day 09:00"), None);
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        for text in [
+            "every 2h",
+            "every 3d",
+            "every day 09:00 Europe/Berlin",
+            "every mon,wed 18:30 UTC",
+            "every month 15 12:00 America/New_York",
+        ] {
+            let recurrence = Recurrence::try_from(String::from(text)).unwrap();
+            assert_eq!(recurrence.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn interval_runs_skip_missed_runs() {
+        let recurrence = Recurrence::Interval { seconds: 60 * 60 };
+        let previous = utc("2026-10-19T10:00:00Z");
+        assert_eq!(
+            recurrence.next_run(previous, utc("2026-10-19T10:30:00Z")),
+            Some(utc("2026-10-19T11:00:00Z"))
+        );
+        assert_eq!(
+            recurrence.next_run(previous, utc("2026-10-19T13:15:00Z")),
+            Some(utc("2026-10-19T14:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn huge_intervals_do_not_panic() {
+        let recurrence = Recurrence::Interval { seconds: i64::MAX };
+        let now = utc("2026-10-19T10:00:00Z");
+        assert_eq!(recurrence.next_after(now), None);
+        assert_eq!(recurrence.next_run(now, now), None);
+    }
+
+    #[test]
+    fn weekly_runs_follow_local_time_across_daylight_saving() {
+        let recurrence = parse("every sun 09:00").unwrap();
+        // 09:00 in Berlin is 07:00 UTC in summer and 08:00 UTC in winter.
+        assert_eq!(
+            recurrence.preview(utc("2026-10-18T12:00:00Z"), 2),
+            vec![utc("2026-10-25T08:00:00Z"), utc("2026-11-01T08:00:00Z")]
+        );
+        assert_eq!(
+            recurrence.next_after(utc("2026-03-28T12:00:00Z")),
+            Some(utc("2026-03-29T07:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn runs_in_skipped_or_repeated_hours_still_happen_once() {
+        let recurrence = parse("every day 02:30").unwrap();
+        // 02:30 does not exist on the spring switch, the run moves to 03:30 summer time.
+        assert_eq!(
+            recurrence.next_after(utc("2026-03-28T12:00:00Z")),
+            Some(utc("2026-03-29T01:30:00Z"))
+        );
+        // 02:30 happens twice on the autumn switch, only the first one runs.
+        let autumn = recurrence.preview(utc("2026-10-24T12:00:00Z"), 2);
+        assert_eq!(
+            autumn,
+            vec![utc("2026-10-25T00:30:00Z"), utc("2026-10-26T01:30:00Z")]
+        );
+    }
+
+    #[test]
+    fn monthly_runs_use_the_last_day_of_short_months() {
+        let recurrence = parse("every month 31 12:00 UTC").unwrap();
+        assert_eq!(
+            recurrence.preview(utc("2027-01-31T13:00:00Z"), 3),
+            vec![
+                utc("2027-02-28T12:00:00Z"),
+                utc("2027-03-31T12:00:00Z"),
+                utc("2027-04-30T12:00:00Z"),
+            ]
+        );
+        assert_eq!(
+            recurrence.next_after(utc("2028-02-01T00:00:00Z")),
+            Some(utc("2028-02-29T12:00:00Z"))
+        );
+    }
+}
@@ -0,0 +1,394 @@
+use crate::command_base::*;
+use crate::paginator::Paginator;
+use crate::scheduler::SchedulerContext;
+use crate::storage::save_json;
+use crate::time_parse::parse_when;
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use serenity::all::{CreateAllowedMentions, CreateMessage};
+use serenity::model::id::{ChannelId, UserId};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+pub const REMINDERS_PATH: &str = "data/reminders.json";
+pub const TIMEZONES_PATH: &str = "data/timezones.json";
+const MAX_REMINDERS_PER_USER: usize = 25;
+const MAX_REMINDER_LENGTH: usize = 1000;
+/// Reminders further out than this are most likely typos.
+const MAX_REMINDER_DAYS: i64 = 5 * 365;
+/// Reminders delivered more than this late mention when they were due.
+const LATE_AFTER_SECS: i64 = 60;
+/// Reminders that could not be delivered are tried again this much later, a few times.
+const RETRY_AFTER_SECS: i64 = 60;
+const MAX_DELIVERY_ATTEMPTS: u32 = 5;
+
+#[group]
+#[prefixes("remind")]
+#[commands(me, dm, list, delete, timezone)]
+pub struct Remind;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Reminder {
+    pub user: UserId,
+    /// Where to deliver the reminder, `None` for a direct message.
+    pub channel: Option<ChannelId>,
+    pub text: String,
+    /// Unix timestamps in seconds.
+    pub due: i64,
+    pub created: i64,
+    /// Failed deliveries so far.
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+impl Reminder {
+    /// Whether delivery should be tried at `now`, later after each failed attempt.
+    fn is_due(&self, now: i64) -> bool {
+        self.due + RETRY_AFTER_SECS * i64::from(self.attempts) <= now
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct StoredReminders {
+    pub next_id: u64,
+    pub reminders: BTreeMap<u64, Reminder>,
+}
+
+pub struct Reminders;
+
+impl TypeMapKey for Reminders {
+    type Value = StoredReminders;
+}
+
+/// The time zone every user picked with `remind timezone`, by IANA name.
+pub struct UserTimezones;
+
+impl TypeMapKey for UserTimezones {
+    type Value = HashMap<UserId, String>;
+}
+
+/// The time zone of `user`, UTC if they did not pick one.
+pub async fn user_timezone(data: &RwLock<TypeMap>, user: UserId) -> Tz {
+    let data = data.read().await;
+    data.get::<UserTimezones>()
+        .expect("Expected UserTimezones in TypeMap.")
+        .get(&user)
+        .and_then(|name| name.parse().ok())
+        .unwrap_or(Tz::UTC)
+}
+
+/// What is left of `text` after skipping `count` words, with the original spacing.
+pub fn skip_words(text: &str, count: usize) -> &str {
+    let mut rest = text.trim_start();
+    for _ in 0..count {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        rest = rest[end..].trim_start();
+    }
+    rest
+}
+
+async fn save_reminders(data: &RwLock<TypeMap>) -> CommandResult {
+    let reminders = {
+        let data = data.read().await;
+        data.get::<Reminders>()
+            .expect("Expected Reminders in TypeMap.")
+            .clone()
+    };
+    save_json(REMINDERS_PATH, &reminders).await?;
+    Ok(())
+}
+
+/// Delivers and forgets every reminder that is due at `now`. Reminders that could not be
+/// delivered stay stored and are retried a few times.
+pub async fn run_due(scheduler: &SchedulerContext, now: DateTime<Utc>) -> CommandResult {
+    let due = {
+        let data = scheduler.data.read().await;
+        data.get::<Reminders>()
+            .expect("Expected Reminders in TypeMap.")
+            .reminders
+            .iter()
+            .filter(|(_, reminder)| reminder.is_due(now.timestamp()))
+            .map(|(id, reminder)| (*id, reminder.clone()))
+            .collect::<Vec<_>>()
+    };
+    if due.is_empty() {
+        return Ok(());
+    }
+
+    let mut results = Vec::new();
+    for (id, reminder) in due {
+        let mut content = format!(
+            "<@{}>, you asked me <t:{}:R> to remind you: {}",
+            reminder.user, reminder.created, reminder.text
+        );
+        if now.timestamp() - reminder.due > LATE_AFTER_SECS {
+            write!(
+                content,
+                "\n(Sorry, this was due <t:{}:R>, I was offline.)",
+                reminder.due
+            )?;
+        }
+        let message = CreateMessage::new()
+            .content(content)
+            .allowed_mentions(CreateAllowedMentions::new().users([reminder.user]));
+
+        let mut delivered = match reminder.channel {
+            Some(channel) => channel
+                .send_message(&scheduler.http, message.clone())
+                .await
+                .is_ok(),
+            None => false,
+        };
+        // The channel may be gone or closed to the bot, a DM is better than nothing.
+        if !delivered {
+            match reminder.user.direct_message(&scheduler.http, message).await {
+                Ok(_) => delivered = true,
+                Err(why) => {
+                    println!("Could not deliver a reminder to {}: {why:?}", reminder.user)
+                }
+            }
+        }
+        results.push((id, delivered));
+    }
+
+    {
+        let mut data = scheduler.data.write().await;
+        let stored = data
+            .get_mut::<Reminders>()
+            .expect("Expected Reminders in TypeMap.");
+        for (id, delivered) in results {
+            // The reminder may have been deleted while it was being delivered.
+            let Some(reminder) = stored.reminders.get_mut(&id) else {
+                continue;
+            };
+            reminder.attempts += 1;
+            if delivered || reminder.attempts >= MAX_DELIVERY_ATTEMPTS {
+                stored.reminders.remove(&id);
+            }
+        }
+    }
+    save_reminders(&scheduler.data).await
+}
+
+async fn add_reminder(
+    ctx: &Context,
+    msg: &Message,
+    args: Args,
+    channel: Option<ChannelId>,
+) -> CommandResult {
+    let input = args.rest();
+    let words = input.split_whitespace().collect::<Vec<_>>();
+    let tz = user_timezone(&ctx.data, msg.author.id).await;
+    let now = Utc::now();
+    let Some((due, used)) = parse_when(&words, tz, now) else {
+        msg.reply(
+            ctx,
+            "I don't understand when. Try `in 2h`, `in 1 day 3 hours`, `at 18:00`, \
+            `at 2026-11-01 18:00` or `tomorrow at 9:00`.",
+        )
+        .await?;
+        return Ok(());
+    };
+    let mut text = skip_words(input, used);
+    if let Some(stripped) = text.strip_prefix("to ") {
+        text = stripped.trim_start();
+    }
+    if text.is_empty() {
+        msg.reply(ctx, "What should I remind you of?").await?;
+        return Ok(());
+    }
+    if text.chars().count() > MAX_REMINDER_LENGTH {
+        msg.reply(
+            ctx,
+            format!(
+                "Reminders can be at most {} characters long.",
+                MAX_REMINDER_LENGTH
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+    if due <= now || due > now + chrono::Duration::days(MAX_REMINDER_DAYS) {
+        msg.reply(ctx, "That time is in the past or too far in the future.")
+            .await?;
+        return Ok(());
+    }
+
+    let added = {
+        let mut data = ctx.data.write().await;
+        let stored = data
+            .get_mut::<Reminders>()
+            .expect("Expected Reminders in TypeMap.");
+        let count = stored
+            .reminders
+            .values()
+            .filter(|reminder| reminder.user == msg.author.id)
+            .count();
+        if count >= MAX_REMINDERS_PER_USER {
+            None
+        } else {
+            stored.next_id += 1;
+            let id = stored.next_id;
+            stored.reminders.insert(
+                id,
+                Reminder {
+                    user: msg.author.id,
+                    channel,
+                    text: text.to_string(),
+                    due: due.timestamp(),
+                    created: now.timestamp(),
+                    attempts: 0,
+                },
+            );
+            Some(id)
+        }
+    };
+    let Some(id) = added else {
+        msg.reply(
+            ctx,
+            format!(
+                "You already have {} reminders, delete some first.",
+                MAX_REMINDERS_PER_USER
+            ),
+        )
+        .await?;
+        return Ok(());
+    };
+    save_reminders(&ctx.data).await?;
+
+    msg.reply(
+        ctx,
+        format!(
+            "Reminder #{} set for <t:{}:f> (<t:{}:R>).",
+            id,
+            due.timestamp(),
+            due.timestamp()
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Reminds you in this channel. Times are in your time zone, see \
+`remind timezone`."]
+#[usage = "<in 2h|at 18:00|at 2026-11-01 18:00|tomorrow at 9:00> [to] <text>"]
+#[example = "in 2h to take out the trash"]
+async fn me(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    add_reminder(ctx, msg, args, Some(msg.channel_id)).await
+}
+
+#[command]
+#[description = "Reminds you by direct message."]
+#[usage = "<in 2h|at 18:00|at 2026-11-01 18:00|tomorrow at 9:00> [to] <text>"]
+#[example = "tomorrow at 9:00 to call mom"]
+async fn dm(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    add_reminder(ctx, msg, args, None).await
+}
+
+#[command]
+#[description = "Lists your reminders."]
+async fn list(ctx: &Context, msg: &Message) -> CommandResult {
+    let reminders = {
+        let data = ctx.data.read().await;
+        data.get::<Reminders>()
+            .expect("Expected Reminders in TypeMap.")
+            .reminders
+            .iter()
+            .filter(|(_, reminder)| reminder.user == msg.author.id)
+            .map(|(id, reminder)| (*id, reminder.clone()))
+            .collect::<Vec<_>>()
+    };
+    if reminders.is_empty() {
+        msg.reply(ctx, "You have no reminders.").await?;
+        return Ok(());
+    }
+
+    let entries = reminders
+        .into_iter()
+        .map(|(id, reminder)| {
+            let place = match reminder.channel {
+                Some(channel) => format!("<#{}>", channel),
+                None => String::from("DM"),
+            };
+            let text = reminder.text.chars().take(100).collect::<String>();
+            format!("#{} <t:{}:f> in {}: {}", id, reminder.due, place, text)
+        })
+        .collect();
+    Paginator::new("Your reminders", entries)
+        .send(ctx, msg)
+        .await
+}
+
+#[command]
+#[description = "Deletes one of your reminders."]
+#[usage = "<id>"]
+async fn delete(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Ok(id) = args.single::<u64>() else {
+        msg.reply(ctx, "You need to provide a reminder id, see `remind list`.")
+            .await?;
+        return Ok(());
+    };
+
+    let removed = {
+        let mut data = ctx.data.write().await;
+        let stored = data
+            .get_mut::<Reminders>()
+            .expect("Expected Reminders in TypeMap.");
+        if stored
+            .reminders
+            .get(&id)
+            .is_some_and(|reminder| reminder.user == msg.author.id)
+        {
+            stored.reminders.remove(&id)
+        } else {
+            None
+        }
+    };
+    if removed.is_none() {
+        msg.reply(ctx, "You have no reminder with that id.").await?;
+        return Ok(());
+    }
+    save_reminders(&ctx.data).await?;
+    msg.reply(ctx, format!("Reminder #{} deleted.", id)).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Shows or sets your time zone, as used by reminders. Use names like \
+`Europe/Berlin` or `America/New_York`."]
+#[usage = "[time zone]"]
+async fn timezone(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Ok(name) = args.single::<String>() else {
+        let tz = user_timezone(&ctx.data, msg.author.id).await;
+        msg.reply(ctx, format!("Your time zone is `{}`.", tz.name()))
+            .await?;
+        return Ok(());
+    };
+    let Ok(tz) = name.parse::<Tz>() else {
+        msg.reply(
+            ctx,
+            "I don't know that time zone. Use names like `Europe/Berlin` or `America/New_York`.",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    let timezones = {
+        let mut data = ctx.data.write().await;
+        let timezones = data
+            .get_mut::<UserTimezones>()
+            .expect("Expected UserTimezones in TypeMap.");
+        timezones.insert(msg.author.id, tz.name().to_string());
+        timezones.clone()
+    };
+    save_json(TIMEZONES_PATH, &timezones).await?;
+    msg.reply(ctx, format!("Your time zone is now `{}`.", tz.name()))
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,886 @@
+use crate::command_base::*;
+use crate::paginator::Paginator;
+use crate::storage::save_json;
+
+use serde::{Deserialize, Serialize};
+use serenity::all::{
+    ButtonStyle, ComponentInteraction, ComponentInteractionDataKind, CreateActionRow, CreateButton,
+    CreateEmbed, CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseMessage,
+    CreateMessage, CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption,
+    EditInteractionResponse, EditMessage, Guild, Reaction, ReactionType,
+};
+use serenity::model::id::{ChannelId, GuildId, MessageId, RoleId, UserId};
+use serenity::utils::{parse_emoji, parse_role_mention};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+pub const ROLE_MENUS_PATH: &str = "data/role_menus.json";
+/// Prefix of the custom ids of role menu buttons and select menus.
+pub const ROLE_MENU_PREFIX: &str = "role_menu";
+/// Discord allows 5 rows of 5 buttons and 25 select menu options.
+const MAX_MENU_OPTIONS: usize = 25;
+
+#[group]
+#[prefixes("roles")]
+#[commands(menu, react)]
+#[required_permissions(MANAGE_ROLES)]
+#[only_in(guilds)]
+pub struct Roles;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum MenuMode {
+    /// Every option can be picked up and dropped freely.
+    Toggle,
+    /// Only one role of the menu at a time.
+    Unique,
+    /// Roles can only be added, e.g. to accept the rules.
+    Verify,
+}
+
+impl MenuMode {
+    fn from_name(name: &str) -> Option<MenuMode> {
+        match name {
+            "toggle" => Some(MenuMode::Toggle),
+            "unique" => Some(MenuMode::Unique),
+            "verify" => Some(MenuMode::Verify),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            MenuMode::Toggle => "toggle",
+            MenuMode::Unique => "unique",
+            MenuMode::Verify => "verify",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum MenuStyle {
+    Buttons,
+    Select,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MenuOption {
+    pub role: RoleId,
+    pub label: String,
+    pub emoji: Option<ReactionType>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RoleMenu {
+    pub channel: ChannelId,
+    pub title: String,
+    pub mode: MenuMode,
+    pub style: MenuStyle,
+    pub options: Vec<MenuOption>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct GuildRoleMenus {
+    /// Role menus keyed by the message they are posted in.
+    pub menus: BTreeMap<MessageId, RoleMenu>,
+    /// Classic reaction roles: message, then emoji (see `emoji_key`) to role.
+    pub reactions: BTreeMap<MessageId, BTreeMap<String, RoleId>>,
+}
+
+pub struct RoleMenus;
+
+impl TypeMapKey for RoleMenus {
+    type Value = HashMap<GuildId, GuildRoleMenus>;
+}
+
+/// Custom emojis are identified by id, since their name can change.
+fn emoji_key(emoji: &ReactionType) -> String {
+    match emoji {
+        ReactionType::Custom { id, .. } => id.to_string(),
+        _ => emoji.to_string(),
+    }
+}
+
+/// Parses a custom emoji, or a unicode emoji, which is anything without letters or digits.
+fn parse_reaction(arg: &str) -> Option<ReactionType> {
+    if let Some(emoji) = parse_emoji(arg) {
+        return Some(ReactionType::Custom {
+            animated: emoji.animated,
+            id: emoji.id,
+            name: Some(emoji.name),
+        });
+    }
+    (!arg.chars().any(|c| c.is_alphanumeric())).then(|| ReactionType::Unicode(arg.to_string()))
+}
+
+/// Parses a message link, or a message id in `channel`.
+fn parse_message_ref(arg: &str, channel: ChannelId) -> Option<(ChannelId, MessageId)> {
+    let mut parts = arg.rsplit('/');
+    let message = parts.next()?.parse().ok()?;
+    let channel = match parts.next() {
+        Some(linked) => linked.parse().ok()?,
+        None => channel,
+    };
+    Some((channel, message))
+}
+
+fn parse_role(arg: &str) -> Option<RoleId> {
+    parse_role_mention(arg).or_else(|| arg.parse().ok())
+}
+
+/// Position of the highest role of `user_id`, 0 if they only have @everyone.
+fn top_position(guild: &Guild, user_id: UserId) -> u16 {
+    guild
+        .members
+        .get(&user_id)
+        .and_then(|member| {
+            member
+                .roles
+                .iter()
+                .filter_map(|role| guild.roles.get(role))
+                .map(|role| role.position)
+                .max()
+        })
+        .unwrap_or(0)
+}
+
+/// Why the bot cannot hand out `role_id`, if it cannot.
+pub fn role_problem(ctx: &Context, guild_id: GuildId, role_id: RoleId) -> Option<String> {
+    if role_id == guild_id.everyone_role() {
+        return Some(String::from("Everyone already has @everyone."));
+    }
+    let guild = guild_id.to_guild_cached(&ctx.cache)?;
+    let Some(role) = guild.roles.get(&role_id) else {
+        return Some(String::from("That role does not exist."));
+    };
+    if role.managed {
+        return Some(format!(
+            "<@&{}> is managed by an integration and cannot be given out.",
+            role_id
+        ));
+    }
+    let bot_id = ctx.cache.current_user().id;
+    (role.position >= top_position(&guild, bot_id)).then(|| {
+        format!(
+            "<@&{}> is not below my highest role, so I cannot give it out.",
+            role_id
+        )
+    })
+}
+
+/// Why `invoker` cannot set up the bot to hand out `role_id`, if they cannot. On top of
+/// [`role_problem`], the role has to be below the invoker's highest role unless they own the
+/// server, so nobody can give out roles they could not assign by hand.
+pub fn setup_problem(
+    ctx: &Context,
+    guild_id: GuildId,
+    invoker: UserId,
+    role_id: RoleId,
+) -> Option<String> {
+    if let Some(problem) = role_problem(ctx, guild_id, role_id) {
+        return Some(problem);
+    }
+    let guild = guild_id.to_guild_cached(&ctx.cache)?;
+    let role = guild.roles.get(&role_id)?;
+    (guild.owner_id != invoker && role.position >= top_position(&guild, invoker)).then(|| {
+        format!(
+            "<@&{}> is not below your highest role, so you cannot give it out.",
+            role_id
+        )
+    })
+}
+
+/// Works out which roles to add and remove when a member picks `chosen` in `menu`. Buttons pick
+/// a single role, select menus send the whole selection.
+pub fn menu_changes(
+    menu: &RoleMenu,
+    current: &[RoleId],
+    chosen: &[RoleId],
+) -> (Vec<RoleId>, Vec<RoleId>) {
+    let menu_roles = menu
+        .options
+        .iter()
+        .map(|option| option.role)
+        .collect::<Vec<_>>();
+    let held = |role: &RoleId| current.contains(role);
+    let chosen = chosen
+        .iter()
+        .filter(|role| menu_roles.contains(role))
+        .copied()
+        .collect::<Vec<_>>();
+
+    let mut add = Vec::new();
+    let mut remove = Vec::new();
+    match (menu.style, menu.mode) {
+        (_, MenuMode::Verify) => add.extend(chosen.iter().filter(|role| !held(role))),
+        (MenuStyle::Select, _) => {
+            add.extend(chosen.iter().filter(|role| !held(role)));
+            remove.extend(
+                menu_roles
+                    .iter()
+                    .filter(|role| held(role) && !chosen.contains(role)),
+            );
+        }
+        (MenuStyle::Buttons, mode) => {
+            for role in chosen {
+                if held(&role) {
+                    remove.push(role);
+                } else {
+                    add.push(role);
+                    if mode == MenuMode::Unique {
+                        remove.extend(
+                            menu_roles
+                                .iter()
+                                .filter(|other| **other != role && held(other)),
+                        );
+                    }
+                }
+            }
+        }
+    }
+    (add, remove)
+}
+
+fn menu_embed(menu: &RoleMenu) -> CreateEmbed {
+    let mut description = String::new();
+    for option in &menu.options {
+        match &option.emoji {
+            Some(emoji) => {
+                let _ = writeln!(description, "{} <@&{}>", emoji, option.role);
+            }
+            None => {
+                let _ = writeln!(description, "<@&{}>", option.role);
+            }
+        }
+    }
+    if menu.options.is_empty() {
+        description.push_str("No roles yet.");
+    }
+    let footer = match menu.mode {
+        MenuMode::Toggle => "Pick as many roles as you like.",
+        MenuMode::Unique => "You can only have one of these roles.",
+        MenuMode::Verify => "Roles can not be removed here.",
+    };
+    CreateEmbed::new()
+        .title(&menu.title)
+        .description(description)
+        .footer(CreateEmbedFooter::new(footer))
+}
+
+fn menu_components(menu: &RoleMenu) -> Vec<CreateActionRow> {
+    if menu.options.is_empty() {
+        return Vec::new();
+    }
+    match menu.style {
+        MenuStyle::Buttons => menu
+            .options
+            .chunks(5)
+            .map(|row| {
+                CreateActionRow::Buttons(
+                    row.iter()
+                        .map(|option| {
+                            let mut button =
+                                CreateButton::new(format!("{}:{}", ROLE_MENU_PREFIX, option.role))
+                                    .label(&option.label)
+                                    .style(ButtonStyle::Secondary);
+                            if let Some(emoji) = &option.emoji {
+                                button = button.emoji(emoji.clone());
+                            }
+                            button
+                        })
+                        .collect(),
+                )
+            })
+            .collect(),
+        MenuStyle::Select => {
+            let options = menu
+                .options
+                .iter()
+                .map(|option| {
+                    let mut select_option =
+                        CreateSelectMenuOption::new(&option.label, option.role.to_string());
+                    if let Some(emoji) = &option.emoji {
+                        select_option = select_option.emoji(emoji.clone());
+                    }
+                    select_option
+                })
+                .collect();
+            let max_values = match menu.mode {
+                MenuMode::Unique => 1,
+                _ => menu.options.len() as u8,
+            };
+            vec![CreateActionRow::SelectMenu(
+                CreateSelectMenu::new(ROLE_MENU_PREFIX, CreateSelectMenuKind::String { options })
+                    .placeholder("Pick your roles")
+                    .min_values(0)
+                    .max_values(max_values),
+            )]
+        }
+    }
+}
+
+async fn save_menus(ctx: &Context) -> CommandResult {
+    let menus = {
+        let data = ctx.data.read().await;
+        data.get::<RoleMenus>()
+            .expect("Expected RoleMenus in TypeMap.")
+            .clone()
+    };
+    save_json(ROLE_MENUS_PATH, &menus).await?;
+    Ok(())
+}
+
+fn find_menu(data: &TypeMap, guild_id: GuildId, message: MessageId) -> Option<RoleMenu> {
+    data.get::<RoleMenus>()
+        .expect("Expected RoleMenus in TypeMap.")
+        .get(&guild_id)?
+        .menus
+        .get(&message)
+        .cloned()
+}
+
+/// Handles a click on a role menu button or a choice in a role menu select.
+pub async fn handle_interaction(ctx: &Context, component: &ComponentInteraction) -> CommandResult {
+    let (Some(guild_id), Some(member)) = (component.guild_id, &component.member) else {
+        return Ok(());
+    };
+    let menu = {
+        let data = ctx.data.read().await;
+        find_menu(&data, guild_id, component.message.id)
+    };
+    let Some(menu) = menu else {
+        return respond(
+            ctx,
+            component,
+            String::from("This role menu no longer exists."),
+        )
+        .await;
+    };
+
+    let chosen = match &component.data.kind {
+        ComponentInteractionDataKind::Button => component
+            .data
+            .custom_id
+            .split(':')
+            .nth(1)
+            .and_then(|role| role.parse().ok())
+            .into_iter()
+            .collect(),
+        ComponentInteractionDataKind::StringSelect { values } => values
+            .iter()
+            .filter_map(|role| role.parse().ok())
+            .collect::<Vec<RoleId>>(),
+        _ => return Ok(()),
+    };
+    let (add, remove) = menu_changes(&menu, &member.roles, &chosen);
+    // Changing several roles can take longer than Discord waits for an answer.
+    component.defer_ephemeral(&ctx.http).await?;
+
+    let mut contents = String::new();
+    for role in add {
+        if let Some(problem) = role_problem(ctx, guild_id, role) {
+            writeln!(contents, "{}", problem)?;
+            continue;
+        }
+        match ctx
+            .http
+            .add_member_role(guild_id, member.user.id, role, Some("Role menu"))
+            .await
+        {
+            Ok(()) => writeln!(contents, "Gave you <@&{}>.", role)?,
+            Err(why) => {
+                println!("Giving role {} failed: {:?}", role, why);
+                writeln!(contents, "I could not give you <@&{}>.", role)?;
+            }
+        }
+    }
+    for role in remove {
+        if let Some(problem) = role_problem(ctx, guild_id, role) {
+            writeln!(contents, "{}", problem)?;
+            continue;
+        }
+        match ctx
+            .http
+            .remove_member_role(guild_id, member.user.id, role, Some("Role menu"))
+            .await
+        {
+            Ok(()) => writeln!(contents, "Took <@&{}> from you.", role)?,
+            Err(why) => {
+                println!("Taking role {} failed: {:?}", role, why);
+                writeln!(contents, "I could not take <@&{}> from you.", role)?;
+            }
+        }
+    }
+    if contents.is_empty() {
+        contents.push_str("Nothing changed.");
+    }
+    component
+        .edit_response(&ctx.http, EditInteractionResponse::new().content(contents))
+        .await?;
+    Ok(())
+}
+
+async fn respond(
+    ctx: &Context,
+    component: &ComponentInteraction,
+    content: String,
+) -> CommandResult {
+    component
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Looks up the role of a reaction on a reaction role message.
+async fn reaction_role(ctx: &Context, reaction: &Reaction) -> Option<(GuildId, UserId, RoleId)> {
+    let guild_id = reaction.guild_id?;
+    let user_id = reaction.user_id?;
+    if user_id == ctx.cache.current_user().id {
+        return None;
+    }
+    let data = ctx.data.read().await;
+    let role = *data
+        .get::<RoleMenus>()
+        .expect("Expected RoleMenus in TypeMap.")
+        .get(&guild_id)?
+        .reactions
+        .get(&reaction.message_id)?
+        .get(&emoji_key(&reaction.emoji))?;
+    Some((guild_id, user_id, role))
+}
+
+pub async fn handle_reaction_add(ctx: &Context, reaction: &Reaction) -> CommandResult {
+    let Some((guild_id, user_id, role)) = reaction_role(ctx, reaction).await else {
+        return Ok(());
+    };
+    if reaction
+        .member
+        .as_ref()
+        .is_some_and(|member| member.user.bot)
+    {
+        return Ok(());
+    }
+    if let Some(problem) = role_problem(ctx, guild_id, role) {
+        println!(
+            "Reaction role on {} failed: {}",
+            reaction.message_id, problem
+        );
+        return Ok(());
+    }
+    ctx.http
+        .add_member_role(guild_id, user_id, role, Some("Reaction role"))
+        .await?;
+    Ok(())
+}
+
+pub async fn handle_reaction_remove(ctx: &Context, reaction: &Reaction) -> CommandResult {
+    let Some((guild_id, user_id, role)) = reaction_role(ctx, reaction).await else {
+        return Ok(());
+    };
+    if role_problem(ctx, guild_id, role).is_some() {
+        return Ok(());
+    }
+    ctx.http
+        .remove_member_role(guild_id, user_id, role, Some("Reaction role"))
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Manages role menus: messages with buttons or a select menu to pick roles."]
+#[sub_commands(menu_create, menu_add, menu_remove, menu_delete, menu_list)]
+async fn menu(ctx: &Context, msg: &Message) -> CommandResult {
+    msg.reply(
+        ctx,
+        "Use `roles menu create`, `add`, `remove`, `delete` or `list`. See `help roles menu` for \
+        details.",
+    )
+    .await?;
+    Ok(())
+}
+
+#[command("create")]
+#[description = "Posts a new, empty role menu in this channel. `toggle` lets members pick any \
+roles, `unique` only one of them and `verify` only adds roles."]
+#[usage = "<buttons|select> <toggle|unique|verify> <title>"]
+async fn menu_create(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let style = match args.single::<String>().unwrap_or_default().as_str() {
+        "buttons" => Some(MenuStyle::Buttons),
+        "select" => Some(MenuStyle::Select),
+        _ => None,
+    };
+    let mode = args
+        .single::<String>()
+        .ok()
+        .and_then(|mode| MenuMode::from_name(&mode));
+    let title = args.rest().trim().to_string();
+    let (Some(style), Some(mode), false) = (style, mode, title.is_empty()) else {
+        msg.reply(
+            ctx,
+            "Usage: `roles menu create <buttons|select> <toggle|unique|verify> <title>`",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    let menu = RoleMenu {
+        channel: msg.channel_id,
+        title,
+        mode,
+        style,
+        options: Vec::new(),
+    };
+    let posted = msg
+        .channel_id
+        .send_message(&ctx.http, CreateMessage::new().embed(menu_embed(&menu)))
+        .await?;
+    {
+        let mut data = ctx.data.write().await;
+        data.get_mut::<RoleMenus>()
+            .expect("Expected RoleMenus in TypeMap.")
+            .entry(msg.guild_id.unwrap())
+            .or_default()
+            .menus
+            .insert(posted.id, menu);
+    }
+    save_menus(ctx).await?;
+
+    msg.reply(
+        ctx,
+        format!(
+            "Menu created, add roles with `roles menu add {} <@role> [emoji] [label]`.",
+            posted.id
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Applies `change` to a menu, then saves it and updates its message.
+async fn edit_menu(
+    ctx: &Context,
+    msg: &Message,
+    message: MessageId,
+    change: impl FnOnce(&mut RoleMenu) -> Result<(), String>,
+) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap();
+    let result = {
+        let mut data = ctx.data.write().await;
+        let menu = data
+            .get_mut::<RoleMenus>()
+            .expect("Expected RoleMenus in TypeMap.")
+            .entry(guild_id)
+            .or_default()
+            .menus
+            .get_mut(&message);
+        match menu {
+            Some(menu) => change(menu).map(|()| menu.clone()),
+            None => Err(String::from("There is no role menu with that id.")),
+        }
+    };
+    let menu = match result {
+        Ok(menu) => menu,
+        Err(problem) => {
+            msg.reply(ctx, problem).await?;
+            return Ok(());
+        }
+    };
+    save_menus(ctx).await?;
+
+    menu.channel
+        .edit_message(
+            &ctx.http,
+            message,
+            EditMessage::new()
+                .embed(menu_embed(&menu))
+                .components(menu_components(&menu)),
+        )
+        .await?;
+    msg.react(ctx, '✅').await?;
+
+    Ok(())
+}
+
+#[command("add")]
+#[description = "Adds a role to a menu. The label defaults to the role name."]
+#[usage = "<menu id> <@role> [emoji] [label]"]
+async fn menu_add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let message = args.single::<MessageId>().ok();
+    let role = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_role(&arg));
+    let (Some(message), Some(role)) = (message, role) else {
+        msg.reply(
+            ctx,
+            "Usage: `roles menu add <menu id> <@role> [emoji] [label]`",
+        )
+        .await?;
+        return Ok(());
+    };
+    let guild_id = msg.guild_id.unwrap();
+    if let Some(problem) = setup_problem(ctx, guild_id, msg.author.id, role) {
+        msg.reply(ctx, problem).await?;
+        return Ok(());
+    }
+
+    let emoji = args.current().and_then(parse_reaction);
+    if emoji.is_some() {
+        args.advance();
+    }
+    let mut label = args.rest().trim().to_string();
+    if label.is_empty() {
+        label = guild_id
+            .to_guild_cached(&ctx.cache)
+            .and_then(|guild| guild.roles.get(&role).map(|role| role.name.clone()))
+            .unwrap_or_else(|| role.to_string());
+    }
+
+    edit_menu(ctx, msg, message, |menu| {
+        if menu.options.len() >= MAX_MENU_OPTIONS {
+            return Err(format!(
+                "A menu can have at most {} roles.",
+                MAX_MENU_OPTIONS
+            ));
+        }
+        if menu.options.iter().any(|option| option.role == role) {
+            return Err(String::from("That role is already in the menu."));
+        }
+        menu.options.push(MenuOption { role, label, emoji });
+        Ok(())
+    })
+    .await
+}
+
+#[command("remove")]
+#[description = "Removes a role from a menu."]
+#[usage = "<menu id> <@role>"]
+async fn menu_remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let message = args.single::<MessageId>().ok();
+    let role = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_role(&arg));
+    let (Some(message), Some(role)) = (message, role) else {
+        msg.reply(ctx, "Usage: `roles menu remove <menu id> <@role>`")
+            .await?;
+        return Ok(());
+    };
+
+    edit_menu(ctx, msg, message, |menu| {
+        let before = menu.options.len();
+        menu.options.retain(|option| option.role != role);
+        if menu.options.len() == before {
+            return Err(String::from("That role is not in the menu."));
+        }
+        Ok(())
+    })
+    .await
+}
+
+#[command("delete")]
+#[description = "Deletes a role menu and its message."]
+#[usage = "<menu id>"]
+async fn menu_delete(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Ok(message) = args.single::<MessageId>() else {
+        msg.reply(ctx, "Usage: `roles menu delete <menu id>`")
+            .await?;
+        return Ok(());
+    };
+
+    let menu = {
+        let mut data = ctx.data.write().await;
+        data.get_mut::<RoleMenus>()
+            .expect("Expected RoleMenus in TypeMap.")
+            .entry(msg.guild_id.unwrap())
+            .or_default()
+            .menus
+            .remove(&message)
+    };
+    let Some(menu) = menu else {
+        msg.reply(ctx, "There is no role menu with that id.")
+            .await?;
+        return Ok(());
+    };
+    save_menus(ctx).await?;
+
+    // The message may already be gone, the menu is deleted either way.
+    let _ = menu.channel.delete_message(&ctx.http, message).await;
+    msg.channel_id.say(&ctx.http, "Role menu deleted.").await?;
+
+    Ok(())
+}
+
+#[command("list")]
+#[description = "Lists the role menus and reaction role messages of this server."]
+async fn menu_list(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild = {
+        let data = ctx.data.read().await;
+        data.get::<RoleMenus>()
+            .expect("Expected RoleMenus in TypeMap.")
+            .get(&msg.guild_id.unwrap())
+            .cloned()
+            .unwrap_or_default()
+    };
+    if guild.menus.is_empty() && guild.reactions.is_empty() {
+        msg.channel_id
+            .say(&ctx.http, "This server has no role menus.")
+            .await?;
+        return Ok(());
+    }
+
+    let mut entries = Vec::new();
+    for (message, menu) in &guild.menus {
+        entries.push(format!(
+            "`{}` in <#{}>: {} ({}, {} roles)",
+            message,
+            menu.channel,
+            menu.title,
+            menu.mode.name(),
+            menu.options.len()
+        ));
+    }
+    for (message, reactions) in &guild.reactions {
+        entries.push(format!(
+            "`{}`: reaction roles ({} roles)",
+            message,
+            reactions.len()
+        ));
+    }
+    Paginator::new("Role menus", entries).send(ctx, msg).await
+}
+
+#[command]
+#[description = "Manages classic reaction roles: reacting to a message gives a role, removing \
+the reaction takes it away again."]
+#[sub_commands(react_add, react_remove)]
+async fn react(ctx: &Context, msg: &Message) -> CommandResult {
+    msg.reply(
+        ctx,
+        "Use `roles react add` or `roles react remove`. See `help roles react` for details.",
+    )
+    .await?;
+    Ok(())
+}
+
+#[command("add")]
+#[description = "Makes reacting with `emoji` to a message give a role. Takes a message link or \
+the id of a message in this channel."]
+#[usage = "<message> <emoji> <@role>"]
+async fn react_add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let message = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_message_ref(&arg, msg.channel_id));
+    let emoji = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_reaction(&arg));
+    let role = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_role(&arg));
+    let (Some((channel, message)), Some(emoji), Some(role)) = (message, emoji, role) else {
+        msg.reply(ctx, "Usage: `roles react add <message> <emoji> <@role>`")
+            .await?;
+        return Ok(());
+    };
+    let guild_id = msg.guild_id.unwrap();
+    if !is_text_channel(ctx, guild_id, channel) {
+        msg.reply(ctx, "That message is not in a text channel of this server.")
+            .await?;
+        return Ok(());
+    }
+    if let Some(problem) = setup_problem(ctx, guild_id, msg.author.id, role) {
+        msg.reply(ctx, problem).await?;
+        return Ok(());
+    }
+
+    // Reacting first checks that the message exists and the emoji is usable.
+    if ctx
+        .http
+        .create_reaction(channel, message, &emoji)
+        .await
+        .is_err()
+    {
+        msg.reply(ctx, "I cannot react to that message with that emoji.")
+            .await?;
+        return Ok(());
+    }
+    {
+        let mut data = ctx.data.write().await;
+        data.get_mut::<RoleMenus>()
+            .expect("Expected RoleMenus in TypeMap.")
+            .entry(guild_id)
+            .or_default()
+            .reactions
+            .entry(message)
+            .or_default()
+            .insert(emoji_key(&emoji), role);
+    }
+    save_menus(ctx).await?;
+    msg.react(ctx, '✅').await?;
+
+    Ok(())
+}
+
+#[command("remove")]
+#[description = "Stops reacting with `emoji` to a message from giving a role."]
+#[usage = "<message> <emoji>"]
+async fn react_remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let message = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_message_ref(&arg, msg.channel_id));
+    let emoji = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_reaction(&arg));
+    let (Some((channel, message)), Some(emoji)) = (message, emoji) else {
+        msg.reply(ctx, "Usage: `roles react remove <message> <emoji>`")
+            .await?;
+        return Ok(());
+    };
+
+    let removed = {
+        let mut data = ctx.data.write().await;
+        let reactions = &mut data
+            .get_mut::<RoleMenus>()
+            .expect("Expected RoleMenus in TypeMap.")
+            .entry(msg.guild_id.unwrap())
+            .or_default()
+            .reactions;
+        let removed = reactions
+            .get_mut(&message)
+            .and_then(|roles| roles.remove(&emoji_key(&emoji)))
+            .is_some();
+        if reactions
+            .get(&message)
+            .is_some_and(|roles| roles.is_empty())
+        {
+            reactions.remove(&message);
+        }
+        removed
+    };
+    if !removed {
+        msg.reply(ctx, "That emoji gives no role on that message.")
+            .await?;
+        return Ok(());
+    }
+    save_menus(ctx).await?;
+
+    let _ = ctx.http.delete_reaction_me(channel, message, &emoji).await;
+    msg.react(ctx, '✅').await?;
+
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use crate::command_base::*;
+
+use chrono::Utc;
+use serenity::cache::Cache;
+use serenity::http::Http;
+use std::time::Duration;
+
+/// How often the scheduler looks for jobs that came due.
+const TICK: Duration = Duration::from_secs(5);
+
+/// What scheduled jobs get to work with. The scheduler starts before the gateway connects, so
+/// there is no `Context` yet.
+pub struct SchedulerContext {
+    pub http: Arc<Http>,
+    pub cache: Arc<Cache>,
+    pub data: Arc<RwLock<TypeMap>>,
+}
+
+/// Runs every persisted job that came due, forever. Jobs that came due while the bot was down
+/// run on the first tick.
+pub async fn run(scheduler: SchedulerContext) {
+    let mut interval = tokio::time::interval(TICK);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let now = Utc::now();
+        if let Err(why) = crate::reminder_commands::run_due(&scheduler, now).await {
+            println!("Reminders returned error {why:?}");
+        }
+        if let Err(why) = crate::announcement_commands::run_due(&scheduler, now).await {
+            println!("Announcements returned error {why:?}");
+        }
+        if let Err(why) = crate::poll_commands::run_due(&scheduler, now).await {
+            println!("Polls returned error {why:?}");
+        }
+        if let Err(why) = crate::giveaway_commands::run_due(&scheduler, now).await {
+            println!("Giveaways returned error {why:?}");
+        }
+        if let Err(why) = crate::leveling_commands::run_due(&scheduler).await {
+            println!("Leveling returned error {why:?}");
+        }
+        if let Err(why) = crate::tag_commands::run_due(&scheduler).await {
+            println!("Tags returned error {why:?}");
+        }
+    }
+}
@@ -0,0 +1,450 @@
+use crate::command_base::*;
+use crate::paginator::Paginator;
+use crate::role_menu_commands::{role_problem, setup_problem};
+use crate::storage::save_json;
+
+use levenshtein::levenshtein;
+use serde::{Deserialize, Serialize};
+use serenity::all::{CreateAllowedMentions, CreateMessage};
+use serenity::model::id::{GuildId, RoleId};
+use serenity::utils::parse_role_mention;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+pub const SELF_ROLES_PATH: &str = "data/self_roles.json";
+const DEFAULT_CATEGORY: &str = "general";
+const CATEGORIES_PER_PAGE: usize = 5;
+/// Same tolerance the help command uses for command names.
+const MAX_LEVENSHTEIN_DISTANCE: usize = 3;
+
+#[group]
+#[prefixes("role")]
+#[commands(iam, iamnot, list, add, remove, exclusive)]
+#[only_in(guilds)]
+pub struct Role;
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct SelfRoleCategory {
+    /// Members can only have one role of an exclusive category.
+    pub exclusive: bool,
+    pub roles: Vec<RoleId>,
+}
+
+/// The self-assignable roles of every guild, by category name.
+pub struct SelfRoles;
+
+impl TypeMapKey for SelfRoles {
+    type Value = HashMap<GuildId, BTreeMap<String, SelfRoleCategory>>;
+}
+
+/// How a role name resolved in [`fuzzy_find`].
+#[derive(PartialEq, Debug)]
+pub enum NameMatch<T> {
+    Exact(T),
+    /// There is no role with that name, but this one is close.
+    Suggestion(String),
+    None,
+}
+
+/// Finds the role called `query` in `roles`, ignoring case. Without an exact match the closest
+/// name is suggested, if it is at most a third of its length and `MAX_LEVENSHTEIN_DISTANCE`
+/// edits away and no other name is as close.
+pub fn fuzzy_find<T: Copy>(query: &str, roles: &[(T, String)]) -> NameMatch<T> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return NameMatch::None;
+    }
+    if let Some((role, _)) = roles.iter().find(|(_, name)| name.to_lowercase() == query) {
+        return NameMatch::Exact(*role);
+    }
+    let mut distances = roles
+        .iter()
+        .map(|(_, name)| (levenshtein(&query, &name.to_lowercase()), name))
+        .filter(|(distance, name)| {
+            *distance <= (name.chars().count() / 3).min(MAX_LEVENSHTEIN_DISTANCE)
+        })
+        .collect::<Vec<_>>();
+    distances.sort_by_key(|(distance, _)| *distance);
+    match distances.as_slice() {
+        [(best, name), (second, _), ..] if best < second => NameMatch::Suggestion(name.to_string()),
+        [(_, name)] => NameMatch::Suggestion(name.to_string()),
+        _ => NameMatch::None,
+    }
+}
+
+fn guild_categories(data: &TypeMap, guild_id: GuildId) -> BTreeMap<String, SelfRoleCategory> {
+    data.get::<SelfRoles>()
+        .expect("Expected SelfRoles in TypeMap.")
+        .get(&guild_id)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Resolves `query` to a self-assignable role and its category. Otherwise returns what to tell
+/// the member.
+async fn find_self_role(
+    ctx: &Context,
+    guild_id: GuildId,
+    query: &str,
+) -> Result<(RoleId, SelfRoleCategory), String> {
+    const NOT_FOUND: &str = "There is no self-assignable role with that name, see `role list`.";
+    let categories = {
+        let data = ctx.data.read().await;
+        guild_categories(&data, guild_id)
+    };
+    let named = {
+        let guild = guild_id
+            .to_guild_cached(&ctx.cache)
+            .ok_or_else(|| String::from(NOT_FOUND))?;
+        categories
+            .values()
+            .flat_map(|category| &category.roles)
+            .filter_map(|role| guild.roles.get(role))
+            .map(|role| (role.id, role.name.clone()))
+            .collect::<Vec<_>>()
+    };
+    let role = match parse_role_mention(query) {
+        Some(role) => role,
+        None => match fuzzy_find(query, &named) {
+            NameMatch::Exact(role) => role,
+            NameMatch::Suggestion(name) => {
+                return Err(format!(
+                    "There is no self-assignable role with that name. Did you mean `{}`?",
+                    name.replace('`', "")
+                ))
+            }
+            NameMatch::None => return Err(String::from(NOT_FOUND)),
+        },
+    };
+    categories
+        .into_values()
+        .find(|category| category.roles.contains(&role))
+        .map(|category| (role, category))
+        .ok_or_else(|| String::from(NOT_FOUND))
+}
+
+async fn save_self_roles(ctx: &Context) -> CommandResult {
+    let roles = {
+        let data = ctx.data.read().await;
+        data.get::<SelfRoles>()
+            .expect("Expected SelfRoles in TypeMap.")
+            .clone()
+    };
+    save_json(SELF_ROLES_PATH, &roles).await?;
+    Ok(())
+}
+
+async fn say_quietly(ctx: &Context, msg: &Message, content: String) -> CommandResult {
+    msg.channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(content)
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Gives you a self-assignable role. For small typos, the right name is suggested."]
+#[usage = "<role name>"]
+async fn iam(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap();
+    let (role, category) = match find_self_role(ctx, guild_id, args.rest().trim()).await {
+        Ok(found) => found,
+        Err(problem) => {
+            say_quietly(ctx, msg, problem).await?;
+            return Ok(());
+        }
+    };
+    if let Some(problem) = role_problem(ctx, guild_id, role) {
+        say_quietly(ctx, msg, problem).await?;
+        return Ok(());
+    }
+
+    let member = guild_id.member(&ctx.http, msg.author.id).await?;
+    if member.roles.contains(&role) {
+        say_quietly(ctx, msg, format!("You already have <@&{}>.", role)).await?;
+        return Ok(());
+    }
+    if category.exclusive {
+        for other in category
+            .roles
+            .iter()
+            .filter(|other| **other != role && member.roles.contains(other))
+        {
+            ctx.http
+                .remove_member_role(guild_id, msg.author.id, *other, Some("Self role"))
+                .await?;
+        }
+    }
+    ctx.http
+        .add_member_role(guild_id, msg.author.id, role, Some("Self role"))
+        .await?;
+    say_quietly(ctx, msg, format!("You now have <@&{}>.", role)).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Takes a self-assignable role away from you."]
+#[usage = "<role name>"]
+async fn iamnot(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap();
+    let (role, _) = match find_self_role(ctx, guild_id, args.rest().trim()).await {
+        Ok(found) => found,
+        Err(problem) => {
+            say_quietly(ctx, msg, problem).await?;
+            return Ok(());
+        }
+    };
+    if let Some(problem) = role_problem(ctx, guild_id, role) {
+        say_quietly(ctx, msg, problem).await?;
+        return Ok(());
+    }
+
+    let member = guild_id.member(&ctx.http, msg.author.id).await?;
+    if !member.roles.contains(&role) {
+        say_quietly(ctx, msg, format!("You don't have <@&{}>.", role)).await?;
+        return Ok(());
+    }
+    ctx.http
+        .remove_member_role(guild_id, msg.author.id, role, Some("Self role"))
+        .await?;
+    say_quietly(ctx, msg, format!("You no longer have <@&{}>.", role)).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Lists the self-assignable roles by category."]
+async fn list(ctx: &Context, msg: &Message) -> CommandResult {
+    let categories = {
+        let data = ctx.data.read().await;
+        guild_categories(&data, msg.guild_id.unwrap())
+    };
+    if categories
+        .values()
+        .all(|category| category.roles.is_empty())
+    {
+        msg.channel_id
+            .say(&ctx.http, "This server has no self-assignable roles.")
+            .await?;
+        return Ok(());
+    }
+
+    let mut entries = Vec::new();
+    for (name, category) in categories
+        .iter()
+        .filter(|(_, category)| !category.roles.is_empty())
+    {
+        let mut contents = format!("**{}**", name);
+        if category.exclusive {
+            write!(contents, " (pick one)")?;
+        }
+        let roles = category
+            .roles
+            .iter()
+            .map(|role| format!("<@&{}>", role))
+            .collect::<Vec<_>>();
+        write!(contents, "\n{}", roles.join(", "))?;
+        entries.push(contents);
+    }
+    Paginator::with_page_size("Self-assignable roles", entries, CATEGORIES_PER_PAGE)
+        .send(ctx, msg)
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Makes a role self-assignable, optionally in a category."]
+#[usage = "<@role> [category]"]
+#[required_permissions(MANAGE_ROLES)]
+async fn add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Some(role) = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_role_mention(&arg).or_else(|| arg.parse().ok()))
+    else {
+        msg.reply(ctx, "You need to provide a role!").await?;
+        return Ok(());
+    };
+    let guild_id = msg.guild_id.unwrap();
+    if let Some(problem) = setup_problem(ctx, guild_id, msg.author.id, role) {
+        say_quietly(ctx, msg, problem).await?;
+        return Ok(());
+    }
+    let mut category_name = args.rest().trim().to_lowercase();
+    if category_name.is_empty() {
+        category_name = String::from(DEFAULT_CATEGORY);
+    }
+
+    {
+        let mut data = ctx.data.write().await;
+        let categories = data
+            .get_mut::<SelfRoles>()
+            .expect("Expected SelfRoles in TypeMap.")
+            .entry(guild_id)
+            .or_default();
+        // A role lives in one category only, so moving it is just adding it again.
+        for category in categories.values_mut() {
+            category.roles.retain(|other| *other != role);
+        }
+        categories.retain(|_, category| !category.roles.is_empty() || category.exclusive);
+        categories
+            .entry(category_name.clone())
+            .or_default()
+            .roles
+            .push(role);
+    }
+    save_self_roles(ctx).await?;
+    say_quietly(
+        ctx,
+        msg,
+        format!(
+            "<@&{}> is now self-assignable in `{}`.",
+            role, category_name
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Makes a role no longer self-assignable."]
+#[usage = "<@role>"]
+#[required_permissions(MANAGE_ROLES)]
+async fn remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Some(role) = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_role_mention(&arg).or_else(|| arg.parse().ok()))
+    else {
+        msg.reply(ctx, "You need to provide a role!").await?;
+        return Ok(());
+    };
+
+    let removed = {
+        let mut data = ctx.data.write().await;
+        let categories = data
+            .get_mut::<SelfRoles>()
+            .expect("Expected SelfRoles in TypeMap.")
+            .entry(msg.guild_id.unwrap())
+            .or_default();
+        let mut removed = false;
+        for category in categories.values_mut() {
+            let before = category.roles.len();
+            category.roles.retain(|other| *other != role);
+            removed |= category.roles.len() != before;
+        }
+        categories.retain(|_, category| !category.roles.is_empty() || category.exclusive);
+        removed
+    };
+    if !removed {
+        msg.reply(ctx, "That role is not self-assignable.").await?;
+        return Ok(());
+    }
+    save_self_roles(ctx).await?;
+    say_quietly(
+        ctx,
+        msg,
+        format!("<@&{}> is no longer self-assignable.", role),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Sets whether members can only have one role of a category."]
+#[usage = "<category> <on|off>"]
+#[required_permissions(MANAGE_ROLES)]
+async fn exclusive(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let category_name = args.single::<String>().unwrap_or_default().to_lowercase();
+    let exclusive = match args.single::<String>().unwrap_or_default().as_str() {
+        "on" => true,
+        "off" => false,
+        _ => {
+            msg.reply(ctx, "Usage: `role exclusive <category> <on|off>`")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    {
+        let mut data = ctx.data.write().await;
+        let categories = data
+            .get_mut::<SelfRoles>()
+            .expect("Expected SelfRoles in TypeMap.")
+            .entry(msg.guild_id.unwrap())
+            .or_default();
+        categories
+            .entry(category_name.clone())
+            .or_default()
+            .exclusive = exclusive;
+        categories.retain(|_, category| !category.roles.is_empty() || category.exclusive);
+    }
+    save_self_roles(ctx).await?;
+    msg.channel_id
+        .say(
+            &ctx.http,
+            if exclusive {
+                format!("Members can only pick one role of `{}`.", category_name)
+            } else {
+                format!("Members can pick any roles of `{}`.", category_name)
+            },
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roles() -> Vec<(u8, String)> {
+        ["Red", "Blue", "Green", "Announcements", "Pings"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (i as u8, name.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn exact_names_ignore_case() {
+        assert_eq!(fuzzy_find("red", &roles()), NameMatch::Exact(0));
+        assert_eq!(fuzzy_find(" ANNOUNCEMENTS ", &roles()), NameMatch::Exact(3));
+    }
+
+    #[test]
+    fn close_names_are_only_suggested() {
+        assert_eq!(
+            fuzzy_find("anouncement", &roles()),
+            NameMatch::Suggestion(String::from("Announcements"))
+        );
+        assert_eq!(
+            fuzzy_find("gren", &roles()),
+            NameMatch::Suggestion(String::from("Green"))
+        );
+    }
+
+    #[test]
+    fn short_and_empty_queries_match_nothing() {
+        assert_eq!(fuzzy_find("", &roles()), NameMatch::None);
+        assert_eq!(fuzzy_find("   ", &roles()), NameMatch::None);
+        // Two edits are too many for the four letters of `Blue`.
+        assert_eq!(fuzzy_find("bu", &roles()), NameMatch::None);
+        assert_eq!(fuzzy_find("purple", &roles()), NameMatch::None);
+    }
+
+    #[test]
+    fn ties_are_not_suggested() {
+        let roles = vec![(0, String::from("Pings")), (1, String::from("Pingy"))];
+        assert_eq!(fuzzy_find("pingz", &roles), NameMatch::None);
+    }
+}
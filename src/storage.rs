@@ -0,0 +1,45 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, LazyLock, Mutex};
+
+/// One lock per file, so two saves of the same file never run at the same time.
+static FILE_LOCKS: LazyLock<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>> =
+    LazyLock::new(Mutex::default);
+
+/// Reads a JSON file into `T`. A missing file is not an error, it just means nothing has been
+/// stored yet, so the default value is returned instead.
+pub async fn load_json<T: DeserializeOwned + Default>(path: &str) -> std::io::Result<T> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|why| std::io::Error::new(std::io::ErrorKind::InvalidData, why)),
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        Err(why) => Err(why),
+    }
+}
+
+/// Writes `value` as pretty printed JSON. The data is written to a temporary file first and then
+/// renamed over the old one, so a crash in the middle of a write never leaves a truncated file.
+/// Saves of the same file wait for each other, so they cannot mix up their temporary files. The
+/// lock is fair and taken before the first await, so a snapshot cloned right before the call
+/// cannot be overwritten by an older one.
+pub async fn save_json<T: Serialize>(path: &str, value: &T) -> std::io::Result<()> {
+    let lock = Arc::clone(
+        FILE_LOCKS
+            .lock()
+            .expect("File locks poisoned")
+            .entry(path.to_string())
+            .or_default(),
+    );
+    let _guard = lock.lock().await;
+
+    if let Some(parent) = Path::new(path).parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let content = serde_json::to_string_pretty(value)
+        .map_err(|why| std::io::Error::new(std::io::ErrorKind::InvalidData, why))?;
+    let tmp_path = format!("{}.tmp", path);
+    tokio::fs::write(&tmp_path, content).await?;
+    tokio::fs::rename(&tmp_path, path).await
+}
@@ -0,0 +1,578 @@
+use crate::command_base::*;
+use crate::log_commands::truncate;
+use crate::paginator::Paginator;
+use crate::storage::save_json;
+
+use serde::{Deserialize, Serialize};
+use serenity::all::{
+    ButtonStyle, ComponentInteraction, CreateActionRow, CreateButton, CreateEmbed,
+    CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage,
+    EditMessage, Timestamp,
+};
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+use std::collections::BTreeMap;
+
+pub const SUGGESTIONS_PATH: &str = "data/suggestions.json";
+/// Prefix of the custom ids of suggestion vote buttons.
+pub const SUGGESTION_PREFIX: &str = "suggestion";
+/// Suggestions are shown in an embed description, which holds 4096 characters.
+const MAX_SUGGESTION_CHARS: usize = 2000;
+const MAX_REASON_CHARS: usize = 1000;
+
+#[group]
+#[commands(suggest, suggestions)]
+#[only_in(guilds)]
+pub struct Suggest;
+
+#[group]
+#[prefixes("suggestion")]
+#[commands(approve, deny, consider, channel)]
+#[required_permissions(MANAGE_GUILD)]
+#[only_in(guilds)]
+pub struct Suggestion;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SuggestionStatus {
+    /// Waiting for a decision.
+    Open,
+    /// Staff is thinking about it, voting goes on.
+    Considered,
+    Approved,
+    Denied,
+}
+
+impl SuggestionStatus {
+    const ALL: [SuggestionStatus; 4] = [
+        SuggestionStatus::Open,
+        SuggestionStatus::Considered,
+        SuggestionStatus::Approved,
+        SuggestionStatus::Denied,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            SuggestionStatus::Open => "open",
+            SuggestionStatus::Considered => "considered",
+            SuggestionStatus::Approved => "approved",
+            SuggestionStatus::Denied => "denied",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<SuggestionStatus> {
+        SuggestionStatus::ALL
+            .into_iter()
+            .find(|status| status.name() == name)
+    }
+
+    fn colour(self) -> u32 {
+        match self {
+            SuggestionStatus::Open => 0x5865f2,
+            SuggestionStatus::Considered => 0xfee75c,
+            SuggestionStatus::Approved => 0x57f287,
+            SuggestionStatus::Denied => 0xed4245,
+        }
+    }
+
+    /// Approved and denied suggestions can no longer be voted on.
+    fn is_final(self) -> bool {
+        matches!(self, SuggestionStatus::Approved | SuggestionStatus::Denied)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Decision {
+    pub by: UserId,
+    pub reason: Option<String>,
+    /// Unix timestamp in seconds.
+    pub at: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StoredSuggestion {
+    pub id: u64,
+    pub author: UserId,
+    pub channel: ChannelId,
+    pub content: String,
+    /// Unix timestamp in seconds.
+    pub created: i64,
+    pub status: SuggestionStatus,
+    /// The latest decision, `None` while the suggestion is open.
+    pub decision: Option<Decision>,
+    /// Every voter, `true` for an up vote.
+    pub votes: BTreeMap<UserId, bool>,
+}
+
+impl StoredSuggestion {
+    /// Toggles the vote of `user`. Voting the other way replaces the old vote. Returns the vote
+    /// `user` now has.
+    fn vote(&mut self, user: UserId, up: bool) -> Option<bool> {
+        if self.votes.get(&user) == Some(&up) {
+            self.votes.remove(&user);
+            None
+        } else {
+            self.votes.insert(user, up);
+            Some(up)
+        }
+    }
+
+    fn tally(&self) -> (usize, usize) {
+        let up = self.votes.values().filter(|up| **up).count();
+        (up, self.votes.len() - up)
+    }
+
+    fn embed(&self) -> CreateEmbed {
+        let (up, down) = self.tally();
+        let mut embed = CreateEmbed::new()
+            .title(format!("Suggestion #{}", self.id))
+            .description(&self.content)
+            .colour(self.status.colour())
+            .field("Author", format!("<@{}>", self.author), true)
+            .field("Votes", format!("👍 {} · 👎 {}", up, down), true)
+            .footer(CreateEmbedFooter::new(self.status.name()))
+            .timestamp(
+                Timestamp::from_unix_timestamp(self.created).unwrap_or_else(|_| Timestamp::now()),
+            );
+        if let Some(decision) = &self.decision {
+            let mut text = format!("by <@{}> <t:{}:R>", decision.by, decision.at);
+            if let Some(reason) = &decision.reason {
+                text.push_str(&format!("\n{}", reason));
+            }
+            embed = embed.field(
+                format!("Decision: {}", self.status.name()),
+                truncate(&text, 1024),
+                false,
+            );
+        }
+        embed
+    }
+
+    fn components(&self) -> Vec<CreateActionRow> {
+        if self.status.is_final() {
+            return vec![];
+        }
+        vec![CreateActionRow::Buttons(vec![
+            CreateButton::new(format!("{}:up", SUGGESTION_PREFIX))
+                .emoji('👍')
+                .style(ButtonStyle::Success),
+            CreateButton::new(format!("{}:down", SUGGESTION_PREFIX))
+                .emoji('👎')
+                .style(ButtonStyle::Danger),
+        ])]
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct GuildSuggestions {
+    /// Where suggestions are posted, suggestions are off if `None`.
+    pub channel: Option<ChannelId>,
+    pub next_id: u64,
+    /// Suggestions keyed by the message they are posted in.
+    pub suggestions: BTreeMap<MessageId, StoredSuggestion>,
+}
+
+pub struct Suggestions;
+
+impl TypeMapKey for Suggestions {
+    type Value = HashMap<GuildId, GuildSuggestions>;
+}
+
+async fn save_suggestions(ctx: &Context) -> CommandResult {
+    let suggestions = {
+        let data = ctx.data.read().await;
+        data.get::<Suggestions>()
+            .expect("Expected Suggestions in TypeMap.")
+            .clone()
+    };
+    save_json(SUGGESTIONS_PATH, &suggestions).await?;
+    Ok(())
+}
+
+async fn respond(
+    ctx: &Context,
+    component: &ComponentInteraction,
+    content: String,
+) -> CommandResult {
+    component
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Handles a click on a vote button.
+pub async fn handle_interaction(ctx: &Context, component: &ComponentInteraction) -> CommandResult {
+    let Some(guild_id) = component.guild_id else {
+        return Ok(());
+    };
+    let up = match component.data.custom_id.split(':').nth(1) {
+        Some("up") => true,
+        Some("down") => false,
+        _ => return Ok(()),
+    };
+    let vote = {
+        let mut data = ctx.data.write().await;
+        data.get_mut::<Suggestions>()
+            .expect("Expected Suggestions in TypeMap.")
+            .get_mut(&guild_id)
+            .and_then(|guild| guild.suggestions.get_mut(&component.message.id))
+            .filter(|suggestion| !suggestion.status.is_final())
+            .map(|suggestion| suggestion.vote(component.user.id, up))
+    };
+    let Some(vote) = vote else {
+        return respond(
+            ctx,
+            component,
+            String::from("Voting on this suggestion is over."),
+        )
+        .await;
+    };
+    save_suggestions(ctx).await?;
+
+    let content = match vote {
+        Some(true) => "You voted for this suggestion.",
+        Some(false) => "You voted against this suggestion.",
+        None => "You removed your vote.",
+    };
+    respond(ctx, component, String::from(content)).await?;
+
+    // Other votes may have come in meanwhile, the embed shows the latest tally.
+    let embed = {
+        let data = ctx.data.read().await;
+        data.get::<Suggestions>()
+            .expect("Expected Suggestions in TypeMap.")
+            .get(&guild_id)
+            .and_then(|guild| guild.suggestions.get(&component.message.id))
+            .map(StoredSuggestion::embed)
+    };
+    if let Some(embed) = embed {
+        component
+            .channel_id
+            .edit_message(
+                &ctx.http,
+                component.message.id,
+                EditMessage::new().embed(embed),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+#[command]
+#[description = "Posts a suggestion in the suggestions channel, where members vote on it."]
+#[usage = "<suggestion>"]
+#[example = "A channel for sharing music"]
+async fn suggest(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let content = args.rest().trim();
+    if content.is_empty() {
+        msg.reply(ctx, "Usage: `suggest <suggestion>`").await?;
+        return Ok(());
+    }
+    if content.chars().count() > MAX_SUGGESTION_CHARS {
+        msg.reply(
+            ctx,
+            format!(
+                "Suggestions can be at most {} characters long.",
+                MAX_SUGGESTION_CHARS
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let reserved = {
+        let mut data = ctx.data.write().await;
+        let guild = data
+            .get_mut::<Suggestions>()
+            .expect("Expected Suggestions in TypeMap.")
+            .entry(guild_id)
+            .or_default();
+        guild.channel.map(|channel| {
+            guild.next_id += 1;
+            (guild.next_id, channel)
+        })
+    };
+    let Some((id, channel)) = reserved else {
+        msg.reply(
+            ctx,
+            "This server takes no suggestions yet. Staff can pick a channel for them with \
+            `suggestion channel <#channel>`.",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    let suggestion = StoredSuggestion {
+        id,
+        author: msg.author.id,
+        channel,
+        content: content.to_string(),
+        created: Timestamp::now().unix_timestamp(),
+        status: SuggestionStatus::Open,
+        decision: None,
+        votes: BTreeMap::new(),
+    };
+    let posted = channel
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .embed(suggestion.embed())
+                .components(suggestion.components()),
+        )
+        .await?;
+    {
+        let mut data = ctx.data.write().await;
+        data.get_mut::<Suggestions>()
+            .expect("Expected Suggestions in TypeMap.")
+            .entry(guild_id)
+            .or_default()
+            .suggestions
+            .insert(posted.id, suggestion);
+    }
+    save_suggestions(ctx).await?;
+
+    msg.reply(ctx, format!("Suggestion #{} posted in <#{}>!", id, channel))
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Searches the suggestions. Give a status to only see suggestions with it, and \
+words the suggestions have to contain."]
+#[usage = "[open|considered|approved|denied] [words]"]
+#[example = "approved music"]
+async fn suggestions(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let status = args
+        .current()
+        .and_then(|arg| SuggestionStatus::from_name(&arg.to_lowercase()));
+    if status.is_some() {
+        args.advance();
+    }
+    let words = args
+        .rest()
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+
+    let mut found = {
+        let data = ctx.data.read().await;
+        let mut found = Vec::new();
+        if let Some(guild) = data
+            .get::<Suggestions>()
+            .expect("Expected Suggestions in TypeMap.")
+            .get(&guild_id)
+        {
+            for (message, suggestion) in &guild.suggestions {
+                let content = suggestion.content.to_lowercase();
+                if status.is_none_or(|status| suggestion.status == status)
+                    && words.iter().all(|word| content.contains(word))
+                {
+                    found.push((*message, suggestion.clone()));
+                }
+            }
+        }
+        found
+    };
+    if found.is_empty() {
+        msg.reply(ctx, "No suggestions found.").await?;
+        return Ok(());
+    }
+    found.sort_by_key(|(_, suggestion)| std::cmp::Reverse(suggestion.id));
+
+    let entries = found
+        .iter()
+        .map(|(message, suggestion)| {
+            let (up, down) = suggestion.tally();
+            format!(
+                "[**#{}**](https://discord.com/channels/{}/{}/{}) {} · 👍 {} 👎 {} · <@{}>\n{}",
+                suggestion.id,
+                guild_id,
+                suggestion.channel,
+                message,
+                suggestion.status.name(),
+                up,
+                down,
+                suggestion.author,
+                truncate(&suggestion.content, 150)
+            )
+        })
+        .collect();
+    Paginator::new(format!("Suggestions ({})", found.len()), entries)
+        .send(ctx, msg)
+        .await
+}
+
+/// Gives suggestion `#id` the status `status`: updates its message and tells its author.
+async fn decide(
+    ctx: &Context,
+    msg: &Message,
+    mut args: Args,
+    status: SuggestionStatus,
+) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let Some(id) = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| arg.trim_start_matches('#').parse::<u64>().ok())
+    else {
+        msg.reply(
+            ctx,
+            format!(
+                "Usage: `suggestion {} <id> [reason]`",
+                status_command(status)
+            ),
+        )
+        .await?;
+        return Ok(());
+    };
+    let reason = Some(args.rest().trim())
+        .filter(|reason| !reason.is_empty())
+        .map(|reason| truncate(reason, MAX_REASON_CHARS));
+
+    let decided = {
+        let mut data = ctx.data.write().await;
+        data.get_mut::<Suggestions>()
+            .expect("Expected Suggestions in TypeMap.")
+            .get_mut(&guild_id)
+            .and_then(|guild| {
+                guild
+                    .suggestions
+                    .iter_mut()
+                    .find(|(_, suggestion)| suggestion.id == id)
+            })
+            .map(|(message, suggestion)| {
+                suggestion.status = status;
+                suggestion.decision = Some(Decision {
+                    by: msg.author.id,
+                    reason: reason.clone(),
+                    at: Timestamp::now().unix_timestamp(),
+                });
+                (*message, suggestion.clone())
+            })
+    };
+    let Some((message, suggestion)) = decided else {
+        msg.reply(ctx, format!("There is no suggestion #{}.", id))
+            .await?;
+        return Ok(());
+    };
+    save_suggestions(ctx).await?;
+
+    let edited = suggestion
+        .channel
+        .edit_message(
+            &ctx.http,
+            message,
+            EditMessage::new()
+                .embed(suggestion.embed())
+                .components(suggestion.components()),
+        )
+        .await;
+
+    let guild_name = guild_id
+        .name(&ctx.cache)
+        .unwrap_or_else(|| String::from("the server"));
+    let mut notice = format!(
+        "Your suggestion #{} in **{}** was {} by {}.",
+        suggestion.id,
+        guild_name,
+        status.name(),
+        msg.author.name
+    );
+    if let Some(reason) = &reason {
+        notice.push_str(&format!(" Reason: {}", reason));
+    }
+    notice.push_str(&format!("\n> {}", truncate(&suggestion.content, 500)));
+    // The author may have DMs disabled, in which case they just don't get notified.
+    let _ = suggestion
+        .author
+        .direct_message(&ctx, CreateMessage::new().content(notice))
+        .await;
+
+    let content = match edited {
+        Ok(_) => format!("Suggestion #{} is now {}.", suggestion.id, status.name()),
+        Err(_) => format!(
+            "Suggestion #{} is now {}, but its message could not be updated.",
+            suggestion.id,
+            status.name()
+        ),
+    };
+    msg.reply(ctx, content).await?;
+    Ok(())
+}
+
+fn status_command(status: SuggestionStatus) -> &'static str {
+    match status {
+        SuggestionStatus::Approved => "approve",
+        SuggestionStatus::Denied => "deny",
+        _ => "consider",
+    }
+}
+
+#[command]
+#[description = "Approves a suggestion. Voting on it ends and its author is told."]
+#[usage = "<id> [reason]"]
+#[example = "12 Coming next week!"]
+async fn approve(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    decide(ctx, msg, args, SuggestionStatus::Approved).await
+}
+
+#[command]
+#[description = "Denies a suggestion. Voting on it ends and its author is told."]
+#[usage = "<id> [reason]"]
+#[example = "12 We already have a channel for that"]
+async fn deny(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    decide(ctx, msg, args, SuggestionStatus::Denied).await
+}
+
+#[command]
+#[description = "Marks a suggestion as being considered. Voting on it goes on and its author is \
+told."]
+#[usage = "<id> [reason]"]
+async fn consider(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    decide(ctx, msg, args, SuggestionStatus::Considered).await
+}
+
+#[command]
+#[description = "Sets the channel suggestions are posted in, or turns suggestions off with `off`."]
+#[usage = "<#channel|off>"]
+async fn channel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let arg = args.single::<String>().unwrap_or_default();
+    let channel = if arg == "off" {
+        None
+    } else {
+        match parse_channel(&arg) {
+            Some(channel) if is_text_channel(ctx, guild_id, channel) => Some(channel),
+            _ => {
+                msg.reply(ctx, "Usage: `suggestion channel <#channel|off>`")
+                    .await?;
+                return Ok(());
+            }
+        }
+    };
+    {
+        let mut data = ctx.data.write().await;
+        data.get_mut::<Suggestions>()
+            .expect("Expected Suggestions in TypeMap.")
+            .entry(guild_id)
+            .or_default()
+            .channel = channel;
+    }
+    save_suggestions(ctx).await?;
+
+    let content = match channel {
+        Some(channel) => format!("Suggestions are now posted in <#{}>.", channel),
+        None => String::from("Suggestions are off, the old ones can still be decided on."),
+    };
+    msg.reply(ctx, content).await?;
+    Ok(())
+}
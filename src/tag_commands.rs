@@ -0,0 +1,518 @@
+use crate::command_base::*;
+use crate::paginator::Paginator;
+use crate::scheduler::SchedulerContext;
+use crate::storage::save_json;
+
+use serde::{Deserialize, Serialize};
+use serenity::all::{CreateAllowedMentions, CreateAttachment, CreateMessage};
+use serenity::model::id::{GuildId, UserId};
+use std::collections::BTreeMap;
+
+pub const TAGS_PATH: &str = "data/tags.json";
+const TAG_FILES_DIR: &str = "data/tags";
+const MAX_TAG_ATTACHMENTS: usize = 3;
+const MAX_TAG_ATTACHMENT_BYTES: u32 = 8 * 1024 * 1024;
+const MAX_TAG_NAME: usize = 32;
+
+#[group]
+#[prefixes("tag")]
+#[commands(add, edit, delete, list, info)]
+#[default_command(show)]
+pub struct Tag;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StoredTag {
+    pub content: String,
+    /// File names relative to the guild's directory in `TAG_FILES_DIR`.
+    #[serde(default)]
+    pub attachments: Vec<String>,
+    pub author: UserId,
+    #[serde(default)]
+    pub uses: u64,
+}
+
+/// Text commands admins created with `tag add`, per guild and keyed by name.
+pub struct Tags;
+
+impl TypeMapKey for Tags {
+    type Value = HashMap<GuildId, BTreeMap<String, StoredTag>>;
+}
+
+/// Whether use counts changed since the tags were last saved.
+pub struct TagUsesChanged;
+
+impl TypeMapKey for TagUsesChanged {
+    type Value = bool;
+}
+
+/// Values that can be used in a tag's content.
+pub struct TemplateContext<'a> {
+    pub user: UserId,
+    pub user_name: &'a str,
+    pub channel: &'a str,
+    pub server: &'a str,
+    pub args: &'a str,
+}
+
+/// Fills in the `{placeholders}` of a tag. Supported are `{user}`, `{user.name}`, `{channel}`,
+/// `{server}`, `{args}` and `{random:a|b|c}`. Anything else is left as it is.
+pub fn render_template(template: &str, context: &TemplateContext, rng: &mut impl Rng) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('}') else {
+            out.push_str(&rest[start..]);
+            return out;
+        };
+        let key = &after[..end];
+        match key {
+            "user" => out.push_str(&format!("<@{}>", context.user)),
+            "user.name" => out.push_str(context.user_name),
+            "channel" => out.push_str(context.channel),
+            "server" => out.push_str(context.server),
+            "args" => out.push_str(context.args),
+            _ => match key.strip_prefix("random:") {
+                Some(choices) => {
+                    let choices = choices.split('|').collect::<Vec<_>>();
+                    out.push_str(choices[rng.gen_range(0..choices.len())]);
+                }
+                None => {
+                    out.push('{');
+                    out.push_str(key);
+                    out.push('}');
+                }
+            },
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Whether `name` would be shadowed by a built-in command, so the tag could never be run.
+fn is_command_name(name: &str) -> bool {
+    name == "help"
+        || crate::GROUPS.iter().any(|group| {
+            if group.options.prefixes.is_empty() {
+                group
+                    .options
+                    .commands
+                    .iter()
+                    .any(|command| command.options.names.contains(&name))
+            } else {
+                group.options.prefixes.contains(&name)
+            }
+        })
+}
+
+fn tag_dir(guild_id: GuildId) -> String {
+    format!("{}/{}", TAG_FILES_DIR, guild_id)
+}
+
+/// Tag names end up in messages and commands, so they are kept to lowercase letters, digits, `-`
+/// and `_`.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_TAG_NAME
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// Downloads the attachments of `msg` into the tag directory of the guild. The files are named
+/// after the message, so they never clash with the files of another tag or an earlier edit.
+async fn store_attachments(msg: &Message, guild_id: GuildId) -> Result<Vec<String>, String> {
+    if msg.attachments.len() > MAX_TAG_ATTACHMENTS {
+        return Err(format!(
+            "A tag can have at most {} attachments.",
+            MAX_TAG_ATTACHMENTS
+        ));
+    }
+    if msg
+        .attachments
+        .iter()
+        .any(|attachment| attachment.size > MAX_TAG_ATTACHMENT_BYTES)
+    {
+        return Err(format!(
+            "Attachments can be at most {} MB.",
+            MAX_TAG_ATTACHMENT_BYTES / 1024 / 1024
+        ));
+    }
+
+    let dir = tag_dir(guild_id);
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|why| why.to_string())?;
+    let mut files = Vec::new();
+    for (i, attachment) in msg.attachments.iter().enumerate() {
+        let bytes = attachment.download().await.map_err(|why| why.to_string())?;
+        // Only keep safe characters, the file name comes from the user.
+        let filename = attachment
+            .filename
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '.' || *c == '-' || *c == '_')
+            .collect::<String>();
+        let file = format!("{}-{}-{}", msg.id, i, filename);
+        tokio::fs::write(format!("{}/{}", dir, file), bytes)
+            .await
+            .map_err(|why| why.to_string())?;
+        files.push(file);
+    }
+    Ok(files)
+}
+
+async fn remove_attachments(guild_id: GuildId, files: &[String]) {
+    for file in files {
+        let _ = tokio::fs::remove_file(format!("{}/{}", tag_dir(guild_id), file)).await;
+    }
+}
+
+/// Runs the tag `name` in reply to `msg`. Returns `false` if the guild has no such tag.
+pub async fn run_tag(ctx: &Context, msg: &Message, name: &str, args: &str) -> CommandResult<bool> {
+    let Some(guild_id) = msg.guild_id else {
+        return Ok(false);
+    };
+    let name = name.to_lowercase();
+    let tag = {
+        let mut data = ctx.data.write().await;
+        let tags = data.get_mut::<Tags>().expect("Expected Tags in TypeMap.");
+        let tag = tags
+            .get_mut(&guild_id)
+            .and_then(|guild| guild.get_mut(&name))
+            .map(|tag| {
+                tag.uses += 1;
+                tag.clone()
+            });
+        if tag.is_some() {
+            data.insert::<TagUsesChanged>(true);
+        }
+        tag
+    };
+    let Some(tag) = tag else {
+        return Ok(false);
+    };
+
+    let server = guild_id
+        .name(&ctx.cache)
+        .unwrap_or_else(|| String::from("this server"));
+    let user_name = msg
+        .author
+        .global_name
+        .clone()
+        .unwrap_or_else(|| msg.author.name.clone());
+    let context = TemplateContext {
+        user: msg.author.id,
+        user_name: &user_name,
+        channel: &format!("<#{}>", msg.channel_id),
+        server: &server,
+        args,
+    };
+    let content = render_template(&tag.content, &context, &mut rand::thread_rng());
+
+    // Tags may contain user input through `{args}`, so only the invoker can be pinged.
+    let mut message = CreateMessage::new()
+        .content(content)
+        .allowed_mentions(CreateAllowedMentions::new().users([msg.author.id]));
+    for file in &tag.attachments {
+        let path = format!("{}/{}", tag_dir(guild_id), file);
+        match CreateAttachment::path(&path).await {
+            Ok(attachment) => message = message.add_file(attachment),
+            Err(why) => println!("Could not attach '{}' to tag '{}': {:?}", path, name, why),
+        }
+    }
+    msg.channel_id.send_message(&ctx.http, message).await?;
+
+    Ok(true)
+}
+
+/// Saves the tags if their use counts changed since the last tick. Counting a use only updates
+/// memory, so running a tag never waits for or fails because of a write.
+pub async fn run_due(scheduler: &SchedulerContext) -> CommandResult {
+    let tags = {
+        let mut data = scheduler.data.write().await;
+        let changed = data
+            .get_mut::<TagUsesChanged>()
+            .expect("Expected TagUsesChanged in TypeMap.");
+        if !std::mem::take(changed) {
+            return Ok(());
+        }
+        data.get::<Tags>()
+            .expect("Expected Tags in TypeMap.")
+            .clone()
+    };
+    if let Err(why) = save_json(TAGS_PATH, &tags).await {
+        scheduler.data.write().await.insert::<TagUsesChanged>(true);
+        return Err(why.into());
+    }
+    Ok(())
+}
+
+async fn save_tags(ctx: &Context) -> CommandResult {
+    let tags = {
+        let data = ctx.data.read().await;
+        data.get::<Tags>()
+            .expect("Expected Tags in TypeMap.")
+            .clone()
+    };
+    save_json(TAGS_PATH, &tags).await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Creates a tag that can be used as `?<name>`. The content can use `{user}`, \
+`{user.name}`, `{channel}`, `{server}`, `{args}` and `{random:a|b|c}`. Attached files are sent \
+with the tag."]
+#[usage = "<name> <content>"]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Ok(name) = args.single::<String>().map(|name| name.to_lowercase()) else {
+        msg.reply(ctx, "You need to provide a name!").await?;
+        return Ok(());
+    };
+    let content = args.rest().trim().to_string();
+    if content.is_empty() && msg.attachments.is_empty() {
+        msg.reply(ctx, "You need to provide some content!").await?;
+        return Ok(());
+    }
+    if !is_valid_name(&name) {
+        msg.reply(
+            ctx,
+            format!(
+                "Tag names can be up to {} lowercase letters, digits, `-` and `_`.",
+                MAX_TAG_NAME
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+    if is_command_name(&name) {
+        msg.reply(ctx, format!("`{}` is already a command.", name))
+            .await?;
+        return Ok(());
+    }
+
+    let guild_id = msg.guild_id.unwrap();
+    let exists = {
+        let data = ctx.data.read().await;
+        let tags = data.get::<Tags>().expect("Expected Tags in TypeMap.");
+        tags.get(&guild_id)
+            .is_some_and(|guild| guild.contains_key(&name))
+    };
+    if exists {
+        msg.reply(
+            ctx,
+            format!("The tag `{}` already exists, use `tag edit` instead.", name),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let attachments = match store_attachments(msg, guild_id).await {
+        Ok(attachments) => attachments,
+        Err(why) => {
+            msg.reply(ctx, why).await?;
+            return Ok(());
+        }
+    };
+    {
+        let mut data = ctx.data.write().await;
+        let tags = data.get_mut::<Tags>().expect("Expected Tags in TypeMap.");
+        tags.entry(guild_id).or_default().insert(
+            name.clone(),
+            StoredTag {
+                content,
+                attachments,
+                author: msg.author.id,
+                uses: 0,
+            },
+        );
+    }
+    save_tags(ctx).await?;
+
+    msg.channel_id
+        .say(&ctx.http, format!("Added the tag `{}`.", name))
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Changes the content of a tag. New attachments replace the old ones."]
+#[usage = "<name> <content>"]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn edit(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Ok(name) = args.single::<String>().map(|name| name.to_lowercase()) else {
+        msg.reply(ctx, "You need to provide a name!").await?;
+        return Ok(());
+    };
+    let content = args.rest().trim().to_string();
+    let guild_id = msg.guild_id.unwrap();
+
+    let new_attachments = if msg.attachments.is_empty() {
+        None
+    } else {
+        match store_attachments(msg, guild_id).await {
+            Ok(attachments) => Some(attachments),
+            Err(why) => {
+                msg.reply(ctx, why).await?;
+                return Ok(());
+            }
+        }
+    };
+
+    let replaced = {
+        let mut data = ctx.data.write().await;
+        let tags = data.get_mut::<Tags>().expect("Expected Tags in TypeMap.");
+        tags.get_mut(&guild_id)
+            .and_then(|guild| guild.get_mut(&name))
+            .map(|tag| {
+                if !content.is_empty() {
+                    tag.content = content;
+                }
+                match &new_attachments {
+                    Some(attachments) => {
+                        std::mem::replace(&mut tag.attachments, attachments.clone())
+                    }
+                    None => Vec::new(),
+                }
+            })
+    };
+    let Some(old_attachments) = replaced else {
+        if let Some(attachments) = &new_attachments {
+            remove_attachments(guild_id, attachments).await;
+        }
+        msg.reply(ctx, format!("There is no tag `{}`.", name))
+            .await?;
+        return Ok(());
+    };
+    let unused = old_attachments
+        .into_iter()
+        .filter(|file| !new_attachments.iter().flatten().any(|new| new == file))
+        .collect::<Vec<_>>();
+    save_tags(ctx).await?;
+    remove_attachments(guild_id, &unused).await;
+
+    msg.channel_id
+        .say(&ctx.http, format!("Updated the tag `{}`.", name))
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[aliases("remove")]
+#[description = "Deletes a tag."]
+#[usage = "<name>"]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+async fn delete(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Ok(name) = args.single::<String>().map(|name| name.to_lowercase()) else {
+        msg.reply(ctx, "You need to provide a name!").await?;
+        return Ok(());
+    };
+    let guild_id = msg.guild_id.unwrap();
+
+    let removed = {
+        let mut data = ctx.data.write().await;
+        let tags = data.get_mut::<Tags>().expect("Expected Tags in TypeMap.");
+        tags.get_mut(&guild_id)
+            .and_then(|guild| guild.remove(&name))
+    };
+    let Some(removed) = removed else {
+        msg.reply(ctx, format!("There is no tag `{}`.", name))
+            .await?;
+        return Ok(());
+    };
+    remove_attachments(guild_id, &removed.attachments).await;
+    save_tags(ctx).await?;
+
+    msg.channel_id
+        .say(&ctx.http, format!("Deleted the tag `{}`.", name))
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Lists the tags of this server."]
+#[only_in(guilds)]
+async fn list(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap();
+    let names = {
+        let data = ctx.data.read().await;
+        let tags = data.get::<Tags>().expect("Expected Tags in TypeMap.");
+        tags.get(&guild_id)
+            .into_iter()
+            .flat_map(|guild| guild.keys())
+            .map(|name| format!("- {name}"))
+            .collect::<Vec<_>>()
+    };
+
+    if names.is_empty() {
+        msg.channel_id
+            .say(&ctx.http, "This server has no tags.")
+            .await?;
+    } else {
+        Paginator::new("Tags", names).send(ctx, msg).await?;
+    }
+
+    Ok(())
+}
+
+#[command]
+#[description = "Shows who created a tag, how often it was used and its raw content."]
+#[usage = "<name>"]
+#[only_in(guilds)]
+async fn info(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let name = args
+        .single::<String>()
+        .map(|name| name.to_lowercase())
+        .unwrap_or_default();
+    let guild_id = msg.guild_id.unwrap();
+    let tag = {
+        let data = ctx.data.read().await;
+        let tags = data.get::<Tags>().expect("Expected Tags in TypeMap.");
+        tags.get(&guild_id)
+            .and_then(|guild| guild.get(&name))
+            .cloned()
+    };
+    let Some(tag) = tag else {
+        msg.reply(ctx, format!("There is no tag `{}`.", name))
+            .await?;
+        return Ok(());
+    };
+
+    msg.channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(format!(
+                    "Tag `{}` by <@{}>, used {} times, {} attachments:\n```\n{}\n```",
+                    name,
+                    tag.author,
+                    tag.uses,
+                    tag.attachments.len(),
+                    tag.content.replace("```", "`\u{200b}``")
+                ))
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+
+    Ok(())
+}
+
+// Runs for `tag <name>`.
+#[command]
+#[only_in(guilds)]
+async fn show(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let name = args.single::<String>().unwrap_or_default();
+    if !run_tag(ctx, msg, &name, args.rest()).await? {
+        msg.reply(ctx, format!("There is no tag `{}`.", name))
+            .await?;
+    }
+    Ok(())
+}
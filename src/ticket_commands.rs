@@ -0,0 +1,1191 @@
+use crate::command_base::*;
+use crate::log_commands::truncate;
+use crate::storage::save_json;
+use crate::time_parse::format_duration;
+
+use serde::{Deserialize, Serialize};
+use serenity::all::{
+    Attachment, ButtonStyle, ChannelType, ComponentInteraction, CreateActionRow,
+    CreateAllowedMentions, CreateAttachment, CreateButton, CreateChannel, CreateEmbed,
+    CreateEmbedAuthor, CreateEmbedFooter, CreateInteractionResponse,
+    CreateInteractionResponseMessage, CreateMessage, EditInteractionResponse, Embed, GuildChannel,
+    PermissionOverwrite, PermissionOverwriteType, Permissions, Timestamp, User,
+};
+use serenity::futures::StreamExt;
+use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+use serenity::utils::parse_role_mention;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+pub const TICKETS_PATH: &str = "data/tickets.json";
+/// Prefix of the custom ids of ticket buttons.
+pub const TICKET_PREFIX: &str = "ticket";
+/// A panel has one button per category, and a message holds at most 25 buttons.
+const MAX_CATEGORIES: usize = 25;
+const MAX_CATEGORY_NAME: usize = 32;
+/// Transcripts stop at this many messages, counted from the newest.
+const MAX_TRANSCRIPT_MESSAGES: usize = 5000;
+
+#[group]
+#[prefixes("tickets")]
+#[commands(panel, category, staff, parent, transcripts, modmail, settings)]
+#[required_permissions(MANAGE_GUILD)]
+#[only_in(guilds)]
+pub struct Tickets;
+
+#[group]
+#[prefixes("ticket")]
+#[commands(open, close, reply)]
+#[only_in(guilds)]
+pub struct Ticket;
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct TicketCategory {
+    pub description: String,
+    /// Discord category the tickets are created in, the default one if `None`.
+    pub parent: Option<ChannelId>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct TicketConfig {
+    /// Roles that can see and close every ticket.
+    pub staff_roles: Vec<RoleId>,
+    pub categories: BTreeMap<String, TicketCategory>,
+    /// Discord category tickets are created in when their category has none.
+    pub parent: Option<ChannelId>,
+    /// Where the transcripts of closed tickets are posted.
+    pub transcript_channel: Option<ChannelId>,
+    /// Members can open a ticket by sending the bot a direct message.
+    pub modmail: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OpenTicket {
+    pub id: u64,
+    pub user: UserId,
+    pub category: Option<String>,
+    /// The member can't see the channel, their direct messages are relayed instead.
+    pub modmail: bool,
+    /// Unix timestamp in seconds.
+    pub opened: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct GuildTickets {
+    pub config: TicketConfig,
+    pub next_id: u64,
+    /// Open tickets keyed by their channel.
+    pub open: BTreeMap<ChannelId, OpenTicket>,
+    /// Members whose ticket channel is being created, so a double click opens only one.
+    #[serde(skip)]
+    pub opening: HashSet<UserId>,
+}
+
+pub struct SupportTickets;
+
+impl TypeMapKey for SupportTickets {
+    type Value = HashMap<GuildId, GuildTickets>;
+}
+
+async fn guild_tickets(ctx: &Context, guild_id: GuildId) -> GuildTickets {
+    let data = ctx.data.read().await;
+    data.get::<SupportTickets>()
+        .expect("Expected SupportTickets in TypeMap.")
+        .get(&guild_id)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Applies `change` to the tickets of the guild and saves the tickets of all guilds.
+async fn update_tickets<T>(
+    ctx: &Context,
+    guild_id: GuildId,
+    change: impl FnOnce(&mut GuildTickets) -> T,
+) -> CommandResult<T> {
+    let (result, tickets) = {
+        let mut data = ctx.data.write().await;
+        let tickets = data
+            .get_mut::<SupportTickets>()
+            .expect("Expected SupportTickets in TypeMap.");
+        let result = change(tickets.entry(guild_id).or_default());
+        (result, tickets.clone())
+    };
+    save_json(TICKETS_PATH, &tickets).await?;
+    Ok(result)
+}
+
+fn parse_role(arg: &str) -> Option<RoleId> {
+    parse_role_mention(arg).or_else(|| arg.parse().ok())
+}
+
+fn is_category(ctx: &Context, guild_id: GuildId, channel_id: ChannelId) -> bool {
+    guild_id.to_guild_cached(&ctx.cache).is_some_and(|guild| {
+        guild
+            .channels
+            .get(&channel_id)
+            .is_some_and(|channel| channel.kind == ChannelType::Category)
+    })
+}
+
+/// Whether `user_id` has one of the staff roles, or may manage channels anyway.
+fn is_staff(ctx: &Context, guild_id: GuildId, user_id: UserId, config: &TicketConfig) -> bool {
+    guild_id.to_guild_cached(&ctx.cache).is_some_and(|guild| {
+        guild.members.get(&user_id).is_some_and(|member| {
+            member
+                .roles
+                .iter()
+                .any(|role| config.staff_roles.contains(role))
+                || guild.member_permissions(member).manage_channels()
+        })
+    })
+}
+
+/// The guilds that accept modmail from `user_id`.
+async fn modmail_guilds(ctx: &Context, user_id: UserId) -> Vec<GuildId> {
+    let data = ctx.data.read().await;
+    let mut guilds = Vec::new();
+    for (guild_id, tickets) in data
+        .get::<SupportTickets>()
+        .expect("Expected SupportTickets in TypeMap.")
+    {
+        let is_member = guild_id
+            .to_guild_cached(&ctx.cache)
+            .is_some_and(|guild| guild.members.contains_key(&user_id));
+        if tickets.config.modmail && is_member {
+            guilds.push(*guild_id);
+        }
+    }
+    guilds.sort();
+    guilds
+}
+
+fn guild_name(ctx: &Context, guild_id: GuildId) -> String {
+    guild_id
+        .name(&ctx.cache)
+        .unwrap_or_else(|| String::from("the server"))
+}
+
+fn category_names(config: &TicketConfig) -> String {
+    config
+        .categories
+        .keys()
+        .map(|name| format!("`{}`", name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Opens a ticket for `user` in a new private channel. Returns the channel, or why no ticket was
+/// opened.
+async fn open_ticket(
+    ctx: &Context,
+    guild_id: GuildId,
+    user: &User,
+    category: Option<String>,
+    modmail: bool,
+) -> CommandResult<Result<ChannelId, String>> {
+    let (id, config) = {
+        let mut data = ctx.data.write().await;
+        let tickets = data
+            .get_mut::<SupportTickets>()
+            .expect("Expected SupportTickets in TypeMap.")
+            .entry(guild_id)
+            .or_default();
+        if let Some(channel) = tickets
+            .open
+            .iter()
+            .find(|(_, ticket)| ticket.user == user.id)
+            .map(|(channel, _)| *channel)
+        {
+            return Ok(Err(format!(
+                "You already have an open ticket: <#{}>",
+                channel
+            )));
+        }
+        if tickets.opening.contains(&user.id) {
+            return Ok(Err(String::from("Your ticket is being opened already.")));
+        }
+        match &category {
+            Some(name) if !tickets.config.categories.contains_key(name) => {
+                return Ok(Err(format!("There is no ticket category `{}`.", name)));
+            }
+            None if !modmail && !tickets.config.categories.is_empty() => {
+                return Ok(Err(format!(
+                    "Pick a category: {}",
+                    category_names(&tickets.config)
+                )));
+            }
+            _ => {}
+        }
+        tickets.next_id += 1;
+        tickets.opening.insert(user.id);
+        (tickets.next_id, tickets.config.clone())
+    };
+
+    let access = Permissions::VIEW_CHANNEL
+        | Permissions::SEND_MESSAGES
+        | Permissions::READ_MESSAGE_HISTORY
+        | Permissions::ATTACH_FILES
+        | Permissions::EMBED_LINKS;
+    let overwrite = |allow: Permissions, kind: PermissionOverwriteType| PermissionOverwrite {
+        allow,
+        deny: Permissions::empty(),
+        kind,
+    };
+    let mut overwrites = vec![
+        PermissionOverwrite {
+            allow: Permissions::empty(),
+            deny: Permissions::VIEW_CHANNEL,
+            kind: PermissionOverwriteType::Role(guild_id.everyone_role()),
+        },
+        overwrite(
+            access | Permissions::MANAGE_CHANNELS,
+            PermissionOverwriteType::Member(ctx.cache.current_user().id),
+        ),
+    ];
+    for role in &config.staff_roles {
+        overwrites.push(overwrite(access, PermissionOverwriteType::Role(*role)));
+    }
+    if !modmail {
+        overwrites.push(overwrite(access, PermissionOverwriteType::Member(user.id)));
+    }
+
+    let parent = category
+        .as_ref()
+        .and_then(|name| config.categories.get(name))
+        .and_then(|category| category.parent)
+        .or(config.parent);
+    let mut builder = CreateChannel::new(format!(
+        "{}-{:04}",
+        category.as_deref().unwrap_or("ticket"),
+        id
+    ))
+    .kind(ChannelType::Text)
+    .topic(format!("Ticket #{} of {} ({})", id, user.name, user.id))
+    .permissions(overwrites);
+    if let Some(parent) = parent {
+        builder = builder.category(parent);
+    }
+    let channel = match guild_id.create_channel(&ctx.http, builder).await {
+        Ok(channel) => channel,
+        Err(why) => {
+            let mut data = ctx.data.write().await;
+            if let Some(tickets) = data
+                .get_mut::<SupportTickets>()
+                .expect("Expected SupportTickets in TypeMap.")
+                .get_mut(&guild_id)
+            {
+                tickets.opening.remove(&user.id);
+            }
+            return Err(why.into());
+        }
+    };
+
+    let ticket = OpenTicket {
+        id,
+        user: user.id,
+        category: category.clone(),
+        modmail,
+        opened: Timestamp::now().unix_timestamp(),
+    };
+    update_tickets(ctx, guild_id, |tickets| {
+        tickets.opening.remove(&ticket.user);
+        tickets.open.insert(channel.id, ticket);
+    })
+    .await?;
+
+    let description = if modmail {
+        format!(
+            "<@{}> opened this ticket by messaging me. Their messages show up here, answer them \
+            with `ticket reply <message>`. Everything else stays between staff.",
+            user.id
+        )
+    } else {
+        format!(
+            "<@{}> opened this ticket. Describe what you need, the staff will be with you shortly.",
+            user.id
+        )
+    };
+    let mut embed = CreateEmbed::new()
+        .title(format!("Ticket #{}", id))
+        .description(description)
+        .timestamp(Timestamp::now());
+    if let Some(category) = &category {
+        embed = embed.field("Category", category, true);
+    }
+    let mut pings = config
+        .staff_roles
+        .iter()
+        .map(|role| format!("<@&{}>", role))
+        .collect::<Vec<_>>();
+    if !modmail {
+        pings.push(format!("<@{}>", user.id));
+    }
+    channel
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(pings.join(" "))
+                .embed(embed)
+                .components(vec![CreateActionRow::Buttons(vec![CreateButton::new(
+                    format!("{}:close", TICKET_PREFIX),
+                )
+                .label("Close ticket")
+                .style(ButtonStyle::Danger)])])
+                .allowed_mentions(
+                    CreateAllowedMentions::new()
+                        .users(vec![user.id])
+                        .roles(config.staff_roles.clone()),
+                ),
+        )
+        .await?;
+
+    Ok(Ok(channel.id))
+}
+
+fn embed_text(embed: &Embed) -> String {
+    let mut parts = Vec::new();
+    if let Some(author) = &embed.author {
+        parts.push(format!("{}:", author.name));
+    }
+    parts.extend(embed.title.clone());
+    parts.extend(embed.description.clone());
+    parts.join(" ")
+}
+
+/// Writes down every message of the ticket channel, oldest first.
+async fn transcript(ctx: &Context, channel_id: ChannelId, header: String) -> CommandResult<String> {
+    let mut messages = Vec::new();
+    let mut stream = Box::pin(channel_id.messages_iter(&ctx.http));
+    while let Some(message) = stream.next().await {
+        messages.push(message?);
+        if messages.len() >= MAX_TRANSCRIPT_MESSAGES {
+            break;
+        }
+    }
+    messages.reverse();
+
+    let mut transcript = header;
+    for message in &messages {
+        writeln!(
+            transcript,
+            "[{}] {} ({}): {}",
+            message.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            message.author.name,
+            message.author.id,
+            message.content
+        )?;
+        // Relayed modmail messages are embeds.
+        for embed in &message.embeds {
+            writeln!(transcript, "    embed: {}", embed_text(embed))?;
+        }
+        for attachment in &message.attachments {
+            writeln!(transcript, "    attachment: {}", attachment.url)?;
+        }
+    }
+    Ok(transcript)
+}
+
+async fn find_ticket(
+    ctx: &Context,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+) -> Option<(OpenTicket, TicketConfig)> {
+    let data = ctx.data.read().await;
+    let tickets = data
+        .get::<SupportTickets>()
+        .expect("Expected SupportTickets in TypeMap.")
+        .get(&guild_id)?;
+    let ticket = tickets.open.get(&channel_id)?;
+    Some((ticket.clone(), tickets.config.clone()))
+}
+
+/// Closes the ticket in `channel_id`: posts its transcript, tells the member and deletes the
+/// channel. If the transcript cannot be posted, the channel is kept so nothing is lost.
+async fn close_ticket(
+    ctx: &Context,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    closed_by: &User,
+    reason: Option<&str>,
+) -> CommandResult {
+    let Some((ticket, _)) = find_ticket(ctx, guild_id, channel_id).await else {
+        return Ok(());
+    };
+    let opener = ticket.user.to_user(ctx).await?;
+    let now = Timestamp::now();
+    let guild_name = guild_name(ctx, guild_id);
+    let mut header = format!(
+        "Ticket #{} of {}\nOpened by {} ({}) on {}\nClosed by {} ({}) on {}\n",
+        ticket.id,
+        guild_name,
+        opener.name,
+        opener.id,
+        Timestamp::from_unix_timestamp(ticket.opened)
+            .unwrap_or(now)
+            .format("%Y-%m-%d %H:%M:%S"),
+        closed_by.name,
+        closed_by.id,
+        now.format("%Y-%m-%d %H:%M:%S")
+    );
+    if let Some(reason) = reason {
+        writeln!(header, "Reason: {}", reason)?;
+    }
+    header.push('\n');
+    let transcript = transcript(ctx, channel_id, header).await?;
+
+    // Someone else may have closed the ticket in the meantime.
+    let Some(config) = update_tickets(ctx, guild_id, |tickets| {
+        tickets
+            .open
+            .remove(&channel_id)
+            .map(|_| tickets.config.clone())
+    })
+    .await?
+    else {
+        return Ok(());
+    };
+
+    let mut keep_channel = false;
+    if let Some(log_channel) = config.transcript_channel {
+        let mut embed = CreateEmbed::new()
+            .title(format!("Ticket #{} closed", ticket.id))
+            .field("Opened by", format!("<@{}>", ticket.user), true)
+            .field("Closed by", format!("<@{}>", closed_by.id), true)
+            .field(
+                "Open for",
+                format_duration(now.unix_timestamp() - ticket.opened),
+                true,
+            )
+            .timestamp(now);
+        if let Some(category) = &ticket.category {
+            embed = embed.field("Category", category, true);
+        }
+        if ticket.modmail {
+            embed = embed.field("Modmail", "yes", true);
+        }
+        if let Some(reason) = reason {
+            embed = embed.field("Reason", truncate(reason, 1024), false);
+        }
+        let posted = log_channel
+            .send_message(
+                &ctx.http,
+                CreateMessage::new()
+                    .embed(embed)
+                    .add_file(CreateAttachment::bytes(
+                        transcript.into_bytes(),
+                        format!("ticket-{}.txt", ticket.id),
+                    )),
+            )
+            .await;
+        if let Err(why) = posted {
+            println!(
+                "Could not post the transcript of ticket #{}: {:?}",
+                ticket.id, why
+            );
+            keep_channel = true;
+        }
+    }
+
+    let mut notice = format!(
+        "Your ticket #{} in **{}** was closed by {}.",
+        ticket.id, guild_name, closed_by.name
+    );
+    if let Some(reason) = reason {
+        write!(notice, " Reason: {}", reason)?;
+    }
+    // The member may have DMs disabled, in which case they just don't get notified.
+    let _ = ticket
+        .user
+        .direct_message(&ctx, CreateMessage::new().content(notice))
+        .await;
+
+    if keep_channel {
+        channel_id
+            .say(
+                &ctx.http,
+                "The ticket is closed, but I could not archive the transcript, so this channel \
+                is kept. Delete it once it is no longer needed.",
+            )
+            .await?;
+    } else {
+        channel_id.delete(&ctx.http).await?;
+    }
+    Ok(())
+}
+
+fn relay_embed(
+    author: &User,
+    content: &str,
+    attachments: &[Attachment],
+    footer: String,
+) -> CreateEmbed {
+    let mut embed = CreateEmbed::new()
+        .author(CreateEmbedAuthor::new(&author.name).icon_url(author.face()))
+        .footer(CreateEmbedFooter::new(footer))
+        .timestamp(Timestamp::now());
+    if !content.is_empty() {
+        embed = embed.description(truncate(content, 4096));
+    }
+    if !attachments.is_empty() {
+        let urls = attachments
+            .iter()
+            .map(|attachment| attachment.url.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        embed = embed.field("Attachments", truncate(&urls, 1024), false);
+    }
+    embed
+}
+
+/// Passes a direct message on to a modmail ticket.
+async fn relay_to_ticket(ctx: &Context, channel_id: ChannelId, msg: &Message) -> CommandResult {
+    channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new().embed(relay_embed(
+                &msg.author,
+                &msg.content,
+                &msg.attachments,
+                String::from("Modmail"),
+            )),
+        )
+        .await?;
+    msg.react(ctx, '✅').await?;
+    Ok(())
+}
+
+/// Opens a modmail ticket in `guild_id` with the direct message `msg`. Returns what to tell the
+/// member.
+async fn send_modmail(ctx: &Context, guild_id: GuildId, msg: &Message) -> CommandResult<String> {
+    let channel = match open_ticket(ctx, guild_id, &msg.author, None, true).await? {
+        Ok(channel) => channel,
+        Err(problem) => return Ok(problem),
+    };
+    relay_to_ticket(ctx, channel, msg).await?;
+    Ok(format!(
+        "Your message was sent to the staff of **{}**. Their answers show up here, and everything \
+        else you send me is passed on until the ticket is closed.",
+        guild_name(ctx, guild_id)
+    ))
+}
+
+/// Passes direct messages on to the modmail ticket of their author. Without one, a ticket is
+/// opened in the server they share with the bot, after asking which one if there are several.
+pub async fn handle_message(ctx: &Context, msg: &Message) -> CommandResult {
+    if msg.guild_id.is_some() || msg.author.bot {
+        return Ok(());
+    }
+    let open = {
+        let data = ctx.data.read().await;
+        data.get::<SupportTickets>()
+            .expect("Expected SupportTickets in TypeMap.")
+            .values()
+            .flat_map(|tickets| &tickets.open)
+            .find(|(_, ticket)| ticket.modmail && ticket.user == msg.author.id)
+            .map(|(channel, _)| *channel)
+    };
+    if let Some(channel) = open {
+        return relay_to_ticket(ctx, channel, msg).await;
+    }
+
+    let guilds = modmail_guilds(ctx, msg.author.id).await;
+    match guilds.as_slice() {
+        [] => {
+            msg.reply(
+                ctx,
+                "None of the servers we share take messages through me.",
+            )
+            .await?;
+        }
+        [guild_id] => {
+            let content = send_modmail(ctx, *guild_id, msg).await?;
+            msg.reply(ctx, content).await?;
+        }
+        _ => {
+            let buttons = guilds
+                .iter()
+                .take(MAX_CATEGORIES)
+                .map(|guild_id| {
+                    CreateButton::new(format!("{}:modmail:{}", TICKET_PREFIX, guild_id))
+                        .label(truncate(&guild_name(ctx, *guild_id), 80))
+                        .style(ButtonStyle::Primary)
+                })
+                .collect::<Vec<_>>();
+            let rows = buttons
+                .chunks(5)
+                .map(|row| CreateActionRow::Buttons(row.to_vec()))
+                .collect();
+            msg.channel_id
+                .send_message(
+                    &ctx.http,
+                    CreateMessage::new()
+                        .content("Which server is your message for?")
+                        .components(rows)
+                        .reference_message(msg),
+                )
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Forgets tickets whose channel was deleted by hand.
+pub async fn handle_channel_delete(ctx: &Context, channel: &GuildChannel) -> CommandResult {
+    let is_ticket = {
+        let data = ctx.data.read().await;
+        data.get::<SupportTickets>()
+            .expect("Expected SupportTickets in TypeMap.")
+            .get(&channel.guild_id)
+            .is_some_and(|tickets| tickets.open.contains_key(&channel.id))
+    };
+    if is_ticket {
+        update_tickets(ctx, channel.guild_id, |tickets| {
+            tickets.open.remove(&channel.id);
+        })
+        .await?;
+    }
+    Ok(())
+}
+
+async fn respond(
+    ctx: &Context,
+    component: &ComponentInteraction,
+    content: String,
+) -> CommandResult {
+    component
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Handles the buttons of ticket panels, the close button of tickets and the server choice of
+/// modmail.
+pub async fn handle_interaction(ctx: &Context, component: &ComponentInteraction) -> CommandResult {
+    let mut parts = component.data.custom_id.split(':').skip(1);
+    match (parts.next(), component.guild_id) {
+        (Some("open"), Some(guild_id)) => {
+            let category = parts
+                .next()
+                .filter(|category| !category.is_empty())
+                .map(String::from);
+            // Creating the channel can take longer than Discord waits for a response.
+            component.defer_ephemeral(&ctx.http).await?;
+            let content = match open_ticket(ctx, guild_id, &component.user, category, false).await?
+            {
+                Ok(channel) => format!("Your ticket is open: <#{}>", channel),
+                Err(problem) => problem,
+            };
+            component
+                .edit_response(&ctx.http, EditInteractionResponse::new().content(content))
+                .await?;
+            Ok(())
+        }
+        (Some("close"), Some(guild_id)) => {
+            let Some((ticket, config)) = find_ticket(ctx, guild_id, component.channel_id).await
+            else {
+                return respond(
+                    ctx,
+                    component,
+                    String::from("This ticket is already closed."),
+                )
+                .await;
+            };
+            if ticket.user != component.user.id
+                && !is_staff(ctx, guild_id, component.user.id, &config)
+            {
+                return respond(
+                    ctx,
+                    component,
+                    format!("Only staff and <@{}> can close this ticket.", ticket.user),
+                )
+                .await;
+            }
+            component
+                .create_response(
+                    &ctx.http,
+                    CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content(format!("<@{}> is closing the ticket…", component.user.id))
+                            .allowed_mentions(CreateAllowedMentions::new()),
+                    ),
+                )
+                .await?;
+            close_ticket(ctx, guild_id, component.channel_id, &component.user, None).await
+        }
+        (Some("modmail"), None) => {
+            let Some(guild_id) = parts.next().and_then(|id| id.parse::<GuildId>().ok()) else {
+                return Ok(());
+            };
+            // Opening the ticket can take longer than Discord waits for a response.
+            component.defer(&ctx.http).await?;
+            let content = if !modmail_guilds(ctx, component.user.id)
+                .await
+                .contains(&guild_id)
+            {
+                String::from("That server no longer takes messages through me.")
+            } else if let Some(message) = component.message.referenced_message.as_deref() {
+                send_modmail(ctx, guild_id, message).await?
+            } else {
+                String::from("I could not find your message anymore, please send it again.")
+            };
+            component
+                .edit_response(
+                    &ctx.http,
+                    EditInteractionResponse::new()
+                        .content(content)
+                        .components(vec![]),
+                )
+                .await?;
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+#[command]
+#[description = "Opens a private ticket with the staff. If the server has ticket categories, \
+name one."]
+#[usage = "[category]"]
+async fn open(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let category = args.single::<String>().ok().map(|name| name.to_lowercase());
+    let content = match open_ticket(ctx, guild_id, &msg.author, category, false).await? {
+        Ok(channel) => format!("Your ticket is open: <#{}>", channel),
+        Err(problem) => problem,
+    };
+    msg.reply(ctx, content).await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Closes the ticket of this channel. The transcript is archived and the channel \
+deleted."]
+#[usage = "[reason]"]
+async fn close(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let Some((ticket, config)) = find_ticket(ctx, guild_id, msg.channel_id).await else {
+        msg.reply(ctx, "This is not a ticket channel.").await?;
+        return Ok(());
+    };
+    if ticket.user != msg.author.id && !is_staff(ctx, guild_id, msg.author.id, &config) {
+        msg.reply(
+            ctx,
+            "Only staff and the member who opened it can close a ticket.",
+        )
+        .await?;
+        return Ok(());
+    }
+    let reason = Some(args.rest().trim()).filter(|reason| !reason.is_empty());
+    close_ticket(ctx, guild_id, msg.channel_id, &msg.author, reason).await
+}
+
+#[command]
+#[description = "Answers the member of a modmail ticket. They get the answer as a direct message."]
+#[usage = "<message>"]
+async fn reply(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let Some((ticket, config)) = find_ticket(ctx, guild_id, msg.channel_id).await else {
+        msg.reply(ctx, "This is not a ticket channel.").await?;
+        return Ok(());
+    };
+    if !ticket.modmail {
+        msg.reply(ctx, "The member can read this channel, just write here.")
+            .await?;
+        return Ok(());
+    }
+    if !is_staff(ctx, guild_id, msg.author.id, &config) {
+        msg.reply(ctx, "Only staff can answer tickets.").await?;
+        return Ok(());
+    }
+    let content = args.rest().trim();
+    if content.is_empty() && msg.attachments.is_empty() {
+        msg.reply(ctx, "Usage: `ticket reply <message>`").await?;
+        return Ok(());
+    }
+
+    let embed = relay_embed(
+        &msg.author,
+        content,
+        &msg.attachments,
+        format!("Staff of {}", guild_name(ctx, guild_id)),
+    );
+    if ticket
+        .user
+        .direct_message(&ctx, CreateMessage::new().embed(embed))
+        .await
+        .is_err()
+    {
+        msg.reply(
+            ctx,
+            "Could not send the answer, they may have closed their DMs.",
+        )
+        .await?;
+        return Ok(());
+    }
+    msg.react(ctx, '✅').await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Posts a message with buttons to open a ticket, one per category."]
+#[usage = "[title]"]
+async fn panel(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let title = match args.rest().trim() {
+        "" => "Support",
+        title => title,
+    };
+    let config = guild_tickets(ctx, guild_id).await.config;
+
+    let mut description = String::from("Press a button to open a private ticket with the staff.\n");
+    for (name, category) in &config.categories {
+        if !category.description.is_empty() {
+            write!(description, "\n**{}**: {}", name, category.description)?;
+        }
+    }
+    let button = |id: String, label: &str| {
+        CreateButton::new(format!("{}:open:{}", TICKET_PREFIX, id))
+            .label(label)
+            .style(ButtonStyle::Primary)
+    };
+    let buttons = if config.categories.is_empty() {
+        vec![button(String::new(), "Open a ticket")]
+    } else {
+        config
+            .categories
+            .keys()
+            .map(|name| button(name.clone(), name))
+            .collect()
+    };
+    let rows = buttons
+        .chunks(5)
+        .map(|row| CreateActionRow::Buttons(row.to_vec()))
+        .collect();
+
+    msg.channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .embed(
+                    CreateEmbed::new()
+                        .title(truncate(title, 256))
+                        .description(description),
+                )
+                .components(rows),
+        )
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Manages ticket categories. Each category gets its own button on the panel."]
+#[sub_commands(category_add, category_remove)]
+async fn category(ctx: &Context, msg: &Message) -> CommandResult {
+    msg.reply(
+        ctx,
+        "Use `tickets category add` or `remove`. See `help tickets category` for details.",
+    )
+    .await?;
+    Ok(())
+}
+
+#[command("add")]
+#[description = "Adds or changes a ticket category. Its tickets are created in the given Discord \
+category, or the default one."]
+#[usage = "<name> [#category] [description]"]
+#[example = "appeals #support Appeal a ban or a warning"]
+async fn category_add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let name = args.single::<String>().unwrap_or_default().to_lowercase();
+    if name.is_empty()
+        || name.chars().count() > MAX_CATEGORY_NAME
+        || !name.chars().all(|c| c.is_alphanumeric() || c == '-')
+    {
+        msg.reply(
+            ctx,
+            format!(
+                "Category names are up to {} letters, digits and dashes.",
+                MAX_CATEGORY_NAME
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+    let parent = args.current().and_then(parse_channel);
+    if let Some(parent) = parent {
+        if !is_category(ctx, guild_id, parent) {
+            msg.reply(ctx, "Tickets can only be created in a Discord category.")
+                .await?;
+            return Ok(());
+        }
+        args.advance();
+    }
+    let description = args.rest().trim().to_string();
+
+    let added = update_tickets(ctx, guild_id, |tickets| {
+        let categories = &mut tickets.config.categories;
+        if categories.len() >= MAX_CATEGORIES && !categories.contains_key(&name) {
+            return false;
+        }
+        categories.insert(
+            name.clone(),
+            TicketCategory {
+                description,
+                parent,
+            },
+        );
+        true
+    })
+    .await?;
+    let content = if added {
+        format!(
+            "Saved the category `{}`. Post a new panel with `tickets panel` to show it.",
+            name
+        )
+    } else {
+        format!("A server can have at most {} categories.", MAX_CATEGORIES)
+    };
+    msg.reply(ctx, content).await?;
+    Ok(())
+}
+
+#[command("remove")]
+#[description = "Removes a ticket category. Its open tickets stay open."]
+#[usage = "<name>"]
+async fn category_remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let name = args.single::<String>().unwrap_or_default().to_lowercase();
+    let removed = update_tickets(ctx, guild_id, |tickets| {
+        tickets.config.categories.remove(&name).is_some()
+    })
+    .await?;
+    let content = if removed {
+        format!("Removed the category `{}`.", name)
+    } else {
+        format!("There is no ticket category `{}`.", name)
+    };
+    msg.reply(ctx, content).await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Manages the staff roles, which can see, answer and close every ticket."]
+#[sub_commands(staff_add, staff_remove)]
+async fn staff(ctx: &Context, msg: &Message) -> CommandResult {
+    msg.reply(
+        ctx,
+        "Use `tickets staff add` or `remove`. See `help tickets staff` for details.",
+    )
+    .await?;
+    Ok(())
+}
+
+#[command("add")]
+#[description = "Adds a staff role. It gets access to tickets opened from now on."]
+#[usage = "<@role>"]
+async fn staff_add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let Some(role) = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_role(&arg))
+    else {
+        msg.reply(ctx, "Usage: `tickets staff add <@role>`").await?;
+        return Ok(());
+    };
+    update_tickets(ctx, guild_id, |tickets| {
+        if !tickets.config.staff_roles.contains(&role) {
+            tickets.config.staff_roles.push(role);
+        }
+    })
+    .await?;
+    msg.channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(format!("<@&{}> is now a staff role.", role))
+                .reference_message(msg)
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+    Ok(())
+}
+
+#[command("remove")]
+#[description = "Removes a staff role."]
+#[usage = "<@role>"]
+async fn staff_remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let Some(role) = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_role(&arg))
+    else {
+        msg.reply(ctx, "Usage: `tickets staff remove <@role>`")
+            .await?;
+        return Ok(());
+    };
+    let removed = update_tickets(ctx, guild_id, |tickets| {
+        let before = tickets.config.staff_roles.len();
+        tickets.config.staff_roles.retain(|staff| *staff != role);
+        tickets.config.staff_roles.len() != before
+    })
+    .await?;
+    let content = if removed {
+        format!("<@&{}> is no longer a staff role.", role)
+    } else {
+        format!("<@&{}> is not a staff role.", role)
+    };
+    msg.channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(content)
+                .reference_message(msg)
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Sets the Discord category tickets are created in when their ticket category has \
+none, or turns it off with `off`."]
+#[usage = "<#category|off>"]
+async fn parent(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let arg = args.single::<String>().unwrap_or_default();
+    let parent = if arg == "off" {
+        None
+    } else {
+        match parse_channel(&arg) {
+            Some(parent) if is_category(ctx, guild_id, parent) => Some(parent),
+            _ => {
+                msg.reply(ctx, "Usage: `tickets parent <#category|off>`")
+                    .await?;
+                return Ok(());
+            }
+        }
+    };
+    update_tickets(ctx, guild_id, |tickets| tickets.config.parent = parent).await?;
+    let content = match parent {
+        Some(parent) => format!("Tickets are now created in <#{}>.", parent),
+        None => String::from("Tickets are now created outside of any category."),
+    };
+    msg.reply(ctx, content).await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Sets the channel the transcripts of closed tickets are posted in, or turns them \
+off with `off`."]
+#[usage = "<#channel|off>"]
+async fn transcripts(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let arg = args.single::<String>().unwrap_or_default();
+    let channel = if arg == "off" {
+        None
+    } else {
+        match parse_channel(&arg) {
+            Some(channel) if is_text_channel(ctx, guild_id, channel) => Some(channel),
+            _ => {
+                msg.reply(ctx, "Usage: `tickets transcripts <#channel|off>`")
+                    .await?;
+                return Ok(());
+            }
+        }
+    };
+    update_tickets(ctx, guild_id, |tickets| {
+        tickets.config.transcript_channel = channel
+    })
+    .await?;
+    let content = match channel {
+        Some(channel) => format!("Transcripts are now posted in <#{}>.", channel),
+        None => String::from("Transcripts are no longer posted."),
+    };
+    msg.reply(ctx, content).await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Turns modmail on or off. With modmail, members open a ticket by sending the bot a \
+direct message, and the conversation is relayed both ways."]
+#[usage = "<on|off>"]
+async fn modmail(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let enabled = match args.single::<String>().unwrap_or_default().as_str() {
+        "on" => true,
+        "off" => false,
+        _ => {
+            msg.reply(ctx, "Usage: `tickets modmail <on|off>`").await?;
+            return Ok(());
+        }
+    };
+    update_tickets(ctx, guild_id, |tickets| tickets.config.modmail = enabled).await?;
+    let content = if enabled {
+        "Members can now open a ticket by sending me a direct message."
+    } else {
+        "Modmail is off, open modmail tickets keep working until they are closed."
+    };
+    msg.reply(ctx, content).await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Shows the ticket settings and how many tickets are open."]
+async fn settings(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Only in guilds")?;
+    let tickets = guild_tickets(ctx, guild_id).await;
+    let config = &tickets.config;
+    let channel = |channel: Option<ChannelId>| {
+        channel.map_or_else(|| String::from("off"), |channel| format!("<#{}>", channel))
+    };
+
+    let staff = if config.staff_roles.is_empty() {
+        String::from("none, only members who can manage channels")
+    } else {
+        config
+            .staff_roles
+            .iter()
+            .map(|role| format!("<@&{}>", role))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let mut categories = String::new();
+    for (name, category) in &config.categories {
+        write!(categories, "`{}`", name)?;
+        if let Some(parent) = category.parent {
+            write!(categories, " in <#{}>", parent)?;
+        }
+        if !category.description.is_empty() {
+            write!(categories, ": {}", category.description)?;
+        }
+        categories.push('\n');
+    }
+    if categories.is_empty() {
+        categories.push_str("none, the panel has a single button");
+    }
+
+    let embed = CreateEmbed::new()
+        .title("Tickets")
+        .field("Staff roles", truncate(&staff, 1024), false)
+        .field("Categories", truncate(&categories, 1024), false)
+        .field("Default category", channel(config.parent), true)
+        .field("Transcripts", channel(config.transcript_channel), true)
+        .field("Modmail", if config.modmail { "on" } else { "off" }, true)
+        .field("Open tickets", tickets.open.len().to_string(), true);
+    msg.channel_id
+        .send_message(&ctx.http, CreateMessage::new().embed(embed))
+        .await?;
+    Ok(())
+}
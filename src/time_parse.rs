@@ -0,0 +1,310 @@
+use chrono::{
+    DateTime, Days, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+
+/// Time of day used when only a date is given.
+const DEFAULT_TIME: (u32, u32) = (9, 0);
+/// Longer durations are rejected, they are far beyond anything the bot schedules and would
+/// overflow date arithmetic.
+pub const MAX_DURATION_SECS: i64 = 100 * 365 * 24 * 60 * 60;
+
+fn unit_seconds(unit: &str) -> Option<i64> {
+    match unit {
+        "s" | "sec" | "secs" | "second" | "seconds" => Some(1),
+        "m" | "min" | "mins" | "minute" | "minutes" => Some(60),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Some(60 * 60),
+        "d" | "day" | "days" => Some(24 * 60 * 60),
+        "w" | "week" | "weeks" => Some(7 * 24 * 60 * 60),
+        _ => None,
+    }
+}
+
+/// Parses a word made of number and unit pairs, like `2h` or `1h30m`.
+fn parse_compact_duration(word: &str) -> Option<i64> {
+    let mut total = 0i64;
+    let mut rest = word;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let unit_end = rest[digits..]
+            .find(|c: char| c.is_ascii_digit())
+            .map_or(rest.len(), |end| digits + end);
+        if digits == 0 {
+            return None;
+        }
+        let amount = rest[..digits].parse::<i64>().ok()?;
+        let unit = unit_seconds(&rest[digits..unit_end])?;
+        total = total.checked_add(amount.checked_mul(unit)?)?;
+        rest = &rest[unit_end..];
+    }
+    Some(total)
+}
+
+/// Parses a duration from the start of `words`, like `2h`, `1h30m` or `2 hours 5 minutes`.
+/// Returns the duration in seconds and how many words it took up.
+pub fn parse_duration(words: &[&str]) -> Option<(i64, usize)> {
+    let mut total = 0i64;
+    let mut used = 0;
+    while used < words.len() {
+        let word = words[used].to_lowercase();
+        let word = word.trim_end_matches(',');
+        if let Some(seconds) = parse_compact_duration(word) {
+            total = total.checked_add(seconds)?;
+            used += 1;
+        } else if let (Ok(amount), Some(unit)) = (
+            word.parse::<i64>(),
+            words
+                .get(used + 1)
+                .and_then(|unit| unit_seconds(unit.to_lowercase().trim_end_matches(','))),
+        ) {
+            total = total.checked_add(amount.checked_mul(unit)?)?;
+            used += 2;
+        } else if word == "and" && used > 0 {
+            used += 1;
+        } else {
+            break;
+        }
+    }
+    // A trailing "and" belongs to the text, not the duration.
+    while used > 0 && words[used - 1].eq_ignore_ascii_case("and") {
+        used -= 1;
+    }
+    (used > 0 && total > 0 && total <= MAX_DURATION_SECS).then_some((total, used))
+}
+
+/// Formats a number of seconds with its two largest units, like `2h 5m` or `3d 4h`.
+pub fn format_duration(seconds: i64) -> String {
+    const UNITS: [(i64, &str); 4] = [(24 * 60 * 60, "d"), (60 * 60, "h"), (60, "m"), (1, "s")];
+    let mut rest = seconds.max(0);
+    let mut parts = Vec::new();
+    for (unit, name) in UNITS {
+        if rest >= unit && parts.len() < 2 {
+            parts.push(format!("{}{}", rest / unit, name));
+            rest %= unit;
+        } else if !parts.is_empty() {
+            break;
+        }
+    }
+    if parts.is_empty() {
+        String::from("0s")
+    } else {
+        parts.join(" ")
+    }
+}
+
+pub fn parse_time_of_day(word: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(word, "%H:%M").ok()
+}
+
+pub fn parse_date(word: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(word, "%Y-%m-%d").ok()
+}
+
+/// Turns a wall clock time in `tz` into an instant. Times skipped by a daylight saving switch
+/// are moved forward by an hour, repeated times use the earlier one.
+pub fn resolve_local(tz: Tz, local: NaiveDateTime) -> Option<DateTime<Utc>> {
+    let resolved = match tz.from_local_datetime(&local) {
+        LocalResult::Single(time) => time,
+        LocalResult::Ambiguous(earlier, _) => earlier,
+        LocalResult::None => tz
+            .from_local_datetime(&(local + chrono::Duration::hours(1)))
+            .earliest()?,
+    };
+    Some(resolved.with_timezone(&Utc))
+}
+
+/// Parses when something should happen from the start of `words`:
+/// - `in 2h`, `in 1 day 3 hours`
+/// - `at 18:00`, the next time it is 18:00 in `tz`
+/// - `at 2026-11-01 18:00` or `at 2026-11-01`
+/// - `tomorrow`, `tomorrow at 18:00`
+///
+/// Returns the instant and how many words it took up.
+pub fn parse_when(words: &[&str], tz: Tz, now: DateTime<Utc>) -> Option<(DateTime<Utc>, usize)> {
+    let first = words.first()?.to_lowercase();
+    let local_now = now.with_timezone(&tz).naive_local();
+    match first.as_str() {
+        "in" => {
+            let (seconds, used) = parse_duration(&words[1..])?;
+            let due = now.checked_add_signed(TimeDelta::try_seconds(seconds)?)?;
+            Some((due, used + 1))
+        }
+        "at" | "on" => {
+            let rest = &words[1..];
+            if let Some(date) = rest.first().and_then(|word| parse_date(word)) {
+                let (time, used) = match rest.get(1).and_then(|word| parse_time_of_day(word)) {
+                    Some(time) => (time, 3),
+                    None => (
+                        NaiveTime::from_hms_opt(DEFAULT_TIME.0, DEFAULT_TIME.1, 0)?,
+                        2,
+                    ),
+                };
+                return Some((resolve_local(tz, date.and_time(time))?, used));
+            }
+            let time = parse_time_of_day(rest.first()?)?;
+            let mut local = local_now.date().and_time(time);
+            if local <= local_now {
+                local = local.checked_add_days(Days::new(1))?;
+            }
+            Some((resolve_local(tz, local)?, 2))
+        }
+        "tomorrow" => {
+            let date = local_now.date().checked_add_days(Days::new(1))?;
+            let (time, used) = match words.get(1).map(|word| word.to_lowercase()) {
+                Some(word) if word == "at" => (parse_time_of_day(words.get(2)?)?, 3),
+                Some(word) => match parse_time_of_day(&word) {
+                    Some(time) => (time, 2),
+                    None => (
+                        NaiveTime::from_hms_opt(DEFAULT_TIME.0, DEFAULT_TIME.1, 0)?,
+                        1,
+                    ),
+                },
+                None => (
+                    NaiveTime::from_hms_opt(DEFAULT_TIME.0, DEFAULT_TIME.1, 0)?,
+                    1,
+                ),
+            };
+            Some((resolve_local(tz, date.and_time(time))?, used))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn parses_compact_and_spelled_out_durations() {
+        assert_eq!(parse_duration(&["2h"]), Some((2 * 60 * 60, 1)));
+        assert_eq!(parse_duration(&["1h30m", "text"]), Some((90 * 60, 1)));
+        assert_eq!(
+            parse_duration(&["2", "hours", "and", "5", "minutes", "later"]),
+            Some((2 * 60 * 60 + 5 * 60, 5))
+        );
+        assert_eq!(parse_duration(&["1w,", "2d"]), Some((9 * 24 * 60 * 60, 2)));
+    }
+
+    #[test]
+    fn a_trailing_and_belongs_to_the_text() {
+        assert_eq!(
+            parse_duration(&["2h", "and", "stuff"]),
+            Some((2 * 60 * 60, 1))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        assert_eq!(parse_duration(&[]), None);
+        assert_eq!(parse_duration(&["soon"]), None);
+        assert_eq!(parse_duration(&["h2"]), None);
+        assert_eq!(parse_duration(&["2x"]), None);
+        assert_eq!(parse_duration(&["0s"]), None);
+        assert_eq!(parse_duration(&["and"]), None);
+    }
+
+    #[test]
+    fn rejects_huge_durations() {
+        assert_eq!(parse_duration(&["9999999999999999s"]), None);
+        assert_eq!(parse_duration(&["99999999999999999999s"]), None);
+        assert_eq!(parse_duration(&["9223372036854775807w"]), None);
+        assert_eq!(
+            parse_duration(&["1000000000000000000s", "1000000000000000000s"]),
+            None
+        );
+        let max = format!("{}s", MAX_DURATION_SECS);
+        assert_eq!(parse_duration(&[&max]), Some((MAX_DURATION_SECS, 1)));
+    }
+
+    #[test]
+    fn format_duration_shows_the_two_largest_units() {
+        assert_eq!(format_duration(0), "0s");
+        assert_eq!(format_duration(-5), "0s");
+        assert_eq!(format_duration(45), "45s");
+        assert_eq!(format_duration(60), "1m");
+        assert_eq!(format_duration(2 * 60 * 60 + 5 * 60 + 9), "2h 5m");
+        assert_eq!(format_duration(3 * 24 * 60 * 60 + 4 * 60 * 60), "3d 4h");
+        assert_eq!(format_duration(24 * 60 * 60 + 30), "1d");
+        assert_eq!(format_duration(60 * 60 + 1), "1h");
+        assert_eq!(format_duration(i64::MAX), "106751991167300d 15h");
+    }
+
+    #[test]
+    fn parse_when_in_does_not_panic_on_huge_durations() {
+        let now = utc("2026-10-19T12:00:00Z");
+        assert_eq!(
+            parse_when(&["in", "9999999999999999s", "x"], Tz::UTC, now),
+            None
+        );
+        assert_eq!(
+            parse_when(&["in", "2h", "x"], Tz::UTC, now),
+            Some((utc("2026-10-19T14:00:00Z"), 2))
+        );
+    }
+
+    #[test]
+    fn parse_when_at_picks_the_next_occurrence() {
+        let now = utc("2026-10-19T12:00:00Z");
+        let tz = Tz::Europe__Berlin;
+        // It is 14:00 in Berlin, 15:00 is still ahead and 13:00 has passed.
+        assert_eq!(
+            parse_when(&["at", "15:00"], tz, now),
+            Some((utc("2026-10-19T13:00:00Z"), 2))
+        );
+        assert_eq!(
+            parse_when(&["at", "13:00"], tz, now),
+            Some((utc("2026-10-20T11:00:00Z"), 2))
+        );
+        assert_eq!(
+            parse_when(&["on", "2026-11-01", "18:00", "x"], tz, now),
+            Some((utc("2026-11-01T17:00:00Z"), 3))
+        );
+        assert_eq!(
+            parse_when(&["at", "2026-11-01", "x"], tz, now),
+            Some((utc("2026-11-01T08:00:00Z"), 2))
+        );
+        assert_eq!(parse_when(&["at", "25:00"], tz, now), None);
+    }
+
+    #[test]
+    fn parse_when_tomorrow() {
+        let now = utc("2026-10-19T12:00:00Z");
+        assert_eq!(
+            parse_when(&["tomorrow", "x"], Tz::UTC, now),
+            Some((utc("2026-10-20T09:00:00Z"), 1))
+        );
+        assert_eq!(
+            parse_when(&["tomorrow", "at", "18:30"], Tz::UTC, now),
+            Some((utc("2026-10-20T18:30:00Z"), 3))
+        );
+        assert_eq!(
+            parse_when(&["Tomorrow", "07:15"], Tz::UTC, now),
+            Some((utc("2026-10-20T07:15:00Z"), 2))
+        );
+        assert_eq!(parse_when(&["yesterday"], Tz::UTC, now), None);
+    }
+
+    #[test]
+    fn resolve_local_handles_daylight_saving_switches() {
+        let tz = Tz::Europe__Berlin;
+        let date = |text| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap();
+        // 02:30 does not exist on the spring switch and moves to 03:30 summer time.
+        assert_eq!(
+            resolve_local(tz, date("2026-03-29 02:30")),
+            Some(utc("2026-03-29T01:30:00Z"))
+        );
+        // 02:30 happens twice on the autumn switch, the earlier one is used.
+        assert_eq!(
+            resolve_local(tz, date("2026-10-25 02:30")),
+            Some(utc("2026-10-25T00:30:00Z"))
+        );
+    }
+}
@@ -0,0 +1,528 @@
+use crate::command_base::*;
+use crate::image_edit;
+use crate::role_menu_commands::setup_problem;
+use crate::storage::save_json;
+use crate::tag_commands::{render_template, TemplateContext};
+
+use serde::{Deserialize, Serialize};
+use serenity::all::{
+    CreateAllowedMentions, CreateAttachment, CreateMessage, GuildMemberUpdateEvent, Member, User,
+};
+use serenity::model::id::{ChannelId, GuildId, RoleId};
+use serenity::utils::{parse_channel_mention, parse_role_mention};
+use std::fmt::Write;
+use std::time::Duration;
+
+pub const WELCOME_PATH: &str = "data/welcome.json";
+/// Pending autoroles only live in memory, so long delays would not survive a restart.
+const MAX_AUTOROLE_DELAY_SECS: u64 = 24 * 60 * 60;
+
+#[group]
+#[prefixes("welcome")]
+#[commands(
+    channel, message, goodbye, card, autorole, delay, screening, test, status
+)]
+#[required_permissions(MANAGE_GUILD)]
+#[only_in(guilds)]
+pub struct Welcome;
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct WelcomeConfig {
+    /// Channel the welcome and goodbye messages are posted in.
+    pub channel: Option<ChannelId>,
+    pub welcome: Option<String>,
+    pub goodbye: Option<String>,
+    /// Attach a card with the avatar and member count to welcome messages.
+    pub card: bool,
+    pub autoroles: Vec<RoleId>,
+    pub autorole_delay_secs: u64,
+    /// Only give the autoroles once the member passed membership screening.
+    pub require_screening: bool,
+}
+
+pub struct WelcomeConfigs;
+
+impl TypeMapKey for WelcomeConfigs {
+    type Value = HashMap<GuildId, WelcomeConfig>;
+}
+
+async fn guild_config(ctx: &Context, guild_id: GuildId) -> Option<WelcomeConfig> {
+    let data = ctx.data.read().await;
+    data.get::<WelcomeConfigs>()
+        .expect("Expected WelcomeConfigs in TypeMap.")
+        .get(&guild_id)
+        .cloned()
+}
+
+/// Fills in a welcome or goodbye template. On top of the tag placeholders `{count}` is the
+/// member count of the server.
+fn render_greeting(ctx: &Context, template: &str, guild_id: GuildId, user: &User) -> String {
+    let (server, count) = guild_id
+        .to_guild_cached(&ctx.cache)
+        .map(|guild| (guild.name.clone(), guild.member_count))
+        .unwrap_or_else(|| (String::from("this server"), 0));
+    let user_name = user
+        .global_name
+        .clone()
+        .unwrap_or_else(|| user.name.clone());
+    let context = TemplateContext {
+        user: user.id,
+        user_name: &user_name,
+        channel: "",
+        server: &server,
+        args: "",
+    };
+    render_template(
+        &template.replace("{count}", &count.to_string()),
+        &context,
+        &mut rand::thread_rng(),
+    )
+}
+
+/// Builds the welcome message for `user`, with a card if the server wants one.
+async fn welcome_message(
+    ctx: &Context,
+    config: &WelcomeConfig,
+    guild_id: GuildId,
+    user: &User,
+) -> CreateMessage {
+    let template = config.welcome.as_deref().unwrap_or("Welcome {user}!");
+    let mut message = CreateMessage::new()
+        .content(render_greeting(ctx, template, guild_id, user))
+        .allowed_mentions(CreateAllowedMentions::new().users([user.id]));
+    if config.card {
+        match welcome_card(ctx, guild_id, user).await {
+            Ok(card) => message = message.add_file(CreateAttachment::bytes(card, "welcome.png")),
+            Err(why) => println!("Could not draw the welcome card of {}: {why:?}", user.id),
+        }
+    }
+    message
+}
+
+async fn welcome_card(ctx: &Context, guild_id: GuildId, user: &User) -> CommandResult<Vec<u8>> {
+    let response = reqwest::get(user.static_face()).await?.error_for_status()?;
+    let avatar = response.bytes().await?;
+    if avatar.len() as u64 > image_edit::MAX_INPUT_BYTES {
+        return Err("Avatar is too big".into());
+    }
+
+    let user_name = user
+        .global_name
+        .clone()
+        .unwrap_or_else(|| user.name.clone());
+    let title = format!("Welcome, {}!", user_name);
+    let subtitle = match guild_id.to_guild_cached(&ctx.cache) {
+        Some(guild) => format!("Member #{} of {}", guild.member_count, guild.name),
+        None => String::from("Glad you are here"),
+    };
+    let card =
+        tokio::task::spawn_blocking(move || image_edit::welcome_card(&avatar, &title, &subtitle))
+            .await??;
+    Ok(card)
+}
+
+async fn give_autoroles(ctx: &Context, guild_id: GuildId, member: &Member, roles: &[RoleId]) {
+    let missing = roles
+        .iter()
+        .filter(|role| !member.roles.contains(role))
+        .copied()
+        .collect::<Vec<_>>();
+    for role in missing {
+        if let Err(why) = ctx
+            .http
+            .add_member_role(guild_id, member.user.id, role, Some("Autorole"))
+            .await
+        {
+            println!(
+                "Could not give autorole {role} to {}: {why:?}",
+                member.user.id
+            );
+        }
+    }
+}
+
+/// Waits the configured delay, then gives the autoroles if the member is still there.
+fn schedule_autoroles(ctx: &Context, guild_id: GuildId, member: &Member, config: &WelcomeConfig) {
+    if config.autoroles.is_empty() {
+        return;
+    }
+    let ctx = ctx.clone();
+    let user_id = member.user.id;
+    let roles = config.autoroles.clone();
+    let delay = Duration::from_secs(config.autorole_delay_secs);
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        match guild_id.member(&ctx.http, user_id).await {
+            Ok(member) => give_autoroles(&ctx, guild_id, &member, &roles).await,
+            Err(why) => println!("Member {user_id} left before getting autoroles: {why:?}"),
+        }
+    });
+}
+
+pub async fn handle_join(ctx: &Context, member: &Member) -> CommandResult {
+    let guild_id = member.guild_id;
+    let Some(config) = guild_config(ctx, guild_id).await else {
+        return Ok(());
+    };
+
+    // Members that still have to pass screening get their roles in `handle_update`.
+    if !(config.require_screening && member.pending) {
+        schedule_autoroles(ctx, guild_id, member, &config);
+    }
+
+    if let (Some(channel), Some(_)) = (config.channel, &config.welcome) {
+        let message = welcome_message(ctx, &config, guild_id, &member.user).await;
+        channel.send_message(&ctx.http, message).await?;
+    }
+    Ok(())
+}
+
+/// Gives the autoroles to members that just passed membership screening. Without the cached old
+/// member there is no telling whether it just happened, so nothing is given.
+pub async fn handle_update(
+    ctx: &Context,
+    old: Option<&Member>,
+    event: &GuildMemberUpdateEvent,
+) -> CommandResult {
+    if !old.is_some_and(|old| old.pending) || event.pending {
+        return Ok(());
+    }
+    let Some(config) = guild_config(ctx, event.guild_id).await else {
+        return Ok(());
+    };
+    if !config.require_screening {
+        return Ok(());
+    }
+    if config
+        .autoroles
+        .iter()
+        .all(|role| event.roles.contains(role))
+    {
+        return Ok(());
+    }
+
+    let member = event.guild_id.member(&ctx.http, event.user.id).await?;
+    schedule_autoroles(ctx, event.guild_id, &member, &config);
+    Ok(())
+}
+
+pub async fn handle_leave(ctx: &Context, guild_id: GuildId, user: &User) -> CommandResult {
+    let Some(config) = guild_config(ctx, guild_id).await else {
+        return Ok(());
+    };
+    let (Some(channel), Some(template)) = (config.channel, &config.goodbye) else {
+        return Ok(());
+    };
+
+    // The member is gone, pinging them would only show an unknown user.
+    let message = CreateMessage::new()
+        .content(render_greeting(ctx, template, guild_id, user))
+        .allowed_mentions(CreateAllowedMentions::new());
+    channel.send_message(&ctx.http, message).await?;
+    Ok(())
+}
+
+/// Applies `change` to the welcome config of the guild and saves all configs.
+async fn update_config(
+    ctx: &Context,
+    guild_id: GuildId,
+    change: impl FnOnce(&mut WelcomeConfig),
+) -> CommandResult {
+    let configs = {
+        let mut data = ctx.data.write().await;
+        let configs = data
+            .get_mut::<WelcomeConfigs>()
+            .expect("Expected WelcomeConfigs in TypeMap.");
+        change(configs.entry(guild_id).or_default());
+        configs.clone()
+    };
+    save_json(WELCOME_PATH, &configs).await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Sets the channel welcome and goodbye messages are posted in."]
+#[usage = "<#channel>"]
+async fn channel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Some(channel) = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_channel_mention(&arg).or_else(|| arg.parse().ok()))
+    else {
+        msg.reply(ctx, "You need to provide a channel!").await?;
+        return Ok(());
+    };
+
+    update_config(ctx, msg.guild_id.unwrap(), |config| {
+        config.channel = Some(channel)
+    })
+    .await?;
+    msg.channel_id
+        .say(
+            &ctx.http,
+            format!("Welcome messages will be posted in <#{}>.", channel),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Sets the welcome message, or turns it off with `off`. Placeholders: `{user}`, \
+`{user.name}`, `{server}`, `{count}` and `{random:a|b|c}`."]
+#[usage = "<message|off>"]
+async fn message(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let template = args.rest().trim().to_string();
+    if template.is_empty() {
+        msg.reply(ctx, "You need to provide a message!").await?;
+        return Ok(());
+    }
+    let welcome = (template != "off").then_some(template);
+    let reply = if welcome.is_some() {
+        "Welcome message set."
+    } else {
+        "Welcome message turned off."
+    };
+
+    update_config(ctx, msg.guild_id.unwrap(), |config| {
+        config.welcome = welcome
+    })
+    .await?;
+    msg.channel_id.say(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Sets the goodbye message, or turns it off with `off`. Same placeholders as the \
+welcome message."]
+#[usage = "<message|off>"]
+async fn goodbye(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let template = args.rest().trim().to_string();
+    if template.is_empty() {
+        msg.reply(ctx, "You need to provide a message!").await?;
+        return Ok(());
+    }
+    let goodbye = (template != "off").then_some(template);
+    let reply = if goodbye.is_some() {
+        "Goodbye message set."
+    } else {
+        "Goodbye message turned off."
+    };
+
+    update_config(ctx, msg.guild_id.unwrap(), |config| {
+        config.goodbye = goodbye
+    })
+    .await?;
+    msg.channel_id.say(&ctx.http, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Sets whether welcome messages come with a card showing the avatar and member \
+count."]
+#[usage = "<on|off>"]
+async fn card(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let card = match args.single::<String>().unwrap_or_default().as_str() {
+        "on" => true,
+        "off" => false,
+        _ => {
+            msg.reply(ctx, "Usage: `welcome card <on|off>`").await?;
+            return Ok(());
+        }
+    };
+
+    update_config(ctx, msg.guild_id.unwrap(), |config| config.card = card).await?;
+    msg.channel_id
+        .say(
+            &ctx.http,
+            format!("Welcome cards are {}.", if card { "on" } else { "off" }),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Adds or removes a role that every new member gets."]
+#[usage = "<add|remove> <@role>"]
+async fn autorole(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mode = args.single::<String>().unwrap_or_default();
+    let role = args
+        .single::<String>()
+        .ok()
+        .and_then(|arg| parse_role_mention(&arg).or_else(|| arg.parse().ok()));
+    let Some(role) = role.filter(|_| mode == "add" || mode == "remove") else {
+        msg.reply(ctx, "Usage: `welcome autorole <add|remove> <@role>`")
+            .await?;
+        return Ok(());
+    };
+    if mode == "add" {
+        if let Some(problem) = setup_problem(ctx, msg.guild_id.unwrap(), msg.author.id, role) {
+            msg.channel_id
+                .send_message(
+                    &ctx.http,
+                    CreateMessage::new()
+                        .content(problem)
+                        .allowed_mentions(CreateAllowedMentions::new()),
+                )
+                .await?;
+            return Ok(());
+        }
+    }
+
+    update_config(ctx, msg.guild_id.unwrap(), |config| {
+        config.autoroles.retain(|autorole| *autorole != role);
+        if mode == "add" {
+            config.autoroles.push(role);
+        }
+    })
+    .await?;
+    msg.channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(if mode == "add" {
+                    format!("New members will get <@&{}>.", role)
+                } else {
+                    format!("New members will no longer get <@&{}>.", role)
+                })
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Sets how many seconds to wait before giving the autoroles."]
+#[usage = "<seconds>"]
+async fn delay(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let Ok(seconds) = args.single::<u64>() else {
+        msg.reply(ctx, "Usage: `welcome delay <seconds>`").await?;
+        return Ok(());
+    };
+    let seconds = seconds.min(MAX_AUTOROLE_DELAY_SECS);
+
+    update_config(ctx, msg.guild_id.unwrap(), |config| {
+        config.autorole_delay_secs = seconds
+    })
+    .await?;
+    msg.channel_id
+        .say(
+            &ctx.http,
+            format!("Autoroles are given {} seconds after joining.", seconds),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Sets whether autoroles are only given once a member passed membership \
+screening."]
+#[usage = "<on|off>"]
+async fn screening(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let require_screening = match args.single::<String>().unwrap_or_default().as_str() {
+        "on" => true,
+        "off" => false,
+        _ => {
+            msg.reply(ctx, "Usage: `welcome screening <on|off>`")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    update_config(ctx, msg.guild_id.unwrap(), |config| {
+        config.require_screening = require_screening
+    })
+    .await?;
+    msg.channel_id
+        .say(
+            &ctx.http,
+            if require_screening {
+                "Autoroles wait for membership screening."
+            } else {
+                "Autoroles no longer wait for membership screening."
+            },
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[description = "Shows the welcome message as if you just joined."]
+async fn test(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.unwrap();
+    let config = guild_config(ctx, guild_id).await.unwrap_or_default();
+    let message = welcome_message(ctx, &config, guild_id, &msg.author).await;
+    msg.channel_id.send_message(&ctx.http, message).await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Shows the welcome settings of this server."]
+async fn status(ctx: &Context, msg: &Message) -> CommandResult {
+    let config = guild_config(ctx, msg.guild_id.unwrap())
+        .await
+        .unwrap_or_default();
+
+    let mut contents = String::new();
+    match config.channel {
+        Some(channel) => writeln!(contents, "Channel: <#{}>", channel)?,
+        None => writeln!(contents, "Channel: none")?,
+    }
+    writeln!(
+        contents,
+        "Welcome: {}",
+        config.welcome.as_deref().unwrap_or("off")
+    )?;
+    writeln!(
+        contents,
+        "Goodbye: {}",
+        config.goodbye.as_deref().unwrap_or("off")
+    )?;
+    writeln!(contents, "Card: {}", if config.card { "on" } else { "off" })?;
+    let autoroles = config
+        .autoroles
+        .iter()
+        .map(|role| format!("<@&{}>", role))
+        .collect::<Vec<_>>();
+    writeln!(
+        contents,
+        "Autoroles: {}",
+        if autoroles.is_empty() {
+            String::from("none")
+        } else {
+            autoroles.join(", ")
+        }
+    )?;
+    writeln!(
+        contents,
+        "Autorole delay: {} seconds",
+        config.autorole_delay_secs
+    )?;
+    writeln!(
+        contents,
+        "Wait for screening: {}",
+        if config.require_screening {
+            "yes"
+        } else {
+            "no"
+        }
+    )?;
+
+    msg.channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(contents)
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+
+    Ok(())
+}